@@ -50,10 +50,8 @@ pub trait Poseidon2: PrimeField64 {
     // Preprocess
     //      M_E * x
     // External_i = M_E * ((x_0 + c_0 ^ {i}) ^ 7, (x_1 + c_1 ^ {i}) ^ 7, ..., (x_{t
-    // - 1}} + c_{t - 1} ^ {i}) ^ 7) Note:
-    //      x_0 + c_0^{i} -- Add roundconstant
-    //      _ ^ 7 -- Sbox
-    //      M_E * -- Linear layer
+    // - 1}} + c_{t - 1} ^ {i}) ^ 7) Note: x_0 + c_0^{i} -- Add roundconstant _ ^ 7
+    //   -- Sbox M_E * -- Linear layer
     // Internal_I = M_I * ((x_0 + c_0 ^ {i}) ^ 7, x_1, x-2, ..., x_{t - 1})
     #[inline]
     fn poseidon2(input: [Self; WIDTH]) -> [Self; WIDTH] {
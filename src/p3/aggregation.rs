@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use anyhow::ensure;
+use anyhow::Result;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::hash::hash_types::HashOut;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::PartialWitness;
+use plonky2::iop::witness::WitnessWrite;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::CircuitConfig;
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::circuit_data::CommonCircuitData;
+use plonky2::plonk::circuit_data::VerifierOnlyCircuitData;
+use plonky2::plonk::config::Hasher;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2::plonk::proof::ProofWithPublicInputsTarget;
+
+use crate::common::poseidon2::poseidon2::Poseidon2Hash;
+use crate::common::poseidon2::poseidon2_gate::Poseidon2GoldilocksConfig;
+use crate::p3::air::Air;
+use crate::p3::circuit::P3VerifierCircuit;
+use crate::p3::verifier::P3VerifierError;
+
+type F = GoldilocksField;
+type C = Poseidon2GoldilocksConfig;
+const D: usize = 2;
+
+/// A proof aggregated by the tree, either a wrapped Plonky3 proof or the proof
+/// of a node of the given shape.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Child {
+    Leaf,
+    Node(usize),
+}
+
+/// Circuit of the nodes of the tree with the same children.
+struct AggregationNode {
+    children: Vec<Child>,
+    data: CircuitData<F, C, D>,
+    proof_targets: Vec<ProofWithPublicInputsTarget<D>>,
+}
+
+/// A tree of plonky2 circuits aggregating a fixed number of proofs of a
+/// [`P3VerifierCircuit`] into one.
+///
+/// Each node recursively verifies up to `arity` proofs of the level below, and
+/// its only public inputs are the Poseidon2 digest of theirs: wrapped proofs
+/// contribute the digest of their public inputs, i.e. of the public values and
+/// trace commitment of the Plonky3 proof, and nodes their own digest. The
+/// public inputs of the root proof thus commit to every wrapped proof, in
+/// order, see [`P3AggregationCircuit::commitment`].
+///
+/// Nodes with the same children share a circuit, so that a tree has at most
+/// two circuits per level, the last node of a level being the only one that
+/// may aggregate fewer proofs.
+pub struct P3AggregationCircuit {
+    pub num_proofs: usize,
+    pub arity: usize,
+    leaf_verifier_only: VerifierOnlyCircuitData<C, D>,
+    leaf_common: CommonCircuitData<F, D>,
+    nodes: Vec<AggregationNode>,
+    /// The shapes of the nodes of each level, from the leaves up to the root.
+    levels: Vec<Vec<usize>>,
+}
+
+impl P3AggregationCircuit {
+    /// Builds the circuits aggregating `num_proofs` proofs of `leaf`, `arity`
+    /// at a time. Fails if `num_proofs` is zero or `arity` is less than two.
+    pub fn new<A: Air, const E: usize>(
+        leaf: &P3VerifierCircuit<C, D, A, E>,
+        num_proofs: usize,
+        arity: usize,
+        circuit_config: CircuitConfig,
+    ) -> Result<Self, P3VerifierError> {
+        if num_proofs == 0 {
+            return Err(P3VerifierError::InvalidProofShape("nothing to aggregate"));
+        }
+        if arity < 2 {
+            return Err(P3VerifierError::InvalidProofShape(
+                "aggregation arity must be at least two",
+            ));
+        }
+
+        let mut aggregation = Self {
+            num_proofs,
+            arity,
+            leaf_verifier_only: leaf.data.verifier_only.clone(),
+            leaf_common: leaf.data.common.clone(),
+            nodes: vec![],
+            levels: vec![],
+        };
+
+        let mut shapes: HashMap<Vec<Child>, usize> = HashMap::new();
+        let mut children = vec![Child::Leaf; num_proofs];
+        // A single proof still goes through one node, so that the root always
+        // exposes a digest.
+        loop {
+            let mut level = vec![];
+            for node_children in children.chunks(arity) {
+                let shape = match shapes.get(node_children) {
+                    Some(&shape) => shape,
+                    None => {
+                        let node = aggregation.build_node(node_children, &circuit_config);
+                        aggregation.nodes.push(node);
+                        let shape = aggregation.nodes.len() - 1;
+                        shapes.insert(node_children.to_vec(), shape);
+                        shape
+                    }
+                };
+                level.push(shape);
+            }
+
+            children = level.iter().map(|&shape| Child::Node(shape)).collect();
+            aggregation.levels.push(level);
+            if children.len() == 1 {
+                break;
+            }
+        }
+
+        Ok(aggregation)
+    }
+
+    fn child_data(
+        &self,
+        child: Child,
+    ) -> (&VerifierOnlyCircuitData<C, D>, &CommonCircuitData<F, D>) {
+        match child {
+            Child::Leaf => (&self.leaf_verifier_only, &self.leaf_common),
+            Child::Node(shape) => (
+                &self.nodes[shape].data.verifier_only,
+                &self.nodes[shape].data.common,
+            ),
+        }
+    }
+
+    fn build_node(&self, children: &[Child], circuit_config: &CircuitConfig) -> AggregationNode {
+        let mut builder = CircuitBuilder::<F, D>::new(circuit_config.clone());
+
+        let mut proof_targets = Vec::with_capacity(children.len());
+        let mut digests: Vec<Target> = vec![];
+        for &child in children {
+            let (verifier_only, common) = self.child_data(child);
+            let proof_target = builder.add_virtual_proof_with_pis(common);
+            let verifier_data = builder.constant_verifier_data(verifier_only);
+            builder.verify_proof::<C>(&proof_target, &verifier_data, common);
+
+            match child {
+                Child::Leaf => {
+                    let digest = builder
+                        .hash_n_to_hash_no_pad::<Poseidon2Hash>(proof_target.public_inputs.clone());
+                    digests.extend(digest.elements);
+                }
+                Child::Node(_) => digests.extend(&proof_target.public_inputs),
+            }
+            proof_targets.push(proof_target);
+        }
+
+        let digest = builder.hash_n_to_hash_no_pad::<Poseidon2Hash>(digests);
+        builder.register_public_inputs(&digest.elements);
+
+        AggregationNode {
+            children: children.to_vec(),
+            data: builder.build::<C>(),
+            proof_targets,
+        }
+    }
+
+    /// Aggregates proofs of the leaf circuit, given in the order their public
+    /// inputs are committed to.
+    pub fn prove(
+        &self,
+        proofs: &[ProofWithPublicInputs<F, C, D>],
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        ensure!(
+            proofs.len() == self.num_proofs,
+            "expected {} proofs, got {}",
+            self.num_proofs,
+            proofs.len()
+        );
+
+        let mut proofs = proofs.to_vec();
+        for level in &self.levels {
+            proofs = level
+                .iter()
+                .zip(proofs.chunks(self.arity))
+                .map(|(&shape, children_proofs)| {
+                    let node = &self.nodes[shape];
+                    let mut pw = PartialWitness::new();
+                    for (target, proof) in node.proof_targets.iter().zip(children_proofs) {
+                        pw.set_proof_with_pis_target(target, proof);
+                    }
+                    node.data.prove(pw)
+                })
+                .collect::<Result<_>>()?;
+        }
+
+        Ok(proofs.pop().unwrap())
+    }
+
+    pub fn verify(&self, proof: ProofWithPublicInputs<F, C, D>) -> Result<()> {
+        let root = self.levels.last().unwrap()[0];
+        self.nodes[root].data.verify(proof)
+    }
+
+    /// The public inputs of the root proof aggregating proofs whose public
+    /// inputs are `public_inputs`.
+    pub fn commitment(&self, public_inputs: &[Vec<F>]) -> HashOut<F> {
+        assert_eq!(public_inputs.len(), self.num_proofs);
+
+        let mut digests: Vec<HashOut<F>> = public_inputs
+            .iter()
+            .map(|inputs| Poseidon2Hash::hash_no_pad(inputs))
+            .collect();
+        for level in &self.levels {
+            digests = level
+                .iter()
+                .zip(digests.chunks(self.arity))
+                .map(|(&shape, children_digests)| {
+                    debug_assert_eq!(self.nodes[shape].children.len(), children_digests.len());
+                    let inputs: Vec<F> = children_digests
+                        .iter()
+                        .flat_map(|digest| digest.elements)
+                        .collect();
+                    Poseidon2Hash::hash_no_pad(&inputs)
+                })
+                .collect();
+        }
+
+        digests[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p3::serde::proof::P3Config;
+    use crate::p3::serde::proof::P3ProofField;
+    use crate::p3::tests::fibonacci_fri_config;
+    use crate::p3::tests::FibonacciAir;
+
+    #[test]
+    fn test_aggregate_plonky3_proofs() {
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+
+        let fri_config = fibonacci_fri_config();
+        let config = P3Config::new(&FibonacciAir {}, fri_config, proof.degree_bits);
+        let leaf = P3VerifierCircuit::<C, D, _>::new::<Poseidon2Hash>(
+            FibonacciAir {},
+            config,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+        let leaf_proof = leaf.prove(&proof, &[]).unwrap();
+
+        // Three proofs in pairs leave the last one on its own at the first
+        // level.
+        let aggregation =
+            P3AggregationCircuit::new(&leaf, 3, 2, CircuitConfig::standard_recursion_config())
+                .unwrap();
+        assert_eq!(aggregation.levels, vec![vec![0, 1], vec![2]]);
+
+        let leaf_proofs = vec![leaf_proof; 3];
+        let root_proof = aggregation.prove(&leaf_proofs).unwrap();
+
+        let public_inputs: Vec<Vec<F>> = leaf_proofs
+            .iter()
+            .map(|proof| proof.public_inputs.clone())
+            .collect();
+        assert_eq!(
+            root_proof.public_inputs,
+            aggregation.commitment(&public_inputs).elements.to_vec()
+        );
+        aggregation.verify(root_proof).unwrap();
+
+        assert!(aggregation.prove(&leaf_proofs[..2]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_degenerate_aggregation() {
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+
+        let fri_config = fibonacci_fri_config();
+        let config = P3Config::new(&FibonacciAir {}, fri_config, proof.degree_bits);
+        let leaf = P3VerifierCircuit::<C, D, _>::new::<Poseidon2Hash>(
+            FibonacciAir {},
+            config,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+
+        for (num_proofs, arity) in [(0, 2), (3, 1)] {
+            assert!(matches!(
+                P3AggregationCircuit::new(
+                    &leaf,
+                    num_proofs,
+                    arity,
+                    CircuitConfig::standard_recursion_config()
+                ),
+                Err(P3VerifierError::InvalidProofShape(_))
+            ));
+        }
+    }
+}
@@ -3,39 +3,148 @@ use plonky2::iop::target::Target;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
 
 use crate::common::richer_field::RicherField;
+use crate::p3::constants::EXT_DEGREE;
 use crate::p3::extension::CircuitBuilderP3ExtArithmetic;
+use crate::p3::lookup::Interaction;
 use crate::p3::serde::proof::BinomialExtensionField;
 use crate::p3::serde::proof::OpenedValues;
+use crate::p3::utils::log2_ceil_usize;
 
 pub trait Air {
     fn name(&self) -> String;
     fn width(&self) -> usize;
-    fn eval<F: RicherField + Extendable<D>, const D: usize>(
+    /// Maximum degree of the constraints, selectors included. Plonky3 splits
+    /// the quotient into `constraint_degree - 1` chunks (rounded up to a power
+    /// of two), so this has to match the degree Plonky3 computes for the AIR.
+    fn max_constraint_degree(&self) -> usize {
+        2
+    }
+    fn log_quotient_degree(&self) -> usize {
+        log2_ceil_usize(self.max_constraint_degree().max(2) - 1)
+    }
+    /// Number of public values the AIR is proven against. They are observed
+    /// right after the trace commitment and exposed to [`Air::eval`] through
+    /// [`VerifierConstraintFolder::public_values`].
+    fn num_public_values(&self) -> usize {
+        0
+    }
+    /// Number of preprocessed (fixed) columns, exposed to [`Air::eval`]
+    /// through `folder.main.preprocessed_local` and `preprocessed_next`.
+    fn preprocessed_width(&self) -> usize {
+        0
+    }
+    /// Number of [`VerifierConstraintFolder::send`] and
+    /// [`VerifierConstraintFolder::receive`] calls made by [`Air::eval`]. The
+    /// LogUp constraints have degree one more than the interaction
+    /// fingerprints, which `max_constraint_degree` has to account for.
+    fn num_interactions(&self) -> usize {
+        0
+    }
+    /// Folds the constraints of the AIR into `folder`, over the degree `E`
+    /// extension the Plonky3 challenges are drawn from.
+    fn eval<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
         &self,
-        folder: &mut VerifierConstraintFolder<Target>,
+        folder: &mut VerifierConstraintFolder<Target, E>,
         cb: &mut CircuitBuilder<F, D>,
     );
 }
 
-pub struct VerifierConstraintFolder<F> {
-    pub main: OpenedValues<F>,
-    pub is_first_row: BinomialExtensionField<F>,
-    pub is_last_row: BinomialExtensionField<F>,
-    pub is_transition: BinomialExtensionField<F>,
-    pub alpha: BinomialExtensionField<F>,
-    pub accumulator: BinomialExtensionField<F>,
+/// Object-safe view of an [`Air`] for a given builder, so that the tables of a
+/// multi-table proof can use different AIRs. Implemented for every [`Air`].
+pub trait AirLike<F: RicherField + Extendable<D>, const D: usize, const E: usize = EXT_DEGREE> {
+    fn name(&self) -> String;
+    fn width(&self) -> usize;
+    fn log_quotient_degree(&self) -> usize;
+    fn num_public_values(&self) -> usize;
+    fn preprocessed_width(&self) -> usize;
+    fn num_interactions(&self) -> usize;
+    fn eval(&self, folder: &mut VerifierConstraintFolder<Target, E>, cb: &mut CircuitBuilder<F, D>);
 }
 
-pub struct FilteredAirBuilder<'a, F> {
-    pub inner: &'a mut VerifierConstraintFolder<F>,
-    pub condition: BinomialExtensionField<F>,
+impl<F: RicherField + Extendable<D>, const D: usize, const E: usize, A: Air> AirLike<F, D, E>
+    for A
+{
+    fn name(&self) -> String {
+        Air::name(self)
+    }
+
+    fn width(&self) -> usize {
+        Air::width(self)
+    }
+
+    fn log_quotient_degree(&self) -> usize {
+        Air::log_quotient_degree(self)
+    }
+
+    fn num_public_values(&self) -> usize {
+        Air::num_public_values(self)
+    }
+
+    fn preprocessed_width(&self) -> usize {
+        Air::preprocessed_width(self)
+    }
+
+    fn num_interactions(&self) -> usize {
+        Air::num_interactions(self)
+    }
+
+    fn eval(
+        &self,
+        folder: &mut VerifierConstraintFolder<Target, E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) {
+        Air::eval(self, folder, cb)
+    }
+}
+
+pub struct VerifierConstraintFolder<F, const E: usize = EXT_DEGREE> {
+    pub main: OpenedValues<F, E>,
+    pub public_values: Vec<F>,
+    pub permutation_challenges: Vec<BinomialExtensionField<F, E>>,
+    pub interactions: Vec<Interaction<F, E>>,
+    pub is_first_row: BinomialExtensionField<F, E>,
+    pub is_last_row: BinomialExtensionField<F, E>,
+    pub is_transition: BinomialExtensionField<F, E>,
+    pub alpha: BinomialExtensionField<F, E>,
+    pub accumulator: BinomialExtensionField<F, E>,
+}
+
+pub struct FilteredAirBuilder<'a, F, const E: usize = EXT_DEGREE> {
+    pub inner: &'a mut VerifierConstraintFolder<F, E>,
+    pub condition: BinomialExtensionField<F, E>,
 }
 
-impl VerifierConstraintFolder<Target> {
+impl<const E: usize> VerifierConstraintFolder<Target, E> {
+    /// Sends `values` on the lookup bus `multiplicity` times.
+    pub fn send(
+        &mut self,
+        values: Vec<BinomialExtensionField<Target, E>>,
+        multiplicity: BinomialExtensionField<Target, E>,
+    ) {
+        self.interactions.push(Interaction {
+            values,
+            multiplicity,
+            is_send: true,
+        });
+    }
+
+    /// Receives `values` from the lookup bus `multiplicity` times.
+    pub fn receive(
+        &mut self,
+        values: Vec<BinomialExtensionField<Target, E>>,
+        multiplicity: BinomialExtensionField<Target, E>,
+    ) {
+        self.interactions.push(Interaction {
+            values,
+            multiplicity,
+            is_send: false,
+        });
+    }
+
     pub fn when<F: RicherField + Extendable<D>, const D: usize>(
         &mut self,
-        condition: BinomialExtensionField<Target>,
-    ) -> FilteredAirBuilder<Target> {
+        condition: BinomialExtensionField<Target, E>,
+    ) -> FilteredAirBuilder<Target, E> {
         FilteredAirBuilder {
             inner: self,
             condition: condition,
@@ -44,25 +153,25 @@ impl VerifierConstraintFolder<Target> {
 
     pub fn when_first_row<F: RicherField + Extendable<D>, const D: usize>(
         &mut self,
-    ) -> FilteredAirBuilder<Target> {
+    ) -> FilteredAirBuilder<Target, E> {
         self.when::<F, D>(self.is_first_row.clone())
     }
 
     pub fn when_last_row<F: RicherField + Extendable<D>, const D: usize>(
         &mut self,
-    ) -> FilteredAirBuilder<Target> {
+    ) -> FilteredAirBuilder<Target, E> {
         self.when::<F, D>(self.is_last_row.clone())
     }
 
     pub fn when_transition<F: RicherField + Extendable<D>, const D: usize>(
         &mut self,
-    ) -> FilteredAirBuilder<Target> {
+    ) -> FilteredAirBuilder<Target, E> {
         self.when::<F, D>(self.is_transition.clone())
     }
 
     pub fn assert_zero<F: RicherField + Extendable<D>, const D: usize>(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         cb: &mut CircuitBuilder<F, D>,
     ) {
         self.accumulator = cb.p3_ext_mul_add(self.accumulator.clone(), self.alpha.clone(), x);
@@ -70,8 +179,8 @@ impl VerifierConstraintFolder<Target> {
 
     pub fn assert_eq<F: RicherField + Extendable<D>, const D: usize>(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
         cb: &mut CircuitBuilder<F, D>,
     ) {
         let x_sub_y = cb.p3_ext_sub(x, y);
@@ -80,7 +189,7 @@ impl VerifierConstraintFolder<Target> {
 
     pub fn assert_bool<F: RicherField + Extendable<D>, const D: usize>(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         cb: &mut CircuitBuilder<F, D>,
     ) {
         let one = cb.p3_ext_one();
@@ -91,10 +200,10 @@ impl VerifierConstraintFolder<Target> {
     }
 }
 
-impl<'a> FilteredAirBuilder<'a, Target> {
+impl<'a, const E: usize> FilteredAirBuilder<'a, Target, E> {
     pub fn assert_zero<F: RicherField + Extendable<D>, const D: usize>(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         cb: &mut CircuitBuilder<F, D>,
     ) {
         let x = cb.p3_ext_mul(&self.condition, &x);
@@ -103,8 +212,8 @@ impl<'a> FilteredAirBuilder<'a, Target> {
 
     pub fn assert_eq<F: RicherField + Extendable<D>, const D: usize>(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
         cb: &mut CircuitBuilder<F, D>,
     ) {
         let x_sub_y = cb.p3_ext_sub(x, y);
@@ -114,7 +223,7 @@ impl<'a> FilteredAirBuilder<'a, Target> {
 
     pub fn assert_bool<F: RicherField + Extendable<D>, const D: usize>(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         cb: &mut CircuitBuilder<F, D>,
     ) {
         let x = cb.p3_ext_mul(&self.condition, &x);
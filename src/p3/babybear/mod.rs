@@ -0,0 +1,98 @@
+//! Verification of Plonky3 proofs over BabyBear, the 31-bit field of the
+//! standard `BabyBear` + Poseidon2 config, with challenges in its quartic
+//! extension. The verifier is the shared one of [`crate::p3::field31`], over
+//! the two-adic FRI PCS, with the constants of [`BabyBear`].
+
+pub mod verifier;
+
+use plonky2::iop::target::Target;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::field31::extension::BinomialExtension;
+use crate::p3::field31::field::Field31;
+use crate::p3::field31::field::TwoAdicField31;
+use crate::p3::field31::poseidon2::Field31Poseidon2;
+use crate::p3::field31::poseidon2::Poseidon2Field31;
+use crate::p3::field31::poseidon2::Poseidon2Params;
+use crate::p3::field31::poseidon2::POSEIDON2_WIDTH;
+use crate::p3::serde::proof::Proof;
+use crate::p3::serde::proof::Value;
+use crate::p3::verifier::P3VerifierError;
+
+pub const BABYBEAR_EXT_DEGREE: usize = 4;
+pub const BABYBEAR_DIGEST_ELEMS: usize = 8;
+pub const BABYBEAR_WIDTH: usize = POSEIDON2_WIDTH;
+/// Rate of the padding-free sponge hashing the Merkle leaves.
+pub const BABYBEAR_RATE: usize = 8;
+
+/// The BabyBear field `p = 15 * 2^27 + 1`.
+#[derive(Clone, Copy, Debug)]
+pub struct BabyBear;
+
+impl Field31 for BabyBear {
+    const ORDER: u32 = 0x7800_0001;
+    const NAME: &'static str = "BabyBear";
+}
+
+impl TwoAdicField31 for BabyBear {
+    const GENERATOR: u32 = 31;
+    const TWO_ADICITY: usize = 27;
+    const TWO_ADIC_GENERATOR: u32 = 0x1a42_7a41;
+}
+
+impl BinomialExtension<BABYBEAR_EXT_DEGREE> for BabyBear {
+    const W: u32 = 11;
+}
+
+impl Poseidon2Field31 for BabyBear {
+    const SBOX_DEGREE: u64 = 7;
+    const INTERNAL_DIAG_M_1: [u32; POSEIDON2_WIDTH] = [
+        Self::ORDER - 2,
+        1,
+        1 << 1,
+        1 << 2,
+        1 << 3,
+        1 << 4,
+        1 << 5,
+        1 << 6,
+        1 << 7,
+        1 << 8,
+        1 << 9,
+        1 << 10,
+        1 << 11,
+        1 << 12,
+        1 << 13,
+        1 << 15,
+    ];
+    /// The inverse of the Montgomery radix `2^32`.
+    const INTERNAL_SCALE: u32 = 0x3840_0000;
+}
+
+pub type BabyBearPoseidon2 = Field31Poseidon2<BabyBear>;
+/// Round constants of the BabyBear Poseidon2 permutation, as canonical
+/// values, not in the Montgomery form Plonky3 stores BabyBear elements in.
+pub type BabyBearPoseidon2Params = Poseidon2Params<BabyBear>;
+
+/// A BabyBear proof as serialized by Plonky3, whose elements are canonical.
+pub type BabyBearProofField = Proof<u32, BABYBEAR_EXT_DEGREE>;
+pub type BabyBearProof = Proof<Target, BABYBEAR_EXT_DEGREE>;
+
+/// Converts a serialized BabyBear proof to values in `F`, ready for
+/// [`Proof::<Target>::set_witness`]. Non-canonical elements are rejected.
+pub fn babybear_proof_to_field<F: RicherField>(
+    proof: &BabyBearProofField,
+) -> Result<Proof<Value<F>, BABYBEAR_EXT_DEGREE>, P3VerifierError> {
+    let mut canonical = true;
+    let proof = proof.clone().map(|v| {
+        canonical &= v < BabyBear::ORDER;
+        Value {
+            value: F::from_canonical_u32(v),
+        }
+    });
+    if !canonical {
+        return Err(P3VerifierError::InvalidProofShape(
+            "BabyBear proof element isn't canonical",
+        ));
+    }
+    Ok(proof)
+}
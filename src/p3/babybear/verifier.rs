@@ -0,0 +1,338 @@
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::babybear::BabyBear;
+use crate::p3::babybear::BabyBearPoseidon2;
+use crate::p3::babybear::BabyBearPoseidon2Params;
+use crate::p3::babybear::BabyBearProof;
+use crate::p3::babybear::BabyBearProofField;
+use crate::p3::babybear::BABYBEAR_DIGEST_ELEMS;
+use crate::p3::babybear::BABYBEAR_EXT_DEGREE;
+use crate::p3::babybear::BABYBEAR_RATE;
+use crate::p3::babybear::BABYBEAR_WIDTH;
+use crate::p3::challenger::DuplexChallengerTarget;
+use crate::p3::commit::MerkleTreeMmcs;
+use crate::p3::field31::air::Field31Air;
+use crate::p3::field31::field::CircuitBuilderField31;
+use crate::p3::field31::field::TwoAdicField31;
+use crate::p3::field31::pcs::TwoAdicFriPcs;
+use crate::p3::field31::verifier::CircuitBuilderField31Verifier;
+use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::fri::FriError;
+use crate::p3::serde::hash::P3HashConfig;
+use crate::p3::serde::proof::P3Config;
+use crate::p3::verifier::P3VerifierError;
+
+impl P3HashConfig {
+    /// Poseidon2 over BabyBear with a width-16 state, as in the standard
+    /// Plonky3 BabyBear config.
+    pub fn babybear() -> Self {
+        Self {
+            width: BABYBEAR_WIDTH,
+            rate: BABYBEAR_RATE,
+            digest_elems: BABYBEAR_DIGEST_ELEMS,
+            challenger_rate: Some(BABYBEAR_RATE),
+        }
+    }
+}
+
+impl P3Config {
+    /// Shape of the proofs of the standard Plonky3 BabyBear config for `air`,
+    /// over a trace of `2^degree_bits` rows.
+    pub fn new_babybear(air: &impl Field31Air, fri_config: FriConfig, degree_bits: usize) -> Self {
+        let opening_matrix_log_max_height = degree_bits + fri_config.log_blowup;
+
+        Self {
+            fri_config,
+            hash_config: P3HashConfig::babybear(),
+            log_quotient_degree: air.log_quotient_degree(),
+            log_trace_height: degree_bits,
+            trace_width: air.width(),
+            opening_matrix_log_max_height,
+            quotient_chunk_width: BABYBEAR_EXT_DEGREE,
+            num_public_values: air.num_public_values(),
+            preprocessed_width: 0,
+            preprocessed_commit: None,
+            num_interactions: 0,
+            degree_bits,
+            min_degree_bits: None,
+        }
+    }
+}
+
+/// Verifier of Plonky3 proofs over BabyBear, emulating the BabyBear field in
+/// the Goldilocks circuit. Preprocessed columns and interactions aren't
+/// supported on this path.
+pub trait CircuitBuilderBabyBearVerifier<F: RicherField + Extendable<D>, const D: usize> {
+    fn p3_verify_babybear_proof(
+        &mut self,
+        proof: BabyBearProofField,
+        air: &impl Field31Air,
+        fri_config: FriConfig,
+        params: &BabyBearPoseidon2Params,
+        public_values: &[Target],
+    ) -> Result<BabyBearProof, P3VerifierError>;
+
+    /// Builds the verifier circuit for every BabyBear proof of the given
+    /// shape. `public_values` have to be canonical BabyBear elements, which
+    /// the circuit range checks.
+    fn p3_verify_babybear_proof_with_config(
+        &mut self,
+        air: &impl Field31Air,
+        config: &P3Config,
+        params: &BabyBearPoseidon2Params,
+        public_values: &[Target],
+    ) -> Result<BabyBearProof, P3VerifierError>;
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderBabyBearVerifier<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn p3_verify_babybear_proof(
+        &mut self,
+        proof: BabyBearProofField,
+        air: &impl Field31Air,
+        fri_config: FriConfig,
+        params: &BabyBearPoseidon2Params,
+        public_values: &[Target],
+    ) -> Result<BabyBearProof, P3VerifierError> {
+        let config = P3Config::new_babybear(air, fri_config, proof.degree_bits);
+
+        proof.check_shape(&config)?;
+
+        self.p3_verify_babybear_proof_with_config(air, &config, params, public_values)
+    }
+
+    fn p3_verify_babybear_proof_with_config(
+        &mut self,
+        air: &impl Field31Air,
+        config: &P3Config,
+        params: &BabyBearPoseidon2Params,
+        public_values: &[Target],
+    ) -> Result<BabyBearProof, P3VerifierError> {
+        if config.hash_config != P3HashConfig::babybear() {
+            return Err(P3VerifierError::InvalidHashConfig(
+                "BabyBear proofs are hashed with the width-16 Poseidon2 config",
+            ));
+        }
+        // The two-adic subgroups of BabyBear only go up to 2^27.
+        if config.log_trace_height + config.log_quotient_degree > BabyBear::TWO_ADICITY
+            || config.log_trace_height + config.fri_config.log_blowup > BabyBear::TWO_ADICITY
+        {
+            return Err(FriError::InvalidProofShape.into());
+        }
+
+        // Every element of the proof is a free witness, so make sure it is a
+        // canonical BabyBear element before doing any arithmetic with it.
+        let proof = BabyBearProof::add_virtual_to(self, config).map(|t| {
+            self.f31_range_check::<BabyBear>(t);
+            t
+        });
+        for &value in public_values {
+            self.f31_range_check::<BabyBear>(value);
+        }
+
+        let mut challenger = DuplexChallengerTarget::<BabyBearPoseidon2>::with_params(
+            self,
+            &config.hash_config,
+            params.clone(),
+        )?;
+        let pcs = TwoAdicFriPcs {
+            fri_config: config.fri_config.clone(),
+            mmcs: MerkleTreeMmcs::<BabyBearPoseidon2>::with_params::<F>(
+                config.hash_config,
+                params.clone(),
+            )?,
+        };
+
+        self.p3_verify_field31_proof(
+            &pcs,
+            air,
+            config,
+            &mut challenger,
+            (&proof).into(),
+            public_values,
+        )?;
+
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::iop::witness::WitnessWrite;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::p3::babybear::babybear_proof_to_field;
+    use crate::p3::circuit::try_prove;
+    use crate::p3::field31::air::Field31ConstraintFolder;
+    use crate::p3::field31::extension::BinomialExtension;
+    use crate::p3::field31::extension::CircuitBuilderField31Ext;
+    use crate::p3::field31::field::Field31;
+
+    /// The Fibonacci AIR of the Plonky3 `uni-stark` tests, whose public
+    /// values are the first two elements and the last element of the
+    /// sequence. The constraints are folded in the same order and with the
+    /// same sign as in Plonky3, or the quotient wouldn't match.
+    struct FibonacciAir;
+
+    impl Field31Air for FibonacciAir {
+        fn name(&self) -> String {
+            "Fibonacci".to_string()
+        }
+
+        fn width(&self) -> usize {
+            2
+        }
+
+        fn num_public_values(&self) -> usize {
+            3
+        }
+
+        fn eval<
+            F: RicherField + Extendable<D>,
+            const D: usize,
+            P: BinomialExtension<E>,
+            const E: usize,
+        >(
+            &self,
+            folder: &mut Field31ConstraintFolder<P, E>,
+            cb: &mut CircuitBuilder<F, D>,
+        ) {
+            let local = folder.main.trace_local.clone();
+            let next = folder.main.trace_next.clone();
+            let [a, b, x] = [0, 1, 2].map(|i| cb.f31_ext_from_base(folder.public_values[i]));
+
+            folder.when_first_row().assert_eq(local[0].clone(), a, cb);
+            folder.when_first_row().assert_eq(local[1].clone(), b, cb);
+
+            let local_sum = cb.f31_ext_add::<P, E>(local[0].clone(), local[1].clone());
+            folder
+                .when_transition()
+                .assert_eq(local[1].clone(), next[0].clone(), cb);
+            folder
+                .when_transition()
+                .assert_eq(local_sum, next[1].clone(), cb);
+
+            folder.when_last_row().assert_eq(local[1].clone(), x, cb);
+        }
+    }
+
+    /// The FRI config `proof_babybear_fibonacci.json` was proven with.
+    fn fri_config() -> FriConfig {
+        FriConfig {
+            log_blowup: 1,
+            num_queries: 1,
+            proof_of_work_bits: 1,
+            max_log_arity: 1,
+            log_final_poly_len: Some(0),
+        }
+    }
+
+    fn params() -> BabyBearPoseidon2Params {
+        serde_json::from_str(include_str!("../../../artifacts/params_babybear.json")).unwrap()
+    }
+
+    fn proof() -> BabyBearProofField {
+        serde_json::from_str(include_str!(
+            "../../../artifacts/proof_babybear_fibonacci.json"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_babybear_proof() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let proof = proof();
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let public_values = builder.add_virtual_targets(3);
+        let proof_target = builder
+            .p3_verify_babybear_proof(
+                proof.clone(),
+                &FibonacciAir,
+                fri_config(),
+                &params(),
+                &public_values,
+            )
+            .unwrap();
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        proof_target.set_witness::<F, D, _>(&mut pw, &babybear_proof_to_field(&proof).unwrap());
+        for (&t, v) in public_values.iter().zip([0, 1, 21]) {
+            pw.set_target(t, F::from_canonical_u32(v));
+        }
+        let plonky2_proof = data.prove(pw).unwrap();
+        assert!(data.verify(plonky2_proof).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_wrong_public_values() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let proof = proof();
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let public_values = builder.add_virtual_targets(3);
+        let proof_target = builder
+            .p3_verify_babybear_proof(
+                proof.clone(),
+                &FibonacciAir,
+                fri_config(),
+                &params(),
+                &public_values,
+            )
+            .unwrap();
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        proof_target.set_witness::<F, D, _>(&mut pw, &babybear_proof_to_field(&proof).unwrap());
+        for (&t, v) in public_values.iter().zip([0, 1, 34]) {
+            pw.set_target(t, F::from_canonical_u32(v));
+        }
+        assert!(try_prove(&data, pw).is_err());
+    }
+
+    #[test]
+    fn test_rejects_non_canonical_proof() {
+        let mut proof = proof();
+        proof.opening_proof.fri_proof.pow_witness = BabyBear::ORDER;
+        assert!(babybear_proof_to_field::<GoldilocksField>(&proof).is_err());
+    }
+
+    #[test]
+    fn test_rejects_unsupported_config() {
+        const D: usize = 2;
+        type F = GoldilocksField;
+
+        let mut config = P3Config::new_babybear(&FibonacciAir, fri_config(), 27);
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let public_values = builder.add_virtual_targets(3);
+        assert!(builder
+            .p3_verify_babybear_proof_with_config(&FibonacciAir, &config, &params(), &public_values)
+            .is_err());
+
+        config = P3Config::new_babybear(&FibonacciAir, fri_config(), 3);
+        config.preprocessed_width = 1;
+        assert!(matches!(
+            builder.p3_verify_babybear_proof_with_config(
+                &FibonacciAir,
+                &config,
+                &params(),
+                &public_values
+            ),
+            Err(P3VerifierError::InvalidProofShape(_))
+        ));
+    }
+}
@@ -1,101 +1,160 @@
+use std::marker::PhantomData;
+
+use itertools::izip;
 use plonky2::field::extension::Extendable;
+use plonky2::iop::target::BoolTarget;
 use plonky2::iop::target::Target;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::config::AlgebraicHasher;
 
-use crate::common::poseidon2::poseidon2::Poseidon2Hash;
 use crate::common::richer_field::RicherField;
 use crate::common::u32::arithmetic_u32::U32Target;
 use crate::common::u32::interleaved_u32::CircuitBuilderB32;
-use crate::p3::constants::EXT_DEGREE;
-use crate::p3::constants::WIDTH;
+use crate::p3::commit::KeccakMerkleTreeMmcs;
+use crate::p3::commit::MerkleTreeMmcs;
+use crate::p3::commit::P3Mmcs;
+use crate::p3::keccak::CircuitBuilderKeccak;
+use crate::p3::permutation::P3Permutation;
+use crate::p3::permutation::P3PermutationParams;
+use crate::p3::serde::hash::P3HashConfig;
 use crate::p3::serde::proof::BinomialExtensionField;
+use crate::p3::verifier::P3VerifierError;
 use crate::p3::CircuitBuilderP3Arithmetic;
 
-pub struct DuplexChallengerTarget {
+/// `DuplexChallenger<Val, Perm, WIDTH>` over the permutation of `H`, whose
+/// commitments are opened with the [`MerkleTreeMmcs`] of the same
+/// [`P3HashConfig`] and permutation parameters.
+pub struct DuplexChallengerTarget<H: P3PermutationParams> {
     sponge_state: Vec<Target>,
     input_buffer: Vec<Target>,
     output_buffer: Vec<Target>,
+    hash_config: P3HashConfig,
+    params: H::Params,
+    _hasher: PhantomData<H>,
 }
 
-impl DuplexChallengerTarget {
+impl<H: P3PermutationParams> Clone for DuplexChallengerTarget<H> {
+    fn clone(&self) -> Self {
+        Self {
+            sponge_state: self.sponge_state.clone(),
+            input_buffer: self.input_buffer.clone(),
+            output_buffer: self.output_buffer.clone(),
+            hash_config: self.hash_config,
+            params: self.params.clone(),
+            _hasher: PhantomData,
+        }
+    }
+}
+
+impl<H: P3PermutationParams> DuplexChallengerTarget<H> {
     pub fn from_builder<F: RicherField + Extendable<D>, const D: usize>(
         cb: &mut CircuitBuilder<F, D>,
-    ) -> Self {
-        Self {
-            sponge_state: cb.p3_arr::<WIDTH>().to_vec(),
+        hash_config: &P3HashConfig,
+    ) -> Result<Self, P3VerifierError>
+    where
+        H: P3Permutation<F>,
+        H::Params: Default,
+    {
+        Self::with_params(cb, hash_config, H::Params::default())
+    }
+
+    /// Same as [`from_builder`](Self::from_builder) for a permutation
+    /// instantiated with `params`, e.g. the round constants of a Plonky3
+    /// config.
+    pub fn with_params<F: RicherField + Extendable<D>, const D: usize>(
+        cb: &mut CircuitBuilder<F, D>,
+        hash_config: &P3HashConfig,
+        params: H::Params,
+    ) -> Result<Self, P3VerifierError>
+    where
+        H: P3Permutation<F>,
+    {
+        hash_config.check_width(H::width(&params))?;
+
+        let zero = cb.zero();
+        Ok(Self {
+            sponge_state: vec![zero; hash_config.width],
             input_buffer: Vec::new(),
             output_buffer: Vec::new(),
-        }
+            hash_config: *hash_config,
+            params,
+            _hasher: PhantomData,
+        })
+    }
+
+    fn challenger_rate(&self) -> usize {
+        self.hash_config
+            .challenger_rate
+            .unwrap_or(self.hash_config.width)
     }
 }
 
 pub trait DuplexChallenger<F: RicherField + Extendable<D>, const D: usize> {
-    fn p3_duplexing<H: AlgebraicHasher<F>>(&mut self, x: &mut DuplexChallengerTarget);
-    fn p3_observe_single<H: AlgebraicHasher<F>>(
+    fn p3_duplexing<H: P3Permutation<F>>(&mut self, x: &mut DuplexChallengerTarget<H>);
+    fn p3_observe_single<H: P3Permutation<F>>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
+        x: &mut DuplexChallengerTarget<H>,
         value: Target,
     );
-    fn p3_observe<H: AlgebraicHasher<F>>(
+    fn p3_observe<H: P3Permutation<F>>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
+        x: &mut DuplexChallengerTarget<H>,
         values: impl IntoIterator<Item = Target>,
     );
-    fn p3_sample<H: AlgebraicHasher<F>>(&mut self, x: &mut DuplexChallengerTarget) -> Target;
-    fn p3_sample_arr<H: AlgebraicHasher<F>>(
+    fn p3_sample<H: P3Permutation<F>>(&mut self, x: &mut DuplexChallengerTarget<H>) -> Target;
+    fn p3_sample_arr<H: P3Permutation<F>, const SIZE: usize>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
-    ) -> [Target; EXT_DEGREE];
-    fn p3_sample_ext<H: AlgebraicHasher<F>>(
+        x: &mut DuplexChallengerTarget<H>,
+    ) -> [Target; SIZE];
+    fn p3_sample_ext<H: P3Permutation<F>, const E: usize>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
-    ) -> BinomialExtensionField<Target>;
-    fn p3_sample_bits<H: AlgebraicHasher<F>>(
+        x: &mut DuplexChallengerTarget<H>,
+    ) -> BinomialExtensionField<Target, E>;
+    fn p3_sample_bits<H: P3Permutation<F>>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
+        x: &mut DuplexChallengerTarget<H>,
         bits: usize,
     ) -> Target;
-    fn p3_check_witness<H: AlgebraicHasher<F>>(
+    fn p3_check_witness<H: P3Permutation<F>>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
+        x: &mut DuplexChallengerTarget<H>,
         bits: usize,
         witness: Target,
+        condition: BoolTarget,
     );
 }
 
 impl<F: RicherField + Extendable<D>, const D: usize> DuplexChallenger<F, D>
     for CircuitBuilder<F, D>
 {
-    fn p3_duplexing<H: AlgebraicHasher<F>>(&mut self, x: &mut DuplexChallengerTarget) {
-        assert!(x.input_buffer.len() <= WIDTH);
+    fn p3_duplexing<H: P3Permutation<F>>(&mut self, x: &mut DuplexChallengerTarget<H>) {
+        assert!(x.input_buffer.len() <= x.challenger_rate());
 
         for (i, val) in x.input_buffer.drain(..).enumerate() {
             x.sponge_state[i] = val;
         }
 
-        x.sponge_state = Poseidon2Hash::permute_targets::<F, D>(&x.sponge_state, self).to_vec();
+        x.sponge_state = H::permute_targets(&x.params, &x.sponge_state, self);
 
         x.output_buffer.clear();
         x.output_buffer.extend(x.sponge_state.clone());
     }
 
-    fn p3_observe_single<H: AlgebraicHasher<F>>(
+    fn p3_observe_single<H: P3Permutation<F>>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
+        x: &mut DuplexChallengerTarget<H>,
         value: Target,
     ) {
         x.output_buffer.clear();
         x.input_buffer.push(value);
 
-        if x.input_buffer.len() == WIDTH {
+        if x.input_buffer.len() == x.challenger_rate() {
             self.p3_duplexing::<H>(x);
         }
     }
 
-    fn p3_observe<H: AlgebraicHasher<F>>(
+    fn p3_observe<H: P3Permutation<F>>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
+        x: &mut DuplexChallengerTarget<H>,
         values: impl IntoIterator<Item = Target>,
     ) {
         for value in values {
@@ -103,7 +162,7 @@ impl<F: RicherField + Extendable<D>, const D: usize> DuplexChallenger<F, D>
         }
     }
 
-    fn p3_sample<H: AlgebraicHasher<F>>(&mut self, x: &mut DuplexChallengerTarget) -> Target {
+    fn p3_sample<H: P3Permutation<F>>(&mut self, x: &mut DuplexChallengerTarget<H>) -> Target {
         // If we have buffered inputs, we must perform a duplexing so that the challenge
         // will reflect them. Or if we've run out of outputs, we must perform a
         // duplexing to get more.
@@ -116,16 +175,16 @@ impl<F: RicherField + Extendable<D>, const D: usize> DuplexChallenger<F, D>
             .expect("Output buffer should be non-empty")
     }
 
-    fn p3_sample_arr<H: AlgebraicHasher<F>>(
+    fn p3_sample_arr<H: P3Permutation<F>, const SIZE: usize>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
-    ) -> [Target; EXT_DEGREE] {
+        x: &mut DuplexChallengerTarget<H>,
+    ) -> [Target; SIZE] {
         core::array::from_fn(|_| self.p3_sample::<H>(x))
     }
 
-    fn p3_sample_bits<H: AlgebraicHasher<F>>(
+    fn p3_sample_bits<H: P3Permutation<F>>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
+        x: &mut DuplexChallengerTarget<H>,
         bits: usize,
     ) -> Target {
         let rand_f = self.p3_sample::<H>(x);
@@ -147,24 +206,401 @@ impl<F: RicherField + Extendable<D>, const D: usize> DuplexChallenger<F, D>
         self.mul_const_add(F::from_canonical_u64(1 << 32), high.0, low.0)
     }
 
-    fn p3_sample_ext<H: AlgebraicHasher<F>>(
+    fn p3_sample_ext<H: P3Permutation<F>, const E: usize>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
-    ) -> BinomialExtensionField<Target> {
+        x: &mut DuplexChallengerTarget<H>,
+    ) -> BinomialExtensionField<Target, E> {
         BinomialExtensionField {
-            value: self.p3_sample_arr::<H>(x),
+            value: self.p3_sample_arr::<H, E>(x),
         }
     }
 
-    fn p3_check_witness<H: AlgebraicHasher<F>>(
+    fn p3_check_witness<H: P3Permutation<F>>(
         &mut self,
-        x: &mut DuplexChallengerTarget,
+        x: &mut DuplexChallengerTarget<H>,
         bits: usize,
         witness: Target,
+        condition: BoolTarget,
     ) {
         self.p3_observe_single::<H>(x, witness);
         let res = self.p3_sample_bits::<H>(x, bits);
         let zero = self.zero();
-        self.connect(res, zero);
+        self.p3_connect_if(condition, res, zero);
+    }
+}
+
+/// Fiat-Shamir transcript of a Plonky3 config, paired with the MMCS its
+/// commitments are opened with, so that the verifier can be instantiated for
+/// either family of configs.
+pub trait P3Challenger<F: RicherField + Extendable<D>, const D: usize>: Sized + Clone {
+    type Mmcs: P3Mmcs<F>;
+
+    /// Starts the transcript of a config hashing with `hash_config`, failing
+    /// if the challenger and its MMCS can't be instantiated with it.
+    fn new(
+        cb: &mut CircuitBuilder<F, D>,
+        hash_config: &P3HashConfig,
+    ) -> Result<Self, P3VerifierError>;
+    fn mmcs(&self) -> Self::Mmcs;
+    fn observe(&mut self, cb: &mut CircuitBuilder<F, D>, values: impl IntoIterator<Item = Target>);
+    /// Observes a commitment, given as the elements of its digest.
+    fn observe_digest(&mut self, cb: &mut CircuitBuilder<F, D>, digest: &[Target]);
+    fn sample_ext<const E: usize>(
+        &mut self,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> BinomialExtensionField<Target, E>;
+    fn sample_bits(&mut self, cb: &mut CircuitBuilder<F, D>, bits: usize) -> Target;
+    /// Observes the proof of work `witness` and checks that the next `bits`
+    /// sampled bits are zero where `condition` holds.
+    fn check_witness(
+        &mut self,
+        cb: &mut CircuitBuilder<F, D>,
+        bits: usize,
+        witness: Target,
+        condition: BoolTarget,
+    );
+    /// The transcript of `self` where `condition` holds and of `other`
+    /// elsewhere, for transcripts whose number of observations depends on the
+    /// witness. Both have to buffer as many inputs and outputs, as is the case
+    /// after the same sequence of observations and samples of the same sizes.
+    fn select(&self, cb: &mut CircuitBuilder<F, D>, condition: BoolTarget, other: &Self) -> Self;
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize, H: P3Permutation<F>> P3Challenger<F, D>
+    for DuplexChallengerTarget<H>
+where
+    H::Params: Default,
+{
+    type Mmcs = MerkleTreeMmcs<H>;
+
+    fn new(
+        cb: &mut CircuitBuilder<F, D>,
+        hash_config: &P3HashConfig,
+    ) -> Result<Self, P3VerifierError> {
+        Self::from_builder(cb, hash_config)
+    }
+
+    fn mmcs(&self) -> Self::Mmcs {
+        MerkleTreeMmcs::with_params::<F>(self.hash_config, self.params.clone())
+            .expect("hash config checked by with_params")
+    }
+
+    fn observe(&mut self, cb: &mut CircuitBuilder<F, D>, values: impl IntoIterator<Item = Target>) {
+        cb.p3_observe::<H>(self, values);
+    }
+
+    fn observe_digest(&mut self, cb: &mut CircuitBuilder<F, D>, digest: &[Target]) {
+        cb.p3_observe::<H>(self, digest.iter().copied());
+    }
+
+    fn sample_ext<const E: usize>(
+        &mut self,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> BinomialExtensionField<Target, E> {
+        cb.p3_sample_ext::<H, E>(self)
+    }
+
+    fn sample_bits(&mut self, cb: &mut CircuitBuilder<F, D>, bits: usize) -> Target {
+        cb.p3_sample_bits::<H>(self, bits)
+    }
+
+    fn check_witness(
+        &mut self,
+        cb: &mut CircuitBuilder<F, D>,
+        bits: usize,
+        witness: Target,
+        condition: BoolTarget,
+    ) {
+        cb.p3_check_witness::<H>(self, bits, witness, condition);
+    }
+
+    fn select(&self, cb: &mut CircuitBuilder<F, D>, condition: BoolTarget, other: &Self) -> Self {
+        assert_eq!(self.input_buffer.len(), other.input_buffer.len());
+        assert_eq!(self.output_buffer.len(), other.output_buffer.len());
+        let mut select = |xs: &[Target], ys: &[Target]| -> Vec<Target> {
+            izip!(xs, ys)
+                .map(|(&x, &y)| cb._if(condition, x, y))
+                .collect()
+        };
+
+        Self {
+            sponge_state: select(&self.sponge_state, &other.sponge_state),
+            input_buffer: select(&self.input_buffer, &other.input_buffer),
+            output_buffer: select(&self.output_buffer, &other.output_buffer),
+            hash_config: self.hash_config,
+            params: self.params.clone(),
+            _hasher: PhantomData,
+        }
+    }
+}
+
+/// `SerializingChallenger64<Goldilocks, HashChallenger<u8, Keccak256Hash,
+/// 32>>`.
+///
+/// Field elements are observed as the 8 little-endian bytes of their
+/// canonical value and digests as their raw bytes. Every observation is a
+/// whole number of 32-bit words, so the input buffer holds words, while the
+/// output buffer holds the bytes of the last digest, sampled from the end.
+#[derive(Clone)]
+pub struct SerializingChallengerTarget {
+    input_buffer: Vec<U32Target>,
+    output_buffer: Vec<Target>,
+}
+
+impl SerializingChallengerTarget {
+    /// Hashes the input buffer, which is then replaced by the digest so that
+    /// later challenges depend on every earlier observation.
+    fn flush<F: RicherField + Extendable<D>, const D: usize>(
+        &mut self,
+        cb: &mut CircuitBuilder<F, D>,
+    ) {
+        let digest = cb.keccak256_u32(&self.input_buffer);
+        self.output_buffer = cb.keccak_unpack_bytes(&digest);
+        self.input_buffer = digest.to_vec();
+    }
+
+    fn observe_words(&mut self, words: impl IntoIterator<Item = U32Target>) {
+        self.output_buffer.clear();
+        self.input_buffer.extend(words);
+    }
+
+    fn sample_byte<F: RicherField + Extendable<D>, const D: usize>(
+        &mut self,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Target {
+        if self.output_buffer.is_empty() {
+            self.flush(cb);
+        }
+        self.output_buffer
+            .pop()
+            .expect("Output buffer should be non-empty")
+    }
+
+    fn sample_u64_bytes<F: RicherField + Extendable<D>, const D: usize>(
+        &mut self,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> [Target; 8] {
+        core::array::from_fn(|_| self.sample_byte(cb))
+    }
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize> P3Challenger<F, D>
+    for SerializingChallengerTarget
+{
+    type Mmcs = KeccakMerkleTreeMmcs;
+
+    fn new(
+        _cb: &mut CircuitBuilder<F, D>,
+        hash_config: &P3HashConfig,
+    ) -> Result<Self, P3VerifierError> {
+        if *hash_config != P3HashConfig::keccak256() {
+            return Err(P3VerifierError::InvalidHashConfig(
+                "the serializing challenger hashes with Keccak-256",
+            ));
+        }
+
+        Ok(Self {
+            input_buffer: Vec::new(),
+            output_buffer: Vec::new(),
+        })
+    }
+
+    fn mmcs(&self) -> Self::Mmcs {
+        KeccakMerkleTreeMmcs
+    }
+
+    fn observe(&mut self, cb: &mut CircuitBuilder<F, D>, values: impl IntoIterator<Item = Target>) {
+        for value in values {
+            let words = cb.p3_u64_to_u32s(value);
+            self.observe_words(words);
+        }
+    }
+
+    fn observe_digest(&mut self, cb: &mut CircuitBuilder<F, D>, digest: &[Target]) {
+        let words = cb.keccak_pack_bytes(digest);
+        self.observe_words(words);
+    }
+
+    /// Plonky3 rejects samples of 8 bytes which aren't canonical; the circuit
+    /// can't retry, so it fails to prove instead, with probability about
+    /// `2^-32` per sampled element.
+    fn sample_ext<const E: usize>(
+        &mut self,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> BinomialExtensionField<Target, E> {
+        BinomialExtensionField {
+            value: core::array::from_fn(|_| {
+                let bytes = self.sample_u64_bytes(cb);
+                cb.p3_u64_from_bytes(&bytes)
+            }),
+        }
+    }
+
+    fn sample_bits(&mut self, cb: &mut CircuitBuilder<F, D>, bits: usize) -> Target {
+        assert!(bits < 64);
+        let bytes = self.sample_u64_bytes(cb);
+
+        // The output bytes are range checked, so the low bits are the low
+        // bytes plus the low bits of the next one.
+        let mut res = cb.zero();
+        for (i, &byte) in bytes.iter().enumerate().take(bits.div_ceil(8)) {
+            let byte_bits = (bits - 8 * i).min(8);
+            let byte = if byte_bits == 8 {
+                byte
+            } else {
+                let byte_le = cb.split_le(byte, 8);
+                cb.le_sum(byte_le[..byte_bits].iter())
+            };
+            res = cb.mul_const_add(F::from_canonical_u64(1 << (8 * i)), byte, res);
+        }
+        res
+    }
+
+    fn check_witness(
+        &mut self,
+        cb: &mut CircuitBuilder<F, D>,
+        bits: usize,
+        witness: Target,
+        condition: BoolTarget,
+    ) {
+        self.observe(cb, [witness]);
+        let res = self.sample_bits(cb, bits);
+        let zero = cb.zero();
+        cb.p3_connect_if(condition, res, zero);
+    }
+
+    fn select(&self, cb: &mut CircuitBuilder<F, D>, condition: BoolTarget, other: &Self) -> Self {
+        assert_eq!(self.input_buffer.len(), other.input_buffer.len());
+        assert_eq!(self.output_buffer.len(), other.output_buffer.len());
+
+        Self {
+            input_buffer: izip!(&self.input_buffer, &other.input_buffer)
+                .map(|(x, y)| U32Target(cb._if(condition, x.0, y.0)))
+                .collect(),
+            output_buffer: izip!(&self.output_buffer, &other.output_buffer)
+                .map(|(&x, &y)| cb._if(condition, x, y))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::field::types::Field64;
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::common::poseidon2::poseidon2::Poseidon2Hash;
+    use crate::p3::native::challenger::SerializingChallenger;
+
+    fn sample_after_observing<H: P3Permutation<GoldilocksField, Params = ()>>(
+    ) -> Vec<GoldilocksField> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let mut challenger =
+            DuplexChallengerTarget::<H>::from_builder(&mut builder, &P3HashConfig::default())
+                .unwrap();
+        let values: Vec<Target> = (0..5).map(|i| builder.p3_constant(i as u64)).collect();
+        builder.p3_observe(&mut challenger, values);
+        let samples = builder.p3_sample_arr::<H, 2>(&mut challenger);
+        builder.register_public_inputs(&samples);
+
+        let data = builder.build::<C>();
+        let proof = data.prove(PartialWitness::new()).unwrap();
+        proof.public_inputs
+    }
+
+    #[test]
+    fn test_transcript_depends_on_permutation() {
+        assert_ne!(
+            sample_after_observing::<Poseidon2Hash>(),
+            sample_after_observing::<PoseidonHash>()
+        );
+    }
+
+    #[test]
+    fn test_rejects_hash_config_of_another_width() {
+        const D: usize = 2;
+        type F = GoldilocksField;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let hash_config = P3HashConfig {
+            width: 16,
+            rate: 8,
+            digest_elems: 8,
+            challenger_rate: None,
+        };
+        assert!(matches!(
+            DuplexChallengerTarget::<Poseidon2Hash>::new(&mut builder, &hash_config),
+            Err(P3VerifierError::InvalidHashConfig(_))
+        ));
+        assert!(matches!(
+            SerializingChallengerTarget::new(&mut builder, &P3HashConfig::default()),
+            Err(P3VerifierError::InvalidHashConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_serializing_challenger_matches_native_transcript() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let values: Vec<F> = (0..20)
+            .map(|i| {
+                F::from_canonical_u64(0x0123_4567_89ab_cdef_u64.wrapping_mul(i + 1) % F::ORDER)
+            })
+            .collect();
+        let digest: Vec<u8> = (0..32).map(|i| 7 * i as u8 + 3).collect();
+
+        let mut native = SerializingChallenger::new();
+        native.observe(values[0]);
+        native.observe(values[1]);
+        native.observe_digest(&digest);
+        let native_ext = native.sample_ext();
+        let native_bits = native.sample_bits(10);
+        for &value in &values[2..] {
+            native.observe(value);
+        }
+        let native_last = native.sample_ext();
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let mut challenger =
+            SerializingChallengerTarget::new(&mut builder, &P3HashConfig::keccak256()).unwrap();
+        let value_targets: Vec<Target> = values
+            .iter()
+            .map(|value| builder.p3_constant(value.0))
+            .collect();
+        let digest_targets: Vec<Target> = digest
+            .iter()
+            .map(|&byte| builder.p3_constant(byte as u64))
+            .collect();
+        challenger.observe(&mut builder, value_targets[..2].to_vec());
+        challenger.observe_digest(&mut builder, &digest_targets);
+        let ext = challenger.sample_ext::<2>(&mut builder);
+        let bits = challenger.sample_bits(&mut builder, 10);
+        challenger.observe(&mut builder, value_targets[2..].to_vec());
+        let last = challenger.sample_ext::<2>(&mut builder);
+        builder.register_public_inputs(&ext.value);
+        builder.register_public_input(bits);
+        builder.register_public_inputs(&last.value);
+
+        let data = builder.build::<C>();
+        let proof = data.prove(PartialWitness::new()).unwrap();
+        let expected: Vec<F> = native_ext
+            .0
+            .into_iter()
+            .chain([F::from_canonical_usize(native_bits)])
+            .chain(native_last.0)
+            .collect();
+        assert_eq!(proof.public_inputs, expected);
     }
 }
@@ -0,0 +1,171 @@
+use std::panic;
+use std::panic::AssertUnwindSafe;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use plonky2::field::extension::Extendable;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::PartialWitness;
+use plonky2::iop::witness::WitnessWrite;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::CircuitConfig;
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+
+use crate::p3::air::Air;
+use crate::p3::challenger::DuplexChallengerTarget;
+use crate::p3::challenger::P3Challenger;
+use crate::p3::constants::EXT_DEGREE;
+use crate::p3::permutation::P3Permutation;
+use crate::p3::serde::proof::P3Config;
+use crate::p3::serde::proof::P3Field;
+use crate::p3::serde::proof::Proof;
+use crate::p3::verifier::P3VerifierError;
+use crate::p3::CircuitBuilderP3Arithmetic;
+
+/// A plonky2 circuit verifying every Plonky3 proof of a given shape.
+///
+/// The circuit is built once in [`P3VerifierCircuit::new`], after which any
+/// number of same-shaped proofs can be wrapped with
+/// [`P3VerifierCircuit::prove`]. The public inputs of the wrapping proof are
+/// the public values of the AIR followed by the trace commitment. `E` is the
+/// degree of the extension the Plonky3 challenges are drawn from, which
+/// `config` has to agree with.
+///
+/// A variable-degree `config`, see [`P3Config::with_min_degree_bits`],
+/// yields a single circuit for proofs of every degree it accepts.
+pub struct P3VerifierCircuit<C, const D: usize, A, const E: usize = EXT_DEGREE>
+where
+    C: GenericConfig<D, F = GoldilocksField>,
+    GoldilocksField: Extendable<D>,
+    A: Air,
+{
+    pub air: A,
+    pub config: P3Config,
+    pub data: CircuitData<GoldilocksField, C, D>,
+    pub proof_target: Proof<Target, E>,
+    pub public_values: Vec<Target>,
+    /// The `degree_bits` of the wrapped proof, for variable-degree configs.
+    pub degree_bits: Option<Target>,
+}
+
+impl<C, const D: usize, A, const E: usize> P3VerifierCircuit<C, D, A, E>
+where
+    C: GenericConfig<D, F = GoldilocksField>,
+    GoldilocksField: Extendable<D>,
+    A: Air,
+{
+    pub fn new<H: P3Permutation<GoldilocksField>>(
+        air: A,
+        config: P3Config,
+        circuit_config: CircuitConfig,
+    ) -> Result<Self, P3VerifierError>
+    where
+        H::Params: Default,
+    {
+        Self::new_with_challenger::<DuplexChallengerTarget<H>>(air, config, circuit_config)
+    }
+
+    /// Builds the circuit for proofs of the Plonky3 config whose transcript is
+    /// `Ch`, see
+    /// [`p3_verify_proof_with_challenger`](CircuitBuilderP3Arithmetic::p3_verify_proof_with_challenger).
+    pub fn new_with_challenger<Ch: P3Challenger<GoldilocksField, D>>(
+        air: A,
+        config: P3Config,
+        circuit_config: CircuitConfig,
+    ) -> Result<Self, P3VerifierError> {
+        let mut builder = CircuitBuilder::<GoldilocksField, D>::new(circuit_config);
+        let public_values = builder.add_virtual_targets(config.num_public_values);
+        builder.register_public_inputs(&public_values);
+        let (proof_target, degree_bits) = match config.min_degree_bits {
+            Some(_) => {
+                let (proof_target, degree_bits) = builder
+                    .p3_verify_variable_degree_proof::<Ch, E>(&air, &config, &public_values)?;
+                (proof_target, Some(degree_bits))
+            }
+            None => {
+                let proof_target = builder.p3_verify_proof_with_challenger::<Ch, E>(
+                    &air,
+                    &config,
+                    &public_values,
+                )?;
+                (proof_target, None)
+            }
+        };
+        builder.register_public_inputs(&proof_target.commitments.trace.value);
+        let data = builder.build::<C>();
+
+        Ok(Self {
+            air,
+            config,
+            data,
+            proof_target,
+            public_values,
+            degree_bits,
+        })
+    }
+
+    pub fn prove(
+        &self,
+        proof: &Proof<P3Field, E>,
+        public_values: &[GoldilocksField],
+    ) -> Result<ProofWithPublicInputs<GoldilocksField, C, D>> {
+        proof.check_shape(&self.config)?;
+        if public_values.len() != self.public_values.len() {
+            return Err(P3VerifierError::PublicValuesMismatch {
+                expected: self.public_values.len(),
+                actual: public_values.len(),
+            }
+            .into());
+        }
+
+        let mut pw = PartialWitness::new();
+        pw.set_target_arr(&self.public_values, public_values);
+        match self.degree_bits {
+            Some(degree_bits) => {
+                pw.set_target(
+                    degree_bits,
+                    GoldilocksField::from_canonical_usize(proof.degree_bits),
+                );
+                self.proof_target.set_witness::<GoldilocksField, D, _>(
+                    &mut pw,
+                    &proof.pad_to_max_degree(&self.config),
+                );
+            }
+            None => self
+                .proof_target
+                .set_witness::<GoldilocksField, D, _>(&mut pw, proof),
+        }
+
+        try_prove(&self.data, pw)
+    }
+
+    pub fn verify(&self, proof: ProofWithPublicInputs<GoldilocksField, C, D>) -> Result<()> {
+        self.data.verify(proof)
+    }
+}
+
+/// Proves `data` on `pw`, with the panic plonky2 raises while generating the
+/// witness of an unsatisfiable circuit, e.g. one verifying a Plonky3 proof of
+/// other public values, returned as an error.
+pub fn try_prove<F, C, const D: usize>(
+    data: &CircuitData<F, C, D>,
+    pw: PartialWitness<F>,
+) -> Result<ProofWithPublicInputs<F, C, D>>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    panic::catch_unwind(AssertUnwindSafe(|| data.prove(pw))).unwrap_or_else(|payload| {
+        let reason = payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| payload.downcast_ref::<&str>().copied())
+            .unwrap_or("witness generation panicked");
+        Err(anyhow!("the witness doesn't satisfy the circuit: {reason}"))
+    })
+}
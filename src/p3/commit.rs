@@ -1,143 +1,497 @@
 use std::cmp::Reverse;
+use std::marker::PhantomData;
 
+use itertools::izip;
 use itertools::Itertools;
 use plonky2::field::extension::Extendable;
 use plonky2::iop::target::BoolTarget;
 use plonky2::iop::target::Target;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::config::AlgebraicHasher;
 
-use crate::common::poseidon2::poseidon2::Poseidon2Hash;
 use crate::common::richer_field::RicherField;
-use crate::p3::constants::CHUNK;
-use crate::p3::constants::DIGEST_ELEMS;
-use crate::p3::constants::N;
-use crate::p3::constants::RATE;
-use crate::p3::constants::WIDTH;
+use crate::common::u32::arithmetic_u32::U32Target;
+use crate::p3::keccak::CircuitBuilderKeccak;
+use crate::p3::keccak::KECCAK256_DIGEST_BYTES;
+use crate::p3::permutation::P3Permutation;
+use crate::p3::permutation::P3PermutationParams;
+use crate::p3::serde::hash::P3HashConfig;
 use crate::p3::serde::Dimensions;
+use crate::p3::verifier::P3VerifierError;
 use crate::p3::CircuitBuilderP3Arithmetic;
 
-pub struct MerkleTreeMmcs;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MmcsError {
+    /// The number of opened matrices doesn't match the batch dimensions.
+    WrongBatchSize,
+    /// An opened row or a sibling digest has the wrong number of elements.
+    WrongWidth,
+    /// The Merkle path length doesn't match the height of the tallest matrix.
+    WrongHeight,
+    /// The recomputed root doesn't match the commitment.
+    RootMismatch,
+}
+
+/// `MerkleTreeMmcs` hashing leaves with the padding-free sponge and merging
+/// nodes with the truncated permutation of `H`, at the rate and digest size
+/// of its [`P3HashConfig`].
+pub struct MerkleTreeMmcs<H: P3PermutationParams> {
+    hash_config: P3HashConfig,
+    params: H::Params,
+    _permutation: PhantomData<H>,
+}
 
-impl MerkleTreeMmcs {
-    pub fn hash_iter_slices<
-        'a,
-        H: AlgebraicHasher<F>,
-        I,
-        F: RicherField + Extendable<D>,
-        const D: usize,
-    >(
+impl<H: P3PermutationParams> MerkleTreeMmcs<H> {
+    pub fn new<F: RicherField>(hash_config: P3HashConfig) -> Result<Self, P3VerifierError>
+    where
+        H: P3Permutation<F>,
+        H::Params: Default,
+    {
+        Self::with_params::<F>(hash_config, H::Params::default())
+    }
+
+    /// Same as [`new`](Self::new) for a permutation instantiated with
+    /// `params`.
+    pub fn with_params<F: RicherField>(
+        hash_config: P3HashConfig,
+        params: H::Params,
+    ) -> Result<Self, P3VerifierError>
+    where
+        H: P3Permutation<F>,
+    {
+        hash_config.check_width(H::width(&params))?;
+
+        Ok(Self {
+            hash_config,
+            params,
+            _permutation: PhantomData,
+        })
+    }
+
+    pub fn hash_iter_slices<'a, I, F: RicherField + Extendable<D>, const D: usize>(
+        &self,
         input: I,
         cb: &mut CircuitBuilder<F, D>,
-    ) -> [Target; DIGEST_ELEMS]
+    ) -> Vec<Target>
     where
+        H: P3Permutation<F>,
         I: Iterator<Item = &'a [Target]>,
     {
-        let mut state = cb.p3_arr::<WIDTH>();
-        for input_chunk in &input.into_iter().flatten().chunks(RATE) {
+        let zero = cb.zero();
+        let mut state = vec![zero; self.hash_config.width];
+        for input_chunk in &input.into_iter().flatten().chunks(self.hash_config.rate) {
             state
                 .iter_mut()
                 .zip(input_chunk)
                 .for_each(|(s, i)| *s = i.clone());
 
-            state = Poseidon2Hash::permute_targets::<F, D>(&state, cb);
+            state = H::permute_targets(&self.params, &state, cb);
         }
-        state[..DIGEST_ELEMS].try_into().unwrap()
+        state.truncate(self.hash_config.digest_elems);
+        state
     }
 
-    pub fn compress<H: AlgebraicHasher<F>, F: RicherField + Extendable<D>, const D: usize>(
-        input: [[Target; CHUNK]; N],
+    pub fn compress<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        input: [&[Target]; 2],
         cb: &mut CircuitBuilder<F, D>,
-    ) -> [Target; CHUNK] {
-        let mut state = cb.p3_arr::<WIDTH>();
-        for i in 0..N {
-            state[i * CHUNK..(i + 1) * CHUNK].copy_from_slice(&input[i]);
+    ) -> Vec<Target>
+    where
+        H: P3Permutation<F>,
+    {
+        let digest_elems = self.hash_config.digest_elems;
+        let zero = cb.zero();
+        let mut state = vec![zero; self.hash_config.width];
+        for (i, chunk) in input.iter().enumerate() {
+            state[i * digest_elems..(i + 1) * digest_elems].copy_from_slice(chunk);
         }
 
-        state = Poseidon2Hash::permute_targets::<F, D>(&state, cb);
+        state = H::permute_targets(&self.params, &state, cb);
 
-        state[..CHUNK].try_into().unwrap()
+        state.truncate(digest_elems);
+        state
     }
+}
 
-    pub fn verify_batch<H: AlgebraicHasher<F>, F: RicherField + Extendable<D>, const D: usize>(
-        commit: &Vec<Target>,
+/// Opening verifier of a Plonky3 `MerkleTreeMmcs`.
+pub trait P3Mmcs<F: RicherField> {
+    /// Checks that `opened_values` are the rows at `index` of the batch
+    /// committed to by `commit` where `condition` holds.
+    fn verify_batch<const D: usize>(
+        &self,
+        commit: &[Target],
         dimensions: &[Dimensions],
-        mut index: Target,
-        opened_values: &Vec<Vec<Target>>,
-        proof: &Vec<Vec<Target>>,
+        index: Target,
+        opened_values: &[Vec<Target>],
+        proof: &[Vec<Target>],
+        condition: BoolTarget,
         cb: &mut CircuitBuilder<F, D>,
-    ) -> Result<(), ()> {
-        let mut heights_tallest_first = dimensions
-            .iter()
-            .enumerate()
-            .sorted_by_key(|(_, dims)| Reverse(dims.height))
-            .peekable();
+    ) -> Result<(), MmcsError>
+    where
+        F: Extendable<D>;
+
+    /// Same as [`verify_batch`](Self::verify_batch) for a batch of matrices
+    /// of one height only known at proving time. The tree has
+    /// `min_depth + i` levels iff `is_depth[i]` holds, in which case the
+    /// opened row is given by the low bits of `index_bits` and only the first
+    /// digests of `proof` lie on its path. Nothing is checked if no flag
+    /// holds.
+    fn verify_batch_at_depth<const D: usize>(
+        &self,
+        commit: &[Target],
+        index_bits: &[BoolTarget],
+        min_depth: usize,
+        is_depth: &[BoolTarget],
+        opened_values: &[Vec<Target>],
+        proof: &[Vec<Target>],
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Result<(), MmcsError>
+    where
+        F: Extendable<D>;
+}
 
-        let mut curr_height_padded = heights_tallest_first
+/// Recomputes the root of a batch opening, hashing the rows of the matrices
+/// of each padded height with `hash_rows` and merging digests with
+/// `compress`.
+fn merkle_root<F: RicherField + Extendable<D>, const D: usize>(
+    dimensions: &[Dimensions],
+    mut index: Target,
+    opened_values: &[Vec<Target>],
+    siblings: &[Vec<Target>],
+    cb: &mut CircuitBuilder<F, D>,
+    mut hash_rows: impl FnMut(Vec<&[Target]>, &mut CircuitBuilder<F, D>) -> Vec<Target>,
+    mut compress: impl FnMut(Vec<Target>, Vec<Target>, &mut CircuitBuilder<F, D>) -> Vec<Target>,
+) -> Result<Vec<Target>, MmcsError> {
+    if dimensions.is_empty() || dimensions.len() != opened_values.len() {
+        return Err(MmcsError::WrongBatchSize);
+    }
+
+    let mut heights_tallest_first = dimensions
+        .iter()
+        .enumerate()
+        .sorted_by_key(|(_, dims)| Reverse(dims.height))
+        .peekable();
+
+    let mut curr_height_padded = heights_tallest_first
+        .peek()
+        .unwrap()
+        .1
+        .height
+        .next_power_of_two();
+
+    let mut root = hash_rows(
+        heights_tallest_first
+            .peeking_take_while(|(_, dims)| dims.height.next_power_of_two() == curr_height_padded)
+            .map(|(i, _)| opened_values[i].as_slice())
+            .collect(),
+        cb,
+    );
+
+    for sibling in siblings.iter() {
+        let one = cb.one();
+        let index_and_one = cb.p3_and(index, one);
+        let is_odd = BoolTarget::new_unsafe(index_and_one);
+
+        let left = izip!(&root, sibling)
+            .map(|(&r, &s)| cb._if(is_odd, s, r))
+            .collect();
+        let right = izip!(&root, sibling)
+            .map(|(&r, &s)| cb._if(is_odd, r, s))
+            .collect();
+
+        root = compress(left, right, cb);
+        index = cb.p3_rsh(index, 1);
+
+        curr_height_padded >>= 1;
+
+        let next_height = heights_tallest_first
             .peek()
-            .unwrap()
-            .1
-            .height
-            .next_power_of_two();
-
-        let mut root = Self::hash_iter_slices::<H, _, F, D>(
-            heights_tallest_first
-                .peeking_take_while(|(_, dims)| {
-                    dims.height.next_power_of_two() == curr_height_padded
-                })
-                .map(|(i, _)| opened_values[i].as_slice()),
-            cb,
-        );
-
-        for sibling in proof.iter() {
-            let one = cb.one();
-            let index_and_one = cb.p3_and(index, one);
-            let is_odd = BoolTarget::new_unsafe(index_and_one);
-            let mut left = cb.p3_arr::<DIGEST_ELEMS>();
-            let mut right = cb.p3_arr::<DIGEST_ELEMS>();
-
-            for i in 0..DIGEST_ELEMS {
-                left[i] = cb._if(is_odd, sibling[i], root[i]);
-                right[i] = cb._if(is_odd, root[i], sibling[i]);
-            }
-
-            root = Self::compress::<H, F, D>([left, right], cb);
-            index = cb.p3_rsh(index, 1);
-
-            curr_height_padded >>= 1;
-
-            let next_height = heights_tallest_first
-                .peek()
-                .map(|(_, dims)| dims.height)
-                .filter(|h| h.next_power_of_two() == curr_height_padded);
-            if let Some(next_height) = next_height {
-                let next_height_openings_digest = Self::hash_iter_slices::<H, _, F, D>(
-                    heights_tallest_first
-                        .peeking_take_while(|(_, dims)| dims.height == next_height)
-                        .map(|(i, _)| opened_values[i].as_slice()),
-                    cb,
-                );
-
-                root = Self::compress::<H, F, D>([root, next_height_openings_digest], cb);
-            }
+            .map(|(_, dims)| dims.height)
+            .filter(|h| h.next_power_of_two() == curr_height_padded);
+        if let Some(next_height) = next_height {
+            let next_height_openings_digest = hash_rows(
+                heights_tallest_first
+                    .peeking_take_while(|(_, dims)| dims.height == next_height)
+                    .map(|(i, _)| opened_values[i].as_slice())
+                    .collect(),
+                cb,
+            );
+
+            root = compress(root, next_height_openings_digest, cb);
+        }
+    }
+
+    Ok(root)
+}
+
+/// Recomputes the roots of the trees of every depth a batch opening of
+/// matrices of a single height may lie in, following the path of the row
+/// whose bits are `index_bits`: the `k`-th root is that of a tree of `k`
+/// levels.
+fn merkle_roots_by_depth<F: RicherField + Extendable<D>, const D: usize>(
+    index_bits: &[BoolTarget],
+    opened_values: &[Vec<Target>],
+    siblings: &[Vec<Target>],
+    cb: &mut CircuitBuilder<F, D>,
+    mut hash_rows: impl FnMut(Vec<&[Target]>, &mut CircuitBuilder<F, D>) -> Vec<Target>,
+    mut compress: impl FnMut(Vec<Target>, Vec<Target>, &mut CircuitBuilder<F, D>) -> Vec<Target>,
+) -> Result<Vec<Vec<Target>>, MmcsError> {
+    if opened_values.is_empty() {
+        return Err(MmcsError::WrongBatchSize);
+    }
+    if index_bits.len() < siblings.len() {
+        return Err(MmcsError::WrongHeight);
+    }
+
+    let mut roots = vec![hash_rows(
+        opened_values.iter().map(|row| row.as_slice()).collect(),
+        cb,
+    )];
+    for (sibling, &is_odd) in izip!(siblings, index_bits) {
+        let root = roots.last().unwrap();
+        let left = izip!(root, sibling)
+            .map(|(&r, &s)| cb._if(is_odd, s, r))
+            .collect();
+        let right = izip!(root, sibling)
+            .map(|(&r, &s)| cb._if(is_odd, r, s))
+            .collect();
+        roots.push(compress(left, right, cb));
+    }
+
+    Ok(roots)
+}
+
+/// Connects `commit` to the root of the tree of `min_depth + i` levels for
+/// the `i` such that `is_depth[i]` holds, if any.
+fn connect_root_at_depth<F: RicherField + Extendable<D>, const D: usize>(
+    commit: &[Target],
+    roots: &[Vec<Target>],
+    min_depth: usize,
+    is_depth: &[BoolTarget],
+    cb: &mut CircuitBuilder<F, D>,
+) -> Result<(), MmcsError> {
+    if roots.len() < min_depth + is_depth.len() {
+        return Err(MmcsError::WrongHeight);
+    }
+
+    // At most one flag holds, so the selected root is the sum of the flagged
+    // ones, and is only checked if there is one.
+    let zero = cb.zero();
+    let is_any_depth = is_depth
+        .iter()
+        .fold(zero, |acc, is_depth| cb.add(acc, is_depth.target));
+    for (i, &c) in commit.iter().enumerate() {
+        let root = izip!(&roots[min_depth..], is_depth).fold(zero, |acc, (root, is_depth)| {
+            cb.mul_add(is_depth.target, root[i], acc)
+        });
+        let expected = cb.mul(is_any_depth, c);
+        cb.connect(root, expected);
+    }
+    Ok(())
+}
+
+impl<F: RicherField, H: P3Permutation<F>> P3Mmcs<F> for MerkleTreeMmcs<H> {
+    fn verify_batch<const D: usize>(
+        &self,
+        commit: &[Target],
+        dimensions: &[Dimensions],
+        index: Target,
+        opened_values: &[Vec<Target>],
+        proof: &[Vec<Target>],
+        condition: BoolTarget,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Result<(), MmcsError>
+    where
+        F: Extendable<D>,
+    {
+        let digest_elems = self.hash_config.digest_elems;
+        if commit.len() != digest_elems || proof.iter().any(|sibling| sibling.len() != digest_elems)
+        {
+            return Err(MmcsError::WrongWidth);
         }
 
+        let root = merkle_root(
+            dimensions,
+            index,
+            opened_values,
+            proof,
+            cb,
+            |rows, cb| self.hash_iter_slices(rows.into_iter(), cb),
+            |left, right, cb| self.compress([&left, &right], cb),
+        )?;
+
         for (x, y) in commit.iter().zip(root.iter()) {
-            cb.connect(x.clone(), y.clone());
+            cb.p3_connect_if(condition, x.clone(), y.clone());
         }
         Ok(())
     }
+
+    fn verify_batch_at_depth<const D: usize>(
+        &self,
+        commit: &[Target],
+        index_bits: &[BoolTarget],
+        min_depth: usize,
+        is_depth: &[BoolTarget],
+        opened_values: &[Vec<Target>],
+        proof: &[Vec<Target>],
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Result<(), MmcsError>
+    where
+        F: Extendable<D>,
+    {
+        let digest_elems = self.hash_config.digest_elems;
+        if commit.len() != digest_elems || proof.iter().any(|sibling| sibling.len() != digest_elems)
+        {
+            return Err(MmcsError::WrongWidth);
+        }
+
+        let roots = merkle_roots_by_depth(
+            index_bits,
+            opened_values,
+            proof,
+            cb,
+            |rows, cb| self.hash_iter_slices(rows.into_iter(), cb),
+            |left, right, cb| self.compress([&left, &right], cb),
+        )?;
+
+        connect_root_at_depth(commit, &roots, min_depth, is_depth, cb)
+    }
+}
+
+/// `MerkleTreeMmcs<SerializingHasher64<Keccak256Hash>,
+/// CompressionFunctionFromHasher<Keccak256Hash, 2, 32>>`: leaves are the
+/// Keccak-256 of the little-endian bytes of the canonical values of a row,
+/// and digests are their 32 bytes.
+pub struct KeccakMerkleTreeMmcs;
+
+impl<F: RicherField> P3Mmcs<F> for KeccakMerkleTreeMmcs {
+    fn verify_batch<const D: usize>(
+        &self,
+        commit: &[Target],
+        dimensions: &[Dimensions],
+        index: Target,
+        opened_values: &[Vec<Target>],
+        proof: &[Vec<Target>],
+        condition: BoolTarget,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Result<(), MmcsError>
+    where
+        F: Extendable<D>,
+    {
+        if commit.len() != KECCAK256_DIGEST_BYTES
+            || proof
+                .iter()
+                .any(|sibling| sibling.len() != KECCAK256_DIGEST_BYTES)
+        {
+            return Err(MmcsError::WrongWidth);
+        }
+
+        // The path is walked on the 32-bit words of the digests, which is
+        // what the Keccak gadget absorbs.
+        let siblings: Vec<Vec<Target>> = proof
+            .iter()
+            .map(|sibling| {
+                cb.keccak_pack_bytes(sibling)
+                    .into_iter()
+                    .map(|word| word.0)
+                    .collect()
+            })
+            .collect();
+
+        let root = merkle_root(
+            dimensions,
+            index,
+            opened_values,
+            &siblings,
+            cb,
+            |rows, cb| {
+                let words: Vec<U32Target> = rows
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|&x| cb.p3_u64_to_u32s(x))
+                    .collect();
+                cb.keccak256_u32(&words).map(|word| word.0).to_vec()
+            },
+            |left, right, cb| {
+                let words: Vec<U32Target> = left.into_iter().chain(right).map(U32Target).collect();
+                cb.keccak256_u32(&words).map(|word| word.0).to_vec()
+            },
+        )?;
+
+        let commit = cb.keccak_pack_bytes(commit);
+        for (x, y) in commit.iter().zip(root) {
+            cb.p3_connect_if(condition, x.0, y);
+        }
+        Ok(())
+    }
+
+    fn verify_batch_at_depth<const D: usize>(
+        &self,
+        commit: &[Target],
+        index_bits: &[BoolTarget],
+        min_depth: usize,
+        is_depth: &[BoolTarget],
+        opened_values: &[Vec<Target>],
+        proof: &[Vec<Target>],
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Result<(), MmcsError>
+    where
+        F: Extendable<D>,
+    {
+        if commit.len() != KECCAK256_DIGEST_BYTES
+            || proof
+                .iter()
+                .any(|sibling| sibling.len() != KECCAK256_DIGEST_BYTES)
+        {
+            return Err(MmcsError::WrongWidth);
+        }
+
+        let siblings: Vec<Vec<Target>> = proof
+            .iter()
+            .map(|sibling| {
+                cb.keccak_pack_bytes(sibling)
+                    .into_iter()
+                    .map(|word| word.0)
+                    .collect()
+            })
+            .collect();
+
+        let roots = merkle_roots_by_depth(
+            index_bits,
+            opened_values,
+            &siblings,
+            cb,
+            |rows, cb| {
+                let words: Vec<U32Target> = rows
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|&x| cb.p3_u64_to_u32s(x))
+                    .collect();
+                cb.keccak256_u32(&words).map(|word| word.0).to_vec()
+            },
+            |left, right, cb| {
+                let words: Vec<U32Target> = left.into_iter().chain(right).map(U32Target).collect();
+                cb.keccak256_u32(&words).map(|word| word.0).to_vec()
+            },
+        )?;
+
+        let commit: Vec<Target> = cb
+            .keccak_pack_bytes(commit)
+            .into_iter()
+            .map(|word| word.0)
+            .collect();
+        connect_root_at_depth(&commit, &roots, min_depth, is_depth, cb)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use plonky2::field::goldilocks_field::GoldilocksField;
-    use plonky2::hash::poseidon::PoseidonHash;
     use plonky2::iop::witness::PartialWitness;
     use plonky2::plonk::circuit_data::CircuitConfig;
     use plonky2::plonk::config::PoseidonGoldilocksConfig;
 
     use super::*;
+    use crate::common::poseidon2::poseidon2::Poseidon2Hash;
 
     #[test]
     fn test_hash_iter_slice() {
@@ -150,10 +504,8 @@ mod tests {
 
         let arr = [builder.zero(); 4];
 
-        let res = MerkleTreeMmcs::hash_iter_slices::<PoseidonHash, _, F, D>(
-            [arr.as_ref(); 2].iter().map(|x| *x),
-            &mut builder,
-        );
+        let mmcs = MerkleTreeMmcs::<Poseidon2Hash>::new::<F>(P3HashConfig::default()).unwrap();
+        let res = mmcs.hash_iter_slices([arr.as_ref(); 2].iter().map(|x| *x), &mut builder);
 
         builder.register_public_inputs(&res);
 
@@ -179,8 +531,9 @@ mod tests {
 
         let mut builder = CircuitBuilder::<F, D>::new(config);
 
-        let res =
-            MerkleTreeMmcs::compress::<PoseidonHash, F, D>([[builder.zero(); 4]; 2], &mut builder);
+        let zero = [builder.zero(); 4];
+        let mmcs = MerkleTreeMmcs::<Poseidon2Hash>::new::<F>(P3HashConfig::default()).unwrap();
+        let res = mmcs.compress([&zero, &zero], &mut builder);
 
         builder.register_public_inputs(&res);
 
@@ -196,4 +549,39 @@ mod tests {
 
         assert!(data.verify(proof).is_ok());
     }
+
+    #[test]
+    fn test_mmcs_follows_hash_config() {
+        type F = GoldilocksField;
+        let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+
+        let hash_config = P3HashConfig {
+            width: 12,
+            rate: 8,
+            digest_elems: 6,
+            challenger_rate: None,
+        };
+        let mmcs = MerkleTreeMmcs::<Poseidon2Hash>::new::<F>(hash_config).unwrap();
+        let row = [builder.zero(); 10];
+        let digest = mmcs.hash_iter_slices([row.as_ref()].into_iter(), &mut builder);
+        assert_eq!(digest.len(), 6);
+        assert_eq!(mmcs.compress([&digest, &digest], &mut builder).len(), 6);
+
+        let wide_config = P3HashConfig {
+            width: 16,
+            ..hash_config
+        };
+        assert!(matches!(
+            MerkleTreeMmcs::<Poseidon2Hash>::new::<F>(wide_config),
+            Err(P3VerifierError::InvalidHashConfig(_))
+        ));
+        let oversized_digest = P3HashConfig {
+            digest_elems: 7,
+            ..hash_config
+        };
+        assert!(matches!(
+            MerkleTreeMmcs::<Poseidon2Hash>::new::<F>(oversized_digest),
+            Err(P3VerifierError::InvalidHashConfig(_))
+        ));
+    }
 }
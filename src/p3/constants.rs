@@ -1,6 +1 @@
-pub const WIDTH: usize = 12;
-pub const DIGEST_ELEMS: usize = 4;
-pub const RATE: usize = 4;
-pub const N: usize = 2;
-pub const CHUNK: usize = 4;
 pub const EXT_DEGREE: usize = 2;
@@ -1,242 +1,289 @@
 use plonky2::field::extension::Extendable;
+use plonky2::field::types::PrimeField64;
 use plonky2::iop::target::BoolTarget;
 use plonky2::iop::target::Target;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
 
 use crate::common::richer_field::RicherField;
 use crate::p3::constants::EXT_DEGREE;
+use crate::p3::native::domain::two_adic_generator;
+use crate::p3::native::domain::TWO_ADICITY;
 use crate::p3::serde::proof::BinomialExtensionField;
 use crate::p3::CircuitBuilderP3Arithmetic;
 
-pub trait CircuitBuilderP3ExtArithmetic<F: RicherField + Extendable<D>, const D: usize> {
+/// Arithmetic over the degree `E` binomial extension `F[X]/(X^E - W)` of the
+/// Plonky3 challenges, emulated with base field targets. `E` is independent of
+/// the plonky2 extension degree `D`.
+///
+/// Methods that don't take or return an extension element, like
+/// [`Self::p3_w`], need the degree spelled out, e.g.
+/// `<Self as CircuitBuilderP3ExtArithmetic<F, D, E>>::p3_w(self)`.
+pub trait CircuitBuilderP3ExtArithmetic<
+    F: RicherField + Extendable<D>,
+    const D: usize,
+    const E: usize = EXT_DEGREE,
+>
+{
+    /// The `W` of `X^E - W` for the Goldilocks extension of degree `E`.
     fn p3_w(&mut self) -> Target;
 
+    /// Plonky3's generator of the subgroup of order `2^bits` of Goldilocks.
+    ///
+    /// Panics if `bits` exceeds the two-adicity 32 of Goldilocks. Verifier
+    /// entry points reject such configs beforehand, see
+    /// [`P3Config::check_two_adicity`](crate::p3::serde::proof::P3Config::check_two_adicity).
     fn p3_two_adic_generator(&mut self, bits: usize) -> Target;
 
-    fn p3_ext_two_adic_generator(&mut self, bits: usize) -> BinomialExtensionField<Target>;
+    /// Plonky3's generator of the subgroup of order `2^bits` of the degree `E`
+    /// extension. Only the quadratic extension goes one bit further than
+    /// Goldilocks, so this panics if `bits` exceeds 33 for `E = 2`, or 32
+    /// otherwise.
+    fn p3_ext_two_adic_generator(&mut self, bits: usize) -> BinomialExtensionField<Target, E>;
 
+    /// `W^((p - 1) / E)`, used by the Frobenius automorphism.
     fn p3_dth_root(&mut self) -> Target;
 
     fn connect_p3_ext(
         &mut self,
-        x: &BinomialExtensionField<Target>,
-        y: &BinomialExtensionField<Target>,
+        x: &BinomialExtensionField<Target, E>,
+        y: &BinomialExtensionField<Target, E>,
+    );
+
+    /// Connects `x` and `y` where `cond` holds, and leaves them free
+    /// elsewhere.
+    fn connect_p3_ext_if(
+        &mut self,
+        cond: BoolTarget,
+        x: &BinomialExtensionField<Target, E>,
+        y: &BinomialExtensionField<Target, E>,
     );
 
     fn p3_ext_if(
         &mut self,
         cond: BoolTarget,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target>;
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_frobenius(
         &mut self,
-        x: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target>;
+        x: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_repeated_frobenius(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         count: usize,
-    ) -> BinomialExtensionField<Target>;
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_frobenius_inv(
         &mut self,
-        x: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target>;
+        x: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E>;
 
-    fn p3_ext_one(&mut self) -> BinomialExtensionField<Target>;
+    fn p3_ext_one(&mut self) -> BinomialExtensionField<Target, E>;
 
-    fn p3_ext_zero(&mut self) -> BinomialExtensionField<Target>;
+    fn p3_ext_zero(&mut self) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_div(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target>;
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_div_single(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         y: Target,
-    ) -> BinomialExtensionField<Target>;
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_inverse(
         &mut self,
-        x: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target>;
+        x: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E>;
 
-    fn p3_ext_neg(&mut self, x: BinomialExtensionField<Target>) -> BinomialExtensionField<Target>;
+    fn p3_ext_neg(
+        &mut self,
+        x: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_add(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target>;
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_add_single(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         y: Target,
-    ) -> BinomialExtensionField<Target>;
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_sub(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target>;
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_sub_single(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         y: Target,
-    ) -> BinomialExtensionField<Target>;
+    ) -> BinomialExtensionField<Target, E>;
 
-    fn p3_ext_arr<const SIZE: usize>(&mut self) -> [BinomialExtensionField<Target>; SIZE];
+    fn p3_ext_arr<const SIZE: usize>(&mut self) -> [BinomialExtensionField<Target, E>; SIZE];
 
     fn p3_ext_arr_fn<const SIZE: usize>(
         &mut self,
-        f: impl FnMut(usize) -> BinomialExtensionField<Target>,
-    ) -> [BinomialExtensionField<Target>; SIZE];
+        f: impl FnMut(usize) -> BinomialExtensionField<Target, E>,
+    ) -> [BinomialExtensionField<Target, E>; SIZE];
 
     fn p3_ext_mul_single(
         &mut self,
-        x: &BinomialExtensionField<Target>,
+        x: &BinomialExtensionField<Target, E>,
         y: Target,
-    ) -> BinomialExtensionField<Target>;
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_mul(
         &mut self,
-        x: &BinomialExtensionField<Target>,
-        y: &BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target>;
+        x: &BinomialExtensionField<Target, E>,
+        y: &BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_exp_power_of_2(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         power_log: usize,
-    ) -> BinomialExtensionField<Target>;
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_powers(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         n: usize,
-    ) -> Vec<BinomialExtensionField<Target>>;
+    ) -> Vec<BinomialExtensionField<Target, E>>;
 
-    fn p3_ext_monomial(&mut self, exponent: usize) -> BinomialExtensionField<Target>;
+    fn p3_ext_monomial(&mut self, exponent: usize) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_mul_add(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-        z: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target>;
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+        z: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E>;
 
     fn p3_ext_add_sub(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-        z: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target>;
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+        z: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E>;
 }
 
-impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3ExtArithmetic<F, D>
-    for CircuitBuilder<F, D>
+impl<F: RicherField + Extendable<D>, const D: usize, const E: usize>
+    CircuitBuilderP3ExtArithmetic<F, D, E> for CircuitBuilder<F, D>
 {
     fn p3_w(&mut self) -> Target {
-        match EXT_DEGREE {
+        match E {
             2 => self.p3_constant(7u32),
+            3 => self.p3_constant(2u32),
+            4 => self.p3_constant(7u32),
             _ => panic!("Unsupported extension degree"),
         }
     }
 
     fn p3_two_adic_generator(&mut self, bits: usize) -> Target {
-        let base = self.p3_constant(1_753_635_133_440_165_772u64);
-        self.exp_power_of_2(base, 32 - bits)
-    }
-
-    fn p3_ext_two_adic_generator(&mut self, bits: usize) -> BinomialExtensionField<Target> {
-        let base = self.p3_constant(1_753_635_133_440_165_772u64);
-        let x = self.exp_power_of_2(base, 32 - bits);
-        if bits == 33 {
-            let mut value = self.p3_field_to_arr(x);
-            value.reverse();
-            BinomialExtensionField::<Target> { value }
-        } else {
-            BinomialExtensionField::<Target> {
-                value: self.p3_field_to_arr(x),
-            }
+        self.constant(F::from_canonical_u64(
+            two_adic_generator(bits).to_canonical_u64(),
+        ))
+    }
+
+    fn p3_ext_two_adic_generator(&mut self, bits: usize) -> BinomialExtensionField<Target, E> {
+        if bits == TWO_ADICITY + 1 {
+            assert_eq!(E, 2, "only the quadratic extension has two-adicity 33");
+            // plonky3/goldilocks/src/extension.rs, a square root of the
+            // generator of order 2^32 divided by `W`.
+            let mut value = [self.zero(); E];
+            value[1] = self.constant(F::from_canonical_u64(15659105665374529263));
+            return BinomialExtensionField::<Target, E> { value };
+        }
+        let x = <Self as CircuitBuilderP3ExtArithmetic<F, D, E>>::p3_two_adic_generator(self, bits);
+        BinomialExtensionField::<Target, E> {
+            value: self.p3_field_to_arr(x),
         }
     }
 
     fn p3_dth_root(&mut self) -> Target {
         // plonky3/goldilocks/src/extension.rs
-        self.constant(F::from_canonical_u64(18446744069414584320))
+        match E {
+            2 => self.constant(F::from_canonical_u64(18446744069414584320)),
+            3 => self.constant(F::from_canonical_u64(4294967295)),
+            4 => self.constant(F::from_canonical_u64(281474976710656)),
+            _ => panic!("Unsupported extension degree"),
+        }
     }
 
     fn p3_ext_frobenius(
         &mut self,
-        x: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target> {
+        x: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E> {
         self.p3_ext_repeated_frobenius(x, 1)
     }
 
     fn p3_ext_if(
         &mut self,
         cond: BoolTarget,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target> {
-        let mut res = [self.zero(); EXT_DEGREE];
-        for i in 0..EXT_DEGREE {
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E> {
+        let mut res = [self.zero(); E];
+        for i in 0..E {
             res[i] = self._if(cond, x.value[i], y.value[i]);
         }
-        BinomialExtensionField::<Target> { value: res }
+        BinomialExtensionField::<Target, E> { value: res }
     }
 
     fn p3_ext_repeated_frobenius(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         count: usize,
-    ) -> BinomialExtensionField<Target> {
+    ) -> BinomialExtensionField<Target, E> {
         if count == 0 {
             return x.clone();
-        } else if count >= EXT_DEGREE {
+        } else if count >= E {
             // x |-> x^(n^D) is the identity, so x^(n^count) ==
             // x^(n^(count % D))
-            return self.p3_ext_repeated_frobenius(x, count % EXT_DEGREE);
+            return self.p3_ext_repeated_frobenius(x, count % E);
         }
         let arr: &[Target] = &x.value;
 
         // z0 = DTH_ROOT^count = W^(k * count) where k = floor((n-1)/D)
-        let mut z0 = <Self as CircuitBuilderP3ExtArithmetic<F, D>>::p3_dth_root(self);
+        let mut z0 = <Self as CircuitBuilderP3ExtArithmetic<F, D, E>>::p3_dth_root(self);
         for _ in 1..count {
-            let dth_root = <Self as CircuitBuilderP3ExtArithmetic<F, D>>::p3_dth_root(self);
+            let dth_root = <Self as CircuitBuilderP3ExtArithmetic<F, D, E>>::p3_dth_root(self);
             z0 = self.mul(z0, dth_root);
         }
 
-        let mut powers: [Target; EXT_DEGREE] = [z0.clone(); EXT_DEGREE];
-        for i in 1..EXT_DEGREE {
+        let mut powers: [Target; E] = [self.one(); E];
+        for i in 1..E {
             powers[i] = self.mul(powers[i - 1], z0.clone());
         }
 
-        let mut res = [self.zero(); EXT_DEGREE];
+        let mut res = [self.zero(); E];
         for (i, z) in powers.into_iter().enumerate() {
             res[i] = self.mul(arr[i], z);
         }
 
-        BinomialExtensionField::<Target> { value: res }
+        BinomialExtensionField::<Target, E> { value: res }
     }
 
     fn p3_ext_frobenius_inv(
         &mut self,
-        x: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target> {
+        x: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E> {
         // Writing 'a' for self, we need to compute a^(r-1):
         // r = n^D-1/n-1 = n^(D-1)+n^(D-2)+...+n
         let mut f = self.p3_ext_one();
-        for _ in 1..EXT_DEGREE {
+        for _ in 1..E {
             let x_mul_f = self.p3_ext_mul(&x, &f);
             f = self.p3_ext_frobenius(x_mul_f);
         }
@@ -246,11 +293,11 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3ExtArithmet
         let a = x.value;
         let b = f.value;
         let mut g = self.p3_constant(0u32);
-        for i in 1..EXT_DEGREE {
-            let a_i_mul_b_e_minus_i = self.mul(a[i], b[EXT_DEGREE - i]);
+        for i in 1..E {
+            let a_i_mul_b_e_minus_i = self.mul(a[i], b[E - i]);
             g = self.add(a_i_mul_b_e_minus_i, g);
         }
-        let w = <Self as CircuitBuilderP3ExtArithmetic<F, D>>::p3_w(self);
+        let w = <Self as CircuitBuilderP3ExtArithmetic<F, D, E>>::p3_w(self);
         g = self.mul(g, w);
         let a_0_mul_b_0 = self.mul(a[0], b[0]);
         g = self.add(a_0_mul_b_0, g);
@@ -259,49 +306,49 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3ExtArithmet
         self.p3_ext_mul_single(&f, g_inverse)
     }
 
-    fn p3_ext_one(&mut self) -> BinomialExtensionField<Target> {
+    fn p3_ext_one(&mut self) -> BinomialExtensionField<Target, E> {
         let one = self.p3_constant(1u32);
-        BinomialExtensionField::<Target> {
+        BinomialExtensionField::<Target, E> {
             value: self.p3_field_to_arr(one),
         }
     }
 
-    fn p3_ext_zero(&mut self) -> BinomialExtensionField<Target> {
+    fn p3_ext_zero(&mut self) -> BinomialExtensionField<Target, E> {
         let zero = self.p3_constant(0u32);
-        BinomialExtensionField::<Target> {
+        BinomialExtensionField::<Target, E> {
             value: self.p3_field_to_arr(zero),
         }
     }
 
     fn p3_ext_div(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target> {
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E> {
         let y_inv = self.p3_ext_inverse(y);
         self.p3_ext_mul(&y_inv, &x)
     }
 
     fn p3_ext_div_single(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         y: Target,
-    ) -> BinomialExtensionField<Target> {
+    ) -> BinomialExtensionField<Target, E> {
         let mut res = x.value;
         let y_inv = self.inverse(y);
 
         for r in res.iter_mut() {
             *r = self.mul(y_inv, *r);
         }
-        BinomialExtensionField::<Target> { value: res }
+        BinomialExtensionField::<Target, E> { value: res }
     }
 
     fn p3_ext_inverse(
         &mut self,
-        a: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target> {
-        let w = <Self as CircuitBuilderP3ExtArithmetic<F, D>>::p3_w(self);
-        match EXT_DEGREE {
+        a: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E> {
+        let w = <Self as CircuitBuilderP3ExtArithmetic<F, D, E>>::p3_w(self);
+        match E {
             2 => {
                 let a_0_square = self.square(a.value[0].clone());
                 let a_1_square = self.square(a.value[1].clone());
@@ -313,11 +360,11 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3ExtArithmet
                 let a_1_neg = self.neg(a.value[1].clone());
                 let a_1_neg_mul_scalar = self.mul(a_1_neg, scalar);
 
-                let mut value = [self.zero(); EXT_DEGREE];
+                let mut value = [self.zero(); E];
                 value[0] = a_0_mul_scalar;
                 value[1] = a_1_neg_mul_scalar;
 
-                BinomialExtensionField::<Target> { value }
+                BinomialExtensionField::<Target, E> { value }
             }
             3 => {
                 let a0_square = self.square(a.value[0].clone());
@@ -358,103 +405,106 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3ExtArithmet
                 let a1_square_minus_a0_mul_a2 = self.sub(a1_square, a0_mul_a2);
 
                 //scalar*[a0^2-wa1a2, wa2^2-a0a1, a1^2-a0a2]
-                let mut value = [self.zero(); EXT_DEGREE];
+                let mut value = [self.zero(); E];
                 value[0] = self.mul(scalar, a0_square_minus_a1_mul_a2w);
                 value[1] = self.mul(scalar, a2w_mul_a2_sub_a0_a1);
                 value[2] = self.mul(scalar, a1_square_minus_a0_mul_a2);
 
-                BinomialExtensionField::<Target> { value }
+                BinomialExtensionField::<Target, E> { value }
             }
             _ => self.p3_ext_frobenius_inv(a),
         }
     }
 
-    fn p3_ext_neg(&mut self, x: BinomialExtensionField<Target>) -> BinomialExtensionField<Target> {
+    fn p3_ext_neg(
+        &mut self,
+        x: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E> {
         let mut res = x.value;
         for r in res.iter_mut() {
             let r_neg = self.neg(*r);
             *r = r_neg;
         }
-        BinomialExtensionField::<Target> { value: res }
+        BinomialExtensionField::<Target, E> { value: res }
     }
 
     fn p3_ext_add(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target> {
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E> {
         let mut res = x.value;
         for (r, rhs_val) in res.iter_mut().zip(y.value) {
             *r = self.add(*r, rhs_val);
         }
-        BinomialExtensionField::<Target> { value: res }
+        BinomialExtensionField::<Target, E> { value: res }
     }
 
     fn p3_ext_add_single(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         y: Target,
-    ) -> BinomialExtensionField<Target> {
+    ) -> BinomialExtensionField<Target, E> {
         let mut res = x.value;
         res[0] = self.add(res[0], y);
-        BinomialExtensionField::<Target> { value: res }
+        BinomialExtensionField::<Target, E> { value: res }
     }
 
     fn p3_ext_sub(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target> {
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E> {
         let mut res = x.value;
         for (r, rhs_val) in res.iter_mut().zip(y.value) {
             *r = self.sub(*r, rhs_val);
         }
-        BinomialExtensionField::<Target> { value: res }
+        BinomialExtensionField::<Target, E> { value: res }
     }
 
     fn p3_ext_sub_single(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         y: Target,
-    ) -> BinomialExtensionField<Target> {
+    ) -> BinomialExtensionField<Target, E> {
         let mut res = x.value;
         res[0] = self.sub(res[0], y);
-        BinomialExtensionField::<Target> { value: res }
+        BinomialExtensionField::<Target, E> { value: res }
     }
 
-    fn p3_ext_arr<const SIZE: usize>(&mut self) -> [BinomialExtensionField<Target>; SIZE] {
-        core::array::from_fn(|_| BinomialExtensionField::<Target> {
+    fn p3_ext_arr<const SIZE: usize>(&mut self) -> [BinomialExtensionField<Target, E>; SIZE] {
+        core::array::from_fn(|_| BinomialExtensionField::<Target, E> {
             value: self.p3_arr(),
         })
     }
 
     fn p3_ext_arr_fn<const SIZE: usize>(
         &mut self,
-        f: impl FnMut(usize) -> BinomialExtensionField<Target>,
-    ) -> [BinomialExtensionField<Target>; SIZE] {
+        f: impl FnMut(usize) -> BinomialExtensionField<Target, E>,
+    ) -> [BinomialExtensionField<Target, E>; SIZE] {
         core::array::from_fn(f)
     }
 
     fn p3_ext_mul_single(
         &mut self,
-        x: &BinomialExtensionField<Target>,
+        x: &BinomialExtensionField<Target, E>,
         y: Target,
-    ) -> BinomialExtensionField<Target> {
-        BinomialExtensionField::<Target> {
+    ) -> BinomialExtensionField<Target, E> {
+        BinomialExtensionField::<Target, E> {
             value: x.value.map(|item| self.mul(item, y.clone())),
         }
     }
 
     fn p3_ext_mul(
         &mut self,
-        x: &BinomialExtensionField<Target>,
-        y: &BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target> {
-        let w_af = <Self as CircuitBuilderP3ExtArithmetic<F, D>>::p3_w(self);
-        let mut res = BinomialExtensionField::<Target> {
+        x: &BinomialExtensionField<Target, E>,
+        y: &BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E> {
+        let w_af = <Self as CircuitBuilderP3ExtArithmetic<F, D, E>>::p3_w(self);
+        let mut res = BinomialExtensionField::<Target, E> {
             value: self.p3_arr(),
         };
-        match EXT_DEGREE {
+        match E {
             2 => {
                 let a_0_mul_b_0 = self.mul(x.value[0].clone(), y.value[0].clone());
                 let w_af_mul_b_1 = self.mul(w_af, y.value[1].clone());
@@ -513,15 +563,15 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3ExtArithmet
             _ =>
             {
                 #[allow(clippy::needless_range_loop)]
-                for i in 0..EXT_DEGREE {
-                    for j in 0..EXT_DEGREE {
-                        if i + j >= EXT_DEGREE {
+                for i in 0..E {
+                    for j in 0..E {
+                        if i + j >= E {
                             let x_i_mul_w_af = self.mul(x.value[i].clone(), w_af.clone());
                             let x_i_mul_w_af_mul_y_j = self.mul(x_i_mul_w_af, y.value[j].clone());
-                            res.value[i + j - EXT_DEGREE] =
-                                self.add(res.value[i + j - EXT_DEGREE], x_i_mul_w_af_mul_y_j);
+                            res.value[i + j - E] =
+                                self.add(res.value[i + j - E], x_i_mul_w_af_mul_y_j);
                         } else {
-                            let x_i_mul_y_j = self.mul(x.value[i].clone(), x.value[j].clone());
+                            let x_i_mul_y_j = self.mul(x.value[i].clone(), y.value[j].clone());
                             res.value[i + j] = self.add(res.value[i + j], x_i_mul_y_j);
                         }
                     }
@@ -533,9 +583,9 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3ExtArithmet
 
     fn p3_ext_exp_power_of_2(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         power_log: usize,
-    ) -> BinomialExtensionField<Target> {
+    ) -> BinomialExtensionField<Target, E> {
         let mut res = x.clone();
         for _ in 0..power_log {
             res = self.p3_ext_mul(&res, &res);
@@ -545,9 +595,9 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3ExtArithmet
 
     fn p3_ext_powers(
         &mut self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         n: usize,
-    ) -> Vec<BinomialExtensionField<Target>> {
+    ) -> Vec<BinomialExtensionField<Target, E>> {
         let mut res = vec![x.clone()];
         for i in 1..n {
             res.push(self.p3_ext_mul(&x, &res[i - 1]));
@@ -555,18 +605,18 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3ExtArithmet
         res
     }
 
-    fn p3_ext_monomial(&mut self, exponent: usize) -> BinomialExtensionField<Target> {
-        let mut value = [self.zero(); EXT_DEGREE];
+    fn p3_ext_monomial(&mut self, exponent: usize) -> BinomialExtensionField<Target, E> {
+        let mut value = [self.zero(); E];
         value[exponent] = self.one();
-        BinomialExtensionField::<Target> { value }
+        BinomialExtensionField::<Target, E> { value }
     }
 
     fn p3_ext_mul_add(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-        z: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target> {
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+        z: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E> {
         let x_mul_y = self.p3_ext_mul(&x, &y);
         let x_mul_y_plus_z = self.p3_ext_add(x_mul_y, z);
         x_mul_y_plus_z
@@ -574,10 +624,10 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3ExtArithmet
 
     fn p3_ext_add_sub(
         &mut self,
-        x: BinomialExtensionField<Target>,
-        y: BinomialExtensionField<Target>,
-        z: BinomialExtensionField<Target>,
-    ) -> BinomialExtensionField<Target> {
+        x: BinomialExtensionField<Target, E>,
+        y: BinomialExtensionField<Target, E>,
+        z: BinomialExtensionField<Target, E>,
+    ) -> BinomialExtensionField<Target, E> {
         let x_plus_y = self.p3_ext_add(x, y);
         let x_plus_y_minus_z = self.p3_ext_sub(x_plus_y, z);
         x_plus_y_minus_z
@@ -585,11 +635,164 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3ExtArithmet
 
     fn connect_p3_ext(
         &mut self,
-        x: &BinomialExtensionField<Target>,
-        y: &BinomialExtensionField<Target>,
+        x: &BinomialExtensionField<Target, E>,
+        y: &BinomialExtensionField<Target, E>,
     ) {
-        for i in 0..EXT_DEGREE {
+        for i in 0..E {
             self.connect(x.value[i].clone(), y.value[i].clone());
         }
     }
+
+    fn connect_p3_ext_if(
+        &mut self,
+        cond: BoolTarget,
+        x: &BinomialExtensionField<Target, E>,
+        y: &BinomialExtensionField<Target, E>,
+    ) {
+        for i in 0..E {
+            self.p3_connect_if(cond, x.value[i], y.value[i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::extension::quadratic::QuadraticExtension;
+    use plonky2::field::extension::quartic::QuarticExtension;
+    use plonky2::field::extension::FieldExtension;
+    use plonky2::field::extension::Frobenius;
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+
+    type F = GoldilocksField;
+    const D: usize = 2;
+
+    fn constant_ext<const E: usize>(
+        cb: &mut CircuitBuilder<F, D>,
+        x: [F; E],
+    ) -> BinomialExtensionField<Target, E> {
+        BinomialExtensionField {
+            value: x.map(|limb| cb.constant(limb)),
+        }
+    }
+
+    fn prove_and_verify(cb: CircuitBuilder<F, D>) {
+        let data = cb.build::<PoseidonGoldilocksConfig>();
+        let proof = data.prove(PartialWitness::new()).unwrap();
+        data.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_quartic_ext_matches_plonky2() {
+        type E4 = QuarticExtension<F>;
+        let mut cb = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+
+        let x = E4::from_basefield_array([1, 2, 3, 4].map(F::from_canonical_u64));
+        let y = E4::from_basefield_array([5, 6, 7, 8].map(F::from_canonical_u64));
+        let x_target = constant_ext(&mut cb, x.0);
+        let y_target = constant_ext(&mut cb, y.0);
+
+        let x_mul_y = cb.p3_ext_mul(&x_target, &y_target);
+        let expected = constant_ext(&mut cb, (x * y).0);
+        cb.connect_p3_ext(&x_mul_y, &expected);
+
+        let x_inv = cb.p3_ext_inverse(x_target);
+        let expected = constant_ext(&mut cb, x.inverse().0);
+        cb.connect_p3_ext(&x_inv, &expected);
+
+        prove_and_verify(cb);
+    }
+
+    /// Connects every repeated Frobenius map of `x` to plonky2's.
+    fn connect_repeated_frobenius<
+        X: Frobenius<E> + FieldExtension<E, BaseField = F>,
+        const E: usize,
+    >(
+        cb: &mut CircuitBuilder<F, D>,
+        x: X,
+    ) {
+        let x_target = constant_ext(cb, x.to_basefield_array());
+        for count in 0..=E {
+            let frobenius = cb.p3_ext_repeated_frobenius(x_target.clone(), count);
+            let expected = constant_ext(cb, x.repeated_frobenius(count).to_basefield_array());
+            cb.connect_p3_ext(&frobenius, &expected);
+        }
+    }
+
+    #[test]
+    fn test_ext_frobenius_matches_plonky2() {
+        let mut cb = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+
+        // The constant limb is fixed by the Frobenius map, the others are
+        // scaled by powers of the root of unity.
+        let x = QuadraticExtension::<F>::from_basefield_array([3, 5].map(F::from_canonical_u64));
+        connect_repeated_frobenius(&mut cb, x);
+        let x =
+            QuarticExtension::<F>::from_basefield_array([1, 2, 3, 4].map(F::from_canonical_u64));
+        connect_repeated_frobenius(&mut cb, x);
+
+        prove_and_verify(cb);
+    }
+
+    #[test]
+    fn test_quartic_ext_mul_by_one() {
+        let mut cb = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+
+        // Products of limbs of both operands, not of the first one with itself.
+        let x = constant_ext(&mut cb, [1, 2, 3, 4].map(F::from_canonical_u64));
+        let one =
+            <CircuitBuilder<F, D> as CircuitBuilderP3ExtArithmetic<F, D, 4>>::p3_ext_one(&mut cb);
+        let x_mul_one = cb.p3_ext_mul(&x, &one);
+        cb.connect_p3_ext(&x_mul_one, &x);
+        let one_mul_x = cb.p3_ext_mul(&one, &x);
+        cb.connect_p3_ext(&one_mul_x, &x);
+
+        prove_and_verify(cb);
+    }
+
+    #[test]
+    fn test_cubic_ext_inverse_and_frobenius() {
+        let mut cb = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+
+        let x = constant_ext(&mut cb, [3, 1, 4].map(F::from_canonical_u64));
+        let y = constant_ext(&mut cb, [1, 5, 9].map(F::from_canonical_u64));
+
+        let x_inv = cb.p3_ext_inverse(x.clone());
+        let x_mul_x_inv = cb.p3_ext_mul(&x, &x_inv);
+        let one = cb.p3_ext_one();
+        cb.connect_p3_ext(&x_mul_x_inv, &one);
+
+        // The norm based inverse agrees with the Frobenius based one.
+        let x_frobenius_inv = cb.p3_ext_frobenius_inv(x.clone());
+        cb.connect_p3_ext(&x_frobenius_inv, &x_inv);
+
+        // The Frobenius map is a ring homomorphism.
+        let x_mul_y = cb.p3_ext_mul(&x, &y);
+        let frobenius_x_mul_y = cb.p3_ext_frobenius(x_mul_y);
+        let frobenius_x = cb.p3_ext_frobenius(x);
+        let frobenius_y = cb.p3_ext_frobenius(y);
+        let frobenius_x_mul_frobenius_y = cb.p3_ext_mul(&frobenius_x, &frobenius_y);
+        cb.connect_p3_ext(&frobenius_x_mul_y, &frobenius_x_mul_frobenius_y);
+
+        prove_and_verify(cb);
+    }
+
+    #[test]
+    fn test_quadratic_ext_two_adic_generator_of_order_2_33() {
+        let mut cb = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+
+        let mut x = cb.p3_ext_two_adic_generator(33);
+        for _ in 0..32 {
+            x = cb.p3_ext_mul(&x, &x);
+        }
+        let neg_one = constant_ext(&mut cb, [F::NEG_ONE, F::ZERO]);
+        cb.connect_p3_ext(&x, &neg_one);
+
+        prove_and_verify(cb);
+    }
 }
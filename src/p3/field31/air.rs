@@ -0,0 +1,165 @@
+use core::marker::PhantomData;
+
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::field31::extension::BinomialExtension;
+use crate::p3::field31::extension::CircuitBuilderField31Ext;
+use crate::p3::field31::extension::Field31ExtTarget;
+use crate::p3::serde::proof::OpenedValues;
+use crate::p3::serde::LagrangeSelectors;
+use crate::p3::utils::log2_ceil_usize;
+
+/// An AIR proven over a 31-bit field. Unlike [`Air`](crate::p3::air::Air), its
+/// constraints are folded with the emulated arithmetic of
+/// [`CircuitBuilderField31Ext`], so the same AIR can be verified over any
+/// [`Field31`](crate::p3::field31::field::Field31).
+pub trait Field31Air {
+    fn name(&self) -> String;
+    fn width(&self) -> usize;
+    /// Maximum degree of the constraints, selectors included.
+    fn max_constraint_degree(&self) -> usize {
+        2
+    }
+    fn log_quotient_degree(&self) -> usize {
+        log2_ceil_usize(self.max_constraint_degree().max(2) - 1)
+    }
+    /// Number of public values, exposed to [`Field31Air::eval`] as canonical
+    /// targets through [`Field31ConstraintFolder::public_values`].
+    fn num_public_values(&self) -> usize {
+        0
+    }
+    fn eval<
+        F: RicherField + Extendable<D>,
+        const D: usize,
+        P: BinomialExtension<E>,
+        const E: usize,
+    >(
+        &self,
+        folder: &mut Field31ConstraintFolder<P, E>,
+        cb: &mut CircuitBuilder<F, D>,
+    );
+}
+
+pub struct Field31ConstraintFolder<P, const E: usize> {
+    pub main: OpenedValues<Target, E>,
+    pub public_values: Vec<Target>,
+    pub is_first_row: Field31ExtTarget<E>,
+    pub is_last_row: Field31ExtTarget<E>,
+    pub is_transition: Field31ExtTarget<E>,
+    pub alpha: Field31ExtTarget<E>,
+    pub accumulator: Field31ExtTarget<E>,
+    _field: PhantomData<P>,
+}
+
+pub struct FilteredField31AirBuilder<'a, P, const E: usize> {
+    pub inner: &'a mut Field31ConstraintFolder<P, E>,
+    pub condition: Field31ExtTarget<E>,
+}
+
+impl<P: BinomialExtension<E>, const E: usize> Field31ConstraintFolder<P, E> {
+    /// A folder of the constraints on `main` at a point with the given
+    /// `selectors`, combined by powers of `alpha`.
+    pub fn new<F: RicherField + Extendable<D>, const D: usize>(
+        main: OpenedValues<Target, E>,
+        public_values: Vec<Target>,
+        selectors: LagrangeSelectors<Field31ExtTarget<E>>,
+        alpha: Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        Self {
+            main,
+            public_values,
+            is_first_row: selectors.is_first_row,
+            is_last_row: selectors.is_last_row,
+            is_transition: selectors.is_transition,
+            alpha,
+            accumulator: cb.f31_ext_zero(),
+            _field: PhantomData,
+        }
+    }
+
+    pub fn when(&mut self, condition: Field31ExtTarget<E>) -> FilteredField31AirBuilder<P, E> {
+        FilteredField31AirBuilder {
+            inner: self,
+            condition,
+        }
+    }
+
+    pub fn when_first_row(&mut self) -> FilteredField31AirBuilder<P, E> {
+        self.when(self.is_first_row.clone())
+    }
+
+    pub fn when_last_row(&mut self) -> FilteredField31AirBuilder<P, E> {
+        self.when(self.is_last_row.clone())
+    }
+
+    pub fn when_transition(&mut self) -> FilteredField31AirBuilder<P, E> {
+        self.when(self.is_transition.clone())
+    }
+
+    pub fn assert_zero<F: RicherField + Extendable<D>, const D: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) {
+        self.accumulator =
+            cb.f31_ext_mul_add::<P, E>(self.accumulator.clone(), self.alpha.clone(), x);
+    }
+
+    pub fn assert_eq<F: RicherField + Extendable<D>, const D: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) {
+        let x_sub_y = cb.f31_ext_sub::<P, E>(x, y);
+        self.assert_zero(x_sub_y, cb)
+    }
+
+    pub fn assert_bool<F: RicherField + Extendable<D>, const D: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) {
+        let one = cb.f31_ext_one();
+        let x_minus_one = cb.f31_ext_sub::<P, E>(x.clone(), one);
+        let x_mul_x_minus_one = cb.f31_ext_mul::<P, E>(&x, &x_minus_one);
+
+        self.assert_zero(x_mul_x_minus_one, cb);
+    }
+}
+
+impl<'a, P: BinomialExtension<E>, const E: usize> FilteredField31AirBuilder<'a, P, E> {
+    pub fn assert_zero<F: RicherField + Extendable<D>, const D: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) {
+        let x = cb.f31_ext_mul::<P, E>(&self.condition, &x);
+        self.inner.assert_zero(x, cb)
+    }
+
+    pub fn assert_eq<F: RicherField + Extendable<D>, const D: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) {
+        let x_sub_y = cb.f31_ext_sub::<P, E>(x, y);
+        self.assert_zero(x_sub_y, cb)
+    }
+
+    pub fn assert_bool<F: RicherField + Extendable<D>, const D: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) {
+        let one = cb.f31_ext_one();
+        let x_minus_one = cb.f31_ext_sub::<P, E>(x.clone(), one);
+        let x_mul_x_minus_one = cb.f31_ext_mul::<P, E>(&x, &x_minus_one);
+        self.assert_zero(x_mul_x_minus_one, cb)
+    }
+}
@@ -0,0 +1,171 @@
+use core::marker::PhantomData;
+
+use plonky2::field::extension::Extendable;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::field31::extension::BinomialExtension;
+use crate::p3::field31::extension::CircuitBuilderField31Ext;
+use crate::p3::field31::extension::Field31ExtTarget;
+use crate::p3::field31::field::CircuitBuilderField31;
+use crate::p3::field31::field::TwoAdicField31;
+use crate::p3::serde::LagrangeSelectors;
+use crate::p3::utils::log2_ceil_usize;
+use crate::p3::utils::log2_strict_usize;
+
+/// The evaluation domain of a PCS over a 31-bit field `P`, i.e.
+/// `PolynomialSpace` in Plonky3. Every domain of a single-table proof is known
+/// when building the circuit, so its parameters are native constants and only
+/// the points it is evaluated at are targets.
+pub trait Field31Domain<P: BinomialExtension<E>, const E: usize>: Clone {
+    fn size(&self) -> usize;
+
+    fn first_point(&self) -> u32;
+
+    /// A domain of at least `min_size` points, disjoint from `self`.
+    fn create_disjoint_domain(&self, min_size: usize) -> Self;
+
+    /// Splits `self` into `num_chunks` domains of equal size.
+    fn split_domains(&self, num_chunks: usize) -> Vec<Self>;
+
+    /// The point following `x` in the order of the trace rows.
+    fn next_point<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        x: &Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Field31ExtTarget<E>;
+
+    fn selectors_at_point<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        point: &Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> LagrangeSelectors<Field31ExtTarget<E>>;
+
+    /// The vanishing polynomial of `self` at `point`.
+    fn zp_at_point<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        point: &Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Field31ExtTarget<E>;
+
+    /// The vanishing polynomial of `self` at a base field `point`.
+    fn zp_at_single_point(&self, point: u32) -> u32;
+}
+
+/// Two-adic coset of a [`TwoAdicField31`]. Unlike
+/// [`TwoAdicMultiplicativeCoset`](crate::p3::serde::two_adic::TwoAdicMultiplicativeCoset)
+/// the shift is a native constant.
+#[derive(Clone, Debug)]
+pub struct TwoAdicCoset<P> {
+    pub log_n: usize,
+    pub shift: u32,
+    _field: PhantomData<P>,
+}
+
+impl<P: TwoAdicField31> TwoAdicCoset<P> {
+    pub fn new(log_n: usize, shift: u32) -> Self {
+        Self {
+            log_n,
+            shift,
+            _field: PhantomData,
+        }
+    }
+
+    pub fn natural_domain_for_degree(degree: usize) -> Self {
+        Self::new(log2_strict_usize(degree), 1)
+    }
+
+    pub fn gen(&self) -> u32 {
+        P::two_adic_generator(self.log_n)
+    }
+
+    fn unshift<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+        &self,
+        point: &Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Field31ExtTarget<E> {
+        cb.f31_ext_mul_const::<P, E>(P::inverse(self.shift), point)
+    }
+}
+
+impl<P: TwoAdicField31 + BinomialExtension<E>, const E: usize> Field31Domain<P, E>
+    for TwoAdicCoset<P>
+{
+    fn size(&self) -> usize {
+        1 << self.log_n
+    }
+
+    fn first_point(&self) -> u32 {
+        self.shift
+    }
+
+    fn create_disjoint_domain(&self, min_size: usize) -> Self {
+        Self::new(log2_ceil_usize(min_size), P::mul(self.shift, P::GENERATOR))
+    }
+
+    fn split_domains(&self, num_chunks: usize) -> Vec<Self> {
+        let log_chunks = log2_strict_usize(num_chunks);
+        let gen = self.gen();
+        (0..num_chunks)
+            .map(|i| {
+                Self::new(
+                    self.log_n - log_chunks,
+                    P::mul(self.shift, P::exp_u64(gen, i as u64)),
+                )
+            })
+            .collect()
+    }
+
+    fn next_point<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        x: &Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Field31ExtTarget<E> {
+        cb.f31_ext_mul_const::<P, E>(self.gen(), x)
+    }
+
+    fn selectors_at_point<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        point: &Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> LagrangeSelectors<Field31ExtTarget<E>> {
+        let unshifted_point = self.unshift(point, cb);
+        let unshifted_point_exp_log_n =
+            cb.f31_ext_exp_power_of_2::<P, E>(unshifted_point.clone(), self.log_n);
+        let one = cb.f31_one();
+        let z_h = cb.f31_ext_sub_base::<P, E>(unshifted_point_exp_log_n, one);
+
+        let unshifted_point_minus_one = cb.f31_ext_sub_base::<P, E>(unshifted_point.clone(), one);
+        let is_first_row = cb.f31_ext_div::<P, E>(z_h.clone(), unshifted_point_minus_one);
+
+        let generator_inv = cb.f31_constant::<P>(P::inverse(self.gen()));
+        let unshifted_point_minus_generator_inv =
+            cb.f31_ext_sub_base::<P, E>(unshifted_point, generator_inv);
+        let is_last_row =
+            cb.f31_ext_div::<P, E>(z_h.clone(), unshifted_point_minus_generator_inv.clone());
+
+        LagrangeSelectors {
+            is_first_row,
+            is_last_row,
+            is_transition: unshifted_point_minus_generator_inv,
+            inv_zeroifier: cb.f31_ext_inverse::<P, E>(z_h),
+        }
+    }
+
+    fn zp_at_point<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        point: &Field31ExtTarget<E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Field31ExtTarget<E> {
+        let unshifted_point = self.unshift(point, cb);
+        let unshifted_point_exp_log_n =
+            cb.f31_ext_exp_power_of_2::<P, E>(unshifted_point, self.log_n);
+        let one = cb.f31_one();
+        cb.f31_ext_sub_base::<P, E>(unshifted_point_exp_log_n, one)
+    }
+
+    fn zp_at_single_point(&self, point: u32) -> u32 {
+        let unshifted_point = P::mul(point, P::inverse(self.shift));
+        P::sub(P::exp_u64(unshifted_point, 1 << self.log_n), 1)
+    }
+}
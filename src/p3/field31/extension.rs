@@ -0,0 +1,510 @@
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::generator::GeneratedValues;
+use plonky2::iop::generator::SimpleGenerator;
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::PartitionWitness;
+use plonky2::iop::witness::Witness;
+use plonky2::iop::witness::WitnessWrite;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::util::serialization::Buffer;
+use plonky2::util::serialization::IoResult;
+use plonky2::util::serialization::Read;
+use plonky2::util::serialization::Write;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::field31::field::CircuitBuilderField31;
+use crate::p3::field31::field::Field31;
+use crate::p3::serde::proof::BinomialExtensionField;
+
+/// Element of the degree `E` extension of a [`Field31`] Plonky3 draws its
+/// challenges from.
+pub type Field31ExtTarget<const E: usize> = BinomialExtensionField<Target, E>;
+
+/// The binomial extension `F[X]/(X^E - W)` of a [`Field31`].
+pub trait BinomialExtension<const E: usize>: Field31 {
+    /// The `W` of `X^E - W`.
+    const W: u32;
+
+    fn ext_mul(x: [u32; E], y: [u32; E]) -> [u32; E] {
+        let mut res = [0u32; E];
+        for i in 0..E {
+            for j in 0..E {
+                let product = Self::mul(x[i], y[j]);
+                if i + j < E {
+                    res[i + j] = Self::add(res[i + j], product);
+                } else {
+                    let product = Self::mul(product, Self::W);
+                    res[i + j - E] = Self::add(res[i + j - E], product);
+                }
+            }
+        }
+        res
+    }
+
+    fn ext_exp_u128(x: [u32; E], mut power: u128) -> [u32; E] {
+        let mut base = x;
+        let mut res = [0; E];
+        res[0] = 1;
+        while power > 0 {
+            if power & 1 == 1 {
+                res = Self::ext_mul(res, base);
+            }
+            base = Self::ext_mul(base, base);
+            power >>= 1;
+        }
+        res
+    }
+
+    fn ext_inverse(x: [u32; E]) -> [u32; E] {
+        assert_ne!(x, [0; E], "zero has no inverse");
+        let order = (Self::ORDER as u128).pow(E as u32);
+        Self::ext_exp_u128(x, order - 2)
+    }
+}
+
+/// Arithmetic over the degree `E` extension of a [`Field31`], emulated with
+/// canonical targets, see [`CircuitBuilderField31`].
+pub trait CircuitBuilderField31Ext<F: RicherField + Extendable<D>, const D: usize> {
+    fn f31_ext_constant<P: Field31, const E: usize>(
+        &mut self,
+        value: [u32; E],
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_from_base<const E: usize>(&mut self, x: Target) -> Field31ExtTarget<E>;
+
+    fn f31_ext_zero<const E: usize>(&mut self) -> Field31ExtTarget<E>;
+
+    fn f31_ext_one<const E: usize>(&mut self) -> Field31ExtTarget<E>;
+
+    /// `X^exponent`, for `exponent < E`.
+    fn f31_ext_monomial<const E: usize>(&mut self, exponent: usize) -> Field31ExtTarget<E>;
+
+    fn f31_ext_range_check<P: Field31, const E: usize>(&mut self, x: &Field31ExtTarget<E>);
+
+    fn connect_f31_ext<const E: usize>(&mut self, x: &Field31ExtTarget<E>, y: &Field31ExtTarget<E>);
+
+    fn f31_ext_if<const E: usize>(
+        &mut self,
+        cond: BoolTarget,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_add<P: Field31, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_add_base<P: Field31, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Target,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_sub<P: Field31, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_sub_base<P: Field31, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Target,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_neg<P: Field31, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_mul<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: &Field31ExtTarget<E>,
+        y: &Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_mul_base<P: Field31, const E: usize>(
+        &mut self,
+        x: &Field31ExtTarget<E>,
+        y: Target,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_mul_const<P: Field31, const E: usize>(
+        &mut self,
+        c: u32,
+        x: &Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E>;
+
+    /// `x * y + z`.
+    fn f31_ext_mul_add<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+        z: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_square<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: &Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_exp_power_of_2<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        power_log: usize,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_inverse<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E>;
+
+    fn f31_ext_div<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E>;
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderField31Ext<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn f31_ext_constant<P: Field31, const E: usize>(
+        &mut self,
+        value: [u32; E],
+    ) -> Field31ExtTarget<E> {
+        BinomialExtensionField {
+            value: value.map(|v| self.f31_constant::<P>(v)),
+        }
+    }
+
+    fn f31_ext_from_base<const E: usize>(&mut self, x: Target) -> Field31ExtTarget<E> {
+        let zero = self.f31_zero();
+        let mut value = [zero; E];
+        value[0] = x;
+        BinomialExtensionField { value }
+    }
+
+    fn f31_ext_zero<const E: usize>(&mut self) -> Field31ExtTarget<E> {
+        let zero = self.f31_zero();
+        BinomialExtensionField { value: [zero; E] }
+    }
+
+    fn f31_ext_one<const E: usize>(&mut self) -> Field31ExtTarget<E> {
+        let one = self.f31_one();
+        self.f31_ext_from_base(one)
+    }
+
+    fn f31_ext_monomial<const E: usize>(&mut self, exponent: usize) -> Field31ExtTarget<E> {
+        assert!(exponent < E);
+        let mut res = self.f31_ext_zero();
+        res.value[exponent] = self.f31_one();
+        res
+    }
+
+    fn f31_ext_range_check<P: Field31, const E: usize>(&mut self, x: &Field31ExtTarget<E>) {
+        for &limb in &x.value {
+            self.f31_range_check::<P>(limb);
+        }
+    }
+
+    fn connect_f31_ext<const E: usize>(
+        &mut self,
+        x: &Field31ExtTarget<E>,
+        y: &Field31ExtTarget<E>,
+    ) {
+        for (&x, &y) in x.value.iter().zip(&y.value) {
+            self.connect(x, y);
+        }
+    }
+
+    fn f31_ext_if<const E: usize>(
+        &mut self,
+        cond: BoolTarget,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E> {
+        BinomialExtensionField {
+            value: core::array::from_fn(|i| self._if(cond, x.value[i], y.value[i])),
+        }
+    }
+
+    fn f31_ext_add<P: Field31, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E> {
+        BinomialExtensionField {
+            value: core::array::from_fn(|i| self.f31_add::<P>(x.value[i], y.value[i])),
+        }
+    }
+
+    fn f31_ext_add_base<P: Field31, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Target,
+    ) -> Field31ExtTarget<E> {
+        let mut value = x.value;
+        value[0] = self.f31_add::<P>(value[0], y);
+        BinomialExtensionField { value }
+    }
+
+    fn f31_ext_sub<P: Field31, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E> {
+        BinomialExtensionField {
+            value: core::array::from_fn(|i| self.f31_sub::<P>(x.value[i], y.value[i])),
+        }
+    }
+
+    fn f31_ext_sub_base<P: Field31, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Target,
+    ) -> Field31ExtTarget<E> {
+        let mut value = x.value;
+        value[0] = self.f31_sub::<P>(value[0], y);
+        BinomialExtensionField { value }
+    }
+
+    fn f31_ext_neg<P: Field31, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E> {
+        BinomialExtensionField {
+            value: x.value.map(|v| self.f31_neg::<P>(v)),
+        }
+    }
+
+    fn f31_ext_mul<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: &Field31ExtTarget<E>,
+        y: &Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E> {
+        // Reduce the products first, then each coefficient once: the wrapped
+        // around products are scaled by W.
+        let mut terms: [Vec<(u32, Target)>; E] = core::array::from_fn(|_| Vec::new());
+        for i in 0..E {
+            for j in 0..E {
+                let product = self.f31_mul::<P>(x.value[i], y.value[j]);
+                if i + j < E {
+                    terms[i + j].push((1, product));
+                } else {
+                    terms[i + j - E].push((P::W, product));
+                }
+            }
+        }
+        BinomialExtensionField {
+            value: core::array::from_fn(|k| self.f31_linear_combination::<P>(&terms[k])),
+        }
+    }
+
+    fn f31_ext_mul_base<P: Field31, const E: usize>(
+        &mut self,
+        x: &Field31ExtTarget<E>,
+        y: Target,
+    ) -> Field31ExtTarget<E> {
+        BinomialExtensionField {
+            value: x.value.map(|v| self.f31_mul::<P>(v, y)),
+        }
+    }
+
+    fn f31_ext_mul_const<P: Field31, const E: usize>(
+        &mut self,
+        c: u32,
+        x: &Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E> {
+        BinomialExtensionField {
+            value: x.value.map(|v| self.f31_mul_const::<P>(c, v)),
+        }
+    }
+
+    fn f31_ext_mul_add<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+        z: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E> {
+        let x_mul_y = self.f31_ext_mul::<P, E>(&x, &y);
+        self.f31_ext_add::<P, E>(x_mul_y, z)
+    }
+
+    fn f31_ext_square<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: &Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E> {
+        self.f31_ext_mul::<P, E>(x, x)
+    }
+
+    fn f31_ext_exp_power_of_2<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        power_log: usize,
+    ) -> Field31ExtTarget<E> {
+        let mut res = x;
+        for _ in 0..power_log {
+            res = self.f31_ext_square::<P, E>(&res);
+        }
+        res
+    }
+
+    fn f31_ext_inverse<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E> {
+        let inverse = Field31ExtTarget::<E>::add_virtual_to(self);
+        self.add_simple_generator(Field31ExtInverseGenerator::<F, D, P, E> {
+            x: x.value,
+            inverse: inverse.value,
+            _phantom: PhantomData,
+        });
+        self.f31_ext_range_check::<P, E>(&inverse);
+
+        let one = self.f31_ext_one();
+        let x_mul_inverse = self.f31_ext_mul::<P, E>(&x, &inverse);
+        self.connect_f31_ext(&x_mul_inverse, &one);
+
+        inverse
+    }
+
+    fn f31_ext_div<P: BinomialExtension<E>, const E: usize>(
+        &mut self,
+        x: Field31ExtTarget<E>,
+        y: Field31ExtTarget<E>,
+    ) -> Field31ExtTarget<E> {
+        let y_inv = self.f31_ext_inverse::<P, E>(y);
+        self.f31_ext_mul::<P, E>(&x, &y_inv)
+    }
+}
+
+#[derive(Debug)]
+struct Field31ExtInverseGenerator<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    P: BinomialExtension<E>,
+    const E: usize,
+> {
+    x: [Target; E],
+    inverse: [Target; E],
+    _phantom: PhantomData<(F, P)>,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize, P: BinomialExtension<E>, const E: usize>
+    SimpleGenerator<F> for Field31ExtInverseGenerator<F, D, P, E>
+{
+    fn dependencies(&self) -> Vec<Target> {
+        self.x.to_vec()
+    }
+
+    fn run_once(&self, witness: &PartitionWitness<F>, out_buffer: &mut GeneratedValues<F>) {
+        let x = self
+            .x
+            .map(|t| P::reduce(witness.get_target(t).to_canonical_u64()));
+        let inverse = P::ext_inverse(x);
+
+        for (&t, v) in self.inverse.iter().zip(inverse) {
+            out_buffer.set_target(t, F::from_canonical_u32(v));
+        }
+    }
+
+    fn id(&self) -> String {
+        format!("{}ExtInverseGenerator", P::NAME)
+    }
+
+    fn serialize(&self, dst: &mut Vec<u8>) -> IoResult<()> {
+        for &t in self.x.iter().chain(&self.inverse) {
+            dst.write_target(t)?;
+        }
+        Ok(())
+    }
+
+    fn deserialize(src: &mut Buffer) -> IoResult<Self>
+    where
+        Self: Sized,
+    {
+        let mut x = [Target::VirtualTarget { index: 0 }; E];
+        let mut inverse = x;
+        for t in x.iter_mut().chain(inverse.iter_mut()) {
+            *t = src.read_target()?;
+        }
+        Ok(Self {
+            x,
+            inverse,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::iop::witness::WitnessWrite;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use rand::Rng;
+
+    use super::*;
+    use crate::p3::babybear::BabyBear;
+    use crate::p3::babybear::BABYBEAR_EXT_DEGREE;
+
+    #[test]
+    fn test_native_inverse() {
+        let mut rng = rand::thread_rng();
+        let x: [u32; 4] = core::array::from_fn(|_| rng.gen_range(0..BabyBear::ORDER));
+        assert_eq!(BabyBear::ext_mul(x, BabyBear::ext_inverse(x)), [1, 0, 0, 0]);
+        // X^4 = W
+        let x_pow_4 = BabyBear::ext_exp_u128([0, 1, 0, 0], 4);
+        assert_eq!(x_pow_4, [<BabyBear as BinomialExtension<4>>::W, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_quartic_ext_arithmetic() {
+        const D: usize = 2;
+        const E: usize = BABYBEAR_EXT_DEGREE;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let mut rng = rand::thread_rng();
+        let x: [u32; E] = core::array::from_fn(|_| rng.gen_range(0..BabyBear::ORDER));
+        let y: [u32; E] = core::array::from_fn(|_| rng.gen_range(1..BabyBear::ORDER));
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let x_t = Field31ExtTarget::<E>::add_virtual_to(&mut builder);
+        let y_t = Field31ExtTarget::<E>::add_virtual_to(&mut builder);
+        builder.f31_ext_range_check::<BabyBear, E>(&x_t);
+        builder.f31_ext_range_check::<BabyBear, E>(&y_t);
+
+        let product = builder.f31_ext_mul::<BabyBear, E>(&x_t, &y_t);
+        let expected = builder.f31_ext_constant::<BabyBear, E>(BabyBear::ext_mul(x, y));
+        builder.connect_f31_ext(&product, &expected);
+
+        let quotient = builder.f31_ext_div::<BabyBear, E>(x_t.clone(), y_t.clone());
+        let expected =
+            builder.f31_ext_constant::<BabyBear, E>(BabyBear::ext_mul(x, BabyBear::ext_inverse(y)));
+        builder.connect_f31_ext(&quotient, &expected);
+
+        let power = builder.f31_ext_exp_power_of_2::<BabyBear, E>(x_t.clone(), 3);
+        let expected = builder.f31_ext_constant::<BabyBear, E>(BabyBear::ext_exp_u128(x, 8));
+        builder.connect_f31_ext(&power, &expected);
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for i in 0..E {
+            pw.set_target(x_t.value[i], F::from_canonical_u32(x[i]));
+            pw.set_target(y_t.value[i], F::from_canonical_u32(y[i]));
+        }
+        let proof = data.prove(pw).unwrap();
+        assert!(data.verify(proof).is_ok());
+    }
+}
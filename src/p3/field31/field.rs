@@ -0,0 +1,488 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::marker::PhantomData;
+
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::generator::GeneratedValues;
+use plonky2::iop::generator::SimpleGenerator;
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::PartitionWitness;
+use plonky2::iop::witness::Witness;
+use plonky2::iop::witness::WitnessWrite;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::util::serialization::Buffer;
+use plonky2::util::serialization::IoResult;
+use plonky2::util::serialization::Read;
+use plonky2::util::serialization::Write;
+
+use crate::common::richer_field::RicherField;
+
+/// A prime field whose order `p` lies between `2^30` and `2^31`, such as
+/// BabyBear or Mersenne31. The native arithmetic on canonical `u32`s computes
+/// the constants of the circuit and the witnesses of the emulated operations.
+pub trait Field31: 'static + Clone + Debug + Send + Sync {
+    const ORDER: u32;
+    /// Name of the field, prefixing the ids of its witness generators.
+    const NAME: &'static str;
+
+    fn reduce(x: u64) -> u32 {
+        (x % Self::ORDER as u64) as u32
+    }
+
+    fn add(x: u32, y: u32) -> u32 {
+        Self::reduce(x as u64 + y as u64)
+    }
+
+    fn sub(x: u32, y: u32) -> u32 {
+        Self::reduce(x as u64 + Self::ORDER as u64 - Self::reduce(y as u64) as u64)
+    }
+
+    fn mul(x: u32, y: u32) -> u32 {
+        Self::reduce(x as u64 * y as u64)
+    }
+
+    fn exp_u64(x: u32, mut power: u64) -> u32 {
+        let mut base = Self::reduce(x as u64);
+        let mut res = 1;
+        while power > 0 {
+            if power & 1 == 1 {
+                res = Self::mul(res, base);
+            }
+            base = Self::mul(base, base);
+            power >>= 1;
+        }
+        res
+    }
+
+    fn inverse(x: u32) -> u32 {
+        assert_ne!(Self::reduce(x as u64), 0, "zero has no inverse");
+        Self::exp_u64(x, Self::ORDER as u64 - 2)
+    }
+
+    /// `x / 2`.
+    fn halve(x: u32) -> u32 {
+        Self::mul(x, Self::ORDER.div_ceil(2))
+    }
+}
+
+/// A [`Field31`] with a large multiplicative subgroup of order a power of two,
+/// whose cosets are the trace and LDE domains of a two-adic PCS.
+pub trait TwoAdicField31: Field31 {
+    /// Multiplicative generator, the shift of the LDE cosets.
+    const GENERATOR: u32;
+    const TWO_ADICITY: usize;
+    /// Generator of the subgroup of order `2^TWO_ADICITY`.
+    const TWO_ADIC_GENERATOR: u32;
+
+    /// Generator of the subgroup of order `2^bits`.
+    fn two_adic_generator(bits: usize) -> u32 {
+        assert!(bits <= Self::TWO_ADICITY);
+        Self::exp_u64(Self::TWO_ADIC_GENERATOR, 1 << (Self::TWO_ADICITY - bits))
+    }
+}
+
+/// Arithmetic over a [`Field31`] emulated in a Goldilocks circuit. Elements
+/// are plain targets holding canonical values, i.e. less than
+/// [`Field31::ORDER`]. Every operation returns a canonical target, provided
+/// its inputs are.
+pub trait CircuitBuilderField31<F: RicherField + Extendable<D>, const D: usize> {
+    fn f31_constant<P: Field31>(&mut self, value: u32) -> Target;
+
+    fn f31_zero(&mut self) -> Target;
+
+    fn f31_one(&mut self) -> Target;
+
+    /// Constrains `x` to be a canonical element.
+    fn f31_range_check<P: Field31>(&mut self, x: Target);
+
+    /// Reduces `x < 2^bits` modulo the order. `bits` must be at most 62 so
+    /// that the quotient can't wrap around the Goldilocks modulus.
+    fn f31_reduce<P: Field31>(&mut self, x: Target, bits: usize) -> Target;
+
+    fn f31_add<P: Field31>(&mut self, x: Target, y: Target) -> Target;
+
+    fn f31_add_many<P: Field31>(&mut self, terms: &[Target]) -> Target;
+
+    /// `sum c_i * x_i` for small constant coefficients `c_i`, reduced once.
+    fn f31_linear_combination<P: Field31>(&mut self, terms: &[(u32, Target)]) -> Target;
+
+    fn f31_sub<P: Field31>(&mut self, x: Target, y: Target) -> Target;
+
+    fn f31_neg<P: Field31>(&mut self, x: Target) -> Target;
+
+    fn f31_mul<P: Field31>(&mut self, x: Target, y: Target) -> Target;
+
+    fn f31_mul_const<P: Field31>(&mut self, c: u32, x: Target) -> Target;
+
+    /// `x * y + z`.
+    fn f31_mul_add<P: Field31>(&mut self, x: Target, y: Target, z: Target) -> Target;
+
+    /// `x * y - z`.
+    fn f31_mul_sub<P: Field31>(&mut self, x: Target, y: Target, z: Target) -> Target;
+
+    fn f31_square<P: Field31>(&mut self, x: Target) -> Target;
+
+    fn f31_inverse<P: Field31>(&mut self, x: Target) -> Target;
+
+    fn f31_div<P: Field31>(&mut self, x: Target, y: Target) -> Target;
+
+    fn f31_exp_power_of_2<P: Field31>(&mut self, x: Target, power_log: usize) -> Target;
+
+    fn f31_exp_u64<P: Field31>(&mut self, x: Target, power: u64) -> Target;
+
+    /// `base^e` where `e` is given by its little-endian `bits`.
+    fn f31_exp_const_base<P: Field31>(&mut self, base: u32, bits: &[BoolTarget]) -> Target;
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderField31<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn f31_constant<P: Field31>(&mut self, value: u32) -> Target {
+        self.constant(F::from_canonical_u32(P::reduce(value as u64)))
+    }
+
+    fn f31_zero(&mut self) -> Target {
+        self.zero()
+    }
+
+    fn f31_one(&mut self) -> Target {
+        self.one()
+    }
+
+    fn f31_range_check<P: Field31>(&mut self, x: Target) {
+        self.range_check(x, 31);
+        // p - 1 - x only fits in 31 bits if x < p.
+        let max = self.constant(F::from_canonical_u32(P::ORDER - 1));
+        let max_minus_x = self.sub(max, x);
+        self.range_check(max_minus_x, 31);
+    }
+
+    fn f31_reduce<P: Field31>(&mut self, x: Target, bits: usize) -> Target {
+        assert!((31..=62).contains(&bits));
+
+        let quotient = self.add_virtual_target();
+        let remainder = self.add_virtual_target();
+        self.add_simple_generator(Field31ReductionGenerator::<F, D, P> {
+            x,
+            quotient,
+            remainder,
+            _phantom: PhantomData,
+        });
+
+        // x < 2^bits and p > 2^30, so the quotient fits in `bits - 30` bits
+        // and quotient * p + remainder < 2^63 doesn't wrap around.
+        self.range_check(quotient, bits - 30);
+        self.f31_range_check::<P>(remainder);
+
+        let recomposed = self.mul_const_add(F::from_canonical_u32(P::ORDER), quotient, remainder);
+        self.connect(x, recomposed);
+
+        remainder
+    }
+
+    fn f31_add<P: Field31>(&mut self, x: Target, y: Target) -> Target {
+        let sum = self.add(x, y);
+        self.f31_reduce::<P>(sum, 32)
+    }
+
+    fn f31_add_many<P: Field31>(&mut self, terms: &[Target]) -> Target {
+        match terms.len() {
+            0 => self.f31_zero(),
+            1 => terms[0],
+            n => {
+                let sum = self.add_many(terms);
+                let bits = 31 + (usize::BITS - (n - 1).leading_zeros()) as usize;
+                self.f31_reduce::<P>(sum, bits)
+            }
+        }
+    }
+
+    fn f31_linear_combination<P: Field31>(&mut self, terms: &[(u32, Target)]) -> Target {
+        let coeffs_sum = terms.iter().map(|&(c, _)| c as u64).sum::<u64>();
+        let bits = 31 + (u64::BITS - coeffs_sum.saturating_sub(1).leading_zeros()) as usize;
+        assert!(bits <= 62, "coefficients too large to reduce at once");
+
+        let mut sum = self.zero();
+        for &(c, x) in terms {
+            sum = self.mul_const_add(F::from_canonical_u32(c), x, sum);
+        }
+        self.f31_reduce::<P>(sum, bits.max(31))
+    }
+
+    fn f31_sub<P: Field31>(&mut self, x: Target, y: Target) -> Target {
+        let order = F::from_canonical_u32(P::ORDER);
+        let x_plus_order = self.add_const(x, order);
+        let diff = self.sub(x_plus_order, y);
+        self.f31_reduce::<P>(diff, 32)
+    }
+
+    fn f31_neg<P: Field31>(&mut self, x: Target) -> Target {
+        let zero = self.f31_zero();
+        self.f31_sub::<P>(zero, x)
+    }
+
+    fn f31_mul<P: Field31>(&mut self, x: Target, y: Target) -> Target {
+        let product = self.mul(x, y);
+        self.f31_reduce::<P>(product, 62)
+    }
+
+    fn f31_mul_const<P: Field31>(&mut self, c: u32, x: Target) -> Target {
+        let c = F::from_canonical_u32(P::reduce(c as u64));
+        let product = self.mul_const(c, x);
+        self.f31_reduce::<P>(product, 62)
+    }
+
+    fn f31_mul_add<P: Field31>(&mut self, x: Target, y: Target, z: Target) -> Target {
+        // (p - 1)^2 + p - 1 < 2^62
+        let res = self.mul_add(x, y, z);
+        self.f31_reduce::<P>(res, 62)
+    }
+
+    fn f31_mul_sub<P: Field31>(&mut self, x: Target, y: Target, z: Target) -> Target {
+        let order = self.constant(F::from_canonical_u32(P::ORDER));
+        let order_minus_z = self.sub(order, z);
+        self.f31_mul_add::<P>(x, y, order_minus_z)
+    }
+
+    fn f31_square<P: Field31>(&mut self, x: Target) -> Target {
+        self.f31_mul::<P>(x, x)
+    }
+
+    fn f31_inverse<P: Field31>(&mut self, x: Target) -> Target {
+        let inverse = self.add_virtual_target();
+        self.add_simple_generator(Field31InverseGenerator::<F, D, P> {
+            x,
+            inverse,
+            _phantom: PhantomData,
+        });
+        self.f31_range_check::<P>(inverse);
+
+        let one = self.f31_one();
+        let x_mul_inverse = self.f31_mul::<P>(x, inverse);
+        self.connect(x_mul_inverse, one);
+
+        inverse
+    }
+
+    fn f31_div<P: Field31>(&mut self, x: Target, y: Target) -> Target {
+        let y_inv = self.f31_inverse::<P>(y);
+        self.f31_mul::<P>(x, y_inv)
+    }
+
+    fn f31_exp_power_of_2<P: Field31>(&mut self, x: Target, power_log: usize) -> Target {
+        let mut res = x;
+        for _ in 0..power_log {
+            res = self.f31_square::<P>(res);
+        }
+        res
+    }
+
+    fn f31_exp_u64<P: Field31>(&mut self, x: Target, power: u64) -> Target {
+        if power == 0 {
+            return self.f31_one();
+        }
+        // Start from the leading bit rather than squaring one.
+        let mut res = x;
+        for i in (0..u64::BITS - 1 - power.leading_zeros()).rev() {
+            res = self.f31_square::<P>(res);
+            if (power >> i) & 1 == 1 {
+                res = self.f31_mul::<P>(res, x);
+            }
+        }
+        res
+    }
+
+    fn f31_exp_const_base<P: Field31>(&mut self, base: u32, bits: &[BoolTarget]) -> Target {
+        let mut res = self.f31_one();
+        let mut base_pow = base;
+        for &bit in bits {
+            let one = self.f31_one();
+            let base_pow_target = self.f31_constant::<P>(base_pow);
+            let factor = self._if(bit, base_pow_target, one);
+            res = self.f31_mul::<P>(res, factor);
+            base_pow = P::mul(base_pow, base_pow);
+        }
+        res
+    }
+}
+
+#[derive(Debug)]
+struct Field31ReductionGenerator<F: RichField + Extendable<D>, const D: usize, P: Field31> {
+    x: Target,
+    quotient: Target,
+    remainder: Target,
+    _phantom: PhantomData<(F, P)>,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize, P: Field31> SimpleGenerator<F>
+    for Field31ReductionGenerator<F, D, P>
+{
+    fn dependencies(&self) -> Vec<Target> {
+        vec![self.x]
+    }
+
+    fn run_once(&self, witness: &PartitionWitness<F>, out_buffer: &mut GeneratedValues<F>) {
+        let x = witness.get_target(self.x).to_canonical_u64();
+        let order = P::ORDER as u64;
+
+        out_buffer.set_target(self.quotient, F::from_canonical_u64(x / order));
+        out_buffer.set_target(self.remainder, F::from_canonical_u64(x % order));
+    }
+
+    fn id(&self) -> String {
+        format!("{}ReductionGenerator", P::NAME)
+    }
+
+    fn serialize(&self, dst: &mut Vec<u8>) -> IoResult<()> {
+        dst.write_target(self.x)?;
+        dst.write_target(self.quotient)?;
+        dst.write_target(self.remainder)
+    }
+
+    fn deserialize(src: &mut Buffer) -> IoResult<Self>
+    where
+        Self: Sized,
+    {
+        let x = src.read_target()?;
+        let quotient = src.read_target()?;
+        let remainder = src.read_target()?;
+        Ok(Self {
+            x,
+            quotient,
+            remainder,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Field31InverseGenerator<F: RichField + Extendable<D>, const D: usize, P: Field31> {
+    x: Target,
+    inverse: Target,
+    _phantom: PhantomData<(F, P)>,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize, P: Field31> SimpleGenerator<F>
+    for Field31InverseGenerator<F, D, P>
+{
+    fn dependencies(&self) -> Vec<Target> {
+        vec![self.x]
+    }
+
+    fn run_once(&self, witness: &PartitionWitness<F>, out_buffer: &mut GeneratedValues<F>) {
+        let x = P::reduce(witness.get_target(self.x).to_canonical_u64());
+
+        out_buffer.set_target(self.inverse, F::from_canonical_u32(P::inverse(x)));
+    }
+
+    fn id(&self) -> String {
+        format!("{}InverseGenerator", P::NAME)
+    }
+
+    fn serialize(&self, dst: &mut Vec<u8>) -> IoResult<()> {
+        dst.write_target(self.x)?;
+        dst.write_target(self.inverse)
+    }
+
+    fn deserialize(src: &mut Buffer) -> IoResult<Self>
+    where
+        Self: Sized,
+    {
+        let x = src.read_target()?;
+        let inverse = src.read_target()?;
+        Ok(Self {
+            x,
+            inverse,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::iop::witness::WitnessWrite;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use rand::Rng;
+
+    use super::*;
+    use crate::p3::babybear::BabyBear;
+
+    #[test]
+    fn test_native_constants() {
+        let g = BabyBear::TWO_ADIC_GENERATOR;
+        assert_eq!(BabyBear::exp_u64(g, 1 << 27), 1);
+        assert_ne!(BabyBear::exp_u64(g, 1 << 26), 1);
+        assert_eq!(BabyBear::two_adic_generator(1), BabyBear::ORDER - 1);
+        assert_eq!(BabyBear::mul(BabyBear::halve(7), 2), 7);
+    }
+
+    #[test]
+    fn test_emulated_arithmetic() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let mut rng = rand::thread_rng();
+        let x = rng.gen_range(1..BabyBear::ORDER);
+        let y = rng.gen_range(1..BabyBear::ORDER);
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let x_t = builder.add_virtual_target();
+        let y_t = builder.add_virtual_target();
+        builder.f31_range_check::<BabyBear>(x_t);
+        builder.f31_range_check::<BabyBear>(y_t);
+
+        let sum = builder.f31_add::<BabyBear>(x_t, y_t);
+        let diff = builder.f31_sub::<BabyBear>(x_t, y_t);
+        let product = builder.f31_mul::<BabyBear>(x_t, y_t);
+        let quotient = builder.f31_div::<BabyBear>(x_t, y_t);
+        let power = builder.f31_exp_u64::<BabyBear>(x_t, 1_000_003);
+
+        let expected = [
+            BabyBear::add(x, y),
+            BabyBear::sub(x, y),
+            BabyBear::mul(x, y),
+            BabyBear::mul(x, BabyBear::inverse(y)),
+            BabyBear::exp_u64(x, 1_000_003),
+        ];
+        for (res, expected) in [sum, diff, product, quotient, power]
+            .into_iter()
+            .zip(expected)
+        {
+            let expected = builder.constant(F::from_canonical_u32(expected));
+            builder.connect(res, expected);
+        }
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(x_t, F::from_canonical_u32(x));
+        pw.set_target(y_t, F::from_canonical_u32(y));
+        let proof = data.prove(pw).unwrap();
+        assert!(data.verify(proof).is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rejects_non_canonical() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let x = builder.add_virtual_target();
+        builder.f31_range_check::<BabyBear>(x);
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        pw.set_target(x, F::from_canonical_u32(BabyBear::ORDER));
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
+}
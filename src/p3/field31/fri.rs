@@ -0,0 +1,246 @@
+use itertools::izip;
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::challenger::DuplexChallenger;
+use crate::p3::challenger::DuplexChallengerTarget;
+use crate::p3::commit::MerkleTreeMmcs;
+use crate::p3::commit::P3Mmcs;
+use crate::p3::field31::extension::BinomialExtension;
+use crate::p3::field31::extension::CircuitBuilderField31Ext;
+use crate::p3::field31::extension::Field31ExtTarget;
+use crate::p3::field31::field::CircuitBuilderField31;
+use crate::p3::field31::poseidon2::Field31Poseidon2;
+use crate::p3::field31::poseidon2::Poseidon2Field31;
+use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::fri::FriError;
+use crate::p3::serde::proof::CommitPhaseProofStep;
+use crate::p3::serde::proof::Commitment;
+use crate::p3::serde::proof::FriProof;
+use crate::p3::serde::Dimensions;
+use crate::p3::verifier::P3VerifierError;
+
+/// Reduced openings of the inputs of one FRI query, as `(log_height,
+/// reduced_opening)` pairs, tallest first.
+pub type ReducedOpenings<const E: usize> = Vec<(usize, Field31ExtTarget<E>)>;
+
+/// A FRI flavour over a 31-bit field folding pairs of rows, i.e. the
+/// `FriGenericConfig` of Plonky3 along with the config and MMCS of its commit
+/// phase. Every round folds the evaluations at a pair of points whose
+/// coordinates are `{x, -x}`, so flavours only differ in how they map rows to
+/// coordinates.
+pub trait Field31Fri<P: Poseidon2Field31> {
+    /// Bits of the query index below those of the tallest commit phase
+    /// codeword, which only the input openings read.
+    const EXTRA_QUERY_INDEX_BITS: usize;
+
+    fn fri_config(&self) -> &FriConfig;
+
+    fn mmcs(&self) -> &MerkleTreeMmcs<Field31Poseidon2<P>>;
+
+    /// The coordinate `x` of the row of the tallest commit phase codeword at
+    /// the little-endian `index_bits`, which folds with the row at `-x`.
+    fn query_coordinate<F: RicherField + Extendable<D>, const D: usize>(
+        cb: &mut CircuitBuilder<F, D>,
+        index_bits: &[BoolTarget],
+    ) -> Target;
+
+    /// The coordinate of the row the pair at `{x, -x}` folds into.
+    fn fold_coordinate<F: RicherField + Extendable<D>, const D: usize>(
+        cb: &mut CircuitBuilder<F, D>,
+        x: Target,
+    ) -> Target;
+}
+
+/// Verifies the commit phase of `proof`. `open_input` checks the input proof
+/// of a query against the committed batches and reduces its openings, given
+/// the little-endian bits of the query index, all
+/// [`EXTRA_QUERY_INDEX_BITS`](Field31Fri::EXTRA_QUERY_INDEX_BITS) included.
+pub fn f31_verify_fri<
+    F: RicherField + Extendable<D>,
+    const D: usize,
+    P: Poseidon2Field31 + BinomialExtension<E>,
+    const E: usize,
+    G: Field31Fri<P>,
+    I,
+>(
+    cb: &mut CircuitBuilder<F, D>,
+    fri: &G,
+    proof: &FriProof<Target, E>,
+    input_proofs: &[I],
+    challenger: &mut DuplexChallengerTarget<Field31Poseidon2<P>>,
+    mut open_input: impl FnMut(
+        &mut CircuitBuilder<F, D>,
+        &[BoolTarget],
+        &I,
+    ) -> Result<ReducedOpenings<E>, P3VerifierError>,
+) -> Result<(), P3VerifierError> {
+    let config = fri.fri_config();
+
+    let betas: Vec<Field31ExtTarget<E>> = proof
+        .commit_phase_commits
+        .iter()
+        .map(|comm| {
+            cb.p3_observe::<Field31Poseidon2<P>>(challenger, comm.value.iter().copied());
+            cb.p3_sample_ext::<Field31Poseidon2<P>, E>(challenger)
+        })
+        .collect();
+
+    // The constant final polynomial only enters the transcript from the
+    // Plonky3 versions configuring its length.
+    if proof.final_poly.len() != 1 {
+        return Err(FriError::InvalidProofShape.into());
+    }
+    if config.log_final_poly_len.is_some() {
+        cb.p3_observe::<Field31Poseidon2<P>>(challenger, proof.final_poly[0].value);
+    }
+
+    if proof.query_proofs.len() != config.num_queries || input_proofs.len() != config.num_queries {
+        return Err(P3VerifierError::QueryCountMismatch {
+            expected: config.num_queries,
+            actual: proof.query_proofs.len(),
+        });
+    }
+
+    let always = cb._true();
+    cb.p3_check_witness::<Field31Poseidon2<P>>(
+        challenger,
+        config.proof_of_work_bits,
+        proof.pow_witness,
+        always,
+    );
+
+    let log_max_height = proof.commit_phase_commits.len() + config.log_blowup;
+    let index_len = log_max_height + G::EXTRA_QUERY_INDEX_BITS;
+
+    for (query_proof, input_proof) in izip!(&proof.query_proofs, input_proofs) {
+        let index = cb.p3_sample_bits::<Field31Poseidon2<P>>(challenger, index_len);
+        let index_bits = cb.split_le(index, index_len);
+
+        let reduced_openings = open_input(cb, &index_bits, input_proof)?;
+
+        let folded_eval = f31_verify_query::<F, D, P, E, G>(
+            cb,
+            fri,
+            &proof.commit_phase_commits,
+            &index_bits[G::EXTRA_QUERY_INDEX_BITS..],
+            &query_proof.commit_phase_openings,
+            &betas,
+            reduced_openings,
+        )?;
+
+        cb.connect_f31_ext(&folded_eval, &proof.final_poly[0]);
+    }
+
+    Ok(())
+}
+
+/// Folds the reduced openings of one query down to the final polynomial,
+/// `index_bits` being the little-endian bits of the query index in the
+/// tallest commit phase codeword.
+fn f31_verify_query<
+    F: RicherField + Extendable<D>,
+    const D: usize,
+    P: Poseidon2Field31 + BinomialExtension<E>,
+    const E: usize,
+    G: Field31Fri<P>,
+>(
+    cb: &mut CircuitBuilder<F, D>,
+    fri: &G,
+    commit_phase_commits: &[Commitment<Target>],
+    index_bits: &[BoolTarget],
+    commit_phase_openings: &[CommitPhaseProofStep<Target, E>],
+    betas: &[Field31ExtTarget<E>],
+    reduced_openings: ReducedOpenings<E>,
+) -> Result<Field31ExtTarget<E>, P3VerifierError> {
+    if commit_phase_openings.len() != commit_phase_commits.len()
+        || betas.len() != commit_phase_commits.len()
+        || commit_phase_openings
+            .iter()
+            .any(|step| step.sibling_values.len() != 1)
+    {
+        return Err(FriError::InvalidProofShape.into());
+    }
+    let log_max_height = index_bits.len();
+
+    let mut reduced_openings = reduced_openings.into_iter().peekable();
+    let mut folded_eval = cb.f31_ext_zero();
+    let mut x = G::query_coordinate(cb, index_bits);
+
+    for (i, (commit, step, beta)) in
+        izip!(commit_phase_commits, commit_phase_openings, betas).enumerate()
+    {
+        let log_folded_height = log_max_height - i - 1;
+        if let Some((_, ro)) =
+            reduced_openings.next_if(|(log_height, _)| *log_height == log_folded_height + 1)
+        {
+            folded_eval = cb.f31_ext_add::<P, E>(folded_eval, ro);
+        }
+
+        // The current index is the original one shifted right by `i`, so
+        // its parity is bit `i`.
+        let is_odd = index_bits[i];
+        let sibling = step.sibling_values[0].clone();
+        let evals = [
+            cb.f31_ext_if(is_odd, sibling.clone(), folded_eval.clone()),
+            cb.f31_ext_if(is_odd, folded_eval, sibling),
+        ];
+
+        let dims = &[Dimensions {
+            width: 2 * E,
+            height: 1 << log_folded_height,
+        }];
+        let parent_index = cb.le_sum(index_bits[i + 1..].iter());
+        let always = cb._true();
+        fri.mmcs()
+            .verify_batch(
+                &commit.value,
+                dims,
+                parent_index,
+                &[evals.iter().flat_map(|e| e.value).collect()],
+                &step.opening_proof,
+                always,
+                cb,
+            )
+            .map_err(P3VerifierError::CommitPhaseMmcs)?;
+
+        // The pair of points is {x, -x}, ordered by the parity of the index.
+        let x_neg = cb.f31_neg::<P>(x);
+        let x_even = cb._if(is_odd, x_neg, x);
+        folded_eval = f31_fold_pair::<F, D, P, E>(cb, &evals, beta, x_even);
+
+        x = G::fold_coordinate(cb, x);
+    }
+
+    // Every input is rolled in before the final polynomial.
+    if reduced_openings.next().is_some() {
+        return Err(FriError::InvalidProofShape.into());
+    }
+
+    Ok(folded_eval)
+}
+
+/// `(e0 + e1) / 2 + beta * (e0 - e1) / (2 * t)`, the fold of the evaluations
+/// `e0` at `t` and `e1` at `-t`.
+pub fn f31_fold_pair<
+    F: RicherField + Extendable<D>,
+    const D: usize,
+    P: BinomialExtension<E>,
+    const E: usize,
+>(
+    cb: &mut CircuitBuilder<F, D>,
+    evals: &[Field31ExtTarget<E>; 2],
+    beta: &Field31ExtTarget<E>,
+    t: Target,
+) -> Field31ExtTarget<E> {
+    let sum = cb.f31_ext_add::<P, E>(evals[0].clone(), evals[1].clone());
+    let diff = cb.f31_ext_sub::<P, E>(evals[0].clone(), evals[1].clone());
+    let t_inv = cb.f31_inverse::<P>(t);
+    let diff_div_t = cb.f31_ext_mul_base::<P, E>(&diff, t_inv);
+    let beta_mul_diff_div_t = cb.f31_ext_mul::<P, E>(beta, &diff_div_t);
+    let folded = cb.f31_ext_add::<P, E>(sum, beta_mul_diff_div_t);
+    cb.f31_ext_mul_const::<P, E>(P::halve(1), &folded)
+}
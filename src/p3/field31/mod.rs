@@ -0,0 +1,14 @@
+//! Verification of Plonky3 proofs over 31-bit fields, generic over the field.
+//! Elements are emulated as canonical values held in Goldilocks targets, see
+//! [`field::CircuitBuilderField31`], and the Poseidon2 permutation, AIR
+//! folding, FRI and PCS of the standard 31-bit configs are shared by the
+//! fields, which only provide their constants and evaluation domains.
+
+pub mod air;
+pub mod domain;
+pub mod extension;
+pub mod field;
+pub mod fri;
+pub mod pcs;
+pub mod poseidon2;
+pub mod verifier;
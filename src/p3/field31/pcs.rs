@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+
+use itertools::izip;
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::challenger::DuplexChallenger;
+use crate::p3::challenger::DuplexChallengerTarget;
+use crate::p3::commit::MerkleTreeMmcs;
+use crate::p3::commit::MmcsError;
+use crate::p3::commit::P3Mmcs;
+use crate::p3::field31::domain::Field31Domain;
+use crate::p3::field31::domain::TwoAdicCoset;
+use crate::p3::field31::extension::BinomialExtension;
+use crate::p3::field31::extension::CircuitBuilderField31Ext;
+use crate::p3::field31::extension::Field31ExtTarget;
+use crate::p3::field31::field::CircuitBuilderField31;
+use crate::p3::field31::field::TwoAdicField31;
+use crate::p3::field31::fri::f31_verify_fri;
+use crate::p3::field31::fri::Field31Fri;
+use crate::p3::field31::poseidon2::Field31Poseidon2;
+use crate::p3::field31::poseidon2::Poseidon2Field31;
+use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::proof::Commitment;
+use crate::p3::serde::proof::TwoAdicFriPcsProof;
+use crate::p3::serde::Dimensions;
+use crate::p3::verifier::P3VerifierError;
+
+/// Matrices of one committed batch, each with the points it is opened at and
+/// its values there.
+pub type BatchPoints<Domain, const E: usize> =
+    Vec<(Domain, Vec<(Field31ExtTarget<E>, Vec<Field31ExtTarget<E>>)>)>;
+
+/// A polynomial commitment scheme over a 31-bit field `P`, opened at points
+/// of its degree `E` extension, i.e. `Pcs` in Plonky3.
+pub trait Field31Pcs<P: Poseidon2Field31 + BinomialExtension<E>, const E: usize> {
+    type Domain: Field31Domain<P, E>;
+    /// Opening proof, as targets.
+    type Proof;
+
+    fn natural_domain_for_degree(&self, degree: usize) -> Self::Domain;
+
+    /// Checks that the batches committed to by the commitments of `rounds`
+    /// take the given values at the given points.
+    fn verify<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        rounds: Vec<(Commitment<Target>, BatchPoints<Self::Domain, E>)>,
+        proof: &Self::Proof,
+        challenger: &mut DuplexChallengerTarget<Field31Poseidon2<P>>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Result<(), P3VerifierError>;
+}
+
+/// `TwoAdicFriPcs` over a [`TwoAdicField31`], committing to LDEs over cosets
+/// of its two-adic subgroups.
+pub struct TwoAdicFriPcs<P: Poseidon2Field31> {
+    pub fri_config: FriConfig,
+    pub mmcs: MerkleTreeMmcs<Field31Poseidon2<P>>,
+}
+
+impl<P: Poseidon2Field31 + TwoAdicField31> Field31Fri<P> for TwoAdicFriPcs<P> {
+    const EXTRA_QUERY_INDEX_BITS: usize = 0;
+
+    fn fri_config(&self) -> &FriConfig {
+        &self.fri_config
+    }
+
+    fn mmcs(&self) -> &MerkleTreeMmcs<Field31Poseidon2<P>> {
+        &self.mmcs
+    }
+
+    fn query_coordinate<F: RicherField + Extendable<D>, const D: usize>(
+        cb: &mut CircuitBuilder<F, D>,
+        index_bits: &[BoolTarget],
+    ) -> Target {
+        let rev_index = index_bits.iter().rev().copied().collect::<Vec<_>>();
+        cb.f31_exp_const_base::<P>(P::two_adic_generator(index_bits.len()), &rev_index)
+    }
+
+    fn fold_coordinate<F: RicherField + Extendable<D>, const D: usize>(
+        cb: &mut CircuitBuilder<F, D>,
+        x: Target,
+    ) -> Target {
+        cb.f31_square::<P>(x)
+    }
+}
+
+impl<P: Poseidon2Field31 + TwoAdicField31 + BinomialExtension<E>, const E: usize> Field31Pcs<P, E>
+    for TwoAdicFriPcs<P>
+{
+    type Domain = TwoAdicCoset<P>;
+    type Proof = TwoAdicFriPcsProof<Target, E>;
+
+    fn natural_domain_for_degree(&self, degree: usize) -> TwoAdicCoset<P> {
+        TwoAdicCoset::natural_domain_for_degree(degree)
+    }
+
+    fn verify<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        rounds: Vec<(Commitment<Target>, BatchPoints<TwoAdicCoset<P>, E>)>,
+        proof: &TwoAdicFriPcsProof<Target, E>,
+        challenger: &mut DuplexChallengerTarget<Field31Poseidon2<P>>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Result<(), P3VerifierError> {
+        // Batch combination challenge.
+        let alpha = cb.p3_sample_ext::<Field31Poseidon2<P>, E>(challenger);
+
+        let log_blowup = self.fri_config.log_blowup;
+        let log_global_max_height = proof.fri_proof.commit_phase_commits.len() + log_blowup;
+
+        f31_verify_fri::<F, D, P, E, Self, _>(
+            cb,
+            self,
+            &proof.fri_proof,
+            &proof.query_openings,
+            challenger,
+            |cb, index_bits, query_opening| {
+                if query_opening.len() != rounds.len() {
+                    return Err(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize));
+                }
+
+                // Openings are reduced per height, each with its own powers of
+                // alpha.
+                let mut reduced_openings = BTreeMap::new();
+                for (batch_opening, (batch_commit, mats)) in izip!(query_opening, &rounds) {
+                    if batch_opening.opened_values.len() != mats.len() {
+                        return Err(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize));
+                    }
+                    let log_batch_max_height = mats
+                        .iter()
+                        .map(|(domain, _)| domain.log_n + log_blowup)
+                        .max()
+                        .ok_or(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize))?;
+                    if log_batch_max_height > log_global_max_height {
+                        return Err(P3VerifierError::BatchMmcs(MmcsError::WrongHeight));
+                    }
+                    let bits_reduced = log_global_max_height - log_batch_max_height;
+                    let reduced_index = cb.le_sum(index_bits[bits_reduced..].iter());
+
+                    let batch_dims: Vec<Dimensions> = mats
+                        .iter()
+                        .map(|(domain, _)| Dimensions {
+                            width: 0,
+                            height: 1 << (domain.log_n + log_blowup),
+                        })
+                        .collect();
+                    let always = cb._true();
+                    self.mmcs
+                        .verify_batch(
+                            &batch_commit.value,
+                            &batch_dims,
+                            reduced_index,
+                            &batch_opening.opened_values,
+                            &batch_opening.opening_proof,
+                            always,
+                            cb,
+                        )
+                        .map_err(P3VerifierError::BatchMmcs)?;
+
+                    for (mat_opening, (mat_domain, mat_points_and_values)) in
+                        izip!(&batch_opening.opened_values, mats)
+                    {
+                        let log_height = mat_domain.log_n + log_blowup;
+                        let bits_reduced = log_global_max_height - log_height;
+                        let rev_reduced_index = index_bits[bits_reduced..]
+                            .iter()
+                            .rev()
+                            .copied()
+                            .collect::<Vec<_>>();
+                        let two_adic_generator_powers_rev_reduced_index = cb
+                            .f31_exp_const_base::<P>(
+                                P::two_adic_generator(log_height),
+                                &rev_reduced_index,
+                            );
+                        let x = cb.f31_mul_const::<P>(
+                            P::GENERATOR,
+                            two_adic_generator_powers_rev_reduced_index,
+                        );
+
+                        let (alpha_pow, ro) = reduced_openings
+                            .entry(log_height)
+                            .or_insert_with(|| (cb.f31_ext_one(), cb.f31_ext_zero()));
+                        for (z, ps_at_z) in mat_points_and_values {
+                            if ps_at_z.len() != mat_opening.len() {
+                                return Err(P3VerifierError::BatchMmcs(MmcsError::WrongWidth));
+                            }
+                            let z_neg = cb.f31_ext_neg::<P, E>(z.clone());
+                            let x_minus_z = cb.f31_ext_add_base::<P, E>(z_neg, x);
+                            let x_minus_z_inv = cb.f31_ext_inverse::<P, E>(x_minus_z);
+
+                            for (&p_at_x, p_at_z) in izip!(mat_opening, ps_at_z) {
+                                let p_at_z_neg = cb.f31_ext_neg::<P, E>(p_at_z.clone());
+                                let p_at_x_minus_p_at_z =
+                                    cb.f31_ext_add_base::<P, E>(p_at_z_neg, p_at_x);
+                                let quotient =
+                                    cb.f31_ext_mul::<P, E>(&p_at_x_minus_p_at_z, &x_minus_z_inv);
+                                let alpha_pow_mul_quotient =
+                                    cb.f31_ext_mul::<P, E>(alpha_pow, &quotient);
+                                *ro = cb.f31_ext_add::<P, E>(ro.clone(), alpha_pow_mul_quotient);
+                                *alpha_pow = cb.f31_ext_mul::<P, E>(alpha_pow, &alpha);
+                            }
+                        }
+                    }
+                }
+
+                Ok(reduced_openings
+                    .into_iter()
+                    .rev()
+                    .map(|(log_height, (_, ro))| (log_height, ro))
+                    .collect())
+            },
+        )
+    }
+}
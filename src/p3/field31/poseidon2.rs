@@ -0,0 +1,289 @@
+use core::marker::PhantomData;
+
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::field31::field::CircuitBuilderField31;
+use crate::p3::field31::field::Field31;
+use crate::p3::permutation::P3Permutation;
+use crate::p3::permutation::P3PermutationParams;
+
+/// State width of the Poseidon2 permutations of the standard 31-bit configs.
+pub const POSEIDON2_WIDTH: usize = 16;
+
+/// The 4x4 MDS matrix of the external layer, as in `apply_mat4`.
+const M4: [[u32; 4]; 4] = [[2, 3, 1, 1], [1, 2, 3, 1], [1, 1, 2, 3], [3, 1, 1, 2]];
+
+/// A [`Field31`] with the constants of its width 16 Poseidon2 permutation that
+/// are fixed by the field rather than sampled by the config.
+pub trait Poseidon2Field31: Field31 {
+    /// Degree of the S-box `x |--> x^SBOX_DEGREE`.
+    const SBOX_DEGREE: u64;
+    /// Diagonal of the internal matrix minus the identity, so that before
+    /// scaling the internal layer maps `x_i` to `d_i * x_i + sum(x)`.
+    const INTERNAL_DIAG_M_1: [u32; POSEIDON2_WIDTH];
+    /// Factor the internal layer multiplies its whole output by, the inverse
+    /// of the Montgomery radix for fields Plonky3 keeps in Montgomery form.
+    const INTERNAL_SCALE: u32;
+}
+
+/// Round constants of the width 16 Poseidon2 permutation over `P`, as
+/// canonical values in `[0, P::ORDER)`, not in the Montgomery form some fields
+/// are stored in. Plonky3 samples them from an RNG when setting up its config,
+/// so they have to be supplied by whoever produced the proofs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Poseidon2Params<P> {
+    /// One row per full round, the first half applied before the partial
+    /// rounds and the second half after them.
+    pub external_constants: Vec<[u32; POSEIDON2_WIDTH]>,
+    /// One constant per partial round, added to the first element.
+    pub internal_constants: Vec<u32>,
+    #[serde(skip)]
+    _field: PhantomData<P>,
+}
+
+impl<P: Poseidon2Field31> Poseidon2Params<P> {
+    pub fn new(
+        external_constants: Vec<[u32; POSEIDON2_WIDTH]>,
+        internal_constants: Vec<u32>,
+    ) -> Self {
+        Self {
+            external_constants,
+            internal_constants,
+            _field: PhantomData,
+        }
+    }
+
+    fn num_begin_rounds(&self) -> usize {
+        assert_eq!(
+            self.external_constants.len() % 2,
+            0,
+            "the number of full rounds must be even"
+        );
+        self.external_constants.len() / 2
+    }
+
+    /// Native permutation, matching
+    /// [`CircuitBuilderPoseidon2Field31::f31_poseidon2_permute`].
+    pub fn permute(&self, mut state: [u32; POSEIDON2_WIDTH]) -> [u32; POSEIDON2_WIDTH] {
+        fn external_layer<P: Field31>(state: &mut [u32; POSEIDON2_WIDTH]) {
+            let input = *state;
+            for (i, s) in state.iter_mut().enumerate() {
+                *s = (0..POSEIDON2_WIDTH).fold(0, |acc, j| {
+                    P::add(acc, P::mul(external_coeff(i, j), input[j]))
+                });
+            }
+        }
+
+        let sbox = |x: u32| P::exp_u64(x, P::SBOX_DEGREE);
+        let begin_rounds = self.num_begin_rounds();
+        external_layer::<P>(&mut state);
+        for rc in &self.external_constants[..begin_rounds] {
+            for (s, &c) in state.iter_mut().zip(rc) {
+                *s = sbox(P::add(*s, c));
+            }
+            external_layer::<P>(&mut state);
+        }
+        for &rc in &self.internal_constants {
+            state[0] = sbox(P::add(state[0], rc));
+            let sum = state.iter().fold(0, |acc, &s| P::add(acc, s));
+            for (s, &d) in state.iter_mut().zip(&P::INTERNAL_DIAG_M_1) {
+                *s = P::mul(P::add(P::mul(*s, d), sum), P::INTERNAL_SCALE);
+            }
+        }
+        for rc in &self.external_constants[begin_rounds..] {
+            for (s, &c) in state.iter_mut().zip(rc) {
+                *s = sbox(P::add(*s, c));
+            }
+            external_layer::<P>(&mut state);
+        }
+        state
+    }
+}
+
+/// Entry `(i, j)` of the external matrix `circ(2 * M4, M4, M4, M4)`.
+fn external_coeff(i: usize, j: usize) -> u32 {
+    let m4 = M4[i % 4][j % 4];
+    if i / 4 == j / 4 {
+        2 * m4
+    } else {
+        m4
+    }
+}
+
+pub trait CircuitBuilderPoseidon2Field31<F: RicherField + Extendable<D>, const D: usize> {
+    fn f31_poseidon2_permute<P: Poseidon2Field31>(
+        &mut self,
+        state: [Target; POSEIDON2_WIDTH],
+        params: &Poseidon2Params<P>,
+    ) -> [Target; POSEIDON2_WIDTH];
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderPoseidon2Field31<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn f31_poseidon2_permute<P: Poseidon2Field31>(
+        &mut self,
+        mut state: [Target; POSEIDON2_WIDTH],
+        params: &Poseidon2Params<P>,
+    ) -> [Target; POSEIDON2_WIDTH] {
+        let begin_rounds = params.num_begin_rounds();
+
+        state = f31_external_layer::<F, D, P>(self, state);
+        for rc in &params.external_constants[..begin_rounds] {
+            for (s, &c) in state.iter_mut().zip(rc) {
+                let c = self.f31_constant::<P>(c);
+                let s_plus_c = self.f31_add::<P>(*s, c);
+                *s = self.f31_exp_u64::<P>(s_plus_c, P::SBOX_DEGREE);
+            }
+            state = f31_external_layer::<F, D, P>(self, state);
+        }
+
+        for &rc in &params.internal_constants {
+            let c = self.f31_constant::<P>(rc);
+            let s_plus_c = self.f31_add::<P>(state[0], c);
+            state[0] = self.f31_exp_u64::<P>(s_plus_c, P::SBOX_DEGREE);
+
+            // scale * (d_i * x_i + sum), with both products reduced at once.
+            let sum = self.f31_add_many::<P>(&state);
+            let scaled_sum = self.f31_mul_const::<P>(P::INTERNAL_SCALE, sum);
+            for (s, &d) in state.iter_mut().zip(&P::INTERNAL_DIAG_M_1) {
+                let scaled_d = P::mul(d, P::INTERNAL_SCALE);
+                *s = self.f31_linear_combination::<P>(&[(scaled_d, *s), (1, scaled_sum)]);
+            }
+        }
+
+        for rc in &params.external_constants[begin_rounds..] {
+            for (s, &c) in state.iter_mut().zip(rc) {
+                let c = self.f31_constant::<P>(c);
+                let s_plus_c = self.f31_add::<P>(*s, c);
+                *s = self.f31_exp_u64::<P>(s_plus_c, P::SBOX_DEGREE);
+            }
+            state = f31_external_layer::<F, D, P>(self, state);
+        }
+
+        state
+    }
+}
+
+/// The Poseidon2 permutation over `P` as the [`P3Permutation`] of the
+/// challenger and MMCS of a 31-bit config, instantiated with its
+/// [`Poseidon2Params`].
+pub struct Field31Poseidon2<P>(PhantomData<P>);
+
+impl<P: Poseidon2Field31> P3PermutationParams for Field31Poseidon2<P> {
+    type Params = Poseidon2Params<P>;
+}
+
+impl<F: RicherField, P: Poseidon2Field31> P3Permutation<F> for Field31Poseidon2<P> {
+    fn width(_params: &Poseidon2Params<P>) -> usize {
+        POSEIDON2_WIDTH
+    }
+
+    fn permute_targets<const D: usize>(
+        params: &Poseidon2Params<P>,
+        state: &[Target],
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Vec<Target>
+    where
+        F: Extendable<D>,
+    {
+        let state = state.try_into().expect("state of the permutation width");
+        cb.f31_poseidon2_permute(state, params).to_vec()
+    }
+}
+
+fn f31_external_layer<F: RicherField + Extendable<D>, const D: usize, P: Field31>(
+    cb: &mut CircuitBuilder<F, D>,
+    state: [Target; POSEIDON2_WIDTH],
+) -> [Target; POSEIDON2_WIDTH] {
+    core::array::from_fn(|i| {
+        let terms = (0..POSEIDON2_WIDTH)
+            .map(|j| (external_coeff(i, j), state[j]))
+            .collect::<Vec<_>>();
+        cb.f31_linear_combination::<P>(&terms)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::iop::witness::WitnessWrite;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::p3::babybear::BabyBear;
+    use crate::p3::mersenne31::Mersenne31;
+
+    /// The permutation of the config `proof_babybear_fibonacci.json` was
+    /// proven with, and its image of `[0, 1, ..., 15]`.
+    fn babybear_test_vector() -> (
+        Poseidon2Params<BabyBear>,
+        [u32; POSEIDON2_WIDTH],
+        [u32; POSEIDON2_WIDTH],
+    ) {
+        let s = include_str!("../../../artifacts/params_babybear.json");
+        let params = serde_json::from_str(s).unwrap();
+        let vector: Value = serde_json::from_str(s).unwrap();
+        let input = serde_json::from_value(vector["input"].clone()).unwrap();
+        let output = serde_json::from_value(vector["output"].clone()).unwrap();
+        (params, input, output)
+    }
+
+    /// The same for `proof_mersenne31_fibonacci.json`.
+    fn mersenne31_test_vector() -> (
+        Poseidon2Params<Mersenne31>,
+        [u32; POSEIDON2_WIDTH],
+        [u32; POSEIDON2_WIDTH],
+    ) {
+        let s = include_str!("../../../artifacts/params_mersenne31.json");
+        let params = serde_json::from_str(s).unwrap();
+        let vector: Value = serde_json::from_str(s).unwrap();
+        let input = serde_json::from_value(vector["input"].clone()).unwrap();
+        let output = serde_json::from_value(vector["output"].clone()).unwrap();
+        (params, input, output)
+    }
+
+    #[test]
+    fn test_native_permute_matches_plonky3() {
+        let (params, input, output) = babybear_test_vector();
+        assert_eq!(params.permute(input), output);
+        let (params, input, output) = mersenne31_test_vector();
+        assert_eq!(params.permute(input), output);
+    }
+
+    #[test]
+    fn test_permute_matches_plonky3() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let (params, input, expected) = babybear_test_vector();
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let input_t: [Target; POSEIDON2_WIDTH] =
+            core::array::from_fn(|_| builder.add_virtual_target());
+        let output = builder.f31_poseidon2_permute(input_t, &params);
+        for (out, expected) in output.into_iter().zip(expected) {
+            let expected = builder.f31_constant::<BabyBear>(expected);
+            builder.connect(out, expected);
+        }
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for (&t, v) in input_t.iter().zip(input) {
+            pw.set_target(t, F::from_canonical_u32(v));
+        }
+        let proof = data.prove(pw).unwrap();
+        assert!(data.verify(proof).is_ok());
+    }
+}
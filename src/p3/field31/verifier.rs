@@ -0,0 +1,252 @@
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::challenger::DuplexChallenger;
+use crate::p3::challenger::DuplexChallengerTarget;
+use crate::p3::field31::air::Field31Air;
+use crate::p3::field31::air::Field31ConstraintFolder;
+use crate::p3::field31::domain::Field31Domain;
+use crate::p3::field31::extension::BinomialExtension;
+use crate::p3::field31::extension::CircuitBuilderField31Ext;
+use crate::p3::field31::extension::Field31ExtTarget;
+use crate::p3::field31::field::CircuitBuilderField31;
+use crate::p3::field31::pcs::Field31Pcs;
+use crate::p3::field31::poseidon2::Field31Poseidon2;
+use crate::p3::field31::poseidon2::Poseidon2Field31;
+use crate::p3::serde::fri::FriError;
+use crate::p3::serde::proof::Commitments;
+use crate::p3::serde::proof::OpenedValues;
+use crate::p3::serde::proof::P3Config;
+use crate::p3::serde::proof::Proof;
+use crate::p3::serde::proof::TwoAdicFriPcsProof;
+use crate::p3::verifier::P3VerifierError;
+
+/// The parts of a single-table proof over a 31-bit field the verifier reads,
+/// whichever PCS opened it.
+pub struct Field31ProofTarget<'a, O, const E: usize> {
+    pub commitments: &'a Commitments<Target>,
+    pub opened_values: &'a OpenedValues<Target, E>,
+    pub opening_proof: &'a O,
+    pub degree_bits: usize,
+}
+
+impl<'a, const E: usize> From<&'a Proof<Target, E>>
+    for Field31ProofTarget<'a, TwoAdicFriPcsProof<Target, E>, E>
+{
+    fn from(proof: &'a Proof<Target, E>) -> Self {
+        Self {
+            commitments: &proof.commitments,
+            opened_values: &proof.opened_values,
+            opening_proof: &proof.opening_proof,
+            degree_bits: proof.degree_bits,
+        }
+    }
+}
+
+/// Verifier of single-table Plonky3 proofs over a 31-bit field, emulating
+/// the field in the Goldilocks circuit. Preprocessed columns and interactions
+/// aren't supported on this path.
+pub trait CircuitBuilderField31Verifier<F: RicherField + Extendable<D>, const D: usize> {
+    /// Verifies `proof` of `air` against `public_values`, following the
+    /// transcript of the `uni-stark` verifier of Plonky3 0.2. `challenger`
+    /// has to be fresh, and every element of `proof` and `public_values` a
+    /// canonical element of `P`.
+    fn p3_verify_field31_proof<
+        P: Poseidon2Field31 + BinomialExtension<E>,
+        const E: usize,
+        Pcs: Field31Pcs<P, E>,
+    >(
+        &mut self,
+        pcs: &Pcs,
+        air: &impl Field31Air,
+        config: &P3Config,
+        challenger: &mut DuplexChallengerTarget<Field31Poseidon2<P>>,
+        proof: Field31ProofTarget<Pcs::Proof, E>,
+        public_values: &[Target],
+    ) -> Result<(), P3VerifierError>;
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderField31Verifier<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn p3_verify_field31_proof<
+        P: Poseidon2Field31 + BinomialExtension<E>,
+        const E: usize,
+        Pcs: Field31Pcs<P, E>,
+    >(
+        &mut self,
+        pcs: &Pcs,
+        air: &impl Field31Air,
+        config: &P3Config,
+        challenger: &mut DuplexChallengerTarget<Field31Poseidon2<P>>,
+        proof: Field31ProofTarget<Pcs::Proof, E>,
+        public_values: &[Target],
+    ) -> Result<(), P3VerifierError> {
+        if public_values.len() != config.num_public_values {
+            return Err(P3VerifierError::PublicValuesMismatch {
+                expected: config.num_public_values,
+                actual: public_values.len(),
+            });
+        }
+        if config.preprocessed_width > 0 || config.num_interactions > 0 {
+            return Err(P3VerifierError::InvalidProofShape(
+                "31-bit proofs with preprocessed columns or interactions aren't supported",
+            ));
+        }
+        if config.fri_config.max_log_arity != 1 {
+            return Err(FriError::InvalidFoldingArity.into());
+        }
+        if !matches!(config.fri_config.log_final_poly_len, None | Some(0)) {
+            return Err(P3VerifierError::InvalidProofShape(
+                "31-bit proofs end with a constant final polynomial",
+            ));
+        }
+        config.check_fixed_degree()?;
+        proof.opened_values.check_shape(config)?;
+
+        let Field31ProofTarget {
+            commitments,
+            opened_values,
+            opening_proof,
+            degree_bits,
+        } = proof;
+
+        let trace_domain = pcs.natural_domain_for_degree(1 << degree_bits);
+        let quotient_domain =
+            trace_domain.create_disjoint_domain(1 << (degree_bits + config.log_quotient_degree));
+        let quotient_chunks_domains =
+            quotient_domain.split_domains(1 << config.log_quotient_degree);
+
+        let degree_bits_t = self.f31_constant::<P>(degree_bits as u32);
+        self.p3_observe::<Field31Poseidon2<P>>(challenger, [degree_bits_t]);
+        self.p3_observe::<Field31Poseidon2<P>>(challenger, commitments.trace.value.iter().copied());
+        self.p3_observe::<Field31Poseidon2<P>>(challenger, public_values.iter().copied());
+        let alpha = self.p3_sample_ext::<Field31Poseidon2<P>, E>(challenger);
+        self.p3_observe::<Field31Poseidon2<P>>(
+            challenger,
+            commitments.quotient_chunks.value.iter().copied(),
+        );
+
+        let zeta = self.p3_sample_ext::<Field31Poseidon2<P>, E>(challenger);
+        let zeta_next = trace_domain.next_point(&zeta, self);
+
+        let rounds = vec![
+            (
+                commitments.trace.clone(),
+                vec![(
+                    trace_domain.clone(),
+                    vec![
+                        (zeta.clone(), opened_values.trace_local.clone()),
+                        (zeta_next, opened_values.trace_next.clone()),
+                    ],
+                )],
+            ),
+            (
+                commitments.quotient_chunks.clone(),
+                quotient_chunks_domains
+                    .iter()
+                    .zip(&opened_values.quotient_chunks)
+                    .map(|(domain, values)| (domain.clone(), vec![(zeta.clone(), values.clone())]))
+                    .collect(),
+            ),
+        ];
+        pcs.verify(rounds, opening_proof, challenger, self)?;
+
+        let air_width = air.width();
+        if opened_values.trace_local.len() != air_width
+            || opened_values.trace_next.len() != air_width
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "trace width doesn't match the air width",
+            ));
+        }
+
+        let quotient = f31_quotient_at_point::<F, D, P, E, _>(
+            self,
+            opened_values,
+            &quotient_chunks_domains,
+            &zeta,
+        );
+
+        let sels = trace_domain.selectors_at_point(&zeta, self);
+        let inv_zeroifier = sels.inv_zeroifier.clone();
+        let mut folder = Field31ConstraintFolder::<P, E>::new(
+            opened_values.clone(),
+            public_values.to_vec(),
+            sels,
+            alpha,
+            self,
+        );
+        air.eval(&mut folder, self);
+
+        let folded_constraints_mul_inv_zeroifier =
+            self.f31_ext_mul::<P, E>(&folder.accumulator, &inv_zeroifier);
+        self.connect_f31_ext(&folded_constraints_mul_inv_zeroifier, &quotient);
+
+        Ok(())
+    }
+}
+
+/// Recombines the opened chunks of the quotient polynomial into its value at
+/// `point`.
+fn f31_quotient_at_point<
+    F: RicherField + Extendable<D>,
+    const D: usize,
+    P: BinomialExtension<E>,
+    const E: usize,
+    Domain: Field31Domain<P, E>,
+>(
+    cb: &mut CircuitBuilder<F, D>,
+    opened_values: &OpenedValues<Target, E>,
+    quotient_chunks_domains: &[Domain],
+    point: &Field31ExtTarget<E>,
+) -> Field31ExtTarget<E> {
+    let zps: Vec<Field31ExtTarget<E>> = quotient_chunks_domains
+        .iter()
+        .enumerate()
+        .map(|(i, domain)| {
+            quotient_chunks_domains
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, other_domain)| {
+                    let other_domain_at_point = other_domain.zp_at_point(point, cb);
+                    let other_domain_first_point_inv =
+                        P::inverse(other_domain.zp_at_single_point(domain.first_point()));
+                    cb.f31_ext_mul_const::<P, E>(
+                        other_domain_first_point_inv,
+                        &other_domain_at_point,
+                    )
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .reduce(|acc, e| cb.f31_ext_mul::<P, E>(&acc, &e))
+                .unwrap_or_else(|| cb.f31_ext_one())
+        })
+        .collect();
+
+    opened_values
+        .quotient_chunks
+        .iter()
+        .zip(&zps)
+        .map(|(chunk, zp)| {
+            let chunk_at_point = chunk
+                .iter()
+                .enumerate()
+                .map(|(e_i, c)| {
+                    let monomial = cb.f31_ext_monomial(e_i);
+                    cb.f31_ext_mul::<P, E>(&monomial, c)
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .reduce(|acc, e| cb.f31_ext_add::<P, E>(acc, e))
+                .unwrap_or_else(|| cb.f31_ext_zero());
+            cb.f31_ext_mul::<P, E>(zp, &chunk_at_point)
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .reduce(|acc, e| cb.f31_ext_add::<P, E>(acc, e))
+        .unwrap_or_else(|| cb.f31_ext_zero())
+}
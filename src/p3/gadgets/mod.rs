@@ -4,6 +4,8 @@ use plonky2::iop::target::Target;
 use plonky2::iop::witness::Witness;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
 
+use crate::p3::serde::proof::Value;
+
 pub trait CircuitBuilderP3Helper<F: RichField + Extendable<D>, const D: usize> {
     fn add_2d_vec_array_inputs(&mut self, rows: usize, cols: usize) -> Vec<Vec<Target>>;
     fn add_2d_vec_array_inputs_with_dims_vec(&mut self, dims: Vec<usize>) -> Vec<Vec<Target>>;
@@ -40,6 +42,7 @@ impl<F: RichField + Extendable<D>, const D: usize> CircuitBuilderP3Helper<F, D>
 
 pub trait WitnessP3Helper<F: RichField> {
     fn set_2d_vec_array(&mut self, targets: &[Vec<Target>], values: &[Vec<F>]);
+    fn set_2d_vec_values(&mut self, targets: &[Vec<Target>], values: &[Vec<Value<F>>]);
 }
 impl<F: RichField, W: Witness<F>> WitnessP3Helper<F> for W {
     fn set_2d_vec_array(&mut self, targets: &[Vec<Target>], values: &[Vec<F>]) {
@@ -49,4 +52,14 @@ impl<F: RichField, W: Witness<F>> WitnessP3Helper<F> for W {
             self.set_target_arr(t, v);
         });
     }
+
+    fn set_2d_vec_values(&mut self, targets: &[Vec<Target>], values: &[Vec<Value<F>>]) {
+        assert!(targets.len() == values.len());
+        targets.iter().zip(values.iter()).for_each(|(t, v)| {
+            assert!(t.len() == v.len());
+            t.iter()
+                .zip(v.iter())
+                .for_each(|(&t, v)| self.set_target(t, v.value));
+        });
+    }
 }
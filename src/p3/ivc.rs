@@ -0,0 +1,506 @@
+use std::ops::Range;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::gates::constant::ConstantGate;
+use plonky2::gates::gate::GateRef;
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::PartialWitness;
+use plonky2::iop::witness::WitnessWrite;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::CircuitConfig;
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::circuit_data::CommonCircuitData;
+use plonky2::plonk::circuit_data::VerifierCircuitTarget;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2::plonk::proof::ProofWithPublicInputsTarget;
+use plonky2::recursion::cyclic_recursion::check_cyclic_proof_verifier_data;
+use plonky2::recursion::dummy_circuit::cyclic_base_proof;
+
+use crate::common::poseidon2::poseidon2_gate::Poseidon2GoldilocksConfig;
+use crate::p3::air::Air;
+use crate::p3::challenger::DuplexChallengerTarget;
+use crate::p3::circuit::try_prove;
+use crate::p3::constants::EXT_DEGREE;
+use crate::p3::permutation::P3Permutation;
+use crate::p3::serde::proof::P3Config;
+use crate::p3::serde::proof::P3Field;
+use crate::p3::serde::proof::Proof;
+use crate::p3::verifier::P3VerifierError;
+use crate::p3::CircuitBuilderP3Arithmetic;
+
+type F = GoldilocksField;
+type C = Poseidon2GoldilocksConfig;
+const D: usize = 2;
+
+/// Bound on the number of builds it takes the step circuit to settle on the
+/// shape of the proofs it verifies.
+const MAX_SHAPE_ITERATIONS: usize = 8;
+
+struct StepTargets<const E: usize> {
+    proof: Proof<Target, E>,
+    public_values: Vec<Target>,
+    initial_state: Vec<Target>,
+    has_previous: BoolTarget,
+    previous: ProofWithPublicInputsTarget<D>,
+    verifier_data: VerifierCircuitTarget,
+}
+
+/// Incrementally verifiable computation over a chain of Plonky3 proofs of
+/// segments of one program, built on plonky2 cyclic recursion.
+///
+/// Each step verifies one segment proof, and the previous step unless it is
+/// the first one. The public values of a segment hold its start state at
+/// `start_state` and its end state at `end_state`, and the start state of a
+/// segment has to be the end state of the one before. The public inputs of a
+/// step proof are the start state of the chain, the end state of its last
+/// segment and the number of segments, followed by the verifier data of the
+/// circuit.
+pub struct P3IvcCircuit<A: Air, const E: usize = EXT_DEGREE> {
+    pub air: A,
+    pub config: P3Config,
+    pub start_state: Range<usize>,
+    pub end_state: Range<usize>,
+    pub data: CircuitData<F, C, D>,
+    targets: StepTargets<E>,
+}
+
+impl<A: Air, const E: usize> P3IvcCircuit<A, E> {
+    pub fn new<H: P3Permutation<F>>(
+        air: A,
+        config: P3Config,
+        start_state: Range<usize>,
+        end_state: Range<usize>,
+        circuit_config: CircuitConfig,
+    ) -> Result<Self>
+    where
+        H::Params: Default,
+    {
+        if start_state.len() != end_state.len()
+            || start_state.end > config.num_public_values
+            || end_state.end > config.num_public_values
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "segment states don't fit the public values",
+            )
+            .into());
+        }
+
+        // The step circuit verifies proofs of itself, so its shape is found by
+        // building it against its previous shape until the two agree.
+        let mut common = Self::seed_common_data(&circuit_config);
+        let mut iterations = 0;
+        loop {
+            let (builder, _) = Self::build_step::<H>(
+                &air,
+                &config,
+                &start_state,
+                &end_state,
+                &circuit_config,
+                &common,
+                false,
+            )?;
+            let next = builder.build::<C>().common;
+            if next == common {
+                break;
+            }
+            common = next;
+
+            iterations += 1;
+            if iterations == MAX_SHAPE_ITERATIONS {
+                return Err(anyhow!("step circuit shape doesn't settle"));
+            }
+        }
+
+        let (builder, targets) = Self::build_step::<H>(
+            &air,
+            &config,
+            &start_state,
+            &end_state,
+            &circuit_config,
+            &common,
+            true,
+        )?;
+        let data = builder.build::<C>();
+
+        Ok(Self {
+            air,
+            config,
+            start_state,
+            end_state,
+            data,
+            targets,
+        })
+    }
+
+    /// Shape of a circuit verifying a plonky2 proof, to start the search for
+    /// the step circuit's shape from. The dummy proofs standing in for a
+    /// missing previous step are padded to this shape, so it has to have the
+    /// gates of the recursive verifier, the constant gate of the dummy circuit
+    /// and room for the step's public inputs.
+    fn seed_common_data(circuit_config: &CircuitConfig) -> CommonCircuitData<F, D> {
+        let mut common = CircuitBuilder::<F, D>::new(circuit_config.clone())
+            .build::<C>()
+            .common;
+        for _ in 0..2 {
+            let mut builder = CircuitBuilder::<F, D>::new(circuit_config.clone());
+            let proof = builder.add_virtual_proof_with_pis(&common);
+            let verifier_data =
+                builder.add_virtual_verifier_data(common.config.fri_config.cap_height);
+            builder.verify_proof::<C>(&proof, &verifier_data, &common);
+            builder.add_gate_to_gate_set(GateRef::new(ConstantGate::new(
+                circuit_config.num_constants,
+            )));
+            common = builder.build::<C>().common;
+        }
+        common
+    }
+
+    /// Lays out the step circuit verifying its previous proofs as proofs of
+    /// shape `common`, cyclically or, to find that shape, as proofs of any
+    /// circuit of that shape.
+    fn build_step<H: P3Permutation<F>>(
+        air: &A,
+        config: &P3Config,
+        start_state: &Range<usize>,
+        end_state: &Range<usize>,
+        circuit_config: &CircuitConfig,
+        common: &CommonCircuitData<F, D>,
+        cyclic: bool,
+    ) -> Result<(CircuitBuilder<F, D>, StepTargets<E>)>
+    where
+        H::Params: Default,
+    {
+        let mut builder = CircuitBuilder::<F, D>::new(circuit_config.clone());
+        let state_len = start_state.len();
+
+        let public_values = builder.add_virtual_targets(config.num_public_values);
+        let proof = builder.p3_verify_proof_with_challenger::<DuplexChallengerTarget<H>, E>(
+            air,
+            config,
+            &public_values,
+        )?;
+
+        let initial_state = builder.add_virtual_targets(state_len);
+        builder.register_public_inputs(&initial_state);
+        builder.register_public_inputs(&public_values[end_state.clone()]);
+        let num_steps = builder.add_virtual_target();
+        builder.register_public_inputs(&[num_steps]);
+        let verifier_data = builder.add_verifier_data_public_inputs();
+
+        let mut common = common.clone();
+        common.num_public_inputs = builder.num_public_inputs();
+        let has_previous = builder.add_virtual_bool_target_safe();
+        let previous = builder.add_virtual_proof_with_pis(&common);
+
+        // The chain starts at the start state of its first segment, and every
+        // other segment starts where the previous one ended.
+        let previous_initial_state = &previous.public_inputs[..state_len];
+        let previous_end_state = &previous.public_inputs[state_len..2 * state_len];
+        for i in 0..state_len {
+            builder.p3_connect_if(has_previous, previous_initial_state[i], initial_state[i]);
+            let expected_start = builder._if(has_previous, previous_end_state[i], initial_state[i]);
+            builder.connect(expected_start, public_values[start_state.start + i]);
+        }
+        let one = builder.one();
+        let previous_num_steps = previous.public_inputs[2 * state_len];
+        let expected_num_steps = builder.mul_add(has_previous.target, previous_num_steps, one);
+        builder.connect(num_steps, expected_num_steps);
+
+        if cyclic {
+            builder.conditionally_verify_cyclic_proof_or_dummy::<C>(
+                has_previous,
+                &previous,
+                &common,
+            )?;
+        } else {
+            builder.conditionally_verify_proof_or_dummy::<C>(
+                has_previous,
+                &previous,
+                &verifier_data,
+                &common,
+            )?;
+            for gate in &common.gates {
+                builder.add_gate_to_gate_set(gate.clone());
+            }
+        }
+
+        Ok((
+            builder,
+            StepTargets {
+                proof,
+                public_values,
+                initial_state,
+                has_previous,
+                previous,
+                verifier_data,
+            },
+        ))
+    }
+
+    /// Extends the chain proven by `previous` with a segment, or starts a
+    /// chain with it.
+    pub fn prove_step(
+        &self,
+        proof: &Proof<P3Field, E>,
+        public_values: &[F],
+        previous: Option<&ProofWithPublicInputs<F, C, D>>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        proof.check_shape(&self.config)?;
+        if public_values.len() != self.targets.public_values.len() {
+            return Err(P3VerifierError::PublicValuesMismatch {
+                expected: self.targets.public_values.len(),
+                actual: public_values.len(),
+            }
+            .into());
+        }
+
+        let targets = &self.targets;
+        let mut pw = PartialWitness::new();
+        pw.set_target_arr(&targets.public_values, public_values);
+        targets.proof.set_witness::<F, D, _>(&mut pw, proof);
+        pw.set_verifier_data_target(&targets.verifier_data, &self.data.verifier_only);
+        match previous {
+            Some(previous) => {
+                check_cyclic_proof_verifier_data(
+                    previous,
+                    &self.data.verifier_only,
+                    &self.data.common,
+                )?;
+                if public_values[self.start_state.clone()] != *self.end_state(previous) {
+                    return Err(anyhow!(
+                        "the segment doesn't start at the end state of the previous step"
+                    ));
+                }
+                pw.set_bool_target(targets.has_previous, true);
+                pw.set_target_arr(&targets.initial_state, self.initial_state(previous));
+                pw.set_proof_with_pis_target(&targets.previous, previous);
+            }
+            None => {
+                pw.set_bool_target(targets.has_previous, false);
+                pw.set_target_arr(
+                    &targets.initial_state,
+                    &public_values[self.start_state.clone()],
+                );
+                let base_proof = cyclic_base_proof(
+                    &self.data.common,
+                    &self.data.verifier_only,
+                    Default::default(),
+                );
+                pw.set_proof_with_pis_target(&targets.previous, &base_proof);
+            }
+        }
+
+        try_prove(&self.data, pw)
+    }
+
+    pub fn verify(&self, proof: ProofWithPublicInputs<F, C, D>) -> Result<()> {
+        check_cyclic_proof_verifier_data(&proof, &self.data.verifier_only, &self.data.common)?;
+        self.data.verify(proof)
+    }
+
+    /// The start state of the chain proven by `proof`.
+    pub fn initial_state<'a>(&self, proof: &'a ProofWithPublicInputs<F, C, D>) -> &'a [F] {
+        &proof.public_inputs[..self.start_state.len()]
+    }
+
+    /// The end state of the last segment of the chain proven by `proof`.
+    pub fn end_state<'a>(&self, proof: &'a ProofWithPublicInputs<F, C, D>) -> &'a [F] {
+        let state_len = self.start_state.len();
+        &proof.public_inputs[state_len..2 * state_len]
+    }
+
+    /// The number of segments of the chain proven by `proof`.
+    pub fn num_steps(&self, proof: &ProofWithPublicInputs<F, C, D>) -> F {
+        proof.public_inputs[2 * self.start_state.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::extension::Extendable;
+    use plonky2::field::types::Field;
+
+    use super::*;
+    use crate::common::poseidon2::poseidon2::Poseidon2Hash;
+    use crate::common::richer_field::RicherField;
+    use crate::p3::air::VerifierConstraintFolder;
+    use crate::p3::extension::CircuitBuilderP3ExtArithmetic;
+    use crate::p3::native::air::NativeAir;
+    use crate::p3::native::air::NativeConstraintFolder;
+    use crate::p3::native::prover;
+    use crate::p3::native::Challenge;
+    use crate::p3::native::Val;
+    use crate::p3::serde::proof::BinomialExtensionField;
+    use crate::p3::serde::proof::P3ProofField;
+    use crate::p3::tests::fibonacci_fri_config;
+    use crate::p3::tests::native_fri_config;
+    use crate::p3::tests::FibonacciAir;
+
+    const LOG_SEGMENT_LEN: usize = 3;
+
+    /// Repeated cubing from its first public value to its second, so that a
+    /// segment carries its start and end state in its public values.
+    struct CubeSegmentAir;
+
+    impl Air for CubeSegmentAir {
+        fn name(&self) -> String {
+            "CubeSegment".to_string()
+        }
+
+        fn width(&self) -> usize {
+            1
+        }
+
+        fn max_constraint_degree(&self) -> usize {
+            4
+        }
+
+        fn num_public_values(&self) -> usize {
+            2
+        }
+
+        fn eval<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+            &self,
+            folder: &mut VerifierConstraintFolder<Target, E>,
+            cb: &mut CircuitBuilder<F, D>,
+        ) {
+            let local = folder.main.trace_local[0].clone();
+            let next = folder.main.trace_next[0].clone();
+            let start = BinomialExtensionField {
+                value: cb.p3_field_to_arr(folder.public_values[0]),
+            };
+            let end = BinomialExtensionField {
+                value: cb.p3_field_to_arr(folder.public_values[1]),
+            };
+
+            folder
+                .when_first_row::<F, D>()
+                .assert_eq(local.clone(), start, cb);
+            let square = cb.p3_ext_mul(&local, &local);
+            let cube = cb.p3_ext_mul(&square, &local);
+            folder.when_transition::<F, D>().assert_eq(next, cube, cb);
+            folder.when_last_row::<F, D>().assert_eq(local, end, cb);
+        }
+    }
+
+    impl NativeAir for CubeSegmentAir {
+        fn width(&self) -> usize {
+            1
+        }
+
+        fn max_constraint_degree(&self) -> usize {
+            4
+        }
+
+        fn num_public_values(&self) -> usize {
+            2
+        }
+
+        fn eval(&self, folder: &mut NativeConstraintFolder) {
+            let local = folder.trace_local[0];
+            let next = folder.trace_next[0];
+            let start = Challenge::from(folder.public_values[0]);
+            let end = Challenge::from(folder.public_values[1]);
+
+            folder.when_first_row().assert_eq(local, start);
+            folder
+                .when_transition()
+                .assert_eq(next, local * local * local);
+            folder.when_last_row().assert_eq(local, end);
+        }
+    }
+
+    /// A proof of the segment of [`CubeSegmentAir`] starting at `start`, with
+    /// its public values.
+    fn prove_segment(start: Val) -> (P3ProofField, Vec<Val>) {
+        let trace = (0..1 << LOG_SEGMENT_LEN)
+            .scan(start, |x, _| {
+                let row = vec![*x];
+                *x = x.cube();
+                Some(row)
+            })
+            .collect::<Vec<_>>();
+        let public_values = vec![start, trace[trace.len() - 1][0]];
+        let proof = prover::prove(
+            &CubeSegmentAir,
+            &trace,
+            &public_values,
+            &native_fri_config(),
+        );
+        (proof, public_values)
+    }
+
+    fn cube_segment_ivc() -> P3IvcCircuit<CubeSegmentAir> {
+        let config = P3Config::new(&CubeSegmentAir, native_fri_config(), LOG_SEGMENT_LEN);
+        P3IvcCircuit::<_>::new::<Poseidon2Hash>(
+            CubeSegmentAir,
+            config,
+            0..1,
+            1..2,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_prove_chain_of_plonky3_proofs() {
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+
+        let fri_config = fibonacci_fri_config();
+        let config = P3Config::new(&FibonacciAir {}, fri_config, proof.degree_bits);
+        // The Fibonacci AIR has no public values, so segments carry no state.
+        let ivc = P3IvcCircuit::<_>::new::<Poseidon2Hash>(
+            FibonacciAir {},
+            config,
+            0..0,
+            0..0,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+
+        let first = ivc.prove_step(&proof, &[], None).unwrap();
+        assert_eq!(ivc.num_steps(&first), F::ONE);
+        ivc.verify(first.clone()).unwrap();
+
+        let second = ivc.prove_step(&proof, &[], Some(&first)).unwrap();
+        assert_eq!(ivc.num_steps(&second), F::TWO);
+        ivc.verify(second).unwrap();
+    }
+
+    #[test]
+    fn test_prove_chain_of_segments_with_states() {
+        let ivc = cube_segment_ivc();
+
+        let (first_segment, first_values) = prove_segment(Val::TWO);
+        let first = ivc.prove_step(&first_segment, &first_values, None).unwrap();
+        assert_eq!(ivc.initial_state(&first), &[Val::TWO]);
+        assert_eq!(ivc.end_state(&first), &first_values[1..]);
+        ivc.verify(first.clone()).unwrap();
+
+        let (second_segment, second_values) = prove_segment(first_values[1]);
+        let second = ivc
+            .prove_step(&second_segment, &second_values, Some(&first))
+            .unwrap();
+        assert_eq!(ivc.initial_state(&second), &[Val::TWO]);
+        assert_eq!(ivc.end_state(&second), &second_values[1..]);
+        assert_eq!(ivc.num_steps(&second), F::TWO);
+        ivc.verify(second).unwrap();
+    }
+
+    #[test]
+    fn test_prove_chain_rejects_segment_not_starting_at_previous_end() {
+        let ivc = cube_segment_ivc();
+
+        let (first_segment, first_values) = prove_segment(Val::TWO);
+        let first = ivc.prove_step(&first_segment, &first_values, None).unwrap();
+
+        let (second_segment, second_values) = prove_segment(first_values[1] + Val::ONE);
+        assert!(ivc
+            .prove_step(&second_segment, &second_values, Some(&first))
+            .is_err());
+    }
+}
@@ -0,0 +1,343 @@
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::common::u32::arithmetic_u32::CircuitBuilderU32;
+use crate::common::u32::arithmetic_u32::U32Target;
+use crate::common::u32::interleaved_u32::CircuitBuilderB32;
+
+/// Number of 64-bit lanes of the Keccak-f[1600] state.
+pub const KECCAK_LANES: usize = 25;
+/// Bytes absorbed per permutation by Keccak-256.
+pub const KECCAK256_RATE: usize = 136;
+/// Bytes of a Keccak-256 digest.
+pub const KECCAK256_DIGEST_BYTES: usize = 32;
+
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000_0000_0000_0001,
+    0x0000_0000_0000_8082,
+    0x8000_0000_0000_808A,
+    0x8000_0000_8000_8000,
+    0x0000_0000_0000_808B,
+    0x0000_0000_8000_0001,
+    0x8000_0000_8000_8081,
+    0x8000_0000_0000_8009,
+    0x0000_0000_0000_008A,
+    0x0000_0000_0000_0088,
+    0x0000_0000_8000_8009,
+    0x0000_0000_8000_000A,
+    0x0000_0000_8000_808B,
+    0x8000_0000_0000_008B,
+    0x8000_0000_0000_8089,
+    0x8000_0000_0000_8003,
+    0x8000_0000_0000_8002,
+    0x8000_0000_0000_0080,
+    0x0000_0000_0000_800A,
+    0x8000_0000_8000_000A,
+    0x8000_0000_8000_8081,
+    0x8000_0000_0000_8080,
+    0x0000_0000_8000_0001,
+    0x8000_0000_8000_8008,
+];
+
+/// Rotation offset of the lane `(x, y)`, stored at `x + 5 * y`.
+const ROTATIONS: [u8; KECCAK_LANES] = [
+    0, 1, 62, 28, 27, //
+    36, 44, 6, 55, 20, //
+    3, 10, 43, 25, 39, //
+    41, 45, 15, 21, 8, //
+    18, 2, 61, 56, 14,
+];
+
+/// Keccak-f[1600] state as the little-endian 32-bit limbs of its lanes.
+pub type KeccakStateTarget = [[U32Target; 2]; KECCAK_LANES];
+
+pub fn keccak_f1600(state: &mut [u64; KECCAK_LANES]) {
+    for rc in ROUND_CONSTANTS {
+        let c: [u64; 5] = core::array::from_fn(|x| (0..5).fold(0, |acc, y| acc ^ state[x + 5 * y]));
+        for x in 0..5 {
+            let d = c[(x + 4) % 5] ^ c[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                state[x + 5 * y] ^= d;
+            }
+        }
+
+        let mut b = [0u64; KECCAK_LANES];
+        for x in 0..5 {
+            for y in 0..5 {
+                b[y + 5 * ((2 * x + 3 * y) % 5)] =
+                    state[x + 5 * y].rotate_left(ROTATIONS[x + 5 * y] as u32);
+            }
+        }
+
+        for x in 0..5 {
+            for y in 0..5 {
+                state[x + 5 * y] =
+                    b[x + 5 * y] ^ (!b[(x + 1) % 5 + 5 * y] & b[(x + 2) % 5 + 5 * y]);
+            }
+        }
+
+        state[0] ^= rc;
+    }
+}
+
+/// Keccak-256 with the original `0x01` padding, i.e. Plonky3's
+/// `Keccak256Hash`.
+pub fn keccak256(input: &[u8]) -> [u8; KECCAK256_DIGEST_BYTES] {
+    let mut padded = input.to_vec();
+    padded.push(0x01);
+    padded.resize(padded.len().next_multiple_of(KECCAK256_RATE), 0);
+    *padded.last_mut().unwrap() |= 0x80;
+
+    let mut state = [0u64; KECCAK_LANES];
+    for block in padded.chunks(KECCAK256_RATE) {
+        for (lane, bytes) in state.iter_mut().zip(block.chunks(8)) {
+            *lane ^= u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        keccak_f1600(&mut state);
+    }
+
+    let mut digest = [0u8; KECCAK256_DIGEST_BYTES];
+    for (bytes, lane) in digest.chunks_mut(8).zip(state) {
+        bytes.copy_from_slice(&lane.to_le_bytes());
+    }
+    digest
+}
+
+/// Constrains the 64-bit integer `high * 2^32 + low` to be smaller than the
+/// Goldilocks modulus `2^64 - 2^32 + 1`.
+fn assert_canonical_u64<F: RicherField + Extendable<D>, const D: usize>(
+    cb: &mut CircuitBuilder<F, D>,
+    low: U32Target,
+    high: U32Target,
+) {
+    let max = cb.constant(F::from_canonical_u32(u32::MAX));
+    let high_is_max = cb.is_equal(high.0, max);
+    let overflow = cb.mul(high_is_max.target, low.0);
+    cb.assert_zero(overflow);
+}
+
+/// Keccak over 32-bit limbs, built from the interleaved binary gadgets.
+///
+/// Inputs are whole 32-bit words, read as their 4 little-endian bytes: every
+/// message hashed by the Plonky3 Keccak configs, 8-byte field elements and
+/// 32-byte digests, is a multiple of 4 bytes long.
+pub trait CircuitBuilderKeccak<F: RicherField + Extendable<D>, const D: usize> {
+    fn keccak_f1600(&mut self, state: &KeccakStateTarget) -> KeccakStateTarget;
+
+    /// Keccak-256 of the bytes of `input`, as the 8 little-endian words of
+    /// the digest.
+    fn keccak256_u32(&mut self, input: &[U32Target]) -> [U32Target; 8];
+
+    /// Packs little-endian bytes into 32-bit words. Each byte is range checked
+    /// to 8 bits, as otherwise distinct byte strings could pack to the same
+    /// words.
+    fn keccak_pack_bytes(&mut self, bytes: &[Target]) -> Vec<U32Target>;
+
+    /// Splits words into their little-endian bytes.
+    fn keccak_unpack_bytes(&mut self, words: &[U32Target]) -> Vec<Target>;
+
+    /// The words of `x.as_canonical_u64().to_le_bytes()`, which is how
+    /// `SerializingHasher64` and `SerializingChallenger64` serialize a field
+    /// element.
+    fn p3_u64_to_u32s(&mut self, x: Target) -> [U32Target; 2];
+
+    /// The field element of the 8 little-endian `bytes`, which must encode an
+    /// integer smaller than the modulus.
+    fn p3_u64_from_bytes(&mut self, bytes: &[Target; 8]) -> Target;
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderKeccak<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn keccak_f1600(&mut self, state: &KeccakStateTarget) -> KeccakStateTarget {
+        let mut state = *state;
+        for rc in ROUND_CONSTANTS {
+            let c: [[U32Target; 2]; 5] = core::array::from_fn(|x| {
+                (1..5).fold(state[x], |acc, y| self.xor_u64(&acc, &state[x + 5 * y]))
+            });
+            for x in 0..5 {
+                let c_rot = self.lrot_u64(&c[(x + 1) % 5], 1);
+                let d = self.xor_u64(&c[(x + 4) % 5], &c_rot);
+                for y in 0..5 {
+                    state[x + 5 * y] = self.xor_u64(&state[x + 5 * y], &d);
+                }
+            }
+
+            let mut b = state;
+            for x in 0..5 {
+                for y in 0..5 {
+                    b[y + 5 * ((2 * x + 3 * y) % 5)] =
+                        self.lrot_u64(&state[x + 5 * y], ROTATIONS[x + 5 * y]);
+                }
+            }
+
+            for x in 0..5 {
+                for y in 0..5 {
+                    let not_b = self.not_u64(&b[(x + 1) % 5 + 5 * y]);
+                    let and_b = self.and_u64(&not_b, &b[(x + 2) % 5 + 5 * y]);
+                    state[x + 5 * y] = self.xor_u64(&b[x + 5 * y], &and_b);
+                }
+            }
+
+            let rc = [
+                self.constant_u32(rc as u32),
+                self.constant_u32((rc >> 32) as u32),
+            ];
+            state[0] = self.xor_u64(&state[0], &rc);
+        }
+        state
+    }
+
+    fn keccak256_u32(&mut self, input: &[U32Target]) -> [U32Target; 8] {
+        const RATE_WORDS: usize = KECCAK256_RATE / 4;
+
+        let mut padding = vec![0u32; RATE_WORDS - input.len() % RATE_WORDS];
+        padding[0] |= 0x01;
+        *padding.last_mut().unwrap() |= 0x80 << 24;
+        let padding: Vec<U32Target> = padding
+            .into_iter()
+            .map(|word| self.constant_u32(word))
+            .collect();
+
+        let padded: Vec<U32Target> = input.iter().copied().chain(padding).collect();
+
+        let zero = self.zero_u32();
+        let mut state = [[zero; 2]; KECCAK_LANES];
+        for (i, block) in padded.chunks(RATE_WORDS).enumerate() {
+            for (lane, words) in state.iter_mut().zip(block.chunks(2)) {
+                let words = [words[0], words[1]];
+                // The state starts zeroed, so the first block is absorbed as is.
+                *lane = if i == 0 {
+                    words
+                } else {
+                    self.xor_u64(lane, &words)
+                };
+            }
+            state = self.keccak_f1600(&state);
+        }
+
+        core::array::from_fn(|i| state[i / 2][i % 2])
+    }
+
+    fn keccak_pack_bytes(&mut self, bytes: &[Target]) -> Vec<U32Target> {
+        assert_eq!(bytes.len() % 4, 0);
+        for &byte in bytes {
+            self.range_check(byte, 8);
+        }
+        bytes
+            .chunks(4)
+            .map(|word| {
+                let word = word.iter().rev().fold(self.zero(), |acc, &byte| {
+                    self.mul_const_add(F::from_canonical_u32(1 << 8), acc, byte)
+                });
+                U32Target(word)
+            })
+            .collect()
+    }
+
+    fn keccak_unpack_bytes(&mut self, words: &[U32Target]) -> Vec<Target> {
+        words
+            .iter()
+            .flat_map(|word| {
+                let bits = self.split_le(word.0, 32);
+                bits.chunks(8)
+                    .map(|byte| self.le_sum(byte.iter()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn p3_u64_to_u32s(&mut self, x: Target) -> [U32Target; 2] {
+        let (low, high) = self.split_low_high(x, 32, 64);
+        let (low, high) = (U32Target(low), U32Target(high));
+        assert_canonical_u64(self, low, high);
+        [low, high]
+    }
+
+    fn p3_u64_from_bytes(&mut self, bytes: &[Target; 8]) -> Target {
+        let words = self.keccak_pack_bytes(bytes);
+        assert_canonical_u64(self, words[0], words[1]);
+        self.mul_const_add(F::from_canonical_u64(1 << 32), words[1].0, words[0].0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::iop::witness::WitnessWrite;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_native_keccak256() {
+        assert_eq!(
+            keccak256(b""),
+            hex!("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470")
+        );
+        assert_eq!(
+            keccak256(b"abc"),
+            hex!("4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45")
+        );
+    }
+
+    #[test]
+    fn test_keccak256_u32() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        // Two blocks, the second one padded.
+        let mut rng = rand::thread_rng();
+        let input: Vec<u8> = (0..152).map(|_| rng.gen()).collect();
+        let expected = keccak256(&input);
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let input_t: Vec<Target> = builder.add_virtual_targets(input.len());
+        let words = builder.keccak_pack_bytes(&input_t);
+        let digest = builder.keccak256_u32(&words);
+        let digest = builder.keccak_unpack_bytes(&digest);
+        for (byte, expected) in digest.into_iter().zip(expected) {
+            let expected = builder.constant(F::from_canonical_u8(expected));
+            builder.connect(byte, expected);
+        }
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for (t, byte) in input_t.into_iter().zip(input) {
+            pw.set_target(t, F::from_canonical_u8(byte));
+        }
+        let proof = data.prove(pw).unwrap();
+        assert!(data.verify(proof).is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_pack_bytes_rejects_overflowing_byte() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        // `[256, 0, 0, 0]` would pack to the same word as `[0, 1, 0, 0]`.
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let input_t: Vec<Target> = builder.add_virtual_targets(4);
+        let words = builder.keccak_pack_bytes(&input_t);
+        let expected = builder.constant(F::from_canonical_u32(1 << 8));
+        builder.connect(words[0].0, expected);
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for (t, byte) in input_t.into_iter().zip([256, 0, 0, 0]) {
+            pw.set_target(t, F::from_canonical_u32(byte));
+        }
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
+}
@@ -0,0 +1,322 @@
+//! LogUp argument for lookups and cross-table interactions.
+//!
+//! Every interaction `k` of a table gets an extension-field permutation column
+//! holding `±multiplicity_k / fingerprint_k` on each row, with
+//! `fingerprint = alpha + Σ beta^i * values_i`, positive for sends and negative
+//! for receives. One more column accumulates these over the rows and ends on
+//! the cumulative sum of the table. All interactions balance exactly when the
+//! cumulative sums of all tables add up to zero.
+
+use itertools::izip;
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::air::VerifierConstraintFolder;
+use crate::p3::constants::EXT_DEGREE;
+use crate::p3::extension::CircuitBuilderP3ExtArithmetic;
+use crate::p3::serde::proof::BinomialExtensionField;
+use crate::p3::verifier::P3VerifierError;
+
+/// Number of extension challenges sampled for the LogUp argument.
+pub const NUM_PERMUTATION_CHALLENGES: usize = 2;
+
+/// A tuple of `values` sent or received `multiplicity` times on the current
+/// row.
+pub struct Interaction<F, const E: usize = EXT_DEGREE> {
+    pub values: Vec<BinomialExtensionField<F, E>>,
+    pub multiplicity: BinomialExtensionField<F, E>,
+    pub is_send: bool,
+}
+
+pub trait CircuitBuilderP3Lookup<F: RicherField + Extendable<D>, const D: usize> {
+    fn p3_lookup_fingerprint<const E: usize>(
+        &mut self,
+        values: &[BinomialExtensionField<Target, E>],
+        challenges: &[BinomialExtensionField<Target, E>],
+    ) -> BinomialExtensionField<Target, E>;
+
+    /// Recombines `E` opened base columns into the extension column
+    /// they were flattened from.
+    fn p3_ext_from_base_columns<const E: usize>(
+        &mut self,
+        columns: &[BinomialExtensionField<Target, E>],
+    ) -> BinomialExtensionField<Target, E>;
+
+    /// Folds the LogUp constraints of the interactions collected by
+    /// [`Air::eval`](crate::p3::air::Air::eval) into `folder`.
+    fn p3_eval_lookup_constraints<const E: usize>(
+        &mut self,
+        folder: &mut VerifierConstraintFolder<Target, E>,
+    ) -> Result<(), P3VerifierError>;
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3Lookup<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn p3_lookup_fingerprint<const E: usize>(
+        &mut self,
+        values: &[BinomialExtensionField<Target, E>],
+        challenges: &[BinomialExtensionField<Target, E>],
+    ) -> BinomialExtensionField<Target, E> {
+        let (alpha, beta) = (&challenges[0], &challenges[1]);
+
+        let mut fingerprint = alpha.clone();
+        let mut beta_pow = self.p3_ext_one();
+        for value in values {
+            let beta_pow_mul_value = self.p3_ext_mul(&beta_pow, value);
+            fingerprint = self.p3_ext_add(fingerprint, beta_pow_mul_value);
+            beta_pow = self.p3_ext_mul(&beta_pow, beta);
+        }
+        fingerprint
+    }
+
+    fn p3_ext_from_base_columns<const E: usize>(
+        &mut self,
+        columns: &[BinomialExtensionField<Target, E>],
+    ) -> BinomialExtensionField<Target, E> {
+        columns
+            .iter()
+            .enumerate()
+            .map(|(e_i, c)| {
+                let monomial = self.p3_ext_monomial(e_i);
+                self.p3_ext_mul(&monomial, c)
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .reduce(|acc, e| self.p3_ext_add(acc, e))
+            .unwrap()
+    }
+
+    fn p3_eval_lookup_constraints<const E: usize>(
+        &mut self,
+        folder: &mut VerifierConstraintFolder<Target, E>,
+    ) -> Result<(), P3VerifierError> {
+        let interactions = std::mem::take(&mut folder.interactions);
+        let main = &folder.main;
+
+        if interactions.is_empty() {
+            if !main.permutation_local.is_empty()
+                || !main.permutation_next.is_empty()
+                || main.cumulative_sum.is_some()
+            {
+                return Err(P3VerifierError::InvalidProofShape(
+                    "permutation trace without interactions",
+                ));
+            }
+            return Ok(());
+        }
+
+        let permutation_width = (interactions.len() + 1) * E;
+        let cumulative_sum = match &main.cumulative_sum {
+            Some(cumulative_sum)
+                if main.permutation_local.len() == permutation_width
+                    && main.permutation_next.len() == permutation_width
+                    && folder.permutation_challenges.len() == NUM_PERMUTATION_CHALLENGES =>
+            {
+                cumulative_sum.clone()
+            }
+            _ => {
+                return Err(P3VerifierError::InvalidProofShape(
+                    "permutation trace doesn't match the interactions",
+                ))
+            }
+        };
+
+        let permutation_local: Vec<BinomialExtensionField<Target, E>> = main
+            .permutation_local
+            .chunks(E)
+            .map(|columns| self.p3_ext_from_base_columns(columns))
+            .collect();
+        let permutation_next: Vec<BinomialExtensionField<Target, E>> = main
+            .permutation_next
+            .chunks(E)
+            .map(|columns| self.p3_ext_from_base_columns(columns))
+            .collect();
+        let challenges = folder.permutation_challenges.clone();
+
+        for (interaction, permutation) in izip!(&interactions, &permutation_local) {
+            let fingerprint = self.p3_lookup_fingerprint(&interaction.values, &challenges);
+            let multiplicity = if interaction.is_send {
+                interaction.multiplicity.clone()
+            } else {
+                self.p3_ext_neg(interaction.multiplicity.clone())
+            };
+
+            let permutation_mul_fingerprint = self.p3_ext_mul(permutation, &fingerprint);
+            folder.assert_eq(permutation_mul_fingerprint, multiplicity, self);
+        }
+
+        let n = interactions.len();
+        let running_sum_local = permutation_local[n].clone();
+        let running_sum_next = permutation_next[n].clone();
+        let sum_local = permutation_local[..n]
+            .iter()
+            .cloned()
+            .reduce(|acc, e| self.p3_ext_add(acc, e))
+            .unwrap();
+        let sum_next = permutation_next[..n]
+            .iter()
+            .cloned()
+            .reduce(|acc, e| self.p3_ext_add(acc, e))
+            .unwrap();
+
+        folder
+            .when_first_row::<F, D>()
+            .assert_eq(running_sum_local.clone(), sum_local, self);
+
+        let running_sum_local_plus_sum_next = self.p3_ext_add(running_sum_local.clone(), sum_next);
+        folder.when_transition::<F, D>().assert_eq(
+            running_sum_next,
+            running_sum_local_plus_sum_next,
+            self,
+        );
+
+        folder
+            .when_last_row::<F, D>()
+            .assert_eq(running_sum_local, cumulative_sum, self);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::extension::quadratic::QuadraticExtension;
+    use plonky2::field::extension::FieldExtension;
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::p3::serde::proof::OpenedValues;
+
+    type F = GoldilocksField;
+    type E = QuadraticExtension<F>;
+    const D: usize = 2;
+
+    fn constant_ext(cb: &mut CircuitBuilder<F, D>, x: E) -> BinomialExtensionField<Target> {
+        BinomialExtensionField {
+            value: x.to_basefield_array().map(|limb| cb.constant(limb)),
+        }
+    }
+
+    /// Opened base columns of the extension column `x` evaluated at a base
+    /// field point, where every limb column is constant.
+    fn base_columns(cb: &mut CircuitBuilder<F, D>, x: E) -> Vec<BinomialExtensionField<Target>> {
+        x.0.into_iter()
+            .map(|limb| constant_ext(cb, E::from(limb)))
+            .collect()
+    }
+
+    #[test]
+    fn test_lookup_constraints_balance() {
+        let mut cb = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+
+        let (alpha, beta) = (E::from_canonical_u64(3), E::from_canonical_u64(5));
+        let value = E::from_canonical_u64(42);
+        let inv_fingerprint = (alpha + value).inverse();
+
+        // A row sending and receiving the same value once.
+        let permutation = [inv_fingerprint, -inv_fingerprint, E::ZERO];
+        let permutation_local: Vec<_> = permutation
+            .iter()
+            .flat_map(|&p| base_columns(&mut cb, p))
+            .collect();
+        let permutation_next = permutation_local.clone();
+
+        let zero = cb.p3_ext_zero();
+        let one = cb.p3_ext_one();
+        let value_target = constant_ext(&mut cb, value);
+        let mut folder = VerifierConstraintFolder {
+            main: OpenedValues {
+                trace_local: vec![],
+                trace_next: vec![],
+                quotient_chunks: vec![],
+                preprocessed_local: vec![],
+                preprocessed_next: vec![],
+                permutation_local,
+                permutation_next,
+                cumulative_sum: Some(zero.clone()),
+            },
+            public_values: vec![],
+            permutation_challenges: vec![constant_ext(&mut cb, alpha), constant_ext(&mut cb, beta)],
+            interactions: vec![],
+            is_first_row: one.clone(),
+            is_last_row: one.clone(),
+            is_transition: zero.clone(),
+            alpha: constant_ext(&mut cb, E::from_canonical_u64(11)),
+            accumulator: zero.clone(),
+        };
+
+        folder.send(vec![value_target.clone()], one.clone());
+        folder.receive(vec![value_target], one);
+        cb.p3_eval_lookup_constraints(&mut folder).unwrap();
+        cb.connect_p3_ext(&folder.accumulator, &zero);
+
+        let data = cb.build::<PoseidonGoldilocksConfig>();
+        let proof = data.prove(PartialWitness::new()).unwrap();
+        data.verify(proof).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_lookup_constraints_reject_imbalanced_interactions() {
+        let mut cb = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+
+        let (alpha, beta) = (E::from_canonical_u64(3), E::from_canonical_u64(5));
+        let (sent, received) = (E::from_canonical_u64(42), E::from_canonical_u64(43));
+
+        // A row sending one value and receiving another, with honest
+        // permutation columns whose running sum can't end on zero.
+        let sent_inv_fingerprint = (alpha + sent).inverse();
+        let received_inv_fingerprint = (alpha + received).inverse();
+        let permutation = [
+            sent_inv_fingerprint,
+            -received_inv_fingerprint,
+            sent_inv_fingerprint - received_inv_fingerprint,
+        ];
+        let permutation_local: Vec<_> = permutation
+            .iter()
+            .flat_map(|&p| base_columns(&mut cb, p))
+            .collect();
+        let permutation_next = permutation_local.clone();
+
+        let zero = cb.p3_ext_zero();
+        let one = cb.p3_ext_one();
+        let sent_target = constant_ext(&mut cb, sent);
+        let received_target = constant_ext(&mut cb, received);
+        let mut folder = VerifierConstraintFolder {
+            main: OpenedValues {
+                trace_local: vec![],
+                trace_next: vec![],
+                quotient_chunks: vec![],
+                preprocessed_local: vec![],
+                preprocessed_next: vec![],
+                permutation_local,
+                permutation_next,
+                cumulative_sum: Some(zero.clone()),
+            },
+            public_values: vec![],
+            permutation_challenges: vec![constant_ext(&mut cb, alpha), constant_ext(&mut cb, beta)],
+            interactions: vec![],
+            is_first_row: one.clone(),
+            is_last_row: one.clone(),
+            is_transition: zero.clone(),
+            alpha: constant_ext(&mut cb, E::from_canonical_u64(11)),
+            accumulator: zero.clone(),
+        };
+
+        folder.send(vec![sent_target], one.clone());
+        folder.receive(vec![received_target], one);
+        cb.p3_eval_lookup_constraints(&mut folder).unwrap();
+        cb.connect_p3_ext(&folder.accumulator, &zero);
+
+        let data = cb.build::<PoseidonGoldilocksConfig>();
+        let proof = data.prove(PartialWitness::new()).unwrap();
+        data.verify(proof).unwrap();
+    }
+}
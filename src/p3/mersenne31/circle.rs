@@ -0,0 +1,339 @@
+use core::ops::Mul;
+
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::field31::extension::CircuitBuilderField31Ext;
+use crate::p3::field31::field::CircuitBuilderField31;
+use crate::p3::field31::field::Field31;
+use crate::p3::mersenne31::Mersenne31;
+use crate::p3::mersenne31::Mersenne31ExtTarget;
+use crate::p3::mersenne31::MERSENNE31_EXT_DEGREE;
+
+/// Point of the circle `x^2 + y^2 = 1`, the group Circle STARKs evaluate
+/// their polynomials over. The group law is the multiplication of `x + i * y`
+/// in the complex extension, so the inverse of a point is its conjugate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CirclePoint<T> {
+    pub x: T,
+    pub y: T,
+}
+
+impl Mul for CirclePoint<u32> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            x: Mersenne31::sub(
+                Mersenne31::mul(self.x, other.x),
+                Mersenne31::mul(self.y, other.y),
+            ),
+            y: Mersenne31::add(
+                Mersenne31::mul(self.x, other.y),
+                Mersenne31::mul(self.y, other.x),
+            ),
+        }
+    }
+}
+
+/// A point with Mersenne31 coordinates, e.g. a queried point of an LDE.
+pub type CirclePointTarget = CirclePoint<Target>;
+/// A point with coordinates in the cubic extension, e.g. an opening point.
+pub type CircleExtPointTarget = CirclePoint<Mersenne31ExtTarget>;
+
+impl CirclePoint<u32> {
+    pub const IDENTITY: Self = Self { x: 1, y: 0 };
+    /// Generator of the whole circle group, of order `2^31`, the
+    /// `circle_two_adic_generator` of Plonky3.
+    pub const GENERATOR: Self = Self {
+        x: 311_014_874,
+        y: 1_584_694_829,
+    };
+    pub const LOG_ORDER: usize = 31;
+
+    /// Generator of the subgroup of order `2^log_n`.
+    pub fn generator(log_n: usize) -> Self {
+        assert!(log_n <= Self::LOG_ORDER);
+        Self::GENERATOR.exp_u64(1 << (Self::LOG_ORDER - log_n))
+    }
+
+    pub fn inverse(self) -> Self {
+        Self {
+            x: self.x,
+            y: Mersenne31::sub(0, self.y),
+        }
+    }
+
+    pub fn exp_u64(self, mut power: u64) -> Self {
+        let mut base = self;
+        let mut res = Self::IDENTITY;
+        while power > 0 {
+            if power & 1 == 1 {
+                res = res * base;
+            }
+            base = base * base;
+            power >>= 1;
+        }
+        res
+    }
+
+    /// The `x` coordinate of `self^(2^(log_n - 1))`, which vanishes on the
+    /// standard domain of size `2^log_n`.
+    pub fn v_n(self, log_n: usize) -> u32 {
+        let mut x = self.x;
+        for _ in 1..log_n {
+            let x2 = Mersenne31::mul(x, x);
+            x = Mersenne31::sub(Mersenne31::add(x2, x2), 1);
+        }
+        x
+    }
+
+    /// The point of the projective line parameter `t`, see
+    /// [`CircuitBuilderCircle::circle_from_projective_line`].
+    pub fn from_projective_line(t: u32) -> Self {
+        let t2 = Mersenne31::mul(t, t);
+        let one_plus_t2_inv = Mersenne31::inverse(Mersenne31::add(1, t2));
+        Self {
+            x: Mersenne31::mul(Mersenne31::sub(1, t2), one_plus_t2_inv),
+            y: Mersenne31::mul(Mersenne31::add(t, t), one_plus_t2_inv),
+        }
+    }
+
+    /// The projective line parameter `y / (1 + x)` of `self`, which mustn't
+    /// be `(-1, 0)`.
+    pub fn to_projective_line(self) -> u32 {
+        Mersenne31::mul(self.y, Mersenne31::inverse(Mersenne31::add(1, self.x)))
+    }
+}
+
+/// Circle group arithmetic over emulated Mersenne31 coordinates.
+pub trait CircuitBuilderCircle<F: RicherField + Extendable<D>, const D: usize> {
+    /// The point `((1 - t^2) / (1 + t^2), 2t / (1 + t^2))` of the projective
+    /// line parameter `t`, which is how Plonky3 samples out of domain points.
+    fn circle_from_projective_line(&mut self, t: &Mersenne31ExtTarget) -> CircleExtPointTarget;
+
+    /// The projective line parameter `y / (1 + x)` of `p`.
+    fn circle_to_projective_line(&mut self, p: &CircleExtPointTarget) -> Mersenne31ExtTarget;
+
+    fn circle_mul_const(
+        &mut self,
+        p: &CircleExtPointTarget,
+        q: CirclePoint<u32>,
+    ) -> CircleExtPointTarget;
+
+    /// [`CirclePoint::v_n`] of an extension point.
+    fn circle_v_n(&mut self, p: &CircleExtPointTarget, log_n: usize) -> Mersenne31ExtTarget;
+
+    /// [`CirclePoint::v_n`] of a base field point.
+    fn circle_base_v_n(&mut self, p: &CirclePointTarget, log_n: usize) -> Target;
+
+    /// `y / (1 + x)` of `at * p^-1`, which has a simple zero at `p`.
+    fn circle_v_tilde_p(
+        &mut self,
+        at: &CircleExtPointTarget,
+        p: CirclePoint<u32>,
+    ) -> Mersenne31ExtTarget;
+
+    /// `shift * generator^e`, where `e` is given by its little-endian `bits`.
+    fn circle_exp_const_base(
+        &mut self,
+        shift: CirclePoint<u32>,
+        generator: CirclePoint<u32>,
+        bits: &[BoolTarget],
+    ) -> CirclePointTarget;
+
+    fn circle_conjugate_if(&mut self, cond: BoolTarget, p: CirclePointTarget) -> CirclePointTarget;
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderCircle<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn circle_from_projective_line(&mut self, t: &Mersenne31ExtTarget) -> CircleExtPointTarget {
+        let t2 = self.f31_ext_square::<Mersenne31, MERSENNE31_EXT_DEGREE>(t);
+        let one = self.f31_one();
+        let one_plus_t2 =
+            self.f31_ext_add_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(t2.clone(), one);
+        let one_plus_t2_inv =
+            self.f31_ext_inverse::<Mersenne31, MERSENNE31_EXT_DEGREE>(one_plus_t2);
+
+        let t2_neg = self.f31_ext_neg::<Mersenne31, MERSENNE31_EXT_DEGREE>(t2);
+        let one_minus_t2 = self.f31_ext_add_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(t2_neg, one);
+        let two_t = self.f31_ext_add::<Mersenne31, MERSENNE31_EXT_DEGREE>(t.clone(), t.clone());
+
+        CirclePoint {
+            x: self
+                .f31_ext_mul::<Mersenne31, MERSENNE31_EXT_DEGREE>(&one_minus_t2, &one_plus_t2_inv),
+            y: self.f31_ext_mul::<Mersenne31, MERSENNE31_EXT_DEGREE>(&two_t, &one_plus_t2_inv),
+        }
+    }
+
+    fn circle_to_projective_line(&mut self, p: &CircleExtPointTarget) -> Mersenne31ExtTarget {
+        let one = self.f31_one();
+        let one_plus_x =
+            self.f31_ext_add_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(p.x.clone(), one);
+        self.f31_ext_div::<Mersenne31, MERSENNE31_EXT_DEGREE>(p.y.clone(), one_plus_x)
+    }
+
+    fn circle_mul_const(
+        &mut self,
+        p: &CircleExtPointTarget,
+        q: CirclePoint<u32>,
+    ) -> CircleExtPointTarget {
+        let px_qx = self.f31_ext_mul_const::<Mersenne31, MERSENNE31_EXT_DEGREE>(q.x, &p.x);
+        let py_qy = self.f31_ext_mul_const::<Mersenne31, MERSENNE31_EXT_DEGREE>(q.y, &p.y);
+        let px_qy = self.f31_ext_mul_const::<Mersenne31, MERSENNE31_EXT_DEGREE>(q.y, &p.x);
+        let py_qx = self.f31_ext_mul_const::<Mersenne31, MERSENNE31_EXT_DEGREE>(q.x, &p.y);
+
+        CirclePoint {
+            x: self.f31_ext_sub::<Mersenne31, MERSENNE31_EXT_DEGREE>(px_qx, py_qy),
+            y: self.f31_ext_add::<Mersenne31, MERSENNE31_EXT_DEGREE>(px_qy, py_qx),
+        }
+    }
+
+    fn circle_v_n(&mut self, p: &CircleExtPointTarget, log_n: usize) -> Mersenne31ExtTarget {
+        let one = self.f31_one();
+        let mut x = p.x.clone();
+        for _ in 1..log_n {
+            let x2 = self.f31_ext_square::<Mersenne31, MERSENNE31_EXT_DEGREE>(&x);
+            let two_x2 = self.f31_ext_add::<Mersenne31, MERSENNE31_EXT_DEGREE>(x2.clone(), x2);
+            x = self.f31_ext_sub_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(two_x2, one);
+        }
+        x
+    }
+
+    fn circle_base_v_n(&mut self, p: &CirclePointTarget, log_n: usize) -> Target {
+        let one = self.f31_one();
+        let mut x = p.x;
+        for _ in 1..log_n {
+            let x2 = self.f31_square::<Mersenne31>(x);
+            let two_x2 = self.f31_add::<Mersenne31>(x2, x2);
+            x = self.f31_sub::<Mersenne31>(two_x2, one);
+        }
+        x
+    }
+
+    fn circle_v_tilde_p(
+        &mut self,
+        at: &CircleExtPointTarget,
+        p: CirclePoint<u32>,
+    ) -> Mersenne31ExtTarget {
+        let diff = self.circle_mul_const(at, p.inverse());
+        let one = self.f31_one();
+        let one_plus_x = self.f31_ext_add_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(diff.x, one);
+        self.f31_ext_div::<Mersenne31, MERSENNE31_EXT_DEGREE>(diff.y, one_plus_x)
+    }
+
+    fn circle_exp_const_base(
+        &mut self,
+        shift: CirclePoint<u32>,
+        generator: CirclePoint<u32>,
+        bits: &[BoolTarget],
+    ) -> CirclePointTarget {
+        let mut res = CirclePoint {
+            x: self.f31_constant::<Mersenne31>(shift.x),
+            y: self.f31_constant::<Mersenne31>(shift.y),
+        };
+        let mut generator_pow = generator;
+        for &bit in bits {
+            let x_gx = self.f31_mul_const::<Mersenne31>(generator_pow.x, res.x);
+            let y_gy = self.f31_mul_const::<Mersenne31>(generator_pow.y, res.y);
+            let x_gy = self.f31_mul_const::<Mersenne31>(generator_pow.y, res.x);
+            let y_gx = self.f31_mul_const::<Mersenne31>(generator_pow.x, res.y);
+            let product = CirclePoint {
+                x: self.f31_sub::<Mersenne31>(x_gx, y_gy),
+                y: self.f31_add::<Mersenne31>(x_gy, y_gx),
+            };
+
+            res = CirclePoint {
+                x: self._if(bit, product.x, res.x),
+                y: self._if(bit, product.y, res.y),
+            };
+            generator_pow = generator_pow * generator_pow;
+        }
+        res
+    }
+
+    fn circle_conjugate_if(&mut self, cond: BoolTarget, p: CirclePointTarget) -> CirclePointTarget {
+        let y_neg = self.f31_neg::<Mersenne31>(p.y);
+        CirclePoint {
+            x: p.x,
+            y: self._if(cond, y_neg, p.y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::iop::witness::WitnessWrite;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_native_circle_group() {
+        let g = CirclePoint::GENERATOR;
+        assert_eq!(
+            Mersenne31::add(Mersenne31::mul(g.x, g.x), Mersenne31::mul(g.y, g.y)),
+            1
+        );
+        assert_eq!(g.exp_u64(1 << 31), CirclePoint::IDENTITY);
+        assert_ne!(g.exp_u64(1 << 30), CirclePoint::IDENTITY);
+        assert_eq!(
+            CirclePoint::generator(1),
+            CirclePoint {
+                x: Mersenne31::ORDER - 1,
+                y: 0
+            }
+        );
+        assert_eq!(g * g.inverse(), CirclePoint::IDENTITY);
+        assert_eq!(CirclePoint::from_projective_line(g.to_projective_line()), g);
+
+        // The standard domain of size 2^log_n is the coset of the subgroup of
+        // order 2^log_n by a generator of order 2^(log_n + 1).
+        let log_n = 5;
+        let shift = CirclePoint::generator(log_n + 1);
+        for i in 0..1 << log_n {
+            let p = shift * CirclePoint::generator(log_n).exp_u64(i);
+            assert_eq!(p.v_n(log_n), 0);
+        }
+    }
+
+    #[test]
+    fn test_exp_const_base() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let mut rng = rand::thread_rng();
+        let e = rng.gen_range(0..1u64 << 10);
+        let shift = CirclePoint::generator(12);
+        let generator = CirclePoint::generator(10);
+        let expected = shift * generator.exp_u64(e);
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let e_t = builder.add_virtual_target();
+        let bits = builder.split_le(e_t, 10);
+        let res = builder.circle_exp_const_base(shift, generator, &bits);
+        let v_n = builder.circle_base_v_n(&res, 11);
+        let expected_x = builder.f31_constant::<Mersenne31>(expected.x);
+        let expected_y = builder.f31_constant::<Mersenne31>(expected.y);
+        let expected_v_n = builder.f31_constant::<Mersenne31>(expected.v_n(11));
+        builder.connect(res.x, expected_x);
+        builder.connect(res.y, expected_y);
+        builder.connect(v_n, expected_v_n);
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(e_t, F::from_canonical_u64(e));
+        let proof = data.prove(pw).unwrap();
+        assert!(data.verify(proof).is_ok());
+    }
+}
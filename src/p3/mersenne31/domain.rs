@@ -0,0 +1,177 @@
+use plonky2::field::extension::Extendable;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::field31::domain::Field31Domain;
+use crate::p3::field31::extension::CircuitBuilderField31Ext;
+use crate::p3::field31::field::CircuitBuilderField31;
+use crate::p3::field31::field::Field31;
+use crate::p3::mersenne31::circle::CircleExtPointTarget;
+use crate::p3::mersenne31::circle::CirclePoint;
+use crate::p3::mersenne31::circle::CircuitBuilderCircle;
+use crate::p3::mersenne31::Mersenne31;
+use crate::p3::mersenne31::Mersenne31ExtTarget;
+use crate::p3::mersenne31::MERSENNE31_EXT_DEGREE;
+use crate::p3::serde::LagrangeSelectors;
+use crate::p3::utils::log2_ceil_usize;
+use crate::p3::utils::log2_strict_usize;
+
+/// Twin coset `shift * G ∪ shift^-1 * G` of the subgroup `G` of order
+/// `2^(log_n - 1)`, the circle analogue of
+/// [`TwoAdicCoset`](crate::p3::field31::domain::TwoAdicCoset). As in
+/// Plonky3, the points a domain is evaluated at are given by their projective
+/// line parameter, see [`CirclePoint::from_projective_line`].
+#[derive(Clone, Copy, Debug)]
+pub struct CircleDomain {
+    pub log_n: usize,
+    pub shift: CirclePoint<u32>,
+}
+
+impl CircleDomain {
+    /// The domain of size `2^log_n` which is also a coset of the subgroup of
+    /// order `2^log_n`.
+    pub fn standard(log_n: usize) -> Self {
+        Self {
+            log_n,
+            shift: CirclePoint::generator(log_n + 1),
+        }
+    }
+
+    pub fn natural_domain_for_degree(degree: usize) -> Self {
+        Self::standard(log2_strict_usize(degree))
+    }
+
+    /// Generator of the rows of a standard domain.
+    pub fn gen(&self) -> CirclePoint<u32> {
+        CirclePoint::generator(self.log_n)
+    }
+
+    pub fn subgroup_generator(&self) -> CirclePoint<u32> {
+        CirclePoint::generator(self.log_n - 1)
+    }
+
+    /// The last row of a standard domain, the conjugate of its first.
+    pub fn last_point(&self) -> CirclePoint<u32> {
+        self.shift.inverse()
+    }
+
+    /// The vanishing polynomial of `self` at a point of the circle.
+    fn vanishing_poly<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        at: &CircleExtPointTarget,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Mersenne31ExtTarget {
+        let v_n = cb.circle_v_n(at, self.log_n);
+        let shift_v_n = cb.f31_constant::<Mersenne31>(self.shift.v_n(self.log_n));
+        cb.f31_ext_sub_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(v_n, shift_v_n)
+    }
+
+    /// `lim_{x -> p} zp(x) / v_tilde_p(x)` for a point `p` of the domain,
+    /// i.e. `-2 * v_n'(p) * p.y`, with
+    /// `v_n'(p) = 2^(2 * (log_n - 1)) * prod_{i < log_n} v_i(p)`.
+    fn s_p_at_p(&self, p: CirclePoint<u32>) -> u32 {
+        let v_n_prime = (1..self.log_n).fold(
+            Mersenne31::exp_u64(2, 2 * (self.log_n as u64 - 1)),
+            |acc, i| Mersenne31::mul(acc, p.v_n(i)),
+        );
+        Mersenne31::sub(
+            0,
+            Mersenne31::mul(Mersenne31::add(v_n_prime, v_n_prime), p.y),
+        )
+    }
+}
+
+impl Field31Domain<Mersenne31, MERSENNE31_EXT_DEGREE> for CircleDomain {
+    fn size(&self) -> usize {
+        1 << self.log_n
+    }
+
+    fn first_point(&self) -> u32 {
+        self.shift.to_projective_line()
+    }
+
+    /// A larger standard domain, unless `min_size` is already larger, as
+    /// standard domains of different sizes are disjoint.
+    fn create_disjoint_domain(&self, min_size: usize) -> Self {
+        let log_n = log2_ceil_usize(min_size);
+        Self::standard(if log_n == self.log_n {
+            log_n + 1
+        } else {
+            log_n
+        })
+    }
+
+    /// The domains shifted by the first `num_chunks` points of `self`, which
+    /// all lie in the coset `shift * G`.
+    fn split_domains(&self, num_chunks: usize) -> Vec<Self> {
+        let log_chunks = log2_strict_usize(num_chunks);
+        assert!(log_chunks < self.log_n);
+        let subgroup_generator = self.subgroup_generator();
+        (0..num_chunks)
+            .map(|i| Self {
+                log_n: self.log_n - log_chunks,
+                shift: self.shift * subgroup_generator.exp_u64(i as u64),
+            })
+            .collect()
+    }
+
+    fn next_point<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        x: &Mersenne31ExtTarget,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Mersenne31ExtTarget {
+        let point = cb.circle_from_projective_line(x);
+        let next_point = cb.circle_mul_const(&point, self.gen());
+        cb.circle_to_projective_line(&next_point)
+    }
+
+    fn selectors_at_point<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        point: &Mersenne31ExtTarget,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> LagrangeSelectors<Mersenne31ExtTarget> {
+        let point = cb.circle_from_projective_line(point);
+        let zeroifier = self.vanishing_poly(&point, cb);
+
+        let v_tilde_first = cb.circle_v_tilde_p(&point, self.shift);
+        let is_first_row =
+            cb.f31_ext_div::<Mersenne31, MERSENNE31_EXT_DEGREE>(zeroifier.clone(), v_tilde_first);
+
+        let v_tilde_last = cb.circle_v_tilde_p(&point, self.last_point());
+        let is_last_row =
+            cb.f31_ext_div::<Mersenne31, MERSENNE31_EXT_DEGREE>(zeroifier.clone(), v_tilde_last);
+
+        // Zero at the last row, where the normalized selector is one, and one at
+        // the other rows.
+        let is_last_row_normalized = cb.f31_ext_mul_const::<Mersenne31, MERSENNE31_EXT_DEGREE>(
+            Mersenne31::inverse(self.s_p_at_p(self.last_point())),
+            &is_last_row,
+        );
+        let is_last_row_normalized_neg =
+            cb.f31_ext_neg::<Mersenne31, MERSENNE31_EXT_DEGREE>(is_last_row_normalized);
+        let one = cb.f31_one();
+        let is_transition = cb
+            .f31_ext_add_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(is_last_row_normalized_neg, one);
+
+        LagrangeSelectors {
+            is_first_row,
+            is_last_row,
+            is_transition,
+            inv_zeroifier: cb.f31_ext_inverse::<Mersenne31, MERSENNE31_EXT_DEGREE>(zeroifier),
+        }
+    }
+
+    fn zp_at_point<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        point: &Mersenne31ExtTarget,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Mersenne31ExtTarget {
+        let point = cb.circle_from_projective_line(point);
+        self.vanishing_poly(&point, cb)
+    }
+
+    fn zp_at_single_point(&self, point: u32) -> u32 {
+        let point = CirclePoint::from_projective_line(point);
+        Mersenne31::sub(point.v_n(self.log_n), self.shift.v_n(self.log_n))
+    }
+}
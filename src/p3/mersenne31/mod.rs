@@ -0,0 +1,98 @@
+//! Verification of Plonky3 Circle STARK proofs over Mersenne31, the field of
+//! the standard `Mersenne31` + Poseidon2 config, with challenges in its cubic
+//! extension. Mersenne31 has no large two-adic subgroup, so traces are
+//! committed over twin cosets of the circle group and opened with the Circle
+//! PCS, see [`pcs::CirclePcs`]. The verifier is otherwise the shared one of
+//! [`crate::p3::field31`], with the constants of [`Mersenne31`].
+
+pub mod circle;
+pub mod domain;
+pub mod pcs;
+pub mod verifier;
+
+use plonky2::iop::target::Target;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::field31::extension::BinomialExtension;
+use crate::p3::field31::extension::Field31ExtTarget;
+use crate::p3::field31::field::Field31;
+use crate::p3::field31::poseidon2::Field31Poseidon2;
+use crate::p3::field31::poseidon2::Poseidon2Field31;
+use crate::p3::field31::poseidon2::Poseidon2Params;
+use crate::p3::field31::poseidon2::POSEIDON2_WIDTH;
+use crate::p3::serde::circle::CircleProof;
+use crate::p3::serde::proof::Value;
+use crate::p3::verifier::P3VerifierError;
+
+pub const MERSENNE31_EXT_DEGREE: usize = 3;
+pub const MERSENNE31_DIGEST_ELEMS: usize = 8;
+pub const MERSENNE31_WIDTH: usize = POSEIDON2_WIDTH;
+/// Rate of the padding-free sponge hashing the Merkle leaves.
+pub const MERSENNE31_RATE: usize = 8;
+
+/// The Mersenne31 field `p = 2^31 - 1`.
+#[derive(Clone, Copy, Debug)]
+pub struct Mersenne31;
+
+impl Field31 for Mersenne31 {
+    const ORDER: u32 = 0x7fff_ffff;
+    const NAME: &'static str = "Mersenne31";
+}
+
+impl BinomialExtension<MERSENNE31_EXT_DEGREE> for Mersenne31 {
+    const W: u32 = 5;
+}
+
+impl Poseidon2Field31 for Mersenne31 {
+    const SBOX_DEGREE: u64 = 5;
+    const INTERNAL_DIAG_M_1: [u32; POSEIDON2_WIDTH] = [
+        Self::ORDER - 2,
+        1,
+        1 << 1,
+        1 << 2,
+        1 << 3,
+        1 << 4,
+        1 << 5,
+        1 << 6,
+        1 << 7,
+        1 << 8,
+        1 << 10,
+        1 << 12,
+        1 << 13,
+        1 << 14,
+        1 << 15,
+        1 << 16,
+    ];
+    /// Mersenne31 elements aren't kept in Montgomery form.
+    const INTERNAL_SCALE: u32 = 1;
+}
+
+pub type Mersenne31ExtTarget = Field31ExtTarget<MERSENNE31_EXT_DEGREE>;
+pub type Mersenne31Poseidon2 = Field31Poseidon2<Mersenne31>;
+pub type Mersenne31Poseidon2Params = Poseidon2Params<Mersenne31>;
+
+/// A Mersenne31 element as serialized by Plonky3, in canonical form.
+pub type Mersenne31Field = Value<u32>;
+pub type Mersenne31ProofField = CircleProof<Mersenne31Field, MERSENNE31_EXT_DEGREE>;
+pub type Mersenne31Proof = CircleProof<Target, MERSENNE31_EXT_DEGREE>;
+
+/// Converts a serialized Mersenne31 proof to values in `F`, ready for
+/// [`CircleProof::<Target>::set_witness`]. Plonky3 keeps Mersenne31 elements in
+/// `[0, p]`, so `p` is read as zero; larger elements are rejected.
+pub fn mersenne31_proof_to_field<F: RicherField>(
+    proof: &Mersenne31ProofField,
+) -> Result<CircleProof<Value<F>, MERSENNE31_EXT_DEGREE>, P3VerifierError> {
+    let mut canonical = true;
+    let proof = proof.clone().map(|v| {
+        canonical &= v.value <= Mersenne31::ORDER;
+        Value {
+            value: F::from_canonical_u32(v.value % Mersenne31::ORDER),
+        }
+    });
+    if !canonical {
+        return Err(P3VerifierError::InvalidProofShape(
+            "Mersenne31 proof element isn't canonical",
+        ));
+    }
+    Ok(proof)
+}
@@ -0,0 +1,448 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use itertools::izip;
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::challenger::DuplexChallenger;
+use crate::p3::challenger::DuplexChallengerTarget;
+use crate::p3::commit::MerkleTreeMmcs;
+use crate::p3::commit::MmcsError;
+use crate::p3::commit::P3Mmcs;
+use crate::p3::field31::extension::CircuitBuilderField31Ext;
+use crate::p3::field31::field::CircuitBuilderField31;
+use crate::p3::field31::fri::f31_fold_pair;
+use crate::p3::field31::fri::f31_verify_fri;
+use crate::p3::field31::fri::Field31Fri;
+use crate::p3::field31::pcs::BatchPoints;
+use crate::p3::field31::pcs::Field31Pcs;
+use crate::p3::mersenne31::circle::CircleExtPointTarget;
+use crate::p3::mersenne31::circle::CirclePoint;
+use crate::p3::mersenne31::circle::CirclePointTarget;
+use crate::p3::mersenne31::circle::CircuitBuilderCircle;
+use crate::p3::mersenne31::domain::CircleDomain;
+use crate::p3::mersenne31::Mersenne31;
+use crate::p3::mersenne31::Mersenne31ExtTarget;
+use crate::p3::mersenne31::Mersenne31Poseidon2;
+use crate::p3::mersenne31::MERSENNE31_EXT_DEGREE;
+use crate::p3::serde::circle::CirclePcsProof;
+use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::fri::FriError;
+use crate::p3::serde::proof::Commitment;
+use crate::p3::serde::Dimensions;
+use crate::p3::verifier::P3VerifierError;
+
+/// Plonky3's `CirclePcs`. Codewords are committed over twin cosets of the
+/// circle group, and the first FRI layer folds them by conjugation
+/// `(x, y) -> (x, -y)` onto the `x` coordinate, after which the usual folding
+/// by `x -> 2x^2 - 1` takes over.
+pub struct CirclePcs {
+    pub fri_config: FriConfig,
+    pub mmcs: MerkleTreeMmcs<Mersenne31Poseidon2>,
+}
+
+impl Field31Fri<Mersenne31> for CirclePcs {
+    /// The lowest bit selects the row of the first layer pair.
+    const EXTRA_QUERY_INDEX_BITS: usize = 1;
+
+    fn fri_config(&self) -> &FriConfig {
+        &self.fri_config
+    }
+
+    fn mmcs(&self) -> &MerkleTreeMmcs<Mersenne31Poseidon2> {
+        &self.mmcs
+    }
+
+    /// The rows of a codeword of height `2^n` are the `x` coordinates of
+    /// `shift * g^rev(i)` for the generator `g` of the subgroup of order
+    /// `2^n`, and a `shift` of order `2^(n + 2)`, as the `x` coordinates of
+    /// the standard domain of size `2^(n + 1)` it was folded from.
+    fn query_coordinate<F: RicherField + Extendable<D>, const D: usize>(
+        cb: &mut CircuitBuilder<F, D>,
+        index_bits: &[BoolTarget],
+    ) -> Target {
+        let log_height = index_bits.len();
+        let rev_index = index_bits.iter().rev().copied().collect::<Vec<_>>();
+        cb.circle_exp_const_base(
+            CirclePoint::generator(log_height + 2),
+            CirclePoint::generator(log_height),
+            &rev_index,
+        )
+        .x
+    }
+
+    fn fold_coordinate<F: RicherField + Extendable<D>, const D: usize>(
+        cb: &mut CircuitBuilder<F, D>,
+        x: Target,
+    ) -> Target {
+        let x2 = cb.f31_square::<Mersenne31>(x);
+        let two_x2 = cb.f31_add::<Mersenne31>(x2, x2);
+        let one = cb.f31_one();
+        cb.f31_sub::<Mersenne31>(two_x2, one)
+    }
+}
+
+impl Field31Pcs<Mersenne31, MERSENNE31_EXT_DEGREE> for CirclePcs {
+    type Domain = CircleDomain;
+    type Proof = CirclePcsProof<Target, MERSENNE31_EXT_DEGREE>;
+
+    fn natural_domain_for_degree(&self, degree: usize) -> CircleDomain {
+        CircleDomain::natural_domain_for_degree(degree)
+    }
+
+    fn verify<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        rounds: Vec<(
+            Commitment<Target>,
+            BatchPoints<CircleDomain, MERSENNE31_EXT_DEGREE>,
+        )>,
+        proof: &CirclePcsProof<Target, MERSENNE31_EXT_DEGREE>,
+        challenger: &mut DuplexChallengerTarget<Mersenne31Poseidon2>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Result<(), P3VerifierError> {
+        // Batch combination challenge.
+        let alpha = cb.p3_sample_ext::<Mersenne31Poseidon2, MERSENNE31_EXT_DEGREE>(challenger);
+        cb.p3_observe::<Mersenne31Poseidon2>(
+            challenger,
+            proof.first_layer_commitment.value.iter().copied(),
+        );
+        let bivariate_beta =
+            cb.p3_sample_ext::<Mersenne31Poseidon2, MERSENNE31_EXT_DEGREE>(challenger);
+
+        let log_blowup = self.fri_config.log_blowup;
+        // The first layer halves the height before the commit phases start.
+        let log_global_max_height = proof.fri_proof.commit_phase_commits.len() + log_blowup + 1;
+
+        // There is one lambda per height of the committed matrices, lowest
+        // first, as the reduced openings are rolled into the first layer.
+        let log_heights = rounds
+            .iter()
+            .flat_map(|(_, mats)| mats.iter().map(|(domain, _)| domain.log_n + log_blowup))
+            .collect::<BTreeSet<_>>();
+        if log_heights.len() != proof.lambdas.len()
+            || log_heights
+                .iter()
+                .any(|&h| h < 2 || h > log_global_max_height)
+        {
+            return Err(FriError::InvalidProofShape.into());
+        }
+
+        // Opening points are sampled on the projective line.
+        let rounds = rounds
+            .into_iter()
+            .map(|(commit, mats)| {
+                let mats = mats
+                    .into_iter()
+                    .map(|(domain, points_and_values)| {
+                        let points_and_values = points_and_values
+                            .into_iter()
+                            .map(|(z, ps_at_z)| (cb.circle_from_projective_line(&z), ps_at_z))
+                            .collect::<Vec<_>>();
+                        (domain, points_and_values)
+                    })
+                    .collect::<Vec<_>>();
+                (commit, mats)
+            })
+            .collect::<Vec<_>>();
+
+        f31_verify_fri::<F, D, Mersenne31, MERSENNE31_EXT_DEGREE, Self, _>(
+            cb,
+            self,
+            &proof.fri_proof,
+            &proof.query_openings,
+            challenger,
+            |cb, index_bits, query_opening| {
+                if query_opening.input_openings.len() != rounds.len() {
+                    return Err(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize));
+                }
+
+                // Openings are reduced per height, each with its own powers of
+                // alpha.
+                let mut reduced_openings = BTreeMap::new();
+                for (batch_opening, (batch_commit, mats)) in
+                    izip!(&query_opening.input_openings, &rounds)
+                {
+                    if batch_opening.opened_values.len() != mats.len() {
+                        return Err(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize));
+                    }
+                    let log_batch_max_height = mats
+                        .iter()
+                        .map(|(domain, _)| domain.log_n + log_blowup)
+                        .max()
+                        .ok_or(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize))?;
+                    let bits_reduced = log_global_max_height - log_batch_max_height;
+                    let reduced_index = cb.le_sum(index_bits[bits_reduced..].iter());
+
+                    let batch_dims: Vec<Dimensions> = mats
+                        .iter()
+                        .map(|(domain, _)| Dimensions {
+                            width: 0,
+                            height: 1 << (domain.log_n + log_blowup),
+                        })
+                        .collect();
+                    let always = cb._true();
+                    self.mmcs
+                        .verify_batch(
+                            &batch_commit.value,
+                            &batch_dims,
+                            reduced_index,
+                            &batch_opening.opened_values,
+                            &batch_opening.opening_proof,
+                            always,
+                            cb,
+                        )
+                        .map_err(P3VerifierError::BatchMmcs)?;
+
+                    for (mat_opening, (mat_domain, mat_points_and_values)) in
+                        izip!(&batch_opening.opened_values, mats)
+                    {
+                        let log_height = mat_domain.log_n + log_blowup;
+                        let bits_reduced = log_global_max_height - log_height;
+                        let x = circle_lde_point(cb, &index_bits[bits_reduced..]);
+
+                        // alpha^i for i < width, and alpha^width.
+                        let mut alpha_pows = vec![cb.f31_ext_one()];
+                        for _ in 0..mat_opening.len() {
+                            let next = cb.f31_ext_mul::<Mersenne31, MERSENNE31_EXT_DEGREE>(
+                                alpha_pows.last().unwrap(),
+                                &alpha,
+                            );
+                            alpha_pows.push(next);
+                        }
+                        let alpha_pow_width = alpha_pows.pop().unwrap();
+                        let alpha_pow_2_width = cb
+                            .f31_ext_square::<Mersenne31, MERSENNE31_EXT_DEGREE>(&alpha_pow_width);
+
+                        let p_at_x = izip!(mat_opening, &alpha_pows)
+                            .map(|(&p, alpha_pow)| {
+                                cb.f31_ext_mul_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(
+                                    alpha_pow, p,
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                            .into_iter()
+                            .fold(cb.f31_ext_zero(), |acc, e| {
+                                cb.f31_ext_add::<Mersenne31, MERSENNE31_EXT_DEGREE>(acc, e)
+                            });
+
+                        let (alpha_offset, ro) = reduced_openings
+                            .entry(log_height)
+                            .or_insert_with(|| (cb.f31_ext_one(), cb.f31_ext_zero()));
+                        for (z, ps_at_z) in mat_points_and_values {
+                            if ps_at_z.len() != mat_opening.len() {
+                                return Err(P3VerifierError::BatchMmcs(MmcsError::WrongWidth));
+                            }
+                            let p_at_z = izip!(ps_at_z, &alpha_pows)
+                                .map(|(p, alpha_pow)| {
+                                    cb.f31_ext_mul::<Mersenne31, MERSENNE31_EXT_DEGREE>(
+                                        alpha_pow, p,
+                                    )
+                                })
+                                .collect::<Vec<_>>()
+                                .into_iter()
+                                .fold(cb.f31_ext_zero(), |acc, e| {
+                                    cb.f31_ext_add::<Mersenne31, MERSENNE31_EXT_DEGREE>(acc, e)
+                                });
+
+                            let vanishing = circle_deep_vanishing(cb, z, &x, &alpha_pow_width);
+                            let p_at_x_minus_p_at_z = cb
+                                .f31_ext_sub::<Mersenne31, MERSENNE31_EXT_DEGREE>(
+                                    p_at_x.clone(),
+                                    p_at_z,
+                                );
+                            let quotient = cb.f31_ext_mul::<Mersenne31, MERSENNE31_EXT_DEGREE>(
+                                &vanishing,
+                                &p_at_x_minus_p_at_z,
+                            );
+                            let alpha_offset_mul_quotient = cb
+                                .f31_ext_mul::<Mersenne31, MERSENNE31_EXT_DEGREE>(
+                                    alpha_offset,
+                                    &quotient,
+                                );
+                            *ro = cb.f31_ext_add::<Mersenne31, MERSENNE31_EXT_DEGREE>(
+                                ro.clone(),
+                                alpha_offset_mul_quotient,
+                            );
+                            *alpha_offset = cb.f31_ext_mul::<Mersenne31, MERSENNE31_EXT_DEGREE>(
+                                alpha_offset,
+                                &alpha_pow_2_width,
+                            );
+                        }
+                    }
+                }
+
+                if query_opening.first_layer_siblings.len() != proof.lambdas.len() {
+                    return Err(FriError::InvalidProofShape.into());
+                }
+
+                // The first layer folds the reduced openings of every height,
+                // less their multiple of the vanishing polynomial, into
+                // univariate codewords of half their height.
+                let mut fri_inputs = vec![];
+                let mut first_layer_dims = vec![];
+                let mut first_layer_leaves = vec![];
+                for ((log_height, (_, ro)), sibling, lambda) in izip!(
+                    reduced_openings,
+                    &query_opening.first_layer_siblings,
+                    &proof.lambdas
+                ) {
+                    let bits_reduced = log_global_max_height - log_height;
+                    let x = circle_lde_point(cb, &index_bits[bits_reduced..]);
+                    let is_conjugate = index_bits[bits_reduced];
+
+                    let v_n = cb.circle_base_v_n(&x, log_height - log_blowup);
+                    let lambda_mul_v_n =
+                        cb.f31_ext_mul_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(lambda, v_n);
+                    let corrected =
+                        cb.f31_ext_sub::<Mersenne31, MERSENNE31_EXT_DEGREE>(ro, lambda_mul_v_n);
+
+                    let evals = [
+                        cb.f31_ext_if(is_conjugate, sibling.clone(), corrected.clone()),
+                        cb.f31_ext_if(is_conjugate, corrected, sibling.clone()),
+                    ];
+
+                    // The pair of points is {p, conj(p)}, ordered by the
+                    // lowest bit.
+                    let y_neg = cb.f31_neg::<Mersenne31>(x.y);
+                    let y_even = cb._if(is_conjugate, y_neg, x.y);
+                    let folded = f31_fold_pair::<F, D, Mersenne31, MERSENNE31_EXT_DEGREE>(
+                        cb,
+                        &evals,
+                        &bivariate_beta,
+                        y_even,
+                    );
+
+                    fri_inputs.push((log_height - 1, folded));
+                    first_layer_dims.push(Dimensions {
+                        width: 2 * MERSENNE31_EXT_DEGREE,
+                        height: 1 << (log_height - 1),
+                    });
+                    first_layer_leaves.push(evals.iter().flat_map(|e| e.value).collect());
+                }
+
+                let pair_index = cb.le_sum(index_bits[1..].iter());
+                let always = cb._true();
+                self.mmcs
+                    .verify_batch(
+                        &proof.first_layer_commitment.value,
+                        &first_layer_dims,
+                        pair_index,
+                        &first_layer_leaves,
+                        &query_opening.first_layer_proof,
+                        always,
+                        cb,
+                    )
+                    .map_err(P3VerifierError::CommitPhaseMmcs)?;
+
+                fri_inputs.reverse();
+                Ok(fri_inputs)
+            },
+        )
+    }
+}
+
+/// The point of an LDE of height `2^index_bits.len()` committed at the row
+/// given by the little-endian `index_bits`, i.e. `nth_point` of its standard
+/// domain at the `cfft_permute_index` of the row. Rows come in conjugate
+/// pairs, the lowest bit selecting the conjugate, and the pairs are laid out
+/// in bit-reversed order of the subgroup of order `2^(index_bits.len() - 1)`.
+fn circle_lde_point<F: RicherField + Extendable<D>, const D: usize>(
+    cb: &mut CircuitBuilder<F, D>,
+    index_bits: &[BoolTarget],
+) -> CirclePointTarget {
+    let log_height = index_bits.len();
+    let rev_index = index_bits[1..].iter().rev().copied().collect::<Vec<_>>();
+    let point = cb.circle_exp_const_base(
+        CirclePoint::generator(log_height + 1),
+        CirclePoint::generator(log_height - 1),
+        &rev_index,
+    );
+    cb.circle_conjugate_if(index_bits[0], point)
+}
+
+/// The vanishing part of the DEEP quotient of the opening at `z` of the row
+/// at `x`: the real and imaginary parts of `1 - conj(z) * x`, combined with
+/// `alpha^width`, over its norm.
+fn circle_deep_vanishing<F: RicherField + Extendable<D>, const D: usize>(
+    cb: &mut CircuitBuilder<F, D>,
+    z: &CircleExtPointTarget,
+    x: &CirclePointTarget,
+    alpha_pow_width: &Mersenne31ExtTarget,
+) -> Mersenne31ExtTarget {
+    let zx_xx = cb.f31_ext_mul_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(&z.x, x.x);
+    let zy_xy = cb.f31_ext_mul_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(&z.y, x.y);
+    let zy_xx = cb.f31_ext_mul_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(&z.y, x.x);
+    let zx_xy = cb.f31_ext_mul_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(&z.x, x.y);
+    let diff = CirclePoint {
+        x: cb.f31_ext_add::<Mersenne31, MERSENNE31_EXT_DEGREE>(zx_xx, zy_xy),
+        y: cb.f31_ext_sub::<Mersenne31, MERSENNE31_EXT_DEGREE>(zx_xy, zy_xx),
+    };
+
+    let one = cb.f31_one();
+    let diff_x_neg = cb.f31_ext_neg::<Mersenne31, MERSENNE31_EXT_DEGREE>(diff.x);
+    let re = cb.f31_ext_add_base::<Mersenne31, MERSENNE31_EXT_DEGREE>(diff_x_neg, one);
+    let im = cb.f31_ext_neg::<Mersenne31, MERSENNE31_EXT_DEGREE>(diff.y);
+    let alpha_pow_width_mul_im =
+        cb.f31_ext_mul::<Mersenne31, MERSENNE31_EXT_DEGREE>(alpha_pow_width, &im);
+    let numerator =
+        cb.f31_ext_sub::<Mersenne31, MERSENNE31_EXT_DEGREE>(re.clone(), alpha_pow_width_mul_im);
+    let re2 = cb.f31_ext_square::<Mersenne31, MERSENNE31_EXT_DEGREE>(&re);
+    let im2 = cb.f31_ext_square::<Mersenne31, MERSENNE31_EXT_DEGREE>(&im);
+    let denominator = cb.f31_ext_add::<Mersenne31, MERSENNE31_EXT_DEGREE>(re2, im2);
+    cb.f31_ext_div::<Mersenne31, MERSENNE31_EXT_DEGREE>(numerator, denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::iop::witness::WitnessWrite;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use rand::Rng;
+
+    use super::*;
+    use crate::p3::field31::domain::Field31Domain;
+
+    #[test]
+    fn test_lde_point() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let log_height = 8;
+        let mut rng = rand::thread_rng();
+        let index = rng.gen_range(0..1usize << log_height);
+        let rev_pair_index = (index >> 1).reverse_bits() >> (usize::BITS as usize - log_height + 1);
+        let mut expected = CirclePoint::generator(log_height + 1)
+            * CirclePoint::generator(log_height - 1).exp_u64(rev_pair_index as u64);
+        if index & 1 == 1 {
+            expected = expected.inverse();
+        }
+        // Every row of the LDE lies on the standard domain of its height.
+        assert_eq!(
+            CircleDomain::standard(log_height).zp_at_single_point(expected.to_projective_line()),
+            0
+        );
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let index_t = builder.add_virtual_target();
+        let index_bits = builder.split_le(index_t, log_height);
+        let point = circle_lde_point(&mut builder, &index_bits);
+        let expected_x = builder.f31_constant::<Mersenne31>(expected.x);
+        let expected_y = builder.f31_constant::<Mersenne31>(expected.y);
+        builder.connect(point.x, expected_x);
+        builder.connect(point.y, expected_y);
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(index_t, F::from_canonical_usize(index));
+        let proof = data.prove(pw).unwrap();
+        assert!(data.verify(proof).is_ok());
+    }
+}
@@ -0,0 +1,426 @@
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::challenger::DuplexChallengerTarget;
+use crate::p3::commit::MerkleTreeMmcs;
+use crate::p3::field31::air::Field31Air;
+use crate::p3::field31::field::CircuitBuilderField31;
+use crate::p3::field31::verifier::CircuitBuilderField31Verifier;
+use crate::p3::field31::verifier::Field31ProofTarget;
+use crate::p3::mersenne31::circle::CirclePoint;
+use crate::p3::mersenne31::pcs::CirclePcs;
+use crate::p3::mersenne31::Mersenne31;
+use crate::p3::mersenne31::Mersenne31Poseidon2;
+use crate::p3::mersenne31::Mersenne31Poseidon2Params;
+use crate::p3::mersenne31::Mersenne31Proof;
+use crate::p3::mersenne31::Mersenne31ProofField;
+use crate::p3::mersenne31::MERSENNE31_DIGEST_ELEMS;
+use crate::p3::mersenne31::MERSENNE31_EXT_DEGREE;
+use crate::p3::mersenne31::MERSENNE31_RATE;
+use crate::p3::mersenne31::MERSENNE31_WIDTH;
+use crate::p3::serde::circle::CirclePcsProof;
+use crate::p3::serde::circle::CircleProof;
+use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::fri::FriError;
+use crate::p3::serde::hash::P3HashConfig;
+use crate::p3::serde::proof::P3Config;
+use crate::p3::verifier::P3VerifierError;
+
+impl<'a, const E: usize> From<&'a CircleProof<Target, E>>
+    for Field31ProofTarget<'a, CirclePcsProof<Target, E>, E>
+{
+    fn from(proof: &'a CircleProof<Target, E>) -> Self {
+        Self {
+            commitments: &proof.commitments,
+            opened_values: &proof.opened_values,
+            opening_proof: &proof.opening_proof,
+            degree_bits: proof.degree_bits,
+        }
+    }
+}
+
+impl P3HashConfig {
+    /// Poseidon2 over Mersenne31 with a width-16 state, as in the standard
+    /// Plonky3 Mersenne31 config.
+    pub fn mersenne31() -> Self {
+        Self {
+            width: MERSENNE31_WIDTH,
+            rate: MERSENNE31_RATE,
+            digest_elems: MERSENNE31_DIGEST_ELEMS,
+            challenger_rate: Some(MERSENNE31_RATE),
+        }
+    }
+}
+
+impl P3Config {
+    /// Shape of the proofs of the standard Plonky3 Mersenne31 Circle STARK
+    /// config for `air`, over a trace of `2^degree_bits` rows.
+    pub fn new_mersenne31(
+        air: &impl Field31Air,
+        fri_config: FriConfig,
+        degree_bits: usize,
+    ) -> Self {
+        let opening_matrix_log_max_height = degree_bits + fri_config.log_blowup;
+
+        Self {
+            fri_config,
+            hash_config: P3HashConfig::mersenne31(),
+            log_quotient_degree: air.log_quotient_degree(),
+            log_trace_height: degree_bits,
+            trace_width: air.width(),
+            opening_matrix_log_max_height,
+            quotient_chunk_width: MERSENNE31_EXT_DEGREE,
+            num_public_values: air.num_public_values(),
+            preprocessed_width: 0,
+            preprocessed_commit: None,
+            num_interactions: 0,
+            degree_bits,
+            min_degree_bits: None,
+        }
+    }
+}
+
+/// Verifier of Circle STARK proofs over Mersenne31, emulating the Mersenne31
+/// field in the Goldilocks circuit. The opening proof is checked by
+/// [`CirclePcs`], as Mersenne31 has no large two-adic subgroup for the
+/// two-adic FRI PCS. Preprocessed columns and interactions aren't supported on
+/// this path.
+pub trait CircuitBuilderMersenne31Verifier<F: RicherField + Extendable<D>, const D: usize> {
+    fn p3_verify_mersenne31_proof(
+        &mut self,
+        proof: Mersenne31ProofField,
+        air: &impl Field31Air,
+        fri_config: FriConfig,
+        params: &Mersenne31Poseidon2Params,
+        public_values: &[Target],
+    ) -> Result<Mersenne31Proof, P3VerifierError>;
+
+    /// Builds the verifier circuit for every Mersenne31 proof of the given
+    /// shape. `public_values` have to be canonical Mersenne31 elements, which
+    /// the circuit range checks.
+    fn p3_verify_mersenne31_proof_with_config(
+        &mut self,
+        air: &impl Field31Air,
+        config: &P3Config,
+        params: &Mersenne31Poseidon2Params,
+        public_values: &[Target],
+    ) -> Result<Mersenne31Proof, P3VerifierError>;
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderMersenne31Verifier<F, D>
+    for CircuitBuilder<F, D>
+{
+    fn p3_verify_mersenne31_proof(
+        &mut self,
+        proof: Mersenne31ProofField,
+        air: &impl Field31Air,
+        fri_config: FriConfig,
+        params: &Mersenne31Poseidon2Params,
+        public_values: &[Target],
+    ) -> Result<Mersenne31Proof, P3VerifierError> {
+        let config = P3Config::new_mersenne31(air, fri_config, proof.degree_bits);
+
+        proof.check_shape(&config)?;
+
+        self.p3_verify_mersenne31_proof_with_config(air, &config, params, public_values)
+    }
+
+    fn p3_verify_mersenne31_proof_with_config(
+        &mut self,
+        air: &impl Field31Air,
+        config: &P3Config,
+        params: &Mersenne31Poseidon2Params,
+        public_values: &[Target],
+    ) -> Result<Mersenne31Proof, P3VerifierError> {
+        if config.hash_config != P3HashConfig::mersenne31() {
+            return Err(P3VerifierError::InvalidHashConfig(
+                "Mersenne31 proofs are hashed with the width-16 Poseidon2 config",
+            ));
+        }
+        // A standard domain of size 2^n is a coset by a point of order
+        // 2^(n + 1), and the circle group has order 2^31. The quotient is
+        // committed over a domain at least twice the size of the trace.
+        let max_log_height = CirclePoint::LOG_ORDER - 1;
+        if config.log_trace_height == 0
+            || config.log_trace_height + config.log_quotient_degree.max(1) > max_log_height
+            || config.log_trace_height + config.fri_config.log_blowup + 1 > max_log_height
+        {
+            return Err(FriError::InvalidProofShape.into());
+        }
+
+        // Every element of the proof is a free witness, so make sure it is a
+        // canonical Mersenne31 element before doing any arithmetic with it.
+        let proof = Mersenne31Proof::add_virtual_to(self, config).map(|t| {
+            self.f31_range_check::<Mersenne31>(t);
+            t
+        });
+        for &value in public_values {
+            self.f31_range_check::<Mersenne31>(value);
+        }
+
+        let mut challenger = DuplexChallengerTarget::<Mersenne31Poseidon2>::with_params(
+            self,
+            &config.hash_config,
+            params.clone(),
+        )?;
+        let pcs = CirclePcs {
+            fri_config: config.fri_config.clone(),
+            mmcs: MerkleTreeMmcs::<Mersenne31Poseidon2>::with_params::<F>(
+                config.hash_config,
+                params.clone(),
+            )?,
+        };
+
+        self.p3_verify_field31_proof(
+            &pcs,
+            air,
+            config,
+            &mut challenger,
+            (&proof).into(),
+            public_values,
+        )?;
+
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::iop::witness::WitnessWrite;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::*;
+    use crate::p3::field31::air::Field31ConstraintFolder;
+    use crate::p3::field31::extension::BinomialExtension;
+    use crate::p3::field31::extension::CircuitBuilderField31Ext;
+    use crate::p3::field31::field::Field31;
+    use crate::p3::mersenne31::mersenne31_proof_to_field;
+
+    /// The Fibonacci AIR of the Plonky3 `uni-stark` tests, whose public
+    /// values are the first two elements and the last element of the
+    /// sequence, as in the BabyBear verifier tests.
+    struct FibonacciAir;
+
+    impl Field31Air for FibonacciAir {
+        fn name(&self) -> String {
+            "Fibonacci".to_string()
+        }
+
+        fn width(&self) -> usize {
+            2
+        }
+
+        fn num_public_values(&self) -> usize {
+            3
+        }
+
+        fn eval<
+            F: RicherField + Extendable<D>,
+            const D: usize,
+            P: BinomialExtension<E>,
+            const E: usize,
+        >(
+            &self,
+            folder: &mut Field31ConstraintFolder<P, E>,
+            cb: &mut CircuitBuilder<F, D>,
+        ) {
+            let local = folder.main.trace_local.clone();
+            let next = folder.main.trace_next.clone();
+            let [a, b, x] = [0, 1, 2].map(|i| cb.f31_ext_from_base(folder.public_values[i]));
+
+            folder.when_first_row().assert_eq(local[0].clone(), a, cb);
+            folder.when_first_row().assert_eq(local[1].clone(), b, cb);
+
+            let local_sum = cb.f31_ext_add::<P, E>(local[0].clone(), local[1].clone());
+            folder
+                .when_transition()
+                .assert_eq(local[1].clone(), next[0].clone(), cb);
+            folder
+                .when_transition()
+                .assert_eq(local_sum, next[1].clone(), cb);
+
+            folder.when_last_row().assert_eq(local[1].clone(), x, cb);
+        }
+    }
+
+    /// The FRI config `proof_mersenne31_fibonacci.json` was proven with.
+    fn fri_config() -> FriConfig {
+        FriConfig {
+            log_blowup: 1,
+            num_queries: 1,
+            proof_of_work_bits: 1,
+            max_log_arity: 1,
+            log_final_poly_len: Some(0),
+        }
+    }
+
+    fn params() -> Mersenne31Poseidon2Params {
+        serde_json::from_str(include_str!("../../../artifacts/params_mersenne31.json")).unwrap()
+    }
+
+    fn proof() -> Mersenne31ProofField {
+        serde_json::from_str(include_str!(
+            "../../../artifacts/proof_mersenne31_fibonacci.json"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_mersenne31_proof() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let proof = proof();
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let public_values = builder.add_virtual_targets(3);
+        let proof_target = builder
+            .p3_verify_mersenne31_proof(
+                proof.clone(),
+                &FibonacciAir,
+                fri_config(),
+                &params(),
+                &public_values,
+            )
+            .unwrap();
+        // The quotient is committed at twice the height of the trace, so the
+        // first layer folds two heights.
+        assert_eq!(proof_target.opening_proof.lambdas.len(), 2);
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        proof_target.set_witness::<F, D, _>(&mut pw, &mersenne31_proof_to_field(&proof).unwrap());
+        for (&t, v) in public_values.iter().zip([0, 1, 21]) {
+            pw.set_target(t, F::from_canonical_u32(v));
+        }
+        let plonky2_proof = data.prove(pw).unwrap();
+        assert!(data.verify(plonky2_proof).is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rejects_wrong_public_values() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let proof = proof();
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let public_values = builder.add_virtual_targets(3);
+        let proof_target = builder
+            .p3_verify_mersenne31_proof(
+                proof.clone(),
+                &FibonacciAir,
+                fri_config(),
+                &params(),
+                &public_values,
+            )
+            .unwrap();
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        proof_target.set_witness::<F, D, _>(&mut pw, &mersenne31_proof_to_field(&proof).unwrap());
+        for (&t, v) in public_values.iter().zip([0, 1, 34]) {
+            pw.set_target(t, F::from_canonical_u32(v));
+        }
+        data.prove(pw).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_non_canonical_proof() {
+        let mut proof = proof();
+        // `p` is Plonky3's other encoding of zero, as in `lambdas[1]`.
+        assert_eq!(
+            proof.opening_proof.lambdas[1].value[0].value,
+            Mersenne31::ORDER
+        );
+        proof.opening_proof.fri_proof.pow_witness.value = Mersenne31::ORDER + 1;
+        assert!(mersenne31_proof_to_field::<GoldilocksField>(&proof).is_err());
+    }
+
+    #[test]
+    fn test_rejects_wrong_number_of_lambdas() {
+        const D: usize = 2;
+        type F = GoldilocksField;
+
+        let mut proof = proof();
+        proof.opening_proof.lambdas.pop();
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let public_values = builder.add_virtual_targets(3);
+        assert!(builder
+            .p3_verify_mersenne31_proof(
+                proof,
+                &FibonacciAir,
+                fri_config(),
+                &params(),
+                &public_values
+            )
+            .is_err());
+
+        // The PCS checks it too, for proofs whose shape wasn't checked.
+        let config = P3Config::new_mersenne31(&FibonacciAir, fri_config(), 3);
+        let mut proof_target = Mersenne31Proof::add_virtual_to(&mut builder, &config);
+        proof_target.opening_proof.lambdas.pop();
+        let mut challenger = DuplexChallengerTarget::<Mersenne31Poseidon2>::with_params(
+            &mut builder,
+            &config.hash_config,
+            params(),
+        )
+        .unwrap();
+        let pcs = CirclePcs {
+            fri_config: fri_config(),
+            mmcs: MerkleTreeMmcs::<Mersenne31Poseidon2>::with_params::<F>(
+                config.hash_config,
+                params(),
+            )
+            .unwrap(),
+        };
+        assert!(matches!(
+            builder.p3_verify_field31_proof(
+                &pcs,
+                &FibonacciAir,
+                &config,
+                &mut challenger,
+                (&proof_target).into(),
+                &public_values,
+            ),
+            Err(P3VerifierError::Fri(FriError::InvalidProofShape))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_unsupported_config() {
+        const D: usize = 2;
+        type F = GoldilocksField;
+
+        let mut config = P3Config::new_mersenne31(&FibonacciAir, fri_config(), 30);
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let public_values = builder.add_virtual_targets(3);
+        assert!(builder
+            .p3_verify_mersenne31_proof_with_config(
+                &FibonacciAir,
+                &config,
+                &params(),
+                &public_values
+            )
+            .is_err());
+
+        config = P3Config::new_mersenne31(&FibonacciAir, fri_config(), 3);
+        config.preprocessed_width = 1;
+        assert!(matches!(
+            builder.p3_verify_mersenne31_proof_with_config(
+                &FibonacciAir,
+                &config,
+                &params(),
+                &public_values
+            ),
+            Err(P3VerifierError::InvalidProofShape(_))
+        ));
+    }
+}
@@ -1,30 +1,51 @@
+pub mod aggregation;
 pub mod air;
+pub mod babybear;
 pub mod challenger;
+pub mod circuit;
 pub mod commit;
 pub mod constants;
 pub mod extension;
+pub mod field31;
 pub mod gadgets;
+pub mod ivc;
+pub mod keccak;
+pub mod lookup;
+pub mod mersenne31;
+pub mod native;
+pub mod permutation;
 pub mod serde;
+pub mod split;
 pub mod utils;
+pub mod variable_degree;
 pub mod verifier;
 
+use std::collections::HashMap;
+
 use plonky2::field::extension::Extendable;
+use plonky2::iop::target::BoolTarget;
 use plonky2::iop::target::Target;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::config::AlgebraicHasher;
 
 use crate::common::richer_field::RicherField;
 use crate::common::u32::arithmetic_u32::U32Target;
 use crate::common::u32::binary_u32::CircuitBuilderBU32;
 use crate::common::u32::interleaved_u32::CircuitBuilderB32;
 use crate::p3::air::Air;
+use crate::p3::air::AirLike;
 use crate::p3::challenger::DuplexChallengerTarget;
+use crate::p3::challenger::P3Challenger;
+use crate::p3::permutation::P3Permutation;
 use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::multi_proof::MultiProof;
+use crate::p3::serde::multi_proof::P3MultiConfig;
 use crate::p3::serde::proof::P3Config;
-use crate::p3::serde::proof::P3ProofField;
+use crate::p3::serde::proof::P3Field;
 use crate::p3::serde::proof::Proof;
-use crate::p3::utils::log2_ceil_usize;
+use crate::p3::serde::two_adic::TwoAdicDomains;
+use crate::p3::variable_degree::CircuitBuilderP3VariableDegreeVerifier;
 use crate::p3::verifier::CircuitBuilderP3Verifier;
+use crate::p3::verifier::P3VerifierError;
 
 pub trait CircuitBuilderP3Arithmetic<F: RicherField + Extendable<D>, const D: usize> {
     fn p3_constant(&mut self, value: impl Into<u64>) -> Target;
@@ -37,12 +58,93 @@ pub trait CircuitBuilderP3Arithmetic<F: RicherField + Extendable<D>, const D: us
     fn p3_arr<const SIZE: usize>(&mut self) -> [Target; SIZE];
     fn p3_arr_fn<const SIZE: usize>(&mut self, f: impl FnMut(usize) -> Target) -> [Target; SIZE];
     fn p3_field_to_arr<const SIZE: usize>(&mut self, x: Target) -> [Target; SIZE];
-    fn p3_verify_proof<H: AlgebraicHasher<F>>(
+    /// Connects `x` and `y` where `cond` holds, and leaves them free
+    /// elsewhere. Free of gates when `cond` is the constant true.
+    fn p3_connect_if(&mut self, cond: BoolTarget, x: Target, y: Target);
+    /// Verifies a Plonky3 proof whose challenges live in the degree `E`
+    /// extension, usually [`EXT_DEGREE`](constants::EXT_DEGREE), and whose
+    /// challenger and MMCS are built from the permutation `H`, usually
+    /// [`Poseidon2Hash`](crate::common::poseidon2::poseidon2::Poseidon2Hash).
+    fn p3_verify_proof<H: P3Permutation<F>, const E: usize>(
+        &mut self,
+        proof: Proof<P3Field, E>,
+        air: &impl Air,
+        fri_config: FriConfig,
+        public_values: &[Target],
+    ) -> Result<Proof<Target, E>, P3VerifierError>
+    where
+        H::Params: Default;
+    fn p3_verify_proof_with_config<H: P3Permutation<F>, const E: usize>(
+        &mut self,
+        air: &impl Air,
+        config: &P3Config,
+        public_values: &[Target],
+    ) -> Result<Proof<Target, E>, P3VerifierError>
+    where
+        H::Params: Default;
+    /// Same as [`p3_verify_proof_with_config`](Self::p3_verify_proof_with_config)
+    /// for the Plonky3 config whose transcript is `C` and whose commitments
+    /// are opened with `C::Mmcs`, e.g.
+    /// [`SerializingChallengerTarget`](challenger::SerializingChallengerTarget)
+    /// for Keccak configs.
+    fn p3_verify_proof_with_challenger<C: P3Challenger<F, D>, const E: usize>(
+        &mut self,
+        air: &impl Air,
+        config: &P3Config,
+        public_values: &[Target],
+    ) -> Result<Proof<Target, E>, P3VerifierError>;
+    /// Same as [`p3_verify_proof_with_challenger`](Self::p3_verify_proof_with_challenger)
+    /// where `condition` holds. Elsewhere nothing is checked, and the proof
+    /// targets may be set from [`Proof::dummy`], e.g. to aggregate a variable
+    /// number of proofs.
+    fn p3_verify_proof_if<C: P3Challenger<F, D>, const E: usize>(
+        &mut self,
+        condition: BoolTarget,
+        air: &impl Air,
+        config: &P3Config,
+        public_values: &[Target],
+    ) -> Result<Proof<Target, E>, P3VerifierError>;
+    /// Same as [`p3_verify_proof`](Self::p3_verify_proof) for several proofs
+    /// of `air`, each with its own public values, in one circuit. Proofs of
+    /// the same degree share their domains and the inverses taken of them.
+    /// Returns the targets of each proof, in order.
+    fn p3_verify_proofs_batch<H: P3Permutation<F>, const E: usize>(
+        &mut self,
+        proofs: &[Proof<P3Field, E>],
+        air: &impl Air,
+        fri_config: FriConfig,
+        public_values: &[Vec<Target>],
+    ) -> Result<Vec<Proof<Target, E>>, P3VerifierError>
+    where
+        H::Params: Default;
+    /// Same as [`p3_verify_proof_with_challenger`](Self::p3_verify_proof_with_challenger)
+    /// for every proof of a variable-degree `config`, see
+    /// [`P3Config::with_min_degree_bits`]. Returns the targets of its tallest
+    /// proofs, to be set from [`Proof::pad_to_max_degree`], along with the
+    /// target of their `degree_bits`.
+    fn p3_verify_variable_degree_proof<C: P3Challenger<F, D>, const E: usize>(
         &mut self,
-        proof: P3ProofField,
         air: &impl Air,
+        config: &P3Config,
+        public_values: &[Target],
+    ) -> Result<(Proof<Target, E>, Target), P3VerifierError>;
+    fn p3_verify_multi_proof<H: P3Permutation<F>, const E: usize>(
+        &mut self,
+        proof: MultiProof<P3Field, E>,
+        airs: &[&dyn AirLike<F, D, E>],
         fri_config: FriConfig,
-    ) -> Proof<Target>;
+        public_values: &[Vec<Target>],
+    ) -> Result<MultiProof<Target, E>, P3VerifierError>
+    where
+        H::Params: Default;
+    fn p3_verify_multi_proof_with_config<H: P3Permutation<F>, const E: usize>(
+        &mut self,
+        airs: &[&dyn AirLike<F, D, E>],
+        config: &P3MultiConfig,
+        public_values: &[Vec<Target>],
+    ) -> Result<MultiProof<Target, E>, P3VerifierError>
+    where
+        H::Params: Default;
 }
 
 impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3Arithmetic<F, D>
@@ -63,34 +165,195 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3Arithmetic<
         core::array::from_fn(f)
     }
 
-    fn p3_verify_proof<H: AlgebraicHasher<F>>(
+    fn p3_verify_proof<H: P3Permutation<F>, const E: usize>(
         &mut self,
-        proof: P3ProofField,
+        proof: Proof<P3Field, E>,
         air: &impl Air,
         fri_config: FriConfig,
-    ) -> Proof<Target> {
-        let mut challenger = DuplexChallengerTarget::from_builder(self);
-
-        let config = P3Config {
-            fri_config,
-            log_quotient_degree: log2_ceil_usize(proof.opened_values.quotient_chunks.len()),
-            log_trace_height: proof.opening_proof.fri_proof.commit_phase_commits.len(),
-            trace_width: proof.opened_values.trace_local.len(),
-            opening_matrix_log_max_height: proof.opening_proof.query_openings[0][0]
-                .opening_proof
-                .len(),
-            opening_proof_query_openings_opened_values_length: proof.opening_proof.query_openings
-                [0][1]
-                .opened_values[0]
-                .len(),
-            degree_bits: proof.degree_bits,
-        };
+        public_values: &[Target],
+    ) -> Result<Proof<Target, E>, P3VerifierError>
+    where
+        H::Params: Default,
+    {
+        let config = P3Config::new(air, fri_config, proof.degree_bits).with_ext_degree(E);
 
-        let proof_target = Proof::<Target>::add_virtual_to(self, &config);
+        proof.check_shape(&config)?;
 
-        self.__p3_verify_proof__::<H>(air, proof_target.clone(), &config, &mut challenger);
+        self.p3_verify_proof_with_config::<H, E>(air, &config, public_values)
+    }
 
-        proof_target
+    /// Builds the verifier circuit for every proof of the given shape. Proofs
+    /// supplied at witness time should first be checked with
+    /// [`Proof::check_shape`].
+    ///
+    /// `public_values` are only constrained through the challenger; register
+    /// them as public inputs for the outer proof to attest to them.
+    fn p3_verify_proof_with_config<H: P3Permutation<F>, const E: usize>(
+        &mut self,
+        air: &impl Air,
+        config: &P3Config,
+        public_values: &[Target],
+    ) -> Result<Proof<Target, E>, P3VerifierError>
+    where
+        H::Params: Default,
+    {
+        self.p3_verify_proof_with_challenger::<DuplexChallengerTarget<H>, E>(
+            air,
+            config,
+            public_values,
+        )
+    }
+
+    fn p3_verify_proof_with_challenger<C: P3Challenger<F, D>, const E: usize>(
+        &mut self,
+        air: &impl Air,
+        config: &P3Config,
+        public_values: &[Target],
+    ) -> Result<Proof<Target, E>, P3VerifierError> {
+        let condition = self._true();
+        self.p3_verify_proof_if::<C, E>(condition, air, config, public_values)
+    }
+
+    fn p3_verify_proof_if<C: P3Challenger<F, D>, const E: usize>(
+        &mut self,
+        condition: BoolTarget,
+        air: &impl Air,
+        config: &P3Config,
+        public_values: &[Target],
+    ) -> Result<Proof<Target, E>, P3VerifierError> {
+        let mut challenger = C::new(self, &config.hash_config)?;
+
+        let proof_target = Proof::<Target, E>::add_virtual_to(self, config);
+
+        self.__p3_verify_proof__(
+            air,
+            proof_target.clone(),
+            public_values,
+            config,
+            &mut challenger,
+            condition,
+        )?;
+
+        Ok(proof_target)
+    }
+
+    fn p3_verify_proofs_batch<H: P3Permutation<F>, const E: usize>(
+        &mut self,
+        proofs: &[Proof<P3Field, E>],
+        air: &impl Air,
+        fri_config: FriConfig,
+        public_values: &[Vec<Target>],
+    ) -> Result<Vec<Proof<Target, E>>, P3VerifierError>
+    where
+        H::Params: Default,
+    {
+        if public_values.len() != proofs.len() {
+            return Err(P3VerifierError::InvalidProofShape(
+                "expected the public values of every proof",
+            ));
+        }
+
+        let condition = self._true();
+        let mut domains_by_degree: HashMap<usize, TwoAdicDomains> = HashMap::new();
+        let mut proof_targets = Vec::with_capacity(proofs.len());
+        for (proof, public_values) in proofs.iter().zip(public_values) {
+            let config =
+                P3Config::new(air, fri_config.clone(), proof.degree_bits).with_ext_degree(E);
+            proof.check_shape(&config)?;
+
+            let domains = domains_by_degree
+                .entry(proof.degree_bits)
+                .or_insert_with(|| {
+                    TwoAdicDomains::new(
+                        config.log_trace_height,
+                        proof.degree_bits,
+                        config.log_quotient_degree,
+                        self,
+                    )
+                });
+            let mut challenger =
+                DuplexChallengerTarget::<H>::from_builder(self, &config.hash_config)?;
+            let proof_target = Proof::<Target, E>::add_virtual_to(self, &config);
+
+            self.__p3_verify_proof_with_domains__(
+                air,
+                proof_target.clone(),
+                public_values,
+                &config,
+                domains,
+                &mut challenger,
+                condition,
+            )?;
+            proof_targets.push(proof_target);
+        }
+
+        Ok(proof_targets)
+    }
+
+    fn p3_verify_variable_degree_proof<C: P3Challenger<F, D>, const E: usize>(
+        &mut self,
+        air: &impl Air,
+        config: &P3Config,
+        public_values: &[Target],
+    ) -> Result<(Proof<Target, E>, Target), P3VerifierError> {
+        let mut challenger = C::new(self, &config.hash_config)?;
+
+        let proof_target = Proof::<Target, E>::add_virtual_to(self, config);
+        let degree_bits = self.add_virtual_target();
+
+        self.__p3_verify_variable_degree_proof__(
+            air,
+            proof_target.clone(),
+            degree_bits,
+            public_values,
+            config,
+            &mut challenger,
+        )?;
+
+        Ok((proof_target, degree_bits))
+    }
+
+    fn p3_verify_multi_proof<H: P3Permutation<F>, const E: usize>(
+        &mut self,
+        proof: MultiProof<P3Field, E>,
+        airs: &[&dyn AirLike<F, D, E>],
+        fri_config: FriConfig,
+        public_values: &[Vec<Target>],
+    ) -> Result<MultiProof<Target, E>, P3VerifierError>
+    where
+        H::Params: Default,
+    {
+        let config = P3MultiConfig::new(airs, fri_config, &proof.degree_bits)?;
+
+        proof.check_shape(&config)?;
+
+        self.p3_verify_multi_proof_with_config::<H, E>(airs, &config, public_values)
+    }
+
+    /// Builds the verifier circuit for every multi-table proof of the given
+    /// shape, with one challenger transcript shared by all tables.
+    fn p3_verify_multi_proof_with_config<H: P3Permutation<F>, const E: usize>(
+        &mut self,
+        airs: &[&dyn AirLike<F, D, E>],
+        config: &P3MultiConfig,
+        public_values: &[Vec<Target>],
+    ) -> Result<MultiProof<Target, E>, P3VerifierError>
+    where
+        H::Params: Default,
+    {
+        let mut challenger = DuplexChallengerTarget::<H>::from_builder(self, &config.hash_config)?;
+
+        let proof_target = MultiProof::<Target, E>::add_virtual_to(self, config);
+
+        self.__p3_verify_multi_proof__(
+            airs,
+            proof_target.clone(),
+            public_values,
+            config,
+            &mut challenger,
+        )?;
+
+        Ok(proof_target)
     }
 
     fn p3_and(&mut self, x: Target, y: Target) -> Target {
@@ -145,23 +408,45 @@ impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3Arithmetic<
         res[0] = x;
         res
     }
+
+    fn p3_connect_if(&mut self, cond: BoolTarget, x: Target, y: Target) {
+        // Multiplications by the constant one are folded away.
+        let x = self.mul(cond.target, x);
+        let y = self.mul(cond.target, y);
+        self.connect(x, y);
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
+    use plonky2::field::extension::FieldExtension;
     use plonky2::field::goldilocks_field::GoldilocksField;
     use plonky2::field::types::Field;
-    use plonky2::hash::poseidon::PoseidonHash;
     use plonky2::iop::witness::PartialWitness;
     use plonky2::iop::witness::WitnessWrite;
     use plonky2::plonk::circuit_data::CircuitConfig;
     use plonky2::plonk::config::PoseidonGoldilocksConfig;
     use rand::Rng;
 
+    use crate::common::poseidon2::poseidon2::Poseidon2Hash;
     use crate::p3::air::VerifierConstraintFolder;
+    use crate::p3::challenger::SerializingChallengerTarget;
+    use crate::p3::circuit::P3VerifierCircuit;
+    use crate::p3::commit::MmcsError;
+    use crate::p3::constants::EXT_DEGREE;
     use crate::p3::extension::CircuitBuilderP3ExtArithmetic;
+    use crate::p3::keccak::KECCAK256_DIGEST_BYTES;
+    use crate::p3::native::air::NativeAir;
+    use crate::p3::native::air::NativeConstraintFolder;
+    use crate::p3::native::fri::fold_row;
+    use crate::p3::native::prover;
+    use crate::p3::native::verify_proof;
+    use crate::p3::native::Challenge;
+    use crate::p3::native::Val;
+    use crate::p3::serde::hash::P3HashConfig;
     use crate::p3::serde::proof::BinomialExtensionField;
+    use crate::p3::serde::proof::P3ProofField;
     use crate::p3::utils::reverse_bits_len;
 
     pub const NUM_FIBONACCI_COLS: usize = 3;
@@ -184,18 +469,18 @@ mod tests {
             NUM_FIBONACCI_COLS
         }
 
-        fn eval<F: RicherField + Extendable<D>, const D: usize>(
+        fn eval<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
             &self,
-            folder: &mut VerifierConstraintFolder<Target>,
+            folder: &mut VerifierConstraintFolder<Target, E>,
             cb: &mut CircuitBuilder<F, D>,
         ) {
-            let local = FibnacciCols::<BinomialExtensionField<Target>> {
+            let local = FibnacciCols::<BinomialExtensionField<Target, E>> {
                 a: folder.main.trace_local[0].clone(),
                 b: folder.main.trace_local[1].clone(),
                 c: folder.main.trace_local[2].clone(),
             };
 
-            let next = FibnacciCols::<BinomialExtensionField<Target>> {
+            let next = FibnacciCols::<BinomialExtensionField<Target, E>> {
                 a: folder.main.trace_next[0].clone(),
                 b: folder.main.trace_next[1].clone(),
                 c: folder.main.trace_next[2].clone(),
@@ -221,51 +506,1134 @@ mod tests {
         }
     }
 
+    impl NativeAir for FibonacciAir {
+        fn width(&self) -> usize {
+            NUM_FIBONACCI_COLS
+        }
+
+        fn eval(&self, folder: &mut NativeConstraintFolder) {
+            let local = folder.trace_local.clone();
+            let next = folder.trace_next.clone();
+
+            folder.assert_eq(local[0] + local[1], local[2]);
+
+            folder.when_first_row().assert_eq(Challenge::ONE, local[0]);
+            folder.when_first_row().assert_eq(Challenge::ONE, local[1]);
+
+            folder.when_transition().assert_eq(next[0], local[1]);
+            folder.when_transition().assert_eq(next[1], local[2]);
+        }
+    }
+
+    /// Trace of `2^log_n` rows of [`FibonacciAir`].
+    pub fn fibonacci_trace(log_n: usize) -> Vec<Vec<Val>> {
+        let mut row = [Val::ONE, Val::ONE, Val::TWO];
+        (0..1 << log_n)
+            .map(|_| {
+                let current = row.to_vec();
+                row = [row[1], row[2], row[1] + row[2]];
+                current
+            })
+            .collect()
+    }
+
+    /// Repeated cubing from 2, whose transition constraint has degree 4 with
+    /// its selector, so that the quotient is split into 4 chunks.
+    pub struct CubeAir;
+
+    impl Air for CubeAir {
+        fn name(&self) -> String {
+            "Cube".to_string()
+        }
+
+        fn width(&self) -> usize {
+            1
+        }
+
+        fn max_constraint_degree(&self) -> usize {
+            4
+        }
+
+        fn eval<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+            &self,
+            folder: &mut VerifierConstraintFolder<Target, E>,
+            cb: &mut CircuitBuilder<F, D>,
+        ) {
+            let local = folder.main.trace_local[0].clone();
+            let next = folder.main.trace_next[0].clone();
+
+            let one = cb.p3_ext_one();
+            let two = cb.p3_ext_add(one.clone(), one);
+            folder
+                .when_first_row::<F, D>()
+                .assert_eq(local.clone(), two, cb);
+
+            let square = cb.p3_ext_mul(&local, &local);
+            let cube = cb.p3_ext_mul(&square, &local);
+            folder.when_transition::<F, D>().assert_eq(next, cube, cb);
+        }
+    }
+
+    impl NativeAir for CubeAir {
+        fn width(&self) -> usize {
+            1
+        }
+
+        fn max_constraint_degree(&self) -> usize {
+            4
+        }
+
+        fn eval(&self, folder: &mut NativeConstraintFolder) {
+            let local = folder.trace_local[0];
+            let next = folder.trace_next[0];
+
+            folder
+                .when_first_row()
+                .assert_eq(local, Challenge::from(Val::TWO));
+            folder
+                .when_transition()
+                .assert_eq(next, local * local * local);
+        }
+    }
+
+    /// [`CubeAir`] whose last row is its public value.
+    pub struct CubeAirWithOutput;
+
+    impl Air for CubeAirWithOutput {
+        fn name(&self) -> String {
+            "CubeWithOutput".to_string()
+        }
+
+        fn width(&self) -> usize {
+            1
+        }
+
+        fn max_constraint_degree(&self) -> usize {
+            4
+        }
+
+        fn num_public_values(&self) -> usize {
+            1
+        }
+
+        fn eval<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+            &self,
+            folder: &mut VerifierConstraintFolder<Target, E>,
+            cb: &mut CircuitBuilder<F, D>,
+        ) {
+            Air::eval(&CubeAir, folder, cb);
+
+            let local = folder.main.trace_local[0].clone();
+            let output = BinomialExtensionField {
+                value: cb.p3_field_to_arr(folder.public_values[0]),
+            };
+            folder.when_last_row::<F, D>().assert_eq(local, output, cb);
+        }
+    }
+
+    impl NativeAir for CubeAirWithOutput {
+        fn width(&self) -> usize {
+            1
+        }
+
+        fn max_constraint_degree(&self) -> usize {
+            4
+        }
+
+        fn num_public_values(&self) -> usize {
+            1
+        }
+
+        fn eval(&self, folder: &mut NativeConstraintFolder) {
+            NativeAir::eval(&CubeAir, folder);
+
+            let local = folder.trace_local[0];
+            let output = Challenge::from(folder.public_values[0]);
+            folder.when_last_row().assert_eq(local, output);
+        }
+    }
+
+    /// Squares of a preprocessed column holding the row index, whose
+    /// consecutive differences are checked against the next preprocessed row.
+    pub struct SquaresAir;
+
+    impl Air for SquaresAir {
+        fn name(&self) -> String {
+            "Squares".to_string()
+        }
+
+        fn width(&self) -> usize {
+            1
+        }
+
+        fn preprocessed_width(&self) -> usize {
+            1
+        }
+
+        fn eval<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+            &self,
+            folder: &mut VerifierConstraintFolder<Target, E>,
+            cb: &mut CircuitBuilder<F, D>,
+        ) {
+            let local = folder.main.trace_local[0].clone();
+            let next = folder.main.trace_next[0].clone();
+            let index = folder.main.preprocessed_local[0].clone();
+            let index_next = folder.main.preprocessed_next[0].clone();
+
+            let square = cb.p3_ext_mul(&index, &index);
+            folder.assert_eq(local.clone(), square, cb);
+
+            let difference = cb.p3_ext_sub(next, local);
+            let odd = cb.p3_ext_add(index, index_next);
+            folder
+                .when_transition::<F, D>()
+                .assert_eq(difference, odd, cb);
+        }
+    }
+
+    impl NativeAir for SquaresAir {
+        fn width(&self) -> usize {
+            1
+        }
+
+        fn preprocessed_width(&self) -> usize {
+            1
+        }
+
+        fn eval(&self, folder: &mut NativeConstraintFolder) {
+            let local = folder.trace_local[0];
+            let next = folder.trace_next[0];
+            let index = folder.preprocessed_local[0];
+            let index_next = folder.preprocessed_next[0];
+
+            folder.assert_eq(local, index * index);
+            folder
+                .when_transition()
+                .assert_eq(next - local, index + index_next);
+        }
+    }
+
+    /// Preprocessed trace of `2^log_n` rows of [`SquaresAir`].
+    pub fn indices_trace(log_n: usize) -> Vec<Vec<Val>> {
+        (0..1 << log_n)
+            .map(|i| vec![Val::from_canonical_usize(i)])
+            .collect()
+    }
+
+    /// Trace of `2^log_n` rows of [`SquaresAir`].
+    pub fn squares_trace(log_n: usize) -> Vec<Vec<Val>> {
+        (0..1 << log_n)
+            .map(|i| vec![Val::from_canonical_usize(i * i)])
+            .collect()
+    }
+
+    /// Sends its first column and receives its second on the lookup bus, once
+    /// per row.
+    pub struct LookupAir;
+
+    impl Air for LookupAir {
+        fn name(&self) -> String {
+            "Lookup".to_string()
+        }
+
+        fn width(&self) -> usize {
+            2
+        }
+
+        fn num_interactions(&self) -> usize {
+            2
+        }
+
+        fn eval<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+            &self,
+            folder: &mut VerifierConstraintFolder<Target, E>,
+            cb: &mut CircuitBuilder<F, D>,
+        ) {
+            let sent = folder.main.trace_local[0].clone();
+            let received = folder.main.trace_local[1].clone();
+
+            let one = cb.p3_ext_one();
+            folder.send(vec![sent], one.clone());
+            folder.receive(vec![received], one);
+        }
+    }
+
+    impl NativeAir for LookupAir {
+        fn width(&self) -> usize {
+            2
+        }
+
+        fn num_interactions(&self) -> usize {
+            2
+        }
+
+        fn eval(&self, folder: &mut NativeConstraintFolder) {
+            let sent = folder.trace_local[0];
+            let received = folder.trace_local[1];
+
+            folder.send(vec![sent], Challenge::ONE);
+            folder.receive(vec![received], Challenge::ONE);
+        }
+    }
+
+    /// Trace of [`LookupAir`] sending `sent[i]` and receiving `received[i]` on
+    /// row `i`.
+    pub fn lookup_trace(sent: &[usize], received: &[usize]) -> Vec<Vec<Val>> {
+        sent.iter()
+            .zip(received)
+            .map(|(&sent, &received)| {
+                vec![
+                    Val::from_canonical_usize(sent),
+                    Val::from_canonical_usize(received),
+                ]
+            })
+            .collect()
+    }
+
+    /// Trace of `2^log_n` rows of [`CubeAir`].
+    pub fn cube_trace(log_n: usize) -> Vec<Vec<Val>> {
+        (0..1 << log_n)
+            .scan(Val::TWO, |x, _| {
+                let row = vec![*x];
+                *x = x.cube();
+                Some(row)
+            })
+            .collect()
+    }
+
+    /// FRI parameters of the Fibonacci proof artifacts.
+    pub fn fibonacci_fri_config() -> FriConfig {
+        FriConfig {
+            log_blowup: 1,
+            num_queries: 100,
+            proof_of_work_bits: 16,
+            max_log_arity: 1,
+            log_final_poly_len: None,
+        }
+    }
+
+    /// FRI parameters of the proofs made with the native prover, with fewer
+    /// queries and grinding bits than the artifacts to keep the tests fast.
+    pub fn native_fri_config() -> FriConfig {
+        FriConfig {
+            log_blowup: 1,
+            num_queries: 10,
+            proof_of_work_bits: 4,
+            max_log_arity: 1,
+            log_final_poly_len: None,
+        }
+    }
+
+    /// FRI parameters of the tests that only build a verifier.
+    pub fn build_only_fri_config() -> FriConfig {
+        FriConfig {
+            log_blowup: 1,
+            num_queries: 2,
+            proof_of_work_bits: 8,
+            max_log_arity: 1,
+            log_final_poly_len: None,
+        }
+    }
+
     use super::*;
 
     #[test]
     fn test_verify_plonky3_proof() {
         const D: usize = 2;
         type C = PoseidonGoldilocksConfig;
-        type F = GoldilocksField;
-        let config = CircuitConfig::standard_recursion_config();
 
         let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
         let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
-        // let p: Proof<GoldilocksField>;
-        // unsafe { p = std::mem::transmute(proof.clone()) }
-        // std::fs::write("ppp.json", serde_json::to_string(&p).unwrap()).unwrap();
 
-        let mut builder = CircuitBuilder::<F, D>::new(config);
-        let air = FibonacciAir {};
+        let fri_config = fibonacci_fri_config();
+        let config = P3Config::new(&FibonacciAir {}, fri_config, proof.degree_bits);
+
+        let circuit = P3VerifierCircuit::<C, D, _>::new::<Poseidon2Hash>(
+            FibonacciAir {},
+            config,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+
+        let start_time = std::time::Instant::now();
+        let proof = circuit.prove(&proof, &[]).unwrap();
+        std::fs::write("proof.json", serde_json::to_string(&proof).unwrap()).unwrap();
+        let duration_ms = start_time.elapsed().as_millis();
+        println!("demo proved in {}ms", duration_ms);
+        println!("proof public_inputs: {:?}", proof.public_inputs);
 
-        let config = FriConfig {
+        let is_verified = circuit.verify(proof);
+        is_verified.as_ref().unwrap();
+        assert!(is_verified.is_ok());
+    }
+
+    #[test]
+    fn test_verify_plonky3_proof_of_variable_degree() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+
+        let fri_config = fibonacci_fri_config();
+        // One circuit for traces of 2^4 to 2^8 rows.
+        let config = P3Config::new(&FibonacciAir {}, fri_config, proof.degree_bits + 2)
+            .with_min_degree_bits(proof.degree_bits - 2);
+
+        let circuit = P3VerifierCircuit::<C, D, _>::new::<Poseidon2Hash>(
+            FibonacciAir {},
+            config,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+        assert!(circuit.degree_bits.is_some());
+
+        let proof = circuit.prove(&proof, &[]).unwrap();
+        circuit.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_verify_plonky3_proof_with_quotient_chunks() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+
+        let fri_config = native_fri_config();
+        let proof = prover::prove(&CubeAir, &cube_trace(3), &[], &fri_config);
+        let config = P3Config::new(&CubeAir, fri_config, proof.degree_bits);
+        assert_eq!(config.log_quotient_degree, 2);
+
+        let circuit = P3VerifierCircuit::<C, D, _>::new::<Poseidon2Hash>(
+            CubeAir,
+            config,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+        let proof = circuit.prove(&proof, &[]).unwrap();
+        circuit.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_verify_plonky3_proof_with_preprocessed_columns() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+
+        let fri_config = native_fri_config();
+        let preprocessed = indices_trace(3);
+        let proof = prover::prove_with_preprocessed(
+            &SquaresAir,
+            &preprocessed,
+            &squares_trace(3),
+            &[],
+            &fri_config,
+        );
+        let preprocessed_commit = prover::preprocessed_commit(&preprocessed, &fri_config);
+        let config = P3Config::new(&SquaresAir, fri_config, proof.degree_bits)
+            .with_preprocessed_commit(preprocessed_commit.try_into().unwrap());
+
+        let circuit = P3VerifierCircuit::<C, D, _>::new::<Poseidon2Hash>(
+            SquaresAir,
+            config,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+        let proof = circuit.prove(&proof, &[]).unwrap();
+        circuit.verify(proof).unwrap();
+    }
+
+    fn cube_with_output_circuit(
+    ) -> P3VerifierCircuit<PoseidonGoldilocksConfig, 2, CubeAirWithOutput> {
+        let config = P3Config::new(&CubeAirWithOutput, native_fri_config(), 3);
+        P3VerifierCircuit::new::<Poseidon2Hash>(
+            CubeAirWithOutput,
+            config,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_plonky3_proof_with_public_values() {
+        let trace = cube_trace(3);
+        let output = trace[7][0];
+        let proof = prover::prove(&CubeAirWithOutput, &trace, &[output], &native_fri_config());
+
+        let circuit = cube_with_output_circuit();
+        let proof = circuit.prove(&proof, &[output]).unwrap();
+        assert_eq!(proof.public_inputs[0], output);
+        circuit.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_verify_plonky3_proof_rejects_mismatched_public_values() {
+        let trace = cube_trace(3);
+        let output = trace[7][0];
+        let proof = prover::prove(&CubeAirWithOutput, &trace, &[output], &native_fri_config());
+
+        let circuit = cube_with_output_circuit();
+        assert!(circuit.prove(&proof, &[output + Val::ONE]).is_err());
+    }
+
+    #[test]
+    fn test_verify_plonky3_proof_if() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+
+        let fri_config = fibonacci_fri_config();
+        let config = P3Config::new(&FibonacciAir {}, fri_config, proof.degree_bits);
+        let dummy = P3ProofField::dummy(&config);
+        dummy.check_shape(&config).unwrap();
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, D>::new(CircuitConfig::standard_recursion_config());
+        let condition = builder.add_virtual_bool_target_safe();
+        let proof_target = builder
+            .p3_verify_proof_if::<DuplexChallengerTarget<Poseidon2Hash>, EXT_DEGREE>(
+                condition,
+                &FibonacciAir {},
+                &config,
+                &[],
+            )
+            .unwrap();
+        let data = builder.build::<C>();
+
+        // The same circuit verifies the proof, or is switched off for the
+        // dummy proof.
+        for (enabled, p3_proof) in [(true, &proof), (false, &dummy)] {
+            let mut pw = PartialWitness::new();
+            pw.set_bool_target(condition, enabled);
+            proof_target.set_witness::<GoldilocksField, D, _>(&mut pw, p3_proof);
+
+            let proof = data.prove(pw).unwrap();
+            data.verify(proof).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_verify_plonky3_proofs_batch() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+
+        let fri_config = FriConfig {
             log_blowup: 1,
             num_queries: 100,
             proof_of_work_bits: 16,
+            max_log_arity: 1,
+            log_final_poly_len: None,
+        };
+        let proofs = vec![proof.clone(), proof];
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, D>::new(CircuitConfig::standard_recursion_config());
+        let proof_targets = builder
+            .p3_verify_proofs_batch::<Poseidon2Hash, EXT_DEGREE>(
+                &proofs,
+                &FibonacciAir {},
+                fri_config.clone(),
+                &[vec![], vec![]],
+            )
+            .unwrap();
+        assert_eq!(proof_targets.len(), 2);
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        for (proof_target, proof) in proof_targets.iter().zip(&proofs) {
+            proof_target.set_witness::<GoldilocksField, D, _>(&mut pw, proof);
+        }
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, D>::new(CircuitConfig::standard_recursion_config());
+        assert!(matches!(
+            builder.p3_verify_proofs_batch::<Poseidon2Hash, EXT_DEGREE>(
+                &proofs,
+                &FibonacciAir {},
+                fri_config,
+                &[vec![]],
+            ),
+            Err(P3VerifierError::InvalidProofShape(_))
+        ));
+    }
+
+    #[test]
+    fn test_build_variable_degree_verifier() {
+        let config =
+            P3Config::new(&FibonacciAir {}, build_only_fri_config(), 5).with_min_degree_bits(2);
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        let (proof_target, _) = builder
+            .p3_verify_variable_degree_proof::<DuplexChallengerTarget<Poseidon2Hash>, EXT_DEGREE>(
+                &FibonacciAir {},
+                &config,
+                &[],
+            )
+            .unwrap();
+
+        // The circuit has the shape of the tallest proofs.
+        let fri_proof = &proof_target.opening_proof.fri_proof;
+        assert_eq!(fri_proof.commit_phase_commits.len(), 5);
+        let path_lengths: Vec<usize> = fri_proof.query_proofs[0]
+            .commit_phase_openings
+            .iter()
+            .map(|step| step.opening_proof.len())
+            .collect();
+        assert_eq!(path_lengths, vec![5, 4, 3, 2, 1]);
+        assert_eq!(
+            proof_target.opening_proof.query_openings[0][0]
+                .opening_proof
+                .len(),
+            6
+        );
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        assert!(builder
+            .p3_verify_proof_with_config::<Poseidon2Hash, EXT_DEGREE>(
+                &FibonacciAir {},
+                &config,
+                &[]
+            )
+            .is_err());
+
+        // Every proof needs a commit phase round.
+        let config = config.with_min_degree_bits(0);
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        assert!(builder
+            .p3_verify_variable_degree_proof::<DuplexChallengerTarget<Poseidon2Hash>, EXT_DEGREE>(
+                &FibonacciAir {},
+                &config,
+                &[],
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_pad_to_max_degree() {
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+        let fri_config = fibonacci_fri_config();
+
+        let config = P3Config::new(&FibonacciAir {}, fri_config, 8).with_min_degree_bits(4);
+        proof.check_shape(&config).unwrap();
+        assert!(proof
+            .check_shape(&config.clone().with_min_degree_bits(7))
+            .is_err());
+
+        let padded = proof.pad_to_max_degree(&config);
+        assert_eq!(padded.degree_bits, proof.degree_bits);
+        let fri_proof = &padded.opening_proof.fri_proof;
+        assert_eq!(fri_proof.commit_phase_commits.len(), 8);
+        let steps = &fri_proof.query_proofs[0].commit_phase_openings;
+        assert_eq!(steps.len(), 8);
+        assert_eq!(steps[0].opening_proof.len(), 8);
+        assert_eq!(steps[7].opening_proof.len(), 1);
+        assert!(padded
+            .opening_proof
+            .query_openings
+            .iter()
+            .flatten()
+            .all(|batch_opening| batch_opening.opening_proof.len() == 9));
+
+        // The proof itself is left as it was.
+        let original = &proof.opening_proof.fri_proof.query_proofs[0].commit_phase_openings;
+        let values = |path: &[Vec<P3Field>]| -> Vec<Vec<GoldilocksField>> {
+            path.iter()
+                .map(|digest| digest.iter().map(|v| v.value).collect())
+                .collect()
         };
+        assert_eq!(
+            values(&steps[0].opening_proof[..original[0].opening_proof.len()]),
+            values(&original[0].opening_proof)
+        );
+    }
 
-        let proof_target = builder.p3_verify_proof::<PoseidonHash>(proof.clone(), &air, config);
+    #[test]
+    fn test_p3_config_from_air() {
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+        let fri_config = fibonacci_fri_config();
+
+        let config = P3Config::new(&FibonacciAir {}, fri_config, 6);
+        proof.check_shape(&config).unwrap();
+
+        let config_str = serde_json::to_string(&config).unwrap();
+        let config = serde_json::from_str::<P3Config>(&config_str).unwrap();
+        proof.check_shape(&config).unwrap();
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        builder
+            .p3_verify_proof_with_config::<Poseidon2Hash, EXT_DEGREE>(
+                &FibonacciAir {},
+                &config,
+                &[],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_build_keccak_verifier() {
+        let config = P3Config::new(&FibonacciAir {}, build_only_fri_config(), 3)
+            .with_hash_config(P3HashConfig::keccak256());
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        let proof_target = builder
+            .p3_verify_proof_with_challenger::<SerializingChallengerTarget, EXT_DEGREE>(
+                &FibonacciAir {},
+                &config,
+                &[],
+            )
+            .unwrap();
+        assert_eq!(
+            proof_target.commitments.trace.value.len(),
+            KECCAK256_DIGEST_BYTES
+        );
+
+        // Keccak commitments aren't Poseidon2 digests.
+        let config = config.with_hash_config(P3HashConfig::default());
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        let res = builder
+            .p3_verify_proof_with_challenger::<SerializingChallengerTarget, EXT_DEGREE>(
+                &FibonacciAir {},
+                &config,
+                &[],
+            );
+        assert!(matches!(res, Err(P3VerifierError::InvalidHashConfig(_))));
+    }
 
+    #[test]
+    fn test_build_verifiers_of_several_hash_configs() {
+        let config = P3Config::new(&FibonacciAir {}, build_only_fri_config(), 3);
+        let wide_config = config.clone().with_hash_config(P3HashConfig {
+            width: 12,
+            rate: 8,
+            digest_elems: 6,
+            challenger_rate: None,
+        });
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        for (config, digest_elems) in [(&config, 4), (&wide_config, 6)] {
+            let proof_target = builder
+                .p3_verify_proof_with_config::<Poseidon2Hash, EXT_DEGREE>(
+                    &FibonacciAir {},
+                    config,
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(proof_target.commitments.trace.value.len(), digest_elems);
+            assert!(proof_target.opening_proof.query_openings[0][0]
+                .opening_proof
+                .iter()
+                .all(|sibling| sibling.len() == digest_elems));
+        }
+
+        // The Goldilocks Poseidon2 permutation has a width-12 state.
+        let narrow_config = config.with_hash_config(P3HashConfig {
+            width: 8,
+            rate: 4,
+            digest_elems: 4,
+            challenger_rate: None,
+        });
+        assert!(matches!(
+            builder.p3_verify_proof_with_config::<Poseidon2Hash, EXT_DEGREE>(
+                &FibonacciAir {},
+                &narrow_config,
+                &[],
+            ),
+            Err(P3VerifierError::InvalidHashConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_build_verifier_folding_four_points() {
+        let fri_config = FriConfig {
+            max_log_arity: 2,
+            ..build_only_fri_config()
+        };
+        let config = P3Config::new(&FibonacciAir {}, fri_config, 3);
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        let proof_target = builder
+            .p3_verify_proof_with_config::<Poseidon2Hash, EXT_DEGREE>(
+                &FibonacciAir {},
+                &config,
+                &[],
+            )
+            .unwrap();
+
+        // A trace of 2^3 rows folds by 4, then by 2.
+        let fri_proof = &proof_target.opening_proof.fri_proof;
+        assert_eq!(fri_proof.commit_phase_commits.len(), 2);
+        let steps = &fri_proof.query_proofs[0].commit_phase_openings;
+        assert_eq!(steps[0].sibling_values.len(), 3);
+        assert_eq!(steps[0].opening_proof.len(), 2);
+        assert_eq!(steps[1].sibling_values.len(), 1);
+        assert_eq!(steps[1].opening_proof.len(), 1);
+
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+        let config = P3Config::new(&FibonacciAir {}, config.fri_config, proof.degree_bits);
+        assert!(proof.check_shape(&config).is_err());
+    }
+
+    #[test]
+    fn test_verify_plonky3_proof_folding_four_points() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+
+        let fri_config = FriConfig {
+            max_log_arity: 2,
+            ..native_fri_config()
+        };
+        let proof = prover::prove(&FibonacciAir {}, &fibonacci_trace(3), &[], &fri_config);
+        // A trace of 2^3 rows folds by 4, then by 2.
+        assert_eq!(proof.opening_proof.fri_proof.commit_phase_commits.len(), 2);
+        verify_proof(&proof, &FibonacciAir {}, &[], &fri_config).unwrap();
+
+        let config = P3Config::new(&FibonacciAir {}, fri_config, proof.degree_bits);
+        let circuit = P3VerifierCircuit::<C, D, _>::new::<Poseidon2Hash>(
+            FibonacciAir {},
+            config,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+        let proof = circuit.prove(&proof, &[]).unwrap();
+        circuit.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_build_verifier_with_final_poly() {
+        let fri_config = FriConfig {
+            log_final_poly_len: Some(1),
+            ..build_only_fri_config()
+        };
+        let config = P3Config::new(&FibonacciAir {}, fri_config, 3);
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        let proof_target = builder
+            .p3_verify_proof_with_config::<Poseidon2Hash, EXT_DEGREE>(
+                &FibonacciAir {},
+                &config,
+                &[],
+            )
+            .unwrap();
+
+        // Folding stops one round early, at a polynomial of 2 coefficients.
+        let fri_proof = &proof_target.opening_proof.fri_proof;
+        assert_eq!(fri_proof.commit_phase_commits.len(), 2);
+        assert_eq!(fri_proof.final_poly.len(), 2);
+        let steps = &fri_proof.query_proofs[0].commit_phase_openings;
+        assert_eq!(steps[0].opening_proof.len(), 3);
+        assert_eq!(steps[1].opening_proof.len(), 2);
+
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+        let config = P3Config::new(&FibonacciAir {}, config.fri_config, proof.degree_bits);
+        assert!(proof.check_shape(&config).is_err());
+    }
+
+    #[test]
+    fn test_verify_plonky3_proof_with_final_poly() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+
+        let fri_config = FriConfig {
+            log_final_poly_len: Some(2),
+            ..native_fri_config()
+        };
+        let proof = prover::prove(&FibonacciAir {}, &fibonacci_trace(4), &[], &fri_config);
+        let final_poly = &proof.opening_proof.fri_proof.final_poly;
+        assert_eq!(final_poly.len(), 4);
+        assert!(final_poly[1..]
+            .iter()
+            .any(|c| c.value.iter().any(|x| x.value != Val::ZERO)));
+        verify_proof(&proof, &FibonacciAir {}, &[], &fri_config).unwrap();
+
+        let config = P3Config::new(&FibonacciAir {}, fri_config, proof.degree_bits);
+        let circuit = P3VerifierCircuit::<C, D, _>::new::<Poseidon2Hash>(
+            FibonacciAir {},
+            config,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+        let proof = circuit.prove(&proof, &[]).unwrap();
+        circuit.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_fold_row() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        let x0_value = Val::from_canonical_u64(7).exp_u64(21);
+        let beta_value = Challenge::from_basefield_array([
+            Val::from_canonical_u64(5),
+            Val::from_canonical_u64(11),
+        ]);
+        let evals_value: Vec<Challenge> = (0..4)
+            .map(|i| {
+                Challenge::from_basefield_array([
+                    Val::from_canonical_u64(i + 1),
+                    Val::from_canonical_u64(3 * i),
+                ])
+            })
+            .collect();
+        let expected = fold_row(x0_value, 2, beta_value, &evals_value);
+
+        let mut builder = CircuitBuilder::<Val, D>::new(CircuitConfig::standard_recursion_config());
+        let x0 = builder.add_virtual_target();
+        let beta = BinomialExtensionField::<Target, EXT_DEGREE>::add_virtual_to(&mut builder);
+        let evals: Vec<_> = (0..4)
+            .map(|_| BinomialExtensionField::<Target, EXT_DEGREE>::add_virtual_to(&mut builder))
+            .collect();
+        let folded = builder.p3_fold_row(x0, 2, &beta, &evals);
+        builder.register_public_inputs(&folded.value);
         let data = builder.build::<C>();
 
         let mut pw = PartialWitness::new();
+        pw.set_target(x0, x0_value);
+        for (eval, value) in evals.iter().zip(&evals_value).chain([(&beta, &beta_value)]) {
+            pw.set_target_arr(&eval.value, &value.0);
+        }
+        let proof = data.prove(pw).unwrap();
 
-        let p: Proof<GoldilocksField>;
-        unsafe { p = std::mem::transmute(proof) }
+        assert_eq!(proof.public_inputs, expected.0);
+        assert!(data.verify(proof).is_ok());
+    }
 
-        proof_target.set_witness::<F, D, _>(&mut pw, &p);
+    #[test]
+    fn test_verify_plonky3_proof_rejects_malformed_proof() {
+        const D: usize = 2;
+        type F = GoldilocksField;
 
-        let start_time = std::time::Instant::now();
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+        let air = FibonacciAir {};
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let mut truncated = proof.clone();
+        truncated.opening_proof.query_openings.pop();
+        assert_eq!(
+            builder
+                .p3_verify_proof::<Poseidon2Hash, EXT_DEGREE>(
+                    truncated,
+                    &air,
+                    fibonacci_fri_config(),
+                    &[]
+                )
+                .unwrap_err(),
+            P3VerifierError::QueryCountMismatch {
+                expected: 100,
+                actual: 99
+            }
+        );
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let mut narrow = proof.clone();
+        narrow.opened_values.trace_local.pop();
+        narrow.opened_values.trace_next.pop();
+        assert!(matches!(
+            builder.p3_verify_proof::<Poseidon2Hash, EXT_DEGREE>(
+                narrow,
+                &air,
+                fibonacci_fri_config(),
+                &[]
+            ),
+            Err(P3VerifierError::InvalidProofShape(_))
+        ));
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let mut short_path = proof.clone();
+        short_path.opening_proof.fri_proof.query_proofs[3].commit_phase_openings[1]
+            .opening_proof
+            .pop();
+        assert!(matches!(
+            builder.p3_verify_proof::<Poseidon2Hash, EXT_DEGREE>(
+                short_path,
+                &air,
+                fibonacci_fri_config(),
+                &[]
+            ),
+            Err(P3VerifierError::CommitPhaseMmcs(MmcsError::WrongHeight))
+        ));
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let public_value = builder.add_virtual_target();
+        assert_eq!(
+            builder
+                .p3_verify_proof::<Poseidon2Hash, EXT_DEGREE>(
+                    proof,
+                    &air,
+                    fibonacci_fri_config(),
+                    &[public_value]
+                )
+                .unwrap_err(),
+            P3VerifierError::PublicValuesMismatch {
+                expected: 0,
+                actual: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_plonky3_multi_proof() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+
+        // A single table proof has the layout and transcript of a one-table
+        // multi proof.
+        let multi_proof = MultiProof {
+            commitments: proof.commitments,
+            opened_values: vec![proof.opened_values],
+            opening_proof: proof.opening_proof,
+            degree_bits: vec![proof.degree_bits],
+        };
+        let air = FibonacciAir {};
+        let airs: [&dyn AirLike<F, D>; 1] = [&air];
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let mut two_tables = multi_proof.clone();
+        two_tables.degree_bits.push(6);
+        assert!(matches!(
+            builder.p3_verify_multi_proof::<Poseidon2Hash, EXT_DEGREE>(
+                two_tables,
+                &airs,
+                fibonacci_fri_config(),
+                &[vec![]]
+            ),
+            Err(P3VerifierError::InvalidProofShape(_))
+        ));
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let proof_target = builder
+            .p3_verify_multi_proof::<Poseidon2Hash, EXT_DEGREE>(
+                multi_proof.clone(),
+                &airs,
+                fibonacci_fri_config(),
+                &[vec![]],
+            )
+            .unwrap();
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        proof_target.set_witness::<F, D, _>(&mut pw, &multi_proof);
         let proof = data.prove(pw).unwrap();
-        std::fs::write("proof.json", serde_json::to_string(&proof).unwrap()).unwrap();
-        let duration_ms = start_time.elapsed().as_millis();
-        println!("demo proved in {}ms", duration_ms);
-        println!("proof public_inputs: {:?}", proof.public_inputs);
+        data.verify(proof).unwrap();
+    }
 
-        let is_verified = data.verify(proof);
-        is_verified.as_ref().unwrap();
-        assert!(is_verified.is_ok());
+    #[test]
+    fn test_verify_plonky3_multi_proof_of_two_tables() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        // Tables of different heights and quotient degrees.
+        let multi_proof = prover::prove_multi(
+            &[&FibonacciAir {}, &CubeAir],
+            &[fibonacci_trace(4), cube_trace(3)],
+            &[vec![], vec![]],
+            &native_fri_config(),
+        );
+        let fibonacci = FibonacciAir {};
+        let airs: [&dyn AirLike<F, D>; 2] = [&fibonacci, &CubeAir];
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let proof_target = builder
+            .p3_verify_multi_proof::<Poseidon2Hash, EXT_DEGREE>(
+                multi_proof.clone(),
+                &airs,
+                native_fri_config(),
+                &[vec![], vec![]],
+            )
+            .unwrap();
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        proof_target.set_witness::<F, D, _>(&mut pw, &multi_proof);
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_verify_plonky3_proof_with_interactions() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+
+        let sent: Vec<usize> = (0..8).collect();
+        let received: Vec<usize> = (0..8).rev().collect();
+        let fri_config = native_fri_config();
+        let proof = prover::prove(
+            &LookupAir,
+            &lookup_trace(&sent, &received),
+            &[],
+            &fri_config,
+        );
+        let config = P3Config::new(&LookupAir, fri_config, proof.degree_bits);
+
+        let circuit = P3VerifierCircuit::<C, D, _>::new::<Poseidon2Hash>(
+            LookupAir,
+            config,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+        let proof = circuit.prove(&proof, &[]).unwrap();
+        circuit.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_verify_plonky3_multi_proof_with_cross_table_interactions() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = GoldilocksField;
+
+        // Each table receives what the other sends, and neither balances on
+        // its own. The Fibonacci table has no interactions.
+        let low: Vec<usize> = (0..8).collect();
+        let high: Vec<usize> = (8..16).collect();
+        let multi_proof = prover::prove_multi(
+            &[&LookupAir, &FibonacciAir {}, &LookupAir],
+            &[
+                lookup_trace(&low, &high),
+                fibonacci_trace(4),
+                lookup_trace(&high, &low),
+            ],
+            &[vec![], vec![], vec![]],
+            &native_fri_config(),
+        );
+        let fibonacci = FibonacciAir {};
+        let airs: [&dyn AirLike<F, D>; 3] = [&LookupAir, &fibonacci, &LookupAir];
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let proof_target = builder
+            .p3_verify_multi_proof::<Poseidon2Hash, EXT_DEGREE>(
+                multi_proof.clone(),
+                &airs,
+                native_fri_config(),
+                &[vec![], vec![], vec![]],
+            )
+            .unwrap();
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        proof_target.set_witness::<F, D, _>(&mut pw, &multi_proof);
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
     }
 
     #[test]
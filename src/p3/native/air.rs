@@ -0,0 +1,121 @@
+use plonky2::field::types::Field;
+
+use crate::p3::native::lookup::NativeInteraction;
+use crate::p3::native::Challenge;
+use crate::p3::native::Val;
+use crate::p3::utils::log2_ceil_usize;
+
+/// Value-level counterpart of [`Air`](crate::p3::air::Air), evaluated over
+/// the opened values of a proof instead of circuit targets.
+pub trait NativeAir {
+    fn width(&self) -> usize;
+    /// See [`Air::max_constraint_degree`](crate::p3::air::Air::max_constraint_degree).
+    fn max_constraint_degree(&self) -> usize {
+        2
+    }
+    fn log_quotient_degree(&self) -> usize {
+        log2_ceil_usize(self.max_constraint_degree().max(2) - 1)
+    }
+    fn num_public_values(&self) -> usize {
+        0
+    }
+    /// See [`Air::preprocessed_width`](crate::p3::air::Air::preprocessed_width).
+    fn preprocessed_width(&self) -> usize {
+        0
+    }
+    /// See [`Air::num_interactions`](crate::p3::air::Air::num_interactions).
+    fn num_interactions(&self) -> usize {
+        0
+    }
+    fn eval(&self, folder: &mut NativeConstraintFolder);
+}
+
+pub struct NativeConstraintFolder {
+    pub trace_local: Vec<Challenge>,
+    pub trace_next: Vec<Challenge>,
+    pub preprocessed_local: Vec<Challenge>,
+    pub preprocessed_next: Vec<Challenge>,
+    /// Permutation trace flattened to base field columns, see
+    /// [`lookup`](crate::p3::native::lookup).
+    pub permutation_local: Vec<Challenge>,
+    pub permutation_next: Vec<Challenge>,
+    pub cumulative_sum: Option<Challenge>,
+    pub public_values: Vec<Val>,
+    pub permutation_challenges: Vec<Challenge>,
+    pub interactions: Vec<NativeInteraction>,
+    pub is_first_row: Challenge,
+    pub is_last_row: Challenge,
+    pub is_transition: Challenge,
+    pub alpha: Challenge,
+    pub accumulator: Challenge,
+}
+
+pub struct NativeFilteredAirBuilder<'a> {
+    pub inner: &'a mut NativeConstraintFolder,
+    pub condition: Challenge,
+}
+
+impl NativeConstraintFolder {
+    /// Sends `values` on the lookup bus `multiplicity` times.
+    pub fn send(&mut self, values: Vec<Challenge>, multiplicity: Challenge) {
+        self.interactions.push(NativeInteraction {
+            values,
+            multiplicity,
+            is_send: true,
+        });
+    }
+
+    /// Receives `values` from the lookup bus `multiplicity` times.
+    pub fn receive(&mut self, values: Vec<Challenge>, multiplicity: Challenge) {
+        self.interactions.push(NativeInteraction {
+            values,
+            multiplicity,
+            is_send: false,
+        });
+    }
+
+    pub fn when(&mut self, condition: Challenge) -> NativeFilteredAirBuilder {
+        NativeFilteredAirBuilder {
+            inner: self,
+            condition,
+        }
+    }
+
+    pub fn when_first_row(&mut self) -> NativeFilteredAirBuilder {
+        self.when(self.is_first_row)
+    }
+
+    pub fn when_last_row(&mut self) -> NativeFilteredAirBuilder {
+        self.when(self.is_last_row)
+    }
+
+    pub fn when_transition(&mut self) -> NativeFilteredAirBuilder {
+        self.when(self.is_transition)
+    }
+
+    pub fn assert_zero(&mut self, x: Challenge) {
+        self.accumulator = self.accumulator * self.alpha + x;
+    }
+
+    pub fn assert_eq(&mut self, x: Challenge, y: Challenge) {
+        self.assert_zero(x - y)
+    }
+
+    pub fn assert_bool(&mut self, x: Challenge) {
+        self.assert_zero(x * (x - Challenge::ONE))
+    }
+}
+
+impl<'a> NativeFilteredAirBuilder<'a> {
+    pub fn assert_zero(&mut self, x: Challenge) {
+        self.inner.assert_zero(self.condition * x)
+    }
+
+    pub fn assert_eq(&mut self, x: Challenge, y: Challenge) {
+        self.inner.assert_zero(self.condition * (x - y))
+    }
+
+    pub fn assert_bool(&mut self, x: Challenge) {
+        self.inner.assert_bool(self.condition * x)
+    }
+}
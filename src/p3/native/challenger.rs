@@ -0,0 +1,159 @@
+use plonky2::field::extension::quadratic::QuadraticExtension;
+use plonky2::field::types::Field;
+use plonky2::field::types::Field64;
+use plonky2::field::types::PrimeField64;
+
+use crate::common::poseidon2::poseidon2::Poseidon2;
+use crate::common::poseidon2::poseidon2::WIDTH;
+use crate::p3::keccak::keccak256;
+use crate::p3::native::Challenge;
+use crate::p3::native::Val;
+
+/// Value-level counterpart of
+/// [`DuplexChallengerTarget`](crate::p3::challenger::DuplexChallengerTarget).
+#[derive(Clone)]
+pub struct DuplexChallenger {
+    sponge_state: [Val; WIDTH],
+    input_buffer: Vec<Val>,
+    output_buffer: Vec<Val>,
+}
+
+impl Default for DuplexChallenger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DuplexChallenger {
+    pub fn new() -> Self {
+        Self {
+            sponge_state: [Val::ZERO; WIDTH],
+            input_buffer: Vec::new(),
+            output_buffer: Vec::new(),
+        }
+    }
+
+    fn duplexing(&mut self) {
+        assert!(self.input_buffer.len() <= WIDTH);
+
+        for (i, val) in self.input_buffer.drain(..).enumerate() {
+            self.sponge_state[i] = val;
+        }
+
+        self.sponge_state = Val::poseidon2(self.sponge_state);
+
+        self.output_buffer.clear();
+        self.output_buffer.extend(self.sponge_state);
+    }
+
+    pub fn observe(&mut self, value: Val) {
+        self.output_buffer.clear();
+        self.input_buffer.push(value);
+
+        if self.input_buffer.len() == WIDTH {
+            self.duplexing();
+        }
+    }
+
+    pub fn observe_slice(&mut self, values: &[Val]) {
+        for value in values {
+            self.observe(*value);
+        }
+    }
+
+    pub fn sample(&mut self) -> Val {
+        // If we have buffered inputs, we must perform a duplexing so that the challenge
+        // will reflect them. Or if we've run out of outputs, we must perform a
+        // duplexing to get more.
+        if !self.input_buffer.is_empty() || self.output_buffer.is_empty() {
+            self.duplexing();
+        }
+
+        self.output_buffer
+            .pop()
+            .expect("Output buffer should be non-empty")
+    }
+
+    pub fn sample_ext(&mut self) -> Challenge {
+        let value = core::array::from_fn(|_| self.sample());
+        QuadraticExtension(value)
+    }
+
+    pub fn sample_bits(&mut self, bits: usize) -> usize {
+        let rand_f = self.sample();
+        (rand_f.to_canonical_u64() & ((1u64 << bits) - 1)) as usize
+    }
+
+    pub fn check_witness(&mut self, bits: usize, witness: Val) -> bool {
+        self.observe(witness);
+        self.sample_bits(bits) == 0
+    }
+}
+
+/// Value-level counterpart of
+/// [`SerializingChallengerTarget`](crate::p3::challenger::SerializingChallengerTarget).
+#[derive(Default)]
+pub struct SerializingChallenger {
+    input_buffer: Vec<u8>,
+    output_buffer: Vec<u8>,
+}
+
+impl SerializingChallenger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn flush(&mut self) {
+        let digest = keccak256(&self.input_buffer);
+        self.output_buffer = digest.to_vec();
+        self.input_buffer = digest.to_vec();
+    }
+
+    fn observe_bytes(&mut self, bytes: &[u8]) {
+        self.output_buffer.clear();
+        self.input_buffer.extend_from_slice(bytes);
+    }
+
+    pub fn observe(&mut self, value: Val) {
+        self.observe_bytes(&value.to_canonical_u64().to_le_bytes());
+    }
+
+    pub fn observe_digest(&mut self, digest: &[u8]) {
+        self.observe_bytes(digest);
+    }
+
+    fn sample_u64(&mut self) -> u64 {
+        u64::from_le_bytes(core::array::from_fn(|_| {
+            if self.output_buffer.is_empty() {
+                self.flush();
+            }
+            self.output_buffer
+                .pop()
+                .expect("Output buffer should be non-empty")
+        }))
+    }
+
+    /// Samples of 8 bytes which aren't canonical are rejected and drawn again.
+    pub fn sample(&mut self) -> Val {
+        loop {
+            let value = self.sample_u64();
+            if value < Val::ORDER {
+                return Val::from_canonical_u64(value);
+            }
+        }
+    }
+
+    pub fn sample_ext(&mut self) -> Challenge {
+        let value = core::array::from_fn(|_| self.sample());
+        QuadraticExtension(value)
+    }
+
+    pub fn sample_bits(&mut self, bits: usize) -> usize {
+        (self.sample_u64() & ((1u64 << bits) - 1)) as usize
+    }
+
+    pub fn check_witness(&mut self, bits: usize, witness: Val) -> bool {
+        self.observe(witness);
+        self.sample_bits(bits) == 0
+    }
+}
@@ -0,0 +1,112 @@
+use std::cmp::Reverse;
+
+use itertools::Itertools;
+use plonky2::field::types::Field;
+
+use crate::common::poseidon2::poseidon2::Poseidon2;
+use crate::common::poseidon2::poseidon2::WIDTH;
+use crate::p3::commit::MmcsError;
+use crate::p3::native::Val;
+use crate::p3::native::DIGEST_ELEMS;
+use crate::p3::native::RATE;
+use crate::p3::serde::Dimensions;
+
+/// Value-level counterpart of
+/// [`MerkleTreeMmcs`](crate::p3::commit::MerkleTreeMmcs).
+pub struct MerkleTreeMmcs;
+
+impl MerkleTreeMmcs {
+    pub fn hash_iter_slices<'a, I>(input: I) -> [Val; DIGEST_ELEMS]
+    where
+        I: Iterator<Item = &'a [Val]>,
+    {
+        let mut state = [Val::ZERO; WIDTH];
+        for input_chunk in &input.into_iter().flatten().chunks(RATE) {
+            state.iter_mut().zip(input_chunk).for_each(|(s, i)| *s = *i);
+
+            state = Val::poseidon2(state);
+        }
+        state[..DIGEST_ELEMS].try_into().unwrap()
+    }
+
+    pub fn compress(input: [[Val; DIGEST_ELEMS]; 2]) -> [Val; DIGEST_ELEMS] {
+        let mut state = [Val::ZERO; WIDTH];
+        for (i, digest) in input.iter().enumerate() {
+            state[i * DIGEST_ELEMS..(i + 1) * DIGEST_ELEMS].copy_from_slice(digest);
+        }
+
+        state = Val::poseidon2(state);
+
+        state[..DIGEST_ELEMS].try_into().unwrap()
+    }
+
+    pub fn verify_batch(
+        commit: &[Val; DIGEST_ELEMS],
+        dimensions: &[Dimensions],
+        mut index: usize,
+        opened_values: &[Vec<Val>],
+        proof: &[Vec<Val>],
+    ) -> Result<(), MmcsError> {
+        if dimensions.is_empty() || dimensions.len() != opened_values.len() {
+            return Err(MmcsError::WrongBatchSize);
+        }
+        if proof.iter().any(|sibling| sibling.len() != DIGEST_ELEMS) {
+            return Err(MmcsError::WrongWidth);
+        }
+
+        let mut heights_tallest_first = dimensions
+            .iter()
+            .enumerate()
+            .sorted_by_key(|(_, dims)| Reverse(dims.height))
+            .peekable();
+
+        let mut curr_height_padded = heights_tallest_first
+            .peek()
+            .unwrap()
+            .1
+            .height
+            .next_power_of_two();
+
+        let mut root = Self::hash_iter_slices(
+            heights_tallest_first
+                .peeking_take_while(|(_, dims)| {
+                    dims.height.next_power_of_two() == curr_height_padded
+                })
+                .map(|(i, _)| opened_values[i].as_slice()),
+        );
+
+        for sibling in proof.iter() {
+            let sibling: [Val; DIGEST_ELEMS] = sibling.as_slice().try_into().unwrap();
+            let (left, right) = if index & 1 == 0 {
+                (root, sibling)
+            } else {
+                (sibling, root)
+            };
+
+            root = Self::compress([left, right]);
+            index >>= 1;
+
+            curr_height_padded >>= 1;
+
+            let next_height = heights_tallest_first
+                .peek()
+                .map(|(_, dims)| dims.height)
+                .filter(|h| h.next_power_of_two() == curr_height_padded);
+            if let Some(next_height) = next_height {
+                let next_height_openings_digest = Self::hash_iter_slices(
+                    heights_tallest_first
+                        .peeking_take_while(|(_, dims)| dims.height == next_height)
+                        .map(|(i, _)| opened_values[i].as_slice()),
+                );
+
+                root = Self::compress([root, next_height_openings_digest]);
+            }
+        }
+
+        if *commit == root {
+            Ok(())
+        } else {
+            Err(MmcsError::RootMismatch)
+        }
+    }
+}
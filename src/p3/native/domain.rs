@@ -0,0 +1,94 @@
+use plonky2::field::types::Field;
+
+use crate::p3::native::Challenge;
+use crate::p3::native::Val;
+use crate::p3::serde::LagrangeSelectors;
+use crate::p3::utils::log2_ceil_usize;
+use crate::p3::utils::log2_strict_usize;
+
+pub const TWO_ADICITY: usize = 32;
+
+/// `Goldilocks::generator()` of Plonky3, the shift of the LDE cosets and of
+/// the quotient domain.
+pub const GENERATOR: u64 = 7;
+
+/// Plonky3's Goldilocks two-adic generator, which differs from the one used by
+/// plonky2.
+pub fn two_adic_generator(bits: usize) -> Val {
+    assert!(bits <= TWO_ADICITY);
+    Val::from_canonical_u64(1_753_635_133_440_165_772).exp_power_of_2(TWO_ADICITY - bits)
+}
+
+/// Value-level counterpart of
+/// [`TwoAdicMultiplicativeCoset`](crate::p3::serde::two_adic::TwoAdicMultiplicativeCoset).
+#[derive(Clone, Copy, Debug)]
+pub struct TwoAdicMultiplicativeCoset {
+    pub log_n: usize,
+    pub shift: Val,
+}
+
+impl TwoAdicMultiplicativeCoset {
+    pub fn size(&self) -> usize {
+        1 << self.log_n
+    }
+
+    pub fn first_point(&self) -> Val {
+        self.shift
+    }
+
+    pub fn gen(&self) -> Val {
+        two_adic_generator(self.log_n)
+    }
+
+    pub fn next_point(&self, x: Challenge) -> Challenge {
+        x * Challenge::from(self.gen())
+    }
+
+    pub fn natural_domain_for_degree(degree: usize) -> Self {
+        Self {
+            log_n: log2_strict_usize(degree),
+            shift: Val::ONE,
+        }
+    }
+
+    pub fn create_disjoint_domain(&self, min_size: usize) -> Self {
+        Self {
+            log_n: log2_ceil_usize(min_size),
+            shift: self.shift * Val::from_canonical_u64(GENERATOR),
+        }
+    }
+
+    pub fn split_domains(&self, num_chunks: usize) -> Vec<Self> {
+        let log_chunks = log2_strict_usize(num_chunks);
+        let generator = self.gen();
+
+        (0..num_chunks)
+            .map(|i| Self {
+                log_n: self.log_n - log_chunks,
+                shift: self.shift * generator.exp_u64(i as u64),
+            })
+            .collect()
+    }
+
+    pub fn selectors_at_point(&self, point: Challenge) -> LagrangeSelectors<Challenge> {
+        let unshifted_point = point * Challenge::from(self.shift.inverse());
+        let z_h = unshifted_point.exp_power_of_2(self.log_n) - Challenge::ONE;
+        let unshifted_point_minus_generator_inv =
+            unshifted_point - Challenge::from(self.gen().inverse());
+
+        LagrangeSelectors {
+            is_first_row: z_h / (unshifted_point - Challenge::ONE),
+            is_last_row: z_h / unshifted_point_minus_generator_inv,
+            is_transition: unshifted_point_minus_generator_inv,
+            inv_zeroifier: z_h.inverse(),
+        }
+    }
+
+    pub fn zp_at_point(&self, point: Challenge) -> Challenge {
+        (point * Challenge::from(self.shift.inverse())).exp_power_of_2(self.log_n) - Challenge::ONE
+    }
+
+    pub fn zp_at_single_point(&self, point: Val) -> Val {
+        (point * self.shift.inverse()).exp_power_of_2(self.log_n) - Val::ONE
+    }
+}
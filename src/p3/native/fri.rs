@@ -0,0 +1,450 @@
+use itertools::izip;
+use plonky2::field::extension::quadratic::QuadraticExtension;
+use plonky2::field::types::Field;
+
+use crate::p3::native::challenger::DuplexChallenger;
+use crate::p3::native::commit::MerkleTreeMmcs;
+use crate::p3::native::domain::two_adic_generator;
+use crate::p3::native::domain::TwoAdicMultiplicativeCoset;
+use crate::p3::native::domain::GENERATOR;
+use crate::p3::native::domain::TWO_ADICITY;
+use crate::p3::native::Challenge;
+use crate::p3::native::Val;
+use crate::p3::native::VerifyError;
+use crate::p3::native::DIGEST_ELEMS;
+use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::proof::P3CommitmentField;
+use crate::p3::serde::proof::P3Field;
+use crate::p3::serde::proof::P3FriProofField;
+use crate::p3::serde::proof::P3TwoAdicFriPcsProofField;
+use crate::p3::serde::proof::QueryProof;
+use crate::p3::serde::Dimensions;
+use crate::p3::utils::reverse_bits_len;
+
+pub struct FriChallenges {
+    pub query_indices: Vec<usize>,
+    pub betas: Vec<Challenge>,
+}
+
+pub type MatrixOpenings = Vec<(TwoAdicMultiplicativeCoset, Vec<(Challenge, Vec<Challenge>)>)>;
+
+pub fn verify_opening_proof(
+    config: &FriConfig,
+    commits_and_points: &[([Val; DIGEST_ELEMS], MatrixOpenings)],
+    proof: &P3TwoAdicFriPcsProofField,
+    challenger: &mut DuplexChallenger,
+) -> Result<(), VerifyError> {
+    let alpha = challenger.sample_ext();
+
+    let log_trace_heights: Vec<usize> = commits_and_points
+        .iter()
+        .flat_map(|(_, mats)| mats.iter().map(|(domain, _)| domain.log_n))
+        .collect();
+    let log_arities = config
+        .log_arities(&log_trace_heights)
+        .map_err(|_| VerifyError::InvalidProofShape)?;
+    let log_max_height = config.log_max_height(&log_arities);
+
+    let fri_challenges =
+        verify_shape_and_sample_challenges(config, &proof.fri_proof, &log_arities, challenger)?;
+
+    if proof.query_openings.len() != config.num_queries {
+        return Err(VerifyError::InvalidProofShape);
+    }
+
+    let reduced_openings = proof
+        .query_openings
+        .iter()
+        .zip(&fri_challenges.query_indices)
+        .map(|(query_opening, &index)| {
+            if query_opening.len() != commits_and_points.len() {
+                return Err(VerifyError::InvalidProofShape);
+            }
+
+            let mut ro = vec![Challenge::ZERO; log_max_height + 1];
+            let mut alpha_pow = vec![Challenge::ONE; log_max_height + 1];
+
+            for (batch_opening, (batch_commit, mats)) in izip!(query_opening, commits_and_points) {
+                let batch_dims: Vec<Dimensions> = mats
+                    .iter()
+                    .map(|(domain, _)| Dimensions {
+                        // todo: mmcs doesn't really need width
+                        width: 0,
+                        height: domain.size(),
+                    })
+                    .collect();
+
+                let opened_values = values_2d(&batch_opening.opened_values);
+
+                // The tree of a batch is as tall as its tallest matrix, which
+                // may be shorter than the tallest of all batches.
+                let log_batch_max_height = mats
+                    .iter()
+                    .map(|(domain, _)| domain.log_n + config.log_blowup)
+                    .max()
+                    .unwrap_or(log_max_height);
+                let bits_reduced = log_max_height
+                    .checked_sub(log_batch_max_height)
+                    .ok_or(VerifyError::InvalidProofShape)?;
+
+                MerkleTreeMmcs::verify_batch(
+                    batch_commit,
+                    &batch_dims,
+                    index >> bits_reduced,
+                    &opened_values,
+                    &values_2d(&batch_opening.opening_proof),
+                )
+                .map_err(VerifyError::InputMmcsError)?;
+
+                for (mat_opening, (mat_domain, mat_points_and_values)) in
+                    izip!(&opened_values, mats)
+                {
+                    let log_height = mat_domain.log_n + config.log_blowup;
+                    if log_height > log_max_height {
+                        return Err(VerifyError::InvalidProofShape);
+                    }
+
+                    let bits_reduced = log_max_height - log_height;
+                    let rev_reduced_index = reverse_bits_len(index >> bits_reduced, log_height);
+
+                    let x = Val::from_canonical_u64(GENERATOR)
+                        * two_adic_generator(log_height).exp_u64(rev_reduced_index as u64);
+
+                    for (z, ps_at_z) in mat_points_and_values {
+                        if mat_opening.len() != ps_at_z.len() {
+                            return Err(VerifyError::InvalidProofShape);
+                        }
+
+                        for (&p_at_x, &p_at_z) in izip!(mat_opening, ps_at_z) {
+                            let quotient =
+                                (Challenge::from(p_at_x) - p_at_z) / (Challenge::from(x) - *z);
+                            ro[log_height] += alpha_pow[log_height] * quotient;
+                            alpha_pow[log_height] *= alpha;
+                        }
+                    }
+                }
+            }
+            Ok(ro)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    verify_challenges(
+        config,
+        &proof.fri_proof,
+        &log_arities,
+        &fri_challenges,
+        &reduced_openings,
+    )
+}
+
+pub fn verify_shape_and_sample_challenges(
+    config: &FriConfig,
+    proof: &P3FriProofField,
+    log_arities: &[usize],
+    challenger: &mut DuplexChallenger,
+) -> Result<FriChallenges, VerifyError> {
+    if proof.commit_phase_commits.len() != log_arities.len() {
+        return Err(VerifyError::InvalidProofShape);
+    }
+
+    let betas: Vec<Challenge> = proof
+        .commit_phase_commits
+        .iter()
+        .map(|comm| {
+            challenger.observe_slice(&digest(comm)?);
+            Ok(challenger.sample_ext())
+        })
+        .collect::<Result<_, VerifyError>>()?;
+
+    if proof.final_poly.len() != config.final_poly_len() {
+        return Err(VerifyError::InvalidProofShape);
+    }
+    if config.log_final_poly_len.is_some() {
+        for coeff in &proof.final_poly {
+            challenger.observe_slice(&coeff.value.map(|v| v.value));
+        }
+    }
+
+    if proof.query_proofs.len() != config.num_queries {
+        return Err(VerifyError::InvalidProofShape);
+    }
+
+    if !challenger.check_witness(config.proof_of_work_bits, proof.pow_witness.value) {
+        return Err(VerifyError::InvalidPowWitness);
+    }
+
+    let log_max_height = config.log_max_height(log_arities);
+    if log_max_height > TWO_ADICITY {
+        return Err(VerifyError::InvalidProofShape);
+    }
+
+    let query_indices: Vec<usize> = (0..config.num_queries)
+        .map(|_| challenger.sample_bits(log_max_height))
+        .collect();
+
+    Ok(FriChallenges {
+        query_indices,
+        betas,
+    })
+}
+
+pub fn verify_challenges(
+    config: &FriConfig,
+    proof: &P3FriProofField,
+    log_arities: &[usize],
+    challenges: &FriChallenges,
+    reduced_openings: &[Vec<Challenge>],
+) -> Result<(), VerifyError> {
+    let log_max_height = config.log_max_height(log_arities);
+    let commit_phase_commits: Vec<[Val; DIGEST_ELEMS]> = proof
+        .commit_phase_commits
+        .iter()
+        .map(digest)
+        .collect::<Result<_, _>>()?;
+    let final_poly: Vec<Challenge> = proof
+        .final_poly
+        .iter()
+        .map(|coeff| challenge(&coeff.value))
+        .collect();
+
+    for (&index, query_proof, ro) in izip!(
+        &challenges.query_indices,
+        &proof.query_proofs,
+        reduced_openings
+    ) {
+        let (folded_eval, x) = verify_query(
+            &commit_phase_commits,
+            log_arities,
+            index,
+            query_proof,
+            &challenges.betas,
+            ro,
+            log_max_height,
+        )?;
+
+        let final_poly_eval = final_poly
+            .iter()
+            .rev()
+            .fold(Challenge::ZERO, |acc, &coeff| {
+                acc * Challenge::from(x) + coeff
+            });
+        if folded_eval != final_poly_eval {
+            return Err(VerifyError::FinalPolyMismatch);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn verify_query(
+    commit_phase_commits: &[[Val; DIGEST_ELEMS]],
+    log_arities: &[usize],
+    mut index: usize,
+    proof: &QueryProof<P3Field>,
+    betas: &[Challenge],
+    reduced_openings: &[Challenge],
+    log_max_height: usize,
+) -> Result<(Challenge, Val), VerifyError> {
+    if proof.commit_phase_openings.len() != commit_phase_commits.len()
+        || log_arities.len() != commit_phase_commits.len()
+    {
+        return Err(VerifyError::InvalidProofShape);
+    }
+
+    let mut folded_eval = Challenge::ZERO;
+    let mut log_height = log_max_height;
+
+    for (&log_arity, commit, step, beta) in izip!(
+        log_arities,
+        commit_phase_commits,
+        &proof.commit_phase_openings,
+        betas
+    ) {
+        let arity = 1 << log_arity;
+        if step.sibling_values.len() != arity - 1 {
+            return Err(VerifyError::InvalidProofShape);
+        }
+
+        folded_eval += reduced_openings[log_height];
+
+        let log_folded_height = log_height - log_arity;
+        let index_row = index >> log_arity;
+
+        let mut evals: Vec<Challenge> = step
+            .sibling_values
+            .iter()
+            .map(|sibling| challenge(&sibling.value))
+            .collect();
+        evals.insert(index % arity, folded_eval);
+
+        let dims = &[Dimensions {
+            width: arity,
+            height: (1 << log_folded_height),
+        }];
+
+        MerkleTreeMmcs::verify_batch(
+            commit,
+            dims,
+            index_row,
+            &[evals.iter().flat_map(|eval| eval.0).collect()],
+            &values_2d(&step.opening_proof),
+        )
+        .map_err(VerifyError::CommitPhaseMmcsError)?;
+
+        // The row holds the `arity`-th roots of the point of `index_row` in
+        // the folded domain, starting at `x0`.
+        let x0 = two_adic_generator(log_height)
+            .exp_u64(reverse_bits_len(index_row, log_folded_height) as u64);
+        folded_eval = fold_row(x0, log_arity, *beta, &evals);
+
+        index = index_row;
+        log_height = log_folded_height;
+    }
+
+    // Inputs as short as the final polynomial are rolled in unfolded.
+    folded_eval += reduced_openings[log_height];
+    let x = two_adic_generator(log_height).exp_u64(reverse_bits_len(index, log_height) as u64);
+
+    Ok((folded_eval, x))
+}
+
+/// Points of the coset folded by a round of arity `2^log_arity`, relative to
+/// its first point and in the order of a row of the committed matrix, with the
+/// inverses of the denominators of their Lagrange basis polynomials.
+pub(crate) fn fold_coset(log_arity: usize) -> (Vec<Val>, Vec<Val>) {
+    let generator = two_adic_generator(log_arity);
+    let points: Vec<Val> = (0..1 << log_arity)
+        .map(|j| generator.exp_u64(reverse_bits_len(j, log_arity) as u64))
+        .collect();
+    let denominator_invs = points
+        .iter()
+        .enumerate()
+        .map(|(j, &point)| {
+            points
+                .iter()
+                .enumerate()
+                .filter(|&(m, _)| m != j)
+                .map(|(_, &other)| point - other)
+                .product::<Val>()
+                .inverse()
+        })
+        .collect();
+
+    (points, denominator_invs)
+}
+
+/// Evaluates at `beta` the polynomial of degree less than `evals.len()` that
+/// takes the values `evals` on the coset of a row whose first point is `x0`.
+pub(crate) fn fold_row(
+    x0: Val,
+    log_arity: usize,
+    beta: Challenge,
+    evals: &[Challenge],
+) -> Challenge {
+    let (points, denominator_invs) = fold_coset(log_arity);
+    let xs: Vec<Challenge> = points
+        .iter()
+        .map(|&point| Challenge::from(x0 * point))
+        .collect();
+    // The denominators over the coset are those over the unit coset, scaled
+    // by `x0^(arity - 1)`.
+    let x0_pow_inv = x0.exp_u64(points.len() as u64 - 1).inverse();
+
+    izip!(0.., evals, denominator_invs)
+        .map(|(j, &eval, denominator_inv)| {
+            let numerator: Challenge = xs
+                .iter()
+                .enumerate()
+                .filter(|&(m, _)| m != j)
+                .map(|(_, &x)| beta - x)
+                .product();
+            numerator * eval * Challenge::from(denominator_inv * x0_pow_inv)
+        })
+        .sum()
+}
+
+pub(crate) fn challenge(value: &[P3Field]) -> Challenge {
+    QuadraticExtension(core::array::from_fn(|i| value[i].value))
+}
+
+pub(crate) fn digest(commit: &P3CommitmentField) -> Result<[Val; DIGEST_ELEMS], VerifyError> {
+    let values: Vec<Val> = commit.value.iter().map(|v| v.value).collect();
+    values
+        .try_into()
+        .map_err(|_| VerifyError::InvalidProofShape)
+}
+
+fn values_2d(values: &[Vec<P3Field>]) -> Vec<Vec<Val>> {
+    values
+        .iter()
+        .map(|row| row.iter().map(|v| v.value).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_poly(coeffs: &[Challenge], x: Challenge) -> Challenge {
+        coeffs
+            .iter()
+            .rev()
+            .fold(Challenge::ZERO, |acc, &coeff| acc * x + coeff)
+    }
+
+    fn ext(a: u64, b: u64) -> Challenge {
+        QuadraticExtension([Val::from_canonical_u64(a), Val::from_canonical_u64(b)])
+    }
+
+    #[test]
+    fn test_fold_row_interpolates_the_coset() {
+        let x0 = Val::from_canonical_u64(7).exp_u64(13);
+        let beta = ext(5, 11);
+        for log_arity in 1..=3 {
+            let coeffs: Vec<Challenge> = (0..1u64 << log_arity)
+                .map(|i| ext(3 * i + 1, i * i + 2))
+                .collect();
+            let (points, _) = fold_coset(log_arity);
+            let evals: Vec<Challenge> = points
+                .iter()
+                .map(|&point| eval_poly(&coeffs, Challenge::from(x0 * point)))
+                .collect();
+
+            assert_eq!(
+                fold_row(x0, log_arity, beta, &evals),
+                eval_poly(&coeffs, beta)
+            );
+        }
+    }
+
+    #[test]
+    fn test_folding_four_points_folds_pairs_twice() {
+        let coeffs: Vec<Challenge> = (0..8u64).map(|i| ext(i + 1, 2 * i + 3)).collect();
+        let beta = ext(17, 19);
+        // A codeword of height 8 in bit-reversed order.
+        let codeword: Vec<Challenge> = (0..8)
+            .map(|i| {
+                let x = two_adic_generator(3).exp_u64(reverse_bits_len(i, 3) as u64);
+                eval_poly(&coeffs, Challenge::from(x))
+            })
+            .collect();
+        let fold = |codeword: &[Challenge], log_height: usize, log_arity: usize, beta| {
+            let log_folded_height = log_height - log_arity;
+            codeword
+                .chunks(1 << log_arity)
+                .enumerate()
+                .map(|(row, evals)| {
+                    let x0 = two_adic_generator(log_height)
+                        .exp_u64(reverse_bits_len(row, log_folded_height) as u64);
+                    fold_row(x0, log_arity, beta, evals)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let folded_pairs = fold(&codeword, 3, 1, beta);
+        assert_eq!(
+            fold(&codeword, 3, 2, beta),
+            fold(&folded_pairs, 2, 1, beta * beta)
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Value-level counterpart of [`lookup`](crate::p3::lookup), checking the
+//! LogUp constraints of the interactions of an AIR.
+
+use itertools::izip;
+use plonky2::field::types::Field;
+
+use crate::p3::constants::EXT_DEGREE;
+use crate::p3::lookup::NUM_PERMUTATION_CHALLENGES;
+use crate::p3::native::air::NativeConstraintFolder;
+use crate::p3::native::monomial;
+use crate::p3::native::Challenge;
+use crate::p3::native::VerifyError;
+
+/// A tuple of `values` sent or received `multiplicity` times on the current
+/// row.
+pub struct NativeInteraction {
+    pub values: Vec<Challenge>,
+    pub multiplicity: Challenge,
+    pub is_send: bool,
+}
+
+pub fn fingerprint(values: &[Challenge], challenges: &[Challenge]) -> Challenge {
+    let (alpha, beta) = (challenges[0], challenges[1]);
+
+    let mut fingerprint = alpha;
+    let mut beta_pow = Challenge::ONE;
+    for &value in values {
+        fingerprint += beta_pow * value;
+        beta_pow *= beta;
+    }
+    fingerprint
+}
+
+/// Recombines `EXT_DEGREE` opened base columns into the extension column they
+/// were flattened from.
+pub fn ext_from_base_columns(columns: &[Challenge]) -> Challenge {
+    columns
+        .iter()
+        .enumerate()
+        .map(|(e_i, &c)| monomial(e_i) * c)
+        .sum()
+}
+
+/// Folds the LogUp constraints of the interactions collected by
+/// [`NativeAir::eval`](crate::p3::native::air::NativeAir::eval) into `folder`.
+pub fn eval_lookup_constraints(folder: &mut NativeConstraintFolder) -> Result<(), VerifyError> {
+    let interactions = std::mem::take(&mut folder.interactions);
+
+    if interactions.is_empty() {
+        if !folder.permutation_local.is_empty()
+            || !folder.permutation_next.is_empty()
+            || folder.cumulative_sum.is_some()
+        {
+            return Err(VerifyError::InvalidProofShape);
+        }
+        return Ok(());
+    }
+
+    let permutation_width = (interactions.len() + 1) * EXT_DEGREE;
+    let cumulative_sum = match folder.cumulative_sum {
+        Some(cumulative_sum)
+            if folder.permutation_local.len() == permutation_width
+                && folder.permutation_next.len() == permutation_width
+                && folder.permutation_challenges.len() == NUM_PERMUTATION_CHALLENGES =>
+        {
+            cumulative_sum
+        }
+        _ => return Err(VerifyError::InvalidProofShape),
+    };
+
+    let permutation_local: Vec<Challenge> = folder
+        .permutation_local
+        .chunks(EXT_DEGREE)
+        .map(ext_from_base_columns)
+        .collect();
+    let permutation_next: Vec<Challenge> = folder
+        .permutation_next
+        .chunks(EXT_DEGREE)
+        .map(ext_from_base_columns)
+        .collect();
+    let challenges = folder.permutation_challenges.clone();
+
+    for (interaction, &permutation) in izip!(&interactions, &permutation_local) {
+        let fingerprint = fingerprint(&interaction.values, &challenges);
+        let multiplicity = if interaction.is_send {
+            interaction.multiplicity
+        } else {
+            -interaction.multiplicity
+        };
+        folder.assert_eq(permutation * fingerprint, multiplicity);
+    }
+
+    let n = interactions.len();
+    let running_sum_local = permutation_local[n];
+    let running_sum_next = permutation_next[n];
+    let sum_local: Challenge = permutation_local[..n].iter().copied().sum();
+    let sum_next: Challenge = permutation_next[..n].iter().copied().sum();
+
+    folder
+        .when_first_row()
+        .assert_eq(running_sum_local, sum_local);
+    folder
+        .when_transition()
+        .assert_eq(running_sum_next, running_sum_local + sum_next);
+    folder
+        .when_last_row()
+        .assert_eq(running_sum_local, cumulative_sum);
+
+    Ok(())
+}
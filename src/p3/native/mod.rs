@@ -0,0 +1,488 @@
+//! Out-of-circuit Plonky3 verifier.
+//!
+//! Mirrors [`CircuitBuilderP3Verifier`](crate::p3::verifier::CircuitBuilderP3Verifier)
+//! step by step over `GoldilocksField` values, so that a malformed or invalid
+//! proof can be rejected before spending any prover time on it.
+
+pub mod air;
+pub mod challenger;
+pub mod commit;
+pub mod domain;
+pub mod fri;
+pub mod lookup;
+#[cfg(test)]
+pub mod prover;
+
+use plonky2::field::extension::quadratic::QuadraticExtension;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::Field;
+
+use crate::p3::commit::MmcsError;
+use crate::p3::constants::EXT_DEGREE;
+use crate::p3::lookup::NUM_PERMUTATION_CHALLENGES;
+use crate::p3::native::air::NativeAir;
+use crate::p3::native::air::NativeConstraintFolder;
+use crate::p3::native::challenger::DuplexChallenger;
+use crate::p3::native::domain::TwoAdicMultiplicativeCoset;
+use crate::p3::native::fri::challenge;
+use crate::p3::native::fri::digest;
+use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::proof::P3ProofField;
+
+pub type Val = GoldilocksField;
+pub type Challenge = QuadraticExtension<Val>;
+
+/// Elements of a digest of the Goldilocks Poseidon2 config, see
+/// [`P3HashConfig::default`](crate::p3::serde::hash::P3HashConfig).
+pub const DIGEST_ELEMS: usize = 4;
+/// Rate of the padding-free sponge hashing the Merkle leaves.
+pub const RATE: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    InvalidProofShape,
+    InputMmcsError(MmcsError),
+    CommitPhaseMmcsError(MmcsError),
+    InvalidPowWitness,
+    FinalPolyMismatch,
+    OodEvaluationMismatch,
+    /// The interactions of a single table don't balance.
+    CumulativeSumMismatch,
+}
+
+pub fn verify_proof(
+    proof: &P3ProofField,
+    air: &impl NativeAir,
+    public_values: &[Val],
+    fri_config: &FriConfig,
+) -> Result<(), VerifyError> {
+    verify_proof_with_preprocessed(proof, air, None, public_values, fri_config)
+}
+
+/// Verifies a proof of an AIR with preprocessed columns, whose commitment
+/// `preprocessed_commit` is part of the verifying key rather than of the
+/// proof. It is required whenever the AIR has preprocessed columns.
+pub fn verify_proof_with_preprocessed(
+    proof: &P3ProofField,
+    air: &impl NativeAir,
+    preprocessed_commit: Option<&[Val]>,
+    public_values: &[Val],
+    fri_config: &FriConfig,
+) -> Result<(), VerifyError> {
+    let mut challenger = DuplexChallenger::new();
+
+    let degree_bits = proof.degree_bits;
+    let log_quotient_degree = air.log_quotient_degree();
+    let quotient_degree = 1 << log_quotient_degree;
+
+    let air_width = air.width();
+    let preprocessed_width = air.preprocessed_width();
+    let has_interactions = air.num_interactions() > 0;
+    let permutation_width = if has_interactions {
+        (air.num_interactions() + 1) * EXT_DEGREE
+    } else {
+        0
+    };
+    let valid_shape = public_values.len() == air.num_public_values()
+        && proof.opened_values.trace_local.len() == air_width
+        && proof.opened_values.trace_next.len() == air_width
+        && proof.opened_values.preprocessed_local.len() == preprocessed_width
+        && proof.opened_values.preprocessed_next.len() == preprocessed_width
+        && proof.opened_values.quotient_chunks.len() == quotient_degree
+        && proof
+            .opened_values
+            .quotient_chunks
+            .iter()
+            .all(|qc| qc.len() == EXT_DEGREE)
+        && proof.commitments.permutation.is_some() == has_interactions
+        && proof.opened_values.permutation_local.len() == permutation_width
+        && proof.opened_values.permutation_next.len() == permutation_width
+        && proof.opened_values.cumulative_sum.is_some() == has_interactions;
+    if !valid_shape {
+        return Err(VerifyError::InvalidProofShape);
+    }
+
+    let trace_domain = TwoAdicMultiplicativeCoset::natural_domain_for_degree(1 << degree_bits);
+    let quotient_domain =
+        trace_domain.create_disjoint_domain(1 << (degree_bits + log_quotient_degree));
+    let quotient_chunks_domains = quotient_domain.split_domains(quotient_degree);
+
+    let trace_commit: [Val; DIGEST_ELEMS] = digest(&proof.commitments.trace)?;
+    let quotient_chunks_commit: [Val; DIGEST_ELEMS] = digest(&proof.commitments.quotient_chunks)?;
+    let preprocessed_commit: Option<[Val; DIGEST_ELEMS]> = match preprocessed_commit {
+        _ if preprocessed_width == 0 => None,
+        Some(commit) => Some(
+            commit
+                .try_into()
+                .map_err(|_| VerifyError::InvalidProofShape)?,
+        ),
+        None => return Err(VerifyError::InvalidProofShape),
+    };
+    let permutation_commit: Option<[Val; DIGEST_ELEMS]> = proof
+        .commitments
+        .permutation
+        .as_ref()
+        .map(digest)
+        .transpose()?;
+
+    let trace_local: Vec<Challenge> = proof
+        .opened_values
+        .trace_local
+        .iter()
+        .map(|v| challenge(&v.value))
+        .collect();
+    let trace_next: Vec<Challenge> = proof
+        .opened_values
+        .trace_next
+        .iter()
+        .map(|v| challenge(&v.value))
+        .collect();
+    let preprocessed_local: Vec<Challenge> = proof
+        .opened_values
+        .preprocessed_local
+        .iter()
+        .map(|v| challenge(&v.value))
+        .collect();
+    let preprocessed_next: Vec<Challenge> = proof
+        .opened_values
+        .preprocessed_next
+        .iter()
+        .map(|v| challenge(&v.value))
+        .collect();
+    let quotient_chunks: Vec<Vec<Challenge>> = proof
+        .opened_values
+        .quotient_chunks
+        .iter()
+        .map(|qc| qc.iter().map(|v| challenge(&v.value)).collect())
+        .collect();
+    let permutation_local: Vec<Challenge> = proof
+        .opened_values
+        .permutation_local
+        .iter()
+        .map(|v| challenge(&v.value))
+        .collect();
+    let permutation_next: Vec<Challenge> = proof
+        .opened_values
+        .permutation_next
+        .iter()
+        .map(|v| challenge(&v.value))
+        .collect();
+    let cumulative_sum: Option<Challenge> = proof
+        .opened_values
+        .cumulative_sum
+        .as_ref()
+        .map(|v| challenge(&v.value));
+
+    challenger.observe_slice(&trace_commit);
+    if let Some(commit) = &preprocessed_commit {
+        challenger.observe_slice(commit);
+    }
+    challenger.observe_slice(public_values);
+    let permutation_challenges: Vec<Challenge> = match &permutation_commit {
+        Some(commit) => {
+            let challenges = (0..NUM_PERMUTATION_CHALLENGES)
+                .map(|_| challenger.sample_ext())
+                .collect();
+            challenger.observe_slice(commit);
+            if let Some(cumulative_sum) = cumulative_sum {
+                challenger.observe_slice(&cumulative_sum.0);
+            }
+            challenges
+        }
+        None => vec![],
+    };
+    let alpha = challenger.sample_ext();
+    challenger.observe_slice(&quotient_chunks_commit);
+
+    let zeta = challenger.sample_ext();
+    let zeta_next = trace_domain.next_point(zeta);
+
+    let mut commits_and_points = vec![
+        (
+            trace_commit,
+            vec![(
+                trace_domain,
+                vec![(zeta, trace_local.clone()), (zeta_next, trace_next.clone())],
+            )],
+        ),
+        (
+            quotient_chunks_commit,
+            quotient_chunks_domains
+                .iter()
+                .zip(&quotient_chunks)
+                .map(|(domain, values)| (*domain, vec![(zeta, values.clone())]))
+                .collect(),
+        ),
+    ];
+    if let Some(commit) = preprocessed_commit {
+        commits_and_points.push((
+            commit,
+            vec![(
+                trace_domain,
+                vec![
+                    (zeta, preprocessed_local.clone()),
+                    (zeta_next, preprocessed_next.clone()),
+                ],
+            )],
+        ));
+    }
+    if let Some(commit) = permutation_commit {
+        commits_and_points.push((
+            commit,
+            vec![(
+                trace_domain,
+                vec![
+                    (zeta, permutation_local.clone()),
+                    (zeta_next, permutation_next.clone()),
+                ],
+            )],
+        ));
+    }
+    fri::verify_opening_proof(
+        fri_config,
+        &commits_and_points,
+        &proof.opening_proof,
+        &mut challenger,
+    )?;
+
+    // A single table has nothing to interact with, so its interactions must
+    // balance on their own.
+    if cumulative_sum.is_some_and(|cumulative_sum| cumulative_sum != Challenge::ZERO) {
+        return Err(VerifyError::CumulativeSumMismatch);
+    }
+
+    let zps: Vec<Challenge> = quotient_chunks_domains
+        .iter()
+        .enumerate()
+        .map(|(i, domain)| {
+            quotient_chunks_domains
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, other_domain)| {
+                    other_domain.zp_at_point(zeta)
+                        * Challenge::from(
+                            other_domain
+                                .zp_at_single_point(domain.first_point())
+                                .inverse(),
+                        )
+                })
+                .product()
+        })
+        .collect();
+
+    let quotient: Challenge = quotient_chunks
+        .iter()
+        .enumerate()
+        .map(|(ch_i, ch)| {
+            ch.iter()
+                .enumerate()
+                .map(|(e_i, &c)| zps[ch_i] * monomial(e_i) * c)
+                .sum::<Challenge>()
+        })
+        .sum();
+
+    let sels = trace_domain.selectors_at_point(zeta);
+
+    let mut folder = NativeConstraintFolder {
+        trace_local,
+        trace_next,
+        preprocessed_local,
+        preprocessed_next,
+        permutation_local,
+        permutation_next,
+        cumulative_sum,
+        public_values: public_values.to_vec(),
+        permutation_challenges,
+        interactions: vec![],
+        is_first_row: sels.is_first_row,
+        is_last_row: sels.is_last_row,
+        is_transition: sels.is_transition,
+        alpha,
+        accumulator: Challenge::ZERO,
+    };
+
+    air.eval(&mut folder);
+    lookup::eval_lookup_constraints(&mut folder)?;
+
+    let folded_constraints = folder.accumulator;
+
+    if folded_constraints * sels.inv_zeroifier != quotient {
+        return Err(VerifyError::OodEvaluationMismatch);
+    }
+
+    Ok(())
+}
+
+fn monomial(exponent: usize) -> Challenge {
+    let mut value = [Val::ZERO; EXT_DEGREE];
+    value[exponent] = Val::ONE;
+    QuadraticExtension(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p3::serde::proof::Value;
+    use crate::p3::tests::cube_trace;
+    use crate::p3::tests::fibonacci_fri_config;
+    use crate::p3::tests::fibonacci_trace;
+    use crate::p3::tests::indices_trace;
+    use crate::p3::tests::lookup_trace;
+    use crate::p3::tests::native_fri_config;
+    use crate::p3::tests::squares_trace;
+    use crate::p3::tests::CubeAir;
+    use crate::p3::tests::FibonacciAir;
+    use crate::p3::tests::LookupAir;
+    use crate::p3::tests::SquaresAir;
+
+    fn fibonacci_proof() -> P3ProofField {
+        let proof_str = include_str!("../../../artifacts/proof_fibonacci.json");
+        serde_json::from_str::<P3ProofField>(proof_str).unwrap()
+    }
+
+    #[test]
+    fn test_native_verify_proof() {
+        verify_proof(
+            &fibonacci_proof(),
+            &FibonacciAir {},
+            &[],
+            &fibonacci_fri_config(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_native_verify_proof_rejects_tampered_proof() {
+        let mut proof = fibonacci_proof();
+        proof.opened_values.trace_local[0].value[0] = Value {
+            value: proof.opened_values.trace_local[0].value[0].value + Val::ONE,
+        };
+        assert!(verify_proof(&proof, &FibonacciAir {}, &[], &fibonacci_fri_config()).is_err());
+
+        let mut proof = fibonacci_proof();
+        proof.opening_proof.query_openings.pop();
+        assert_eq!(
+            verify_proof(&proof, &FibonacciAir {}, &[], &fibonacci_fri_config()),
+            Err(VerifyError::InvalidProofShape)
+        );
+    }
+
+    #[test]
+    fn test_native_prove_and_verify() {
+        let fri_config = native_fri_config();
+        let proof = prover::prove(&FibonacciAir {}, &fibonacci_trace(3), &[], &fri_config);
+        verify_proof(&proof, &FibonacciAir {}, &[], &fri_config).unwrap();
+
+        let proof = prover::prove(&CubeAir, &cube_trace(3), &[], &fri_config);
+        assert_eq!(proof.opened_values.quotient_chunks.len(), 4);
+        verify_proof(&proof, &CubeAir, &[], &fri_config).unwrap();
+    }
+
+    #[test]
+    fn test_native_verify_observed_constant_final_poly() {
+        let fri_config = FriConfig {
+            log_final_poly_len: Some(0),
+            ..native_fri_config()
+        };
+        let proof = prover::prove(&FibonacciAir {}, &fibonacci_trace(3), &[], &fri_config);
+        verify_proof(&proof, &FibonacciAir {}, &[], &fri_config).unwrap();
+
+        // The same proof with its constant left out of the transcript.
+        assert!(verify_proof(&proof, &FibonacciAir {}, &[], &native_fri_config()).is_err());
+    }
+
+    #[test]
+    fn test_native_verify_preprocessed_proof() {
+        let fri_config = native_fri_config();
+        let preprocessed = indices_trace(3);
+        let proof = prover::prove_with_preprocessed(
+            &SquaresAir,
+            &preprocessed,
+            &squares_trace(3),
+            &[],
+            &fri_config,
+        );
+
+        let commit = prover::preprocessed_commit(&preprocessed, &fri_config);
+        verify_proof_with_preprocessed(&proof, &SquaresAir, Some(&commit), &[], &fri_config)
+            .unwrap();
+
+        // The preprocessed commitment is observed, so committing to other
+        // fixed columns changes the challenges.
+        let other_commit = prover::preprocessed_commit(&squares_trace(3), &fri_config);
+        assert!(verify_proof_with_preprocessed(
+            &proof,
+            &SquaresAir,
+            Some(&other_commit),
+            &[],
+            &fri_config
+        )
+        .is_err());
+        assert_eq!(
+            verify_proof(&proof, &SquaresAir, &[], &fri_config),
+            Err(VerifyError::InvalidProofShape)
+        );
+    }
+
+    #[test]
+    fn test_native_verify_proof_with_interactions() {
+        let fri_config = native_fri_config();
+        let sent: Vec<usize> = (0..8).collect();
+        let received: Vec<usize> = (0..8).rev().collect();
+        let proof = prover::prove(
+            &LookupAir,
+            &lookup_trace(&sent, &received),
+            &[],
+            &fri_config,
+        );
+        verify_proof(&proof, &LookupAir, &[], &fri_config).unwrap();
+
+        // Every row satisfies its constraints, but the values received are
+        // never sent.
+        let other: Vec<usize> = (8..16).collect();
+        let proof = prover::prove(&LookupAir, &lookup_trace(&sent, &other), &[], &fri_config);
+        assert_eq!(
+            verify_proof(&proof, &LookupAir, &[], &fri_config),
+            Err(VerifyError::CumulativeSumMismatch)
+        );
+    }
+
+    #[test]
+    fn test_native_verify_proof_binds_public_values() {
+        struct FibonacciAirWithOutput;
+
+        impl NativeAir for FibonacciAirWithOutput {
+            fn width(&self) -> usize {
+                FibonacciAir {}.width()
+            }
+
+            fn num_public_values(&self) -> usize {
+                1
+            }
+
+            fn eval(&self, folder: &mut NativeConstraintFolder) {
+                FibonacciAir {}.eval(folder)
+            }
+        }
+
+        // The proof was generated without public values, so observing one
+        // changes every challenge after the trace commitment.
+        assert!(verify_proof(
+            &fibonacci_proof(),
+            &FibonacciAirWithOutput,
+            &[Val::ONE],
+            &fibonacci_fri_config()
+        )
+        .is_err());
+        assert_eq!(
+            verify_proof(
+                &fibonacci_proof(),
+                &FibonacciAir {},
+                &[Val::ONE],
+                &fibonacci_fri_config()
+            ),
+            Err(VerifyError::InvalidProofShape)
+        );
+    }
+}
@@ -0,0 +1,834 @@
+//! Out-of-circuit Plonky3 prover, producing proofs with the layout and
+//! transcript of [`verify_proof`](crate::p3::native::verify_proof) so that
+//! tests can cover proof shapes no Plonky3 artifact does.
+//!
+//! Polynomials are interpolated and evaluated naively, which only suits the
+//! small traces of tests.
+
+use itertools::izip;
+use plonky2::field::types::Field;
+
+use crate::p3::constants::EXT_DEGREE;
+use crate::p3::lookup::NUM_PERMUTATION_CHALLENGES;
+use crate::p3::native::air::NativeAir;
+use crate::p3::native::air::NativeConstraintFolder;
+use crate::p3::native::challenger::DuplexChallenger;
+use crate::p3::native::commit::MerkleTreeMmcs;
+use crate::p3::native::domain::two_adic_generator;
+use crate::p3::native::domain::TwoAdicMultiplicativeCoset;
+use crate::p3::native::domain::GENERATOR;
+use crate::p3::native::fri::fold_row;
+use crate::p3::native::lookup::eval_lookup_constraints;
+use crate::p3::native::lookup::fingerprint;
+use crate::p3::native::Challenge;
+use crate::p3::native::Val;
+use crate::p3::native::DIGEST_ELEMS;
+use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::multi_proof::MultiProof;
+use crate::p3::serde::multi_proof::P3MultiProofField;
+use crate::p3::serde::proof::BatchOpening;
+use crate::p3::serde::proof::BinomialExtensionField;
+use crate::p3::serde::proof::CommitPhaseProofStep;
+use crate::p3::serde::proof::Commitment;
+use crate::p3::serde::proof::Commitments;
+use crate::p3::serde::proof::FriProof;
+use crate::p3::serde::proof::OpenedValues;
+use crate::p3::serde::proof::P3ProofField;
+use crate::p3::serde::proof::Proof;
+use crate::p3::serde::proof::QueryProof;
+use crate::p3::serde::proof::TwoAdicFriPcsProof;
+use crate::p3::serde::proof::Value;
+use crate::p3::utils::log2_strict_usize;
+use crate::p3::utils::reverse_bits_len;
+
+type Digest = [Val; DIGEST_ELEMS];
+
+/// Points a matrix is opened at, each with the evaluations of its columns.
+type Openings = Vec<(Challenge, Vec<Challenge>)>;
+
+/// Proves that `trace`, given row by row, satisfies `air` with
+/// `public_values`.
+pub fn prove(
+    air: &impl NativeAir,
+    trace: &[Vec<Val>],
+    public_values: &[Val],
+    fri_config: &FriConfig,
+) -> P3ProofField {
+    prove_with_preprocessed(air, &[], trace, public_values, fri_config)
+}
+
+/// The commitment to the `preprocessed` trace of an AIR, which the verifier
+/// takes from its verifying key.
+pub fn preprocessed_commit(preprocessed: &[Vec<Val>], fri_config: &FriConfig) -> Vec<Val> {
+    let domain = TwoAdicMultiplicativeCoset::natural_domain_for_degree(preprocessed.len());
+    Batch::new(vec![Matrix::new(
+        domain,
+        columns(preprocessed),
+        fri_config.log_blowup,
+    )])
+    .root()
+    .to_vec()
+}
+
+/// Proves that `trace` satisfies `air` along with the `preprocessed` trace,
+/// empty if the AIR has no preprocessed columns.
+pub fn prove_with_preprocessed(
+    air: &impl NativeAir,
+    preprocessed: &[Vec<Val>],
+    trace: &[Vec<Val>],
+    public_values: &[Val],
+    fri_config: &FriConfig,
+) -> P3ProofField {
+    // A single table proof has the layout and transcript of a one-table multi
+    // proof.
+    let MultiProof {
+        commitments,
+        mut opened_values,
+        opening_proof,
+        degree_bits,
+    } = prove_tables(
+        &[air],
+        &[preprocessed.to_vec()],
+        &[trace.to_vec()],
+        &[public_values.to_vec()],
+        fri_config,
+    );
+
+    Proof {
+        commitments,
+        opened_values: opened_values.remove(0),
+        opening_proof,
+        degree_bits: degree_bits[0],
+    }
+    .map(|value| Value { value })
+}
+
+/// Proves that `traces[i]` satisfies `airs[i]` with `public_values[i]` for
+/// every table, with the shared commitments and transcript of
+/// [`p3_verify_multi_proof`](crate::p3::verifier::CircuitBuilderP3Verifier::p3_verify_multi_proof).
+pub fn prove_multi(
+    airs: &[&dyn NativeAir],
+    traces: &[Vec<Vec<Val>>],
+    public_values: &[Vec<Val>],
+    fri_config: &FriConfig,
+) -> P3MultiProofField {
+    prove_tables(
+        airs,
+        &vec![vec![]; airs.len()],
+        traces,
+        public_values,
+        fri_config,
+    )
+    .map(|value| Value { value })
+}
+
+fn prove_tables(
+    airs: &[&dyn NativeAir],
+    preprocessed: &[Vec<Vec<Val>>],
+    traces: &[Vec<Vec<Val>>],
+    public_values: &[Vec<Val>],
+    fri_config: &FriConfig,
+) -> MultiProof<Val> {
+    assert_eq!(airs.len(), traces.len());
+    assert_eq!(airs.len(), public_values.len());
+    for (air, public_values) in izip!(airs, public_values) {
+        assert_eq!(public_values.len(), air.num_public_values());
+    }
+    let mut challenger = DuplexChallenger::new();
+
+    let trace_domains: Vec<TwoAdicMultiplicativeCoset> = traces
+        .iter()
+        .map(|trace| TwoAdicMultiplicativeCoset::natural_domain_for_degree(trace.len()))
+        .collect();
+    let trace_batch = Batch::new(
+        izip!(&trace_domains, traces)
+            .map(|(domain, trace)| Matrix::new(*domain, columns(trace), fri_config.log_blowup))
+            .collect(),
+    );
+
+    // The preprocessed traces of the tables having some share one batch.
+    let preprocessed_tables: Vec<usize> = (0..airs.len())
+        .filter(|&i| airs[i].preprocessed_width() > 0)
+        .collect();
+    let preprocessed_batch = (!preprocessed_tables.is_empty()).then(|| {
+        Batch::new(
+            preprocessed_tables
+                .iter()
+                .map(|&i| {
+                    assert_eq!(preprocessed[i].len(), traces[i].len());
+                    Matrix::new(
+                        trace_domains[i],
+                        columns(&preprocessed[i]),
+                        fri_config.log_blowup,
+                    )
+                })
+                .collect(),
+        )
+    });
+    let preprocessed_matrix = |i: usize| {
+        let position = preprocessed_tables.iter().position(|&j| j == i)?;
+        preprocessed_batch
+            .as_ref()
+            .map(|batch| &batch.matrices[position])
+    };
+
+    challenger.observe_slice(&trace_batch.root());
+    if let Some(batch) = &preprocessed_batch {
+        challenger.observe_slice(&batch.root());
+    }
+    for public_values in public_values {
+        challenger.observe_slice(public_values);
+    }
+
+    // The permutation traces of the tables with interactions share one batch.
+    let interaction_tables: Vec<usize> = (0..airs.len())
+        .filter(|&i| airs[i].num_interactions() > 0)
+        .collect();
+    let mut permutation_challenges = vec![];
+    let mut cumulative_sums = vec![None; airs.len()];
+    let permutation_batch = (!interaction_tables.is_empty()).then(|| {
+        permutation_challenges = (0..NUM_PERMUTATION_CHALLENGES)
+            .map(|_| challenger.sample_ext())
+            .collect();
+        let batch = Batch::new(
+            interaction_tables
+                .iter()
+                .map(|&i| {
+                    let (columns, cumulative_sum) = permutation_trace(
+                        airs[i],
+                        &preprocessed[i],
+                        &traces[i],
+                        &public_values[i],
+                        &permutation_challenges,
+                    );
+                    cumulative_sums[i] = Some(cumulative_sum);
+                    Matrix::new(trace_domains[i], columns, fri_config.log_blowup)
+                })
+                .collect(),
+        );
+        challenger.observe_slice(&batch.root());
+        for cumulative_sum in cumulative_sums.iter().flatten() {
+            challenger.observe_slice(&cumulative_sum.0);
+        }
+        batch
+    });
+    let permutation_matrix = |i: usize| {
+        let position = interaction_tables.iter().position(|&j| j == i)?;
+        permutation_batch
+            .as_ref()
+            .map(|batch| &batch.matrices[position])
+    };
+
+    let alpha = challenger.sample_ext();
+
+    let mut quotient_matrices = vec![];
+    for (i, (air, trace_domain)) in izip!(airs, &trace_domains).enumerate() {
+        let log_quotient_degree = air.log_quotient_degree();
+        let quotient_domain =
+            trace_domain.create_disjoint_domain(trace_domain.size() << log_quotient_degree);
+        let quotient = quotient_values(
+            *air,
+            &trace_batch.matrices[i],
+            preprocessed_matrix(i),
+            permutation_matrix(i),
+            cumulative_sums[i],
+            &public_values[i],
+            &permutation_challenges,
+            *trace_domain,
+            quotient_domain,
+            alpha,
+        );
+        let num_chunks = 1 << log_quotient_degree;
+        for (i, domain) in quotient_domain
+            .split_domains(num_chunks)
+            .into_iter()
+            .enumerate()
+        {
+            // The points of chunk `i` are every `num_chunks`-th point of the
+            // quotient domain, from the `i`-th on.
+            let chunk: Vec<Challenge> = quotient
+                .iter()
+                .skip(i)
+                .step_by(num_chunks)
+                .copied()
+                .collect();
+            let limbs = (0..chunk[0].0.len())
+                .map(|e| chunk.iter().map(|value| value.0[e]).collect())
+                .collect();
+            quotient_matrices.push(Matrix::new(domain, limbs, fri_config.log_blowup));
+        }
+    }
+    let quotient_batch = Batch::new(quotient_matrices);
+    challenger.observe_slice(&quotient_batch.root());
+    let zeta = challenger.sample_ext();
+
+    let mut opened_values = vec![];
+    let mut trace_openings = vec![];
+    let mut quotient_openings = vec![];
+    let mut preprocessed_openings = vec![];
+    let mut permutation_openings = vec![];
+    let mut quotient_chunks = quotient_batch.matrices.iter();
+    for (i, (air, trace_domain)) in izip!(airs, &trace_domains).enumerate() {
+        let zeta_next = trace_domain.next_point(zeta);
+
+        let trace_local = trace_batch.matrices[i].evaluate(zeta);
+        let trace_next = trace_batch.matrices[i].evaluate(zeta_next);
+        trace_openings.push(vec![
+            (zeta, trace_local.clone()),
+            (zeta_next, trace_next.clone()),
+        ]);
+
+        let (preprocessed_local, preprocessed_next) = match preprocessed_matrix(i) {
+            Some(matrix) => (matrix.evaluate(zeta), matrix.evaluate(zeta_next)),
+            None => (vec![], vec![]),
+        };
+        if preprocessed_matrix(i).is_some() {
+            preprocessed_openings.push(vec![
+                (zeta, preprocessed_local.clone()),
+                (zeta_next, preprocessed_next.clone()),
+            ]);
+        }
+
+        let (permutation_local, permutation_next) = match permutation_matrix(i) {
+            Some(matrix) => (matrix.evaluate(zeta), matrix.evaluate(zeta_next)),
+            None => (vec![], vec![]),
+        };
+        if permutation_matrix(i).is_some() {
+            permutation_openings.push(vec![
+                (zeta, permutation_local.clone()),
+                (zeta_next, permutation_next.clone()),
+            ]);
+        }
+
+        let table_quotient_chunks: Vec<Vec<Challenge>> = quotient_chunks
+            .by_ref()
+            .take(1 << air.log_quotient_degree())
+            .map(|matrix| matrix.evaluate(zeta))
+            .collect();
+        quotient_openings.extend(
+            table_quotient_chunks
+                .iter()
+                .map(|values| vec![(zeta, values.clone())]),
+        );
+
+        opened_values.push(OpenedValues {
+            trace_local: ext_values(&trace_local),
+            trace_next: ext_values(&trace_next),
+            quotient_chunks: table_quotient_chunks
+                .iter()
+                .map(|qc| ext_values(qc))
+                .collect(),
+            preprocessed_local: ext_values(&preprocessed_local),
+            preprocessed_next: ext_values(&preprocessed_next),
+            permutation_local: ext_values(&permutation_local),
+            permutation_next: ext_values(&permutation_next),
+            cumulative_sum: cumulative_sums[i].map(ext_value),
+        });
+    }
+
+    let mut batches = vec![
+        (&trace_batch, trace_openings),
+        (&quotient_batch, quotient_openings),
+    ];
+    if let Some(batch) = &preprocessed_batch {
+        batches.push((batch, preprocessed_openings));
+    }
+    if let Some(batch) = &permutation_batch {
+        batches.push((batch, permutation_openings));
+    }
+    let opening_proof = open(fri_config, &batches, &mut challenger);
+
+    MultiProof {
+        commitments: Commitments {
+            trace: commitment(trace_batch.root()),
+            quotient_chunks: commitment(quotient_batch.root()),
+            permutation: permutation_batch
+                .as_ref()
+                .map(|batch| commitment(batch.root())),
+        },
+        opened_values,
+        opening_proof,
+        degree_bits: trace_domains.iter().map(|domain| domain.log_n).collect(),
+    }
+}
+
+/// Evaluations over `quotient_domain` of the folded constraints divided by
+/// the vanishing polynomial of the trace domain.
+#[allow(clippy::too_many_arguments)]
+fn quotient_values(
+    air: &dyn NativeAir,
+    trace: &Matrix,
+    preprocessed: Option<&Matrix>,
+    permutation: Option<&Matrix>,
+    cumulative_sum: Option<Challenge>,
+    public_values: &[Val],
+    permutation_challenges: &[Challenge],
+    trace_domain: TwoAdicMultiplicativeCoset,
+    quotient_domain: TwoAdicMultiplicativeCoset,
+    alpha: Challenge,
+) -> Vec<Challenge> {
+    let evaluate = |matrix: Option<&Matrix>, point: Val| match matrix {
+        Some(matrix) => matrix.evaluate(Challenge::from(point)),
+        None => vec![],
+    };
+
+    (0..quotient_domain.size())
+        .map(|j| {
+            let x = quotient_domain.shift * quotient_domain.gen().exp_u64(j as u64);
+            let x_next = x * trace_domain.gen();
+            let sels = trace_domain.selectors_at_point(Challenge::from(x));
+
+            let mut folder = NativeConstraintFolder {
+                trace_local: trace.evaluate(Challenge::from(x)),
+                trace_next: trace.evaluate(Challenge::from(x_next)),
+                preprocessed_local: evaluate(preprocessed, x),
+                preprocessed_next: evaluate(preprocessed, x_next),
+                permutation_local: evaluate(permutation, x),
+                permutation_next: evaluate(permutation, x_next),
+                cumulative_sum,
+                public_values: public_values.to_vec(),
+                permutation_challenges: permutation_challenges.to_vec(),
+                interactions: vec![],
+                is_first_row: sels.is_first_row,
+                is_last_row: sels.is_last_row,
+                is_transition: sels.is_transition,
+                alpha,
+                accumulator: Challenge::ZERO,
+            };
+            air.eval(&mut folder);
+            eval_lookup_constraints(&mut folder).unwrap();
+            folder.accumulator * sels.inv_zeroifier
+        })
+        .collect()
+}
+
+/// The permutation trace of the interactions of `air` over `trace`, flattened
+/// to base field columns, and its cumulative sum.
+fn permutation_trace(
+    air: &dyn NativeAir,
+    preprocessed: &[Vec<Val>],
+    trace: &[Vec<Val>],
+    public_values: &[Val],
+    permutation_challenges: &[Challenge],
+) -> (Vec<Vec<Val>>, Challenge) {
+    let row = |rows: &[Vec<Val>], index: usize| -> Vec<Challenge> {
+        rows.get(index % trace.len())
+            .map(|row| row.iter().map(|&v| Challenge::from(v)).collect())
+            .unwrap_or_default()
+    };
+
+    // Evaluating the AIR on the rows of the trace collects the interactions
+    // of each row, its constraints aren't checked.
+    let mut running_sum = Challenge::ZERO;
+    let rows: Vec<Vec<Challenge>> = (0..trace.len())
+        .map(|index| {
+            let mut folder = NativeConstraintFolder {
+                trace_local: row(trace, index),
+                trace_next: row(trace, index + 1),
+                preprocessed_local: row(preprocessed, index),
+                preprocessed_next: row(preprocessed, index + 1),
+                permutation_local: vec![],
+                permutation_next: vec![],
+                cumulative_sum: None,
+                public_values: public_values.to_vec(),
+                permutation_challenges: permutation_challenges.to_vec(),
+                interactions: vec![],
+                is_first_row: Challenge::ZERO,
+                is_last_row: Challenge::ZERO,
+                is_transition: Challenge::ZERO,
+                alpha: Challenge::ZERO,
+                accumulator: Challenge::ZERO,
+            };
+            air.eval(&mut folder);
+
+            let mut row: Vec<Challenge> = folder
+                .interactions
+                .iter()
+                .map(|interaction| {
+                    let multiplicity = if interaction.is_send {
+                        interaction.multiplicity
+                    } else {
+                        -interaction.multiplicity
+                    };
+                    multiplicity
+                        * fingerprint(&interaction.values, permutation_challenges).inverse()
+                })
+                .collect();
+            running_sum += row.iter().copied().sum();
+            row.push(running_sum);
+            row
+        })
+        .collect();
+
+    let columns = (0..rows[0].len())
+        .flat_map(|column| {
+            let rows = &rows;
+            (0..EXT_DEGREE).map(move |e| rows.iter().map(|row| row[column].0[e]).collect())
+        })
+        .collect();
+    (columns, running_sum)
+}
+
+/// Opens the matrices of `batches` at their points with a FRI proof, the
+/// points of each matrix listed in the order of the matrices of its batch.
+fn open(
+    config: &FriConfig,
+    batches: &[(&Batch, Vec<Openings>)],
+    challenger: &mut DuplexChallenger,
+) -> TwoAdicFriPcsProof<Val> {
+    let alpha = challenger.sample_ext();
+
+    let log_trace_heights: Vec<usize> = batches
+        .iter()
+        .flat_map(|(batch, _)| batch.matrices.iter().map(|matrix| matrix.log_n))
+        .collect();
+    let log_arities = config.log_arities(&log_trace_heights).unwrap();
+    let log_max_height = config.log_max_height(&log_arities);
+
+    // The openings of all matrices of one height reduce to one codeword,
+    // batched by consecutive powers of `alpha`.
+    let mut reduced_openings: Vec<Vec<Challenge>> = (0..=log_max_height)
+        .map(|log_height| vec![Challenge::ZERO; 1 << log_height])
+        .collect();
+    let mut alpha_pows = vec![Challenge::ONE; log_max_height + 1];
+    for (batch, openings) in batches {
+        for (matrix, points) in izip!(&batch.matrices, openings) {
+            let log_height = matrix.log_n + config.log_blowup;
+            let xs = lde_points(log_height);
+            for (z, ps_at_z) in points {
+                let inv_denominators: Vec<Challenge> = xs
+                    .iter()
+                    .map(|&x| (Challenge::from(x) - *z).inverse())
+                    .collect();
+                for (column, &p_at_z) in ps_at_z.iter().enumerate() {
+                    let alpha_pow = alpha_pows[log_height];
+                    for (ro, row, &inv_denominator) in izip!(
+                        &mut reduced_openings[log_height],
+                        &matrix.lde,
+                        &inv_denominators
+                    ) {
+                        *ro +=
+                            alpha_pow * (Challenge::from(row[column]) - p_at_z) * inv_denominator;
+                    }
+                    alpha_pows[log_height] *= alpha;
+                }
+            }
+        }
+    }
+
+    // Commit phase: each round rolls in the inputs of its height, then folds
+    // the rows of `arity` consecutive points of the codeword into one.
+    let mut codeword = vec![Challenge::ZERO; 1 << log_max_height];
+    let mut log_height = log_max_height;
+    let mut rounds = vec![];
+    for &log_arity in &log_arities {
+        add_assign(&mut codeword, &reduced_openings[log_height]);
+
+        let rows: Vec<Vec<Challenge>> = codeword
+            .chunks(1 << log_arity)
+            .map(|row| row.to_vec())
+            .collect();
+        let leaves: Vec<Vec<Val>> = rows
+            .iter()
+            .map(|row| row.iter().flat_map(|eval| eval.0).collect())
+            .collect();
+        let tree = MerkleTree::new(&[&leaves]);
+        challenger.observe_slice(&tree.root());
+        let beta = challenger.sample_ext();
+
+        let log_folded_height = log_height - log_arity;
+        codeword = rows
+            .iter()
+            .enumerate()
+            .map(|(index_row, row)| {
+                let x0 = two_adic_generator(log_height)
+                    .exp_u64(reverse_bits_len(index_row, log_folded_height) as u64);
+                fold_row(x0, log_arity, beta, row)
+            })
+            .collect();
+        rounds.push((tree, rows));
+        log_height = log_folded_height;
+    }
+    add_assign(&mut codeword, &reduced_openings[log_height]);
+
+    // The codeword is in bit-reversed order over the subgroup of its size.
+    let natural_order: Vec<Challenge> = (0..codeword.len())
+        .map(|i| codeword[reverse_bits_len(i, log_height)])
+        .collect();
+    let coeffs = interpolate(
+        &TwoAdicMultiplicativeCoset::natural_domain_for_degree(codeword.len()),
+        &natural_order,
+    );
+    let (final_poly, rest) = coeffs.split_at(config.final_poly_len());
+    assert!(
+        rest.iter().all(|coeff| *coeff == Challenge::ZERO),
+        "the trace doesn't satisfy the constraints"
+    );
+    if config.log_final_poly_len.is_some() {
+        for coeff in final_poly {
+            challenger.observe_slice(&coeff.0);
+        }
+    }
+
+    let pow_witness = (0..)
+        .map(Val::from_canonical_u64)
+        .find(|&witness| {
+            let mut challenger = challenger.clone();
+            challenger.check_witness(config.proof_of_work_bits, witness)
+        })
+        .unwrap();
+    assert!(challenger.check_witness(config.proof_of_work_bits, pow_witness));
+
+    let query_indices: Vec<usize> = (0..config.num_queries)
+        .map(|_| challenger.sample_bits(log_max_height))
+        .collect();
+
+    let query_proofs = query_indices
+        .iter()
+        .map(|&index| {
+            let mut index = index;
+            let commit_phase_openings = izip!(&log_arities, &rounds)
+                .map(|(&log_arity, (tree, rows))| {
+                    let index_row = index >> log_arity;
+                    let position = index % (1 << log_arity);
+                    let sibling_values = rows[index_row]
+                        .iter()
+                        .enumerate()
+                        .filter(|&(j, _)| j != position)
+                        .map(|(_, &eval)| ext_value(eval))
+                        .collect();
+                    index = index_row;
+                    CommitPhaseProofStep {
+                        sibling_values,
+                        opening_proof: tree.open(index_row),
+                    }
+                })
+                .collect();
+            QueryProof {
+                commit_phase_openings,
+            }
+        })
+        .collect();
+
+    let query_openings = query_indices
+        .iter()
+        .map(|&index| {
+            batches
+                .iter()
+                .map(|(batch, _)| {
+                    let lde_row = |log_height: usize| index >> (log_max_height - log_height);
+                    BatchOpening {
+                        opened_values: batch
+                            .matrices
+                            .iter()
+                            .map(|matrix| {
+                                matrix.lde[lde_row(matrix.log_n + config.log_blowup)].clone()
+                            })
+                            .collect(),
+                        opening_proof: batch.tree.open(lde_row(batch.log_max_height())),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    TwoAdicFriPcsProof {
+        fri_proof: FriProof {
+            commit_phase_commits: rounds
+                .iter()
+                .map(|(tree, _)| commitment(tree.root()))
+                .collect(),
+            query_proofs,
+            final_poly: final_poly.iter().map(|&coeff| ext_value(coeff)).collect(),
+            pow_witness,
+        },
+        query_openings,
+    }
+}
+
+/// Columns of a trace over a domain, with their low-degree extension.
+struct Matrix {
+    /// Log of the height of the domain.
+    log_n: usize,
+    /// Coefficients of each column.
+    coeffs: Vec<Vec<Val>>,
+    /// Rows of the extension over the LDE coset, in bit-reversed order.
+    lde: Vec<Vec<Val>>,
+}
+
+impl Matrix {
+    /// Interpolates `columns` of evaluations over `domain` and extends them by
+    /// `2^log_blowup` over the coset of the multiplicative generator.
+    fn new(domain: TwoAdicMultiplicativeCoset, columns: Vec<Vec<Val>>, log_blowup: usize) -> Self {
+        let coeffs: Vec<Vec<Val>> = columns
+            .iter()
+            .map(|column| interpolate(&domain, column))
+            .collect();
+        let lde = lde_points(domain.log_n + log_blowup)
+            .into_iter()
+            .map(|x| coeffs.iter().map(|column| evaluate(column, x)).collect())
+            .collect();
+
+        Self {
+            log_n: domain.log_n,
+            coeffs,
+            lde,
+        }
+    }
+
+    fn evaluate(&self, point: Challenge) -> Vec<Challenge> {
+        self.coeffs
+            .iter()
+            .map(|column| evaluate(column, point))
+            .collect()
+    }
+}
+
+/// Matrices committed under one Merkle root.
+struct Batch {
+    matrices: Vec<Matrix>,
+    tree: MerkleTree,
+}
+
+impl Batch {
+    fn new(matrices: Vec<Matrix>) -> Self {
+        let ldes: Vec<&[Vec<Val>]> = matrices
+            .iter()
+            .map(|matrix| matrix.lde.as_slice())
+            .collect();
+        let tree = MerkleTree::new(&ldes);
+        Self { matrices, tree }
+    }
+
+    fn root(&self) -> Digest {
+        self.tree.root()
+    }
+
+    fn log_max_height(&self) -> usize {
+        log2_strict_usize(self.tree.layers[0].len())
+    }
+}
+
+/// Merkle tree of the rows of matrices of power of two heights, which
+/// [`MerkleTreeMmcs::verify_batch`] opens: the rows of the tallest matrices
+/// are hashed into leaves, and those of shorter ones are hashed and
+/// compressed into the nodes of their height.
+struct MerkleTree {
+    layers: Vec<Vec<Digest>>,
+}
+
+impl MerkleTree {
+    fn new(matrices: &[&[Vec<Val>]]) -> Self {
+        let max_height = matrices.iter().map(|rows| rows.len()).max().unwrap();
+        let hash_rows = |height: usize, index: usize| {
+            MerkleTreeMmcs::hash_iter_slices(
+                matrices
+                    .iter()
+                    .filter(|rows| rows.len() == height)
+                    .map(|rows| rows[index].as_slice()),
+            )
+        };
+
+        let mut layers = vec![(0..max_height)
+            .map(|index| hash_rows(max_height, index))
+            .collect::<Vec<_>>()];
+        let mut height = max_height;
+        while height > 1 {
+            height /= 2;
+            let previous = layers.last().unwrap();
+            let has_rows = matrices.iter().any(|rows| rows.len() == height);
+            let layer = (0..height)
+                .map(|index| {
+                    let node =
+                        MerkleTreeMmcs::compress([previous[2 * index], previous[2 * index + 1]]);
+                    if has_rows {
+                        MerkleTreeMmcs::compress([node, hash_rows(height, index)])
+                    } else {
+                        node
+                    }
+                })
+                .collect();
+            layers.push(layer);
+        }
+
+        Self { layers }
+    }
+
+    fn root(&self) -> Digest {
+        self.layers.last().unwrap()[0]
+    }
+
+    /// The siblings on the path from leaf `index` to the root.
+    fn open(&self, mut index: usize) -> Vec<Vec<Val>> {
+        self.layers[..self.layers.len() - 1]
+            .iter()
+            .map(|layer| {
+                let sibling = layer[index ^ 1].to_vec();
+                index >>= 1;
+                sibling
+            })
+            .collect()
+    }
+}
+
+fn columns(rows: &[Vec<Val>]) -> Vec<Vec<Val>> {
+    (0..rows[0].len())
+        .map(|column| rows.iter().map(|row| row[column]).collect())
+        .collect()
+}
+
+/// Points of the LDE coset of height `2^log_height` in bit-reversed order.
+fn lde_points(log_height: usize) -> Vec<Val> {
+    let shift = Val::from_canonical_u64(GENERATOR);
+    let generator = two_adic_generator(log_height);
+    (0..1 << log_height)
+        .map(|i| shift * generator.exp_u64(reverse_bits_len(i, log_height) as u64))
+        .collect()
+}
+
+/// Coefficients of the polynomial taking `values` on the points of `domain`
+/// in their natural order.
+fn interpolate<T: Field + From<Val>>(domain: &TwoAdicMultiplicativeCoset, values: &[T]) -> Vec<T> {
+    assert_eq!(values.len(), domain.size());
+    let generator_inv = domain.gen().inverse();
+    let shift_inv = domain.shift.inverse();
+    let size_inv = Val::from_canonical_usize(values.len()).inverse();
+
+    (0..values.len())
+        .map(|k| {
+            let root = T::from(generator_inv.exp_u64(k as u64));
+            let sum = values
+                .iter()
+                .rev()
+                .fold(T::ZERO, |acc, &value| acc * root + value);
+            sum * T::from(size_inv * shift_inv.exp_u64(k as u64))
+        })
+        .collect()
+}
+
+fn evaluate<T: Field + From<Val>>(coeffs: &[Val], point: T) -> T {
+    coeffs
+        .iter()
+        .rev()
+        .fold(T::ZERO, |acc, &coeff| acc * point + T::from(coeff))
+}
+
+fn add_assign(xs: &mut [Challenge], ys: &[Challenge]) {
+    for (x, &y) in xs.iter_mut().zip(ys) {
+        *x += y;
+    }
+}
+
+fn commitment(digest: Digest) -> Commitment<Val> {
+    Commitment {
+        value: digest.to_vec(),
+    }
+}
+
+fn ext_value(value: Challenge) -> BinomialExtensionField<Val> {
+    BinomialExtensionField { value: value.0 }
+}
+
+fn ext_values(values: &[Challenge]) -> Vec<BinomialExtensionField<Val>> {
+    values.iter().map(|&value| ext_value(value)).collect()
+}
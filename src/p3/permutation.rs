@@ -0,0 +1,97 @@
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hashing::PlonkyPermutation;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::AlgebraicHasher;
+
+use crate::common::poseidon2::poseidon2::Poseidon2Hash;
+use crate::common::richer_field::RicherField;
+
+/// The constants a [`P3Permutation`] is instantiated with. Kept apart from
+/// the permutation so that the challenger and the MMCS, which are generic over
+/// the permutation only, can hold them.
+pub trait P3PermutationParams {
+    /// `()` for permutations fixed by their type, such as plonky2's hashers,
+    /// and the round constants for those sampled by the Plonky3 config.
+    type Params: Clone;
+}
+
+/// The permutation a Plonky3 config builds its duplex challenger, its
+/// padding-free sponge and its truncated compression function from, i.e.
+/// `Perm` in `DuplexChallenger<Val, Perm, WIDTH>`,
+/// `PaddingFreeSponge<Perm, WIDTH, RATE, DIGEST_ELEMS>` and
+/// `TruncatedPermutation<Perm, 2, DIGEST_ELEMS, WIDTH>`. The rate and digest
+/// size are runtime parameters, see
+/// [`P3HashConfig`](crate::p3::serde::hash::P3HashConfig).
+pub trait P3Permutation<F: RicherField>: P3PermutationParams {
+    /// Number of elements of the state.
+    fn width(params: &Self::Params) -> usize;
+
+    fn permute_targets<const D: usize>(
+        params: &Self::Params,
+        state: &[Target],
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Vec<Target>
+    where
+        F: Extendable<D>;
+}
+
+/// Permutes `state` with the gate of the plonky2 hasher `H`.
+fn permute_algebraic<F: RicherField + Extendable<D>, const D: usize, H: AlgebraicHasher<F>>(
+    state: &[Target],
+    cb: &mut CircuitBuilder<F, D>,
+) -> Vec<Target> {
+    assert_eq!(
+        state.len(),
+        <H::AlgebraicPermutation as PlonkyPermutation<Target>>::WIDTH
+    );
+    let swap = cb._false();
+    let state = H::AlgebraicPermutation::new(state.iter().copied());
+    H::permute_swapped(state, swap, cb).as_ref().to_vec()
+}
+
+impl P3PermutationParams for Poseidon2Hash {
+    type Params = ();
+}
+
+/// The permutation of the Goldilocks Plonky3 configs this crate verifies.
+impl<F: RicherField> P3Permutation<F> for Poseidon2Hash {
+    fn width(_params: &()) -> usize {
+        <<Self as AlgebraicHasher<F>>::AlgebraicPermutation as PlonkyPermutation<Target>>::WIDTH
+    }
+
+    fn permute_targets<const D: usize>(
+        _params: &(),
+        state: &[Target],
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Vec<Target>
+    where
+        F: Extendable<D>,
+    {
+        permute_algebraic::<F, D, Self>(state, cb)
+    }
+}
+
+impl P3PermutationParams for PoseidonHash {
+    type Params = ();
+}
+
+/// The Poseidon permutation plonky2 itself hashes with, for Plonky3 configs
+/// instantiated with it.
+impl<F: RicherField> P3Permutation<F> for PoseidonHash {
+    fn width(_params: &()) -> usize {
+        <<Self as AlgebraicHasher<F>>::AlgebraicPermutation as PlonkyPermutation<Target>>::WIDTH
+    }
+
+    fn permute_targets<const D: usize>(
+        _params: &(),
+        state: &[Target],
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Vec<Target>
+    where
+        F: Extendable<D>,
+    {
+        permute_algebraic::<F, D, Self>(state, cb)
+    }
+}
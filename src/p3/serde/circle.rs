@@ -0,0 +1,476 @@
+use std::collections::BTreeSet;
+
+use itertools::izip;
+use plonky2::field::extension::Extendable;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::Witness;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::commit::MmcsError;
+use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::fri::FriError;
+use crate::p3::serde::hash::P3HashConfig;
+use crate::p3::serde::proof::BatchOpening;
+use crate::p3::serde::proof::BinomialExtensionField;
+use crate::p3::serde::proof::CommitPhaseProofStep;
+use crate::p3::serde::proof::Commitment;
+use crate::p3::serde::proof::Commitments;
+use crate::p3::serde::proof::FriProof;
+use crate::p3::serde::proof::FriProofWithInputs;
+use crate::p3::serde::proof::OpenedValues;
+use crate::p3::serde::proof::P3Config;
+use crate::p3::serde::proof::QueryProof;
+use crate::p3::serde::proof::Value;
+use crate::p3::verifier::P3VerifierError;
+
+/// Openings of one query of a Circle PCS proof: the input batches at the
+/// queried row, then the sibling of the queried row in the first FRI layer,
+/// which folds the bivariate codewords into univariate ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircleInputProof<F, const E: usize> {
+    pub input_openings: Vec<BatchOpening<F>>,
+    /// One sibling per height of the committed matrices, lowest first.
+    pub first_layer_siblings: Vec<BinomialExtensionField<F, E>>,
+    pub first_layer_proof: Vec<Vec<F>>,
+}
+
+impl<const E: usize> CircleInputProof<Target, E> {
+    pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        batch_widths: &[Vec<usize>],
+        batch_log_heights: &[usize],
+        num_heights: usize,
+        hash_config: &P3HashConfig,
+    ) -> Self {
+        let input_openings = izip!(batch_widths, batch_log_heights)
+            .map(|(widths, &log_height)| {
+                BatchOpening::add_virtual_to(builder, widths, log_height, hash_config)
+            })
+            .collect();
+        let log_max_height = batch_log_heights.iter().copied().max().unwrap_or(1);
+        let first_layer_siblings = (0..num_heights)
+            .map(|_| BinomialExtensionField::add_virtual_to(builder))
+            .collect();
+        let first_layer_proof = (0..log_max_height - 1)
+            .map(|_| builder.add_virtual_targets(hash_config.digest_elems))
+            .collect();
+
+        Self {
+            input_openings,
+            first_layer_siblings,
+            first_layer_proof,
+        }
+    }
+
+    pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
+        &self,
+        witness: &mut W,
+        data: &CircleInputProof<Value<F>, E>,
+    ) {
+        for i in 0..self.input_openings.len() {
+            self.input_openings[i].set_witness(witness, &data.input_openings[i]);
+        }
+        for i in 0..self.first_layer_siblings.len() {
+            self.first_layer_siblings[i].set_witness(witness, &data.first_layer_siblings[i]);
+        }
+        for i in 0..self.first_layer_proof.len() {
+            for j in 0..self.first_layer_proof[i].len() {
+                witness.set_target(
+                    self.first_layer_proof[i][j],
+                    data.first_layer_proof[i][j].value,
+                );
+            }
+        }
+    }
+}
+
+impl<F, const E: usize> CircleInputProof<F, E> {
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> CircleInputProof<G, E> {
+        CircleInputProof {
+            input_openings: self
+                .input_openings
+                .into_iter()
+                .map(|b| b.map(&mut f))
+                .collect(),
+            first_layer_siblings: self
+                .first_layer_siblings
+                .into_iter()
+                .map(|s| s.map(&mut f))
+                .collect(),
+            first_layer_proof: self
+                .first_layer_proof
+                .into_iter()
+                .map(|d| d.into_iter().map(&mut f).collect())
+                .collect(),
+        }
+    }
+}
+
+/// Opening proof of Plonky3's `CirclePcs`. There is one `lambda` per height
+/// of the committed matrices, lowest first, the multiple of the vanishing
+/// polynomial removed from the reduced openings of that height to bring them
+/// back into the FFT space. Also deserializes from Plonky3 0.2, whose query
+/// proofs carry the input proof of their query as `input_proof`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    from = "CirclePcsProofRepr<F, E>",
+    bound(deserialize = "F: Deserialize<'de>")
+)]
+pub struct CirclePcsProof<F, const E: usize> {
+    pub first_layer_commitment: Commitment<F>,
+    pub lambdas: Vec<BinomialExtensionField<F, E>>,
+    pub fri_proof: FriProof<F, E>,
+    pub query_openings: Vec<CircleInputProof<F, E>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, bound(deserialize = "F: Deserialize<'de>"))]
+enum CirclePcsProofRepr<F, const E: usize> {
+    Split {
+        first_layer_commitment: Commitment<F>,
+        lambdas: Vec<BinomialExtensionField<F, E>>,
+        fri_proof: FriProof<F, E>,
+        query_openings: Vec<CircleInputProof<F, E>>,
+    },
+    Inline {
+        first_layer_commitment: Commitment<F>,
+        lambdas: Vec<BinomialExtensionField<F, E>>,
+        fri_proof: FriProofWithInputs<F, CircleInputProof<F, E>, E>,
+    },
+}
+
+impl<F, const E: usize> From<CirclePcsProofRepr<F, E>> for CirclePcsProof<F, E> {
+    fn from(repr: CirclePcsProofRepr<F, E>) -> Self {
+        match repr {
+            CirclePcsProofRepr::Split {
+                first_layer_commitment,
+                lambdas,
+                fri_proof,
+                query_openings,
+            } => Self {
+                first_layer_commitment,
+                lambdas,
+                fri_proof,
+                query_openings,
+            },
+            CirclePcsProofRepr::Inline {
+                first_layer_commitment,
+                lambdas,
+                fri_proof,
+            } => {
+                let (fri_proof, query_openings) = fri_proof.split();
+                Self {
+                    first_layer_commitment,
+                    lambdas,
+                    fri_proof,
+                    query_openings,
+                }
+            }
+        }
+    }
+}
+
+/// Log heights of the committed batches of a single-table proof, before the
+/// blowup. The quotient domain is made disjoint from the standard trace
+/// domain by taking a larger standard domain, so a quotient of degree one is
+/// committed at twice the height of the trace.
+pub fn circle_batch_log_heights<const E: usize>(config: &P3Config) -> Vec<usize> {
+    let quotient_log_height = if config.log_quotient_degree == 0 {
+        config.log_trace_height + 1
+    } else {
+        config.log_trace_height
+    };
+    let mut log_heights = vec![config.log_trace_height, quotient_log_height];
+    log_heights.resize(config.batch_widths().len(), config.log_trace_height);
+    log_heights
+}
+
+impl<const E: usize> CirclePcsProof<Target, E> {
+    /// `batch_log_heights` are the log heights of the batches of
+    /// `batch_widths` before the blowup, see [`circle_batch_log_heights`].
+    pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        fri_config: &FriConfig,
+        hash_config: &P3HashConfig,
+        batch_widths: &[Vec<usize>],
+        batch_log_heights: &[usize],
+    ) -> Self {
+        let num_heights = batch_log_heights.iter().collect::<BTreeSet<_>>().len();
+        let log_max_trace_height = batch_log_heights.iter().copied().max().unwrap_or(1);
+        let log_max_height = log_max_trace_height + fri_config.log_blowup;
+        // The first layer folds the LDE in half, then FRI folds the rest down
+        // to the blowup.
+        let num_commit_phases = log_max_trace_height - 1;
+        let batch_lde_log_heights = batch_log_heights
+            .iter()
+            .map(|&h| h + fri_config.log_blowup)
+            .collect::<Vec<_>>();
+
+        let first_layer_commitment = Commitment::add_virtual_to(builder, hash_config);
+        let lambdas = (0..num_heights)
+            .map(|_| BinomialExtensionField::add_virtual_to(builder))
+            .collect();
+        let fri_proof = FriProof {
+            commit_phase_commits: (0..num_commit_phases)
+                .map(|_| Commitment::add_virtual_to(builder, hash_config))
+                .collect(),
+            query_proofs: (0..fri_config.num_queries)
+                .map(|_| QueryProof {
+                    commit_phase_openings: (0..num_commit_phases)
+                        .map(|i| {
+                            CommitPhaseProofStep::add_virtual_to(
+                                builder,
+                                1,
+                                log_max_height - i - 2,
+                                hash_config,
+                            )
+                        })
+                        .collect(),
+                })
+                .collect(),
+            final_poly: vec![BinomialExtensionField::add_virtual_to(builder)],
+            pow_witness: builder.add_virtual_target(),
+        };
+        let query_openings = (0..fri_config.num_queries)
+            .map(|_| {
+                CircleInputProof::add_virtual_to(
+                    builder,
+                    batch_widths,
+                    &batch_lde_log_heights,
+                    num_heights,
+                    hash_config,
+                )
+            })
+            .collect();
+
+        Self {
+            first_layer_commitment,
+            lambdas,
+            fri_proof,
+            query_openings,
+        }
+    }
+
+    pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
+        &self,
+        witness: &mut W,
+        data: &CirclePcsProof<Value<F>, E>,
+    ) {
+        self.first_layer_commitment
+            .set_witness(witness, &data.first_layer_commitment);
+        for i in 0..self.lambdas.len() {
+            self.lambdas[i].set_witness(witness, &data.lambdas[i]);
+        }
+        self.fri_proof.set_witness(witness, &data.fri_proof);
+        for i in 0..self.query_openings.len() {
+            self.query_openings[i].set_witness(witness, &data.query_openings[i]);
+        }
+    }
+}
+
+impl<F, const E: usize> CirclePcsProof<F, E> {
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> CirclePcsProof<G, E> {
+        CirclePcsProof {
+            first_layer_commitment: self.first_layer_commitment.map(&mut f),
+            lambdas: self.lambdas.into_iter().map(|l| l.map(&mut f)).collect(),
+            fri_proof: self.fri_proof.map(&mut f),
+            query_openings: self
+                .query_openings
+                .into_iter()
+                .map(|q| q.map(&mut f))
+                .collect(),
+        }
+    }
+
+    /// Checks the proof against batches of `2^batch_log_heights` rows before
+    /// the blowup, and that every query opens one matrix of each width in
+    /// `batch_widths` through digests of `hash_config`.
+    pub fn check_shape(
+        &self,
+        fri_config: &FriConfig,
+        hash_config: &P3HashConfig,
+        batch_widths: &[Vec<usize>],
+        batch_log_heights: &[usize],
+    ) -> Result<(), P3VerifierError> {
+        let num_heights = batch_log_heights.iter().collect::<BTreeSet<_>>().len();
+        let log_trace_height = batch_log_heights.iter().copied().max().unwrap_or(0);
+        let log_max_height = log_trace_height + fri_config.log_blowup;
+        let fri_proof = &self.fri_proof;
+        // The circle group has order 2^31, and a standard domain of size 2^n
+        // needs a point of order 2^(n + 1).
+        if batch_log_heights.contains(&0)
+            || batch_log_heights.len() != batch_widths.len()
+            || log_trace_height == 0
+            || log_max_height > 30
+            || fri_proof.commit_phase_commits.len() != log_trace_height - 1
+            || self.lambdas.len() != num_heights
+            || fri_proof.final_poly.len() != 1
+        {
+            return Err(FriError::InvalidProofShape.into());
+        }
+        if [&self.first_layer_commitment]
+            .into_iter()
+            .chain(&fri_proof.commit_phase_commits)
+            .any(|commit| commit.value.len() != hash_config.digest_elems)
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "commitment doesn't match the digest size",
+            ));
+        }
+        if fri_proof.query_proofs.len() != fri_config.num_queries
+            || self.query_openings.len() != fri_config.num_queries
+        {
+            return Err(P3VerifierError::QueryCountMismatch {
+                expected: fri_config.num_queries,
+                actual: self.query_openings.len(),
+            });
+        }
+        for query_proof in &fri_proof.query_proofs {
+            if query_proof.commit_phase_openings.len() != log_trace_height - 1 {
+                return Err(FriError::InvalidProofShape.into());
+            }
+            for (i, step) in query_proof.commit_phase_openings.iter().enumerate() {
+                // Circle FRI folds pairs in every round.
+                if step.sibling_values.len() != 1 {
+                    return Err(FriError::InvalidProofShape.into());
+                }
+                if step.opening_proof.len() != log_max_height - i - 2 {
+                    return Err(P3VerifierError::CommitPhaseMmcs(MmcsError::WrongHeight));
+                }
+                if step
+                    .opening_proof
+                    .iter()
+                    .any(|d| d.len() != hash_config.digest_elems)
+                {
+                    return Err(P3VerifierError::CommitPhaseMmcs(MmcsError::WrongWidth));
+                }
+            }
+        }
+
+        for query_opening in &self.query_openings {
+            if query_opening.first_layer_siblings.len() != self.lambdas.len() {
+                return Err(FriError::InvalidProofShape.into());
+            }
+            if query_opening.first_layer_proof.len() != log_max_height - 1 {
+                return Err(P3VerifierError::CommitPhaseMmcs(MmcsError::WrongHeight));
+            }
+            if query_opening
+                .first_layer_proof
+                .iter()
+                .any(|d| d.len() != hash_config.digest_elems)
+            {
+                return Err(P3VerifierError::CommitPhaseMmcs(MmcsError::WrongWidth));
+            }
+
+            if query_opening.input_openings.len() != batch_widths.len() {
+                return Err(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize));
+            }
+            for (batch_opening, widths, &log_height) in izip!(
+                &query_opening.input_openings,
+                batch_widths,
+                batch_log_heights
+            ) {
+                if batch_opening.opened_values.len() != widths.len() {
+                    return Err(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize));
+                }
+                if batch_opening
+                    .opened_values
+                    .iter()
+                    .zip(widths)
+                    .any(|(row, &width)| row.len() != width)
+                    || batch_opening
+                        .opening_proof
+                        .iter()
+                        .any(|d| d.len() != hash_config.digest_elems)
+                {
+                    return Err(P3VerifierError::BatchMmcs(MmcsError::WrongWidth));
+                }
+                if batch_opening.opening_proof.len() != log_height + fri_config.log_blowup {
+                    return Err(P3VerifierError::BatchMmcs(MmcsError::WrongHeight));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A single-table Plonky3 proof committed with the Circle PCS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircleProof<F, const E: usize> {
+    pub commitments: Commitments<F>,
+    pub opened_values: OpenedValues<F, E>,
+    pub opening_proof: CirclePcsProof<F, E>,
+    pub degree_bits: usize,
+}
+
+impl<const E: usize> CircleProof<Target, E> {
+    pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        config: &P3Config,
+    ) -> Self {
+        let commitments =
+            Commitments::add_virtual_to(builder, &config.hash_config, config.num_interactions > 0);
+        let opened_values = OpenedValues::add_virtual_to(builder, config);
+        let opening_proof = CirclePcsProof::add_virtual_to(
+            builder,
+            &config.fri_config,
+            &config.hash_config,
+            &config.batch_widths(),
+            &circle_batch_log_heights::<E>(config),
+        );
+
+        Self {
+            commitments,
+            opened_values,
+            opening_proof,
+            degree_bits: config.degree_bits,
+        }
+    }
+
+    pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
+        &self,
+        witness: &mut W,
+        data: &CircleProof<Value<F>, E>,
+    ) {
+        self.commitments.set_witness(witness, &data.commitments);
+        self.opened_values.set_witness(witness, &data.opened_values);
+        self.opening_proof.set_witness(witness, &data.opening_proof);
+    }
+}
+
+impl<F, const E: usize> CircleProof<F, E> {
+    /// Applies `f` to every field element of the proof, keeping its shape.
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> CircleProof<G, E> {
+        CircleProof {
+            commitments: self.commitments.map(&mut f),
+            opened_values: self.opened_values.map(&mut f),
+            opening_proof: self.opening_proof.map(&mut f),
+            degree_bits: self.degree_bits,
+        }
+    }
+
+    /// Same as [`Proof::check_shape`](crate::p3::serde::proof::Proof::check_shape),
+    /// for the Circle PCS opening proof.
+    pub fn check_shape(&self, config: &P3Config) -> Result<(), P3VerifierError> {
+        self.opened_values.check_shape(config)?;
+        self.commitments
+            .check_shape(&config.hash_config, config.num_interactions > 0)?;
+
+        if self.degree_bits != config.degree_bits || config.degree_bits != config.log_trace_height {
+            return Err(P3VerifierError::InvalidProofShape(
+                "degree bits don't match the trace height",
+            ));
+        }
+
+        self.opening_proof.check_shape(
+            &config.fri_config,
+            &config.hash_config,
+            &config.batch_widths(),
+            &circle_batch_log_heights::<E>(config),
+        )
+    }
+}
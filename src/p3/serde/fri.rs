@@ -1,21 +1,144 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::p3::constants::EXT_DEGREE;
 use crate::p3::serde::proof::BinomialExtensionField;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FriConfig {
     pub log_blowup: usize,
     pub num_queries: usize,
     pub proof_of_work_bits: usize,
+    /// Log of the largest number of points a commit phase round folds into
+    /// one, so that arity 2 is `1`.
+    #[serde(default = "default_max_log_arity")]
+    pub max_log_arity: usize,
+    /// Log of the number of coefficients of the final polynomial, at which
+    /// the commit phase stops folding. `None` for Plonky3 versions whose final
+    /// polynomial is a constant left out of the transcript. `Some(0)` folds
+    /// down to a constant as well, but one the challenger observes, so the two
+    /// aren't interchangeable.
+    #[serde(default)]
+    pub log_final_poly_len: Option<usize>,
+}
+
+fn default_max_log_arity() -> usize {
+    1
 }
 
-pub struct FriChallenges<F> {
+impl FriConfig {
+    /// Log arities of the commit phase rounds folding the codewords of traces
+    /// of `2^log_trace_heights[i]` rows down to the final polynomial. A round
+    /// folds by at most `2^max_log_arity` and stops at the height of the next
+    /// input, whose reduced opening is rolled in before the following round.
+    pub fn log_arities(&self, log_trace_heights: &[usize]) -> Result<Vec<usize>, FriError> {
+        if self.max_log_arity == 0 {
+            return Err(FriError::InvalidFoldingArity);
+        }
+        let log_final_poly_len = self.log_final_poly_len.unwrap_or(0);
+        // Inputs shorter than the final polynomial would never be rolled in.
+        if log_trace_heights.iter().any(|&h| h < log_final_poly_len) {
+            return Err(FriError::InvalidProofShape);
+        }
+
+        let mut log_height = log_trace_heights
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(log_final_poly_len);
+        let mut log_arities = vec![];
+        while log_height > log_final_poly_len {
+            let next_input_height = log_trace_heights
+                .iter()
+                .copied()
+                .filter(|&h| h < log_height)
+                .max()
+                .unwrap_or(log_final_poly_len);
+            let log_arity = self.max_log_arity.min(log_height - next_input_height);
+            log_arities.push(log_arity);
+            log_height -= log_arity;
+        }
+
+        Ok(log_arities)
+    }
+
+    /// Log height of the tallest codeword, folded by rounds of `log_arities`
+    /// down to the final polynomial.
+    pub fn log_max_height(&self, log_arities: &[usize]) -> usize {
+        log_arities.iter().sum::<usize>() + self.log_blowup + self.log_final_poly_len.unwrap_or(0)
+    }
+
+    /// Number of coefficients of the final polynomial.
+    pub fn final_poly_len(&self) -> usize {
+        1 << self.log_final_poly_len.unwrap_or(0)
+    }
+}
+
+pub struct FriChallenges<F, const E: usize = EXT_DEGREE> {
     pub query_indices: Vec<F>,
-    pub betas: Vec<BinomialExtensionField<F>>,
+    pub betas: Vec<BinomialExtensionField<F, E>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FriError {
     InvalidProofShape,
     CommitPhaseMmcsError,
     FinalPolyMismatch,
     InvalidPowWitness,
+    InvalidFoldingArity,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fri_config(max_log_arity: usize) -> FriConfig {
+        FriConfig {
+            log_blowup: 1,
+            num_queries: 2,
+            proof_of_work_bits: 8,
+            max_log_arity,
+            log_final_poly_len: None,
+        }
+    }
+
+    #[test]
+    fn test_log_arities() {
+        assert_eq!(fri_config(1).log_arities(&[3]).unwrap(), vec![1, 1, 1]);
+        assert_eq!(fri_config(2).log_arities(&[5]).unwrap(), vec![2, 2, 1]);
+        assert_eq!(
+            fri_config(3).log_arities(&[0]).unwrap(),
+            Vec::<usize>::new()
+        );
+        // Rounds stop at the height of every input.
+        assert_eq!(
+            fri_config(3).log_arities(&[7, 5, 5, 1]).unwrap(),
+            vec![2, 3, 1, 1]
+        );
+        assert!(fri_config(0).log_arities(&[3]).is_err());
+    }
+
+    #[test]
+    fn test_log_arities_stop_at_final_poly() {
+        let config = FriConfig {
+            log_final_poly_len: Some(2),
+            ..fri_config(2)
+        };
+        assert_eq!(config.log_arities(&[7]).unwrap(), vec![2, 2, 1]);
+        assert_eq!(config.log_arities(&[7, 4]).unwrap(), vec![2, 1, 2]);
+        assert_eq!(config.log_arities(&[2]).unwrap(), Vec::<usize>::new());
+        assert_eq!(config.log_max_height(&[2, 2, 1]), 8);
+        assert_eq!(config.final_poly_len(), 4);
+        assert!(config.log_arities(&[7, 1]).is_err());
+    }
+
+    #[test]
+    fn test_max_log_arity_defaults_to_folding_pairs() {
+        let config: FriConfig = serde_json::from_str(
+            r#"{"log_blowup": 1, "num_queries": 100, "proof_of_work_bits": 16}"#,
+        )
+        .unwrap();
+        assert_eq!(config.max_log_arity, 1);
+        assert_eq!(config.log_final_poly_len, None);
+    }
 }
@@ -0,0 +1,79 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::common::poseidon2::poseidon2::WIDTH;
+use crate::p3::keccak::KECCAK256_DIGEST_BYTES;
+use crate::p3::keccak::KECCAK256_RATE;
+use crate::p3::keccak::KECCAK_LANES;
+use crate::p3::verifier::P3VerifierError;
+
+/// Sponge and compression parameters of a Plonky3 hashing config: the state
+/// `width` of the permutation, the `rate` of the padding-free sponge hashing
+/// Merkle leaves, and the `digest_elems` of a commitment. Defaults to the
+/// Goldilocks Poseidon2 config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct P3HashConfig {
+    pub width: usize,
+    pub rate: usize,
+    pub digest_elems: usize,
+    /// Number of observations the duplex challenger buffers before
+    /// permuting, `RATE` in `DuplexChallenger<Val, Perm, WIDTH, RATE>`.
+    /// `None` for Plonky3 versions absorbing the whole width.
+    #[serde(default)]
+    pub challenger_rate: Option<usize>,
+}
+
+impl Default for P3HashConfig {
+    fn default() -> Self {
+        Self {
+            width: WIDTH,
+            rate: 4,
+            digest_elems: 4,
+            challenger_rate: None,
+        }
+    }
+}
+
+impl P3HashConfig {
+    /// Keccak-256 over bytes, as used by `SerializingHasher64<Keccak256Hash>`.
+    pub fn keccak256() -> Self {
+        Self {
+            width: KECCAK_LANES * 8,
+            rate: KECCAK256_RATE,
+            digest_elems: KECCAK256_DIGEST_BYTES,
+            challenger_rate: None,
+        }
+    }
+
+    /// Checks that the parameters fit a permutation of `width` elements: the
+    /// sponge can't absorb more than the state, and the compression function
+    /// needs two digests side by side. The width is that of the permutation
+    /// instance, e.g. as given by its round constants.
+    pub fn check_width(&self, width: usize) -> Result<(), P3VerifierError> {
+        if self.width != width {
+            return Err(P3VerifierError::InvalidHashConfig(
+                "width doesn't match the permutation",
+            ));
+        }
+        if self.rate == 0 || self.rate > self.width {
+            return Err(P3VerifierError::InvalidHashConfig(
+                "rate doesn't fit in the state",
+            ));
+        }
+        if self.digest_elems == 0 || 2 * self.digest_elems > self.width {
+            return Err(P3VerifierError::InvalidHashConfig(
+                "two digests don't fit in the state",
+            ));
+        }
+        if self
+            .challenger_rate
+            .is_some_and(|rate| rate == 0 || rate > self.width)
+        {
+            return Err(P3VerifierError::InvalidHashConfig(
+                "challenger rate doesn't fit in the state",
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
+pub mod circle;
 pub mod fri;
+pub mod hash;
+pub mod multi_proof;
 pub mod proof;
 pub mod two_adic;
 
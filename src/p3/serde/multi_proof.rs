@@ -0,0 +1,271 @@
+use itertools::izip;
+use plonky2::field::extension::Extendable;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::Witness;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::air::AirLike;
+use crate::p3::constants::EXT_DEGREE;
+use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::hash::P3HashConfig;
+use crate::p3::serde::proof::Commitments;
+use crate::p3::serde::proof::OpenedValues;
+use crate::p3::serde::proof::P3Config;
+use crate::p3::serde::proof::TwoAdicFriPcsProof;
+use crate::p3::serde::proof::Value;
+use crate::p3::verifier::P3VerifierError;
+
+/// Proof of several tables of possibly different heights. The main traces of
+/// all tables share one commitment, and so do their quotient chunks, so that
+/// everything is opened through a single FRI proof.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiProof<F, const E: usize = EXT_DEGREE> {
+    pub commitments: Commitments<F>,
+    pub opened_values: Vec<OpenedValues<F, E>>,
+    pub opening_proof: TwoAdicFriPcsProof<F, E>,
+    pub degree_bits: Vec<usize>,
+}
+
+impl<const E: usize> MultiProof<Target, E> {
+    pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        config: &P3MultiConfig,
+    ) -> Self {
+        let commitments =
+            Commitments::add_virtual_to(builder, &config.hash_config, config.has_interactions());
+        let opened_values = config
+            .tables
+            .iter()
+            .map(|table| OpenedValues::add_virtual_to(builder, table))
+            .collect();
+        let opening_proof = TwoAdicFriPcsProof::add_virtual_to(
+            builder,
+            &config.fri_config,
+            &config.hash_config,
+            &config.log_trace_heights(),
+            &config.batch_widths(),
+            &config.batch_log_heights(),
+        );
+        let degree_bits = config
+            .tables
+            .iter()
+            .map(|table| table.degree_bits)
+            .collect();
+
+        Self {
+            commitments,
+            opened_values,
+            opening_proof,
+            degree_bits,
+        }
+    }
+
+    pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
+        &self,
+        witness: &mut W,
+        data: &MultiProof<Value<F>, E>,
+    ) {
+        self.commitments.set_witness(witness, &data.commitments);
+        for i in 0..self.opened_values.len() {
+            self.opened_values[i].set_witness(witness, &data.opened_values[i]);
+        }
+        self.opening_proof.set_witness(witness, &data.opening_proof);
+    }
+}
+
+impl<F, const E: usize> MultiProof<F, E> {
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> MultiProof<G, E> {
+        MultiProof {
+            commitments: self.commitments.map(&mut f),
+            opened_values: self
+                .opened_values
+                .into_iter()
+                .map(|v| v.map(&mut f))
+                .collect(),
+            opening_proof: self.opening_proof.map(&mut f),
+            degree_bits: self.degree_bits,
+        }
+    }
+
+    /// Multi-table counterpart of
+    /// [`Proof::check_shape`](crate::p3::serde::proof::Proof::check_shape).
+    pub fn check_shape(&self, config: &P3MultiConfig) -> Result<(), P3VerifierError> {
+        if self.opened_values.len() != config.tables.len()
+            || self.degree_bits.len() != config.tables.len()
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "number of tables doesn't match the config",
+            ));
+        }
+
+        self.commitments
+            .check_shape(&config.hash_config, config.has_interactions())?;
+
+        for (opened_values, &degree_bits, table) in
+            izip!(&self.opened_values, &self.degree_bits, &config.tables)
+        {
+            opened_values.check_shape(table)?;
+            if degree_bits != table.degree_bits {
+                return Err(P3VerifierError::InvalidProofShape(
+                    "degree bits don't match the trace height",
+                ));
+            }
+        }
+
+        self.opening_proof.check_shape(
+            &config.fri_config,
+            &config.hash_config,
+            &config.log_trace_heights(),
+            &config.batch_widths(),
+            &config.batch_log_heights(),
+        )
+    }
+}
+
+/// Shape of a [`MultiProof`], one [`P3Config`] per table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct P3MultiConfig {
+    pub fri_config: FriConfig,
+    /// Sponge and compression parameters shared by the commitments of all
+    /// tables.
+    #[serde(default)]
+    pub hash_config: P3HashConfig,
+    pub tables: Vec<P3Config>,
+}
+
+impl P3MultiConfig {
+    /// Derives the shape of a multi-table proof where the table of `airs[i]`
+    /// has `2^degree_bits[i]` rows. Fails unless there is one degree per AIR.
+    pub fn new<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+        airs: &[&dyn AirLike<F, D, E>],
+        fri_config: FriConfig,
+        degree_bits: &[usize],
+    ) -> Result<Self, P3VerifierError> {
+        if airs.len() != degree_bits.len() {
+            return Err(P3VerifierError::InvalidProofShape(
+                "number of tables doesn't match the number of degrees",
+            ));
+        }
+
+        let log_max_height = degree_bits.iter().copied().max().unwrap_or(0);
+        let tables = airs
+            .iter()
+            .zip(degree_bits)
+            .map(|(air, &degree_bits)| P3Config {
+                fri_config: fri_config.clone(),
+                hash_config: P3HashConfig::default(),
+                log_quotient_degree: air.log_quotient_degree(),
+                log_trace_height: degree_bits,
+                trace_width: air.width(),
+                opening_matrix_log_max_height: log_max_height + fri_config.log_blowup,
+                quotient_chunk_width: E,
+                num_public_values: air.num_public_values(),
+                preprocessed_width: air.preprocessed_width(),
+                preprocessed_commit: None,
+                num_interactions: air.num_interactions(),
+                degree_bits,
+                min_degree_bits: None,
+            })
+            .collect();
+
+        Ok(Self {
+            fri_config,
+            hash_config: P3HashConfig::default(),
+            tables,
+        })
+    }
+
+    /// Log height of the tallest table, which sets the number of FRI rounds.
+    pub fn log_max_height(&self) -> usize {
+        self.tables
+            .iter()
+            .map(|table| table.log_trace_height)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Log heights of the tables, at which the FRI commit phase rolls in
+    /// their reduced openings.
+    pub fn log_trace_heights(&self) -> Vec<usize> {
+        self.tables
+            .iter()
+            .map(|table| table.log_trace_height)
+            .collect()
+    }
+
+    /// Whether any table has interactions, in which case the proof carries a
+    /// permutation commitment shared by all such tables.
+    pub fn has_interactions(&self) -> bool {
+        self.tables.iter().any(|table| table.num_interactions > 0)
+    }
+
+    /// Widths of the matrices of the batches opened at each query: every main
+    /// trace, then every quotient chunk, then the permutation trace of every
+    /// table with interactions, all in table order.
+    pub fn batch_widths(&self) -> Vec<Vec<usize>> {
+        let mut widths = vec![
+            self.tables.iter().map(|table| table.trace_width).collect(),
+            self.tables
+                .iter()
+                .flat_map(|table| vec![table.quotient_chunk_width; 1 << table.log_quotient_degree])
+                .collect(),
+        ];
+        if self.has_interactions() {
+            widths.push(
+                self.tables
+                    .iter()
+                    .filter(|table| table.num_interactions > 0)
+                    .map(|table| table.permutation_width())
+                    .collect(),
+            );
+        }
+        widths
+    }
+
+    /// Log height of the tallest trace of every batch of
+    /// [`Self::batch_widths`], which sets the depth of its Merkle tree. Only
+    /// the permutation batch can be shorter than the tallest table.
+    pub fn batch_log_heights(&self) -> Vec<usize> {
+        let mut log_heights = vec![self.log_max_height(); 2];
+        if self.has_interactions() {
+            log_heights.push(
+                self.tables
+                    .iter()
+                    .filter(|table| table.num_interactions > 0)
+                    .map(|table| table.log_trace_height)
+                    .max()
+                    .unwrap_or(0),
+            );
+        }
+        log_heights
+    }
+}
+
+pub type P3MultiProofField = MultiProof<Value<GoldilocksField>>;
+pub type P3MultiProof = MultiProof<Target>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p3::tests::native_fri_config;
+    use crate::p3::tests::FibonacciAir;
+
+    #[test]
+    fn new_rejects_degrees_of_missing_tables() {
+        let air = FibonacciAir {};
+        let airs: [&dyn AirLike<GoldilocksField, 2>; 1] = [&air];
+
+        for degree_bits in [vec![], vec![3, 4]] {
+            assert!(matches!(
+                P3MultiConfig::new(&airs, native_fri_config(), &degree_bits),
+                Err(P3VerifierError::InvalidProofShape(_))
+            ));
+        }
+        let config = P3MultiConfig::new(&airs, native_fri_config(), &[3]).unwrap();
+        assert_eq!(config.tables.len(), 1);
+    }
+}
@@ -1,3 +1,4 @@
+use itertools::izip;
 use plonky2::field::extension::Extendable;
 use plonky2::field::goldilocks_field::GoldilocksField;
 use plonky2::iop::target::Target;
@@ -5,13 +6,21 @@ use plonky2::iop::witness::Witness;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_with::formats::PreferMany;
+use serde_with::serde_as;
+use serde_with::OneOrMany;
 
 use crate::common::richer_field::RicherField;
-use crate::p3::constants::DIGEST_ELEMS;
+use crate::p3::air::Air;
+use crate::p3::commit::MmcsError;
 use crate::p3::constants::EXT_DEGREE;
 use crate::p3::gadgets::CircuitBuilderP3Helper;
 use crate::p3::gadgets::WitnessP3Helper;
+use crate::p3::native::domain::TWO_ADICITY;
 use crate::p3::serde::fri::FriConfig;
+use crate::p3::serde::fri::FriError;
+use crate::p3::serde::hash::P3HashConfig;
+use crate::p3::verifier::P3VerifierError;
 
 #[derive(Copy, Clone, Default, Serialize, Deserialize)]
 pub struct Value<F> {
@@ -19,90 +28,302 @@ pub struct Value<F> {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenedValues<F> {
-    pub trace_local: Vec<BinomialExtensionField<F>>,
-    pub trace_next: Vec<BinomialExtensionField<F>>,
-    pub quotient_chunks: Vec<Vec<BinomialExtensionField<F>>>,
+#[serde(bound(serialize = "F: Serialize", deserialize = "F: Deserialize<'de>"))]
+pub struct OpenedValues<F, const E: usize = EXT_DEGREE> {
+    pub trace_local: Vec<BinomialExtensionField<F, E>>,
+    pub trace_next: Vec<BinomialExtensionField<F, E>>,
+    pub quotient_chunks: Vec<Vec<BinomialExtensionField<F, E>>>,
+    #[serde(default)]
+    pub preprocessed_local: Vec<BinomialExtensionField<F, E>>,
+    #[serde(default)]
+    pub preprocessed_next: Vec<BinomialExtensionField<F, E>>,
+    #[serde(default)]
+    pub permutation_local: Vec<BinomialExtensionField<F, E>>,
+    #[serde(default)]
+    pub permutation_next: Vec<BinomialExtensionField<F, E>>,
+    /// Final value of the LogUp running sum, present whenever the AIR has
+    /// interactions.
+    #[serde(default)]
+    pub cumulative_sum: Option<BinomialExtensionField<F, E>>,
 }
 
-impl OpenedValues<Target> {
+impl<const E: usize> OpenedValues<Target, E> {
     pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
-        trace_width: usize,
+        config: &P3Config,
     ) -> Self {
-        let trace_local = (0..trace_width)
+        let trace_local = (0..config.trace_width)
             .map(|_| BinomialExtensionField::add_virtual_to(builder))
             .collect();
 
-        let trace_next = (0..trace_width)
+        let trace_next = (0..config.trace_width)
             .map(|_| BinomialExtensionField::add_virtual_to(builder))
             .collect();
 
-        let quotient_chunks = (0..1)
+        let quotient_chunks = (0..1 << config.log_quotient_degree)
             .map(|_| {
-                let a = BinomialExtensionField::add_virtual_to(builder);
-                let b = BinomialExtensionField::add_virtual_to(builder);
-                let r = vec![a, b];
-                r
+                (0..config.quotient_chunk_width)
+                    .map(|_| BinomialExtensionField::add_virtual_to(builder))
+                    .collect()
             })
             .collect();
 
+        let preprocessed_local = (0..config.preprocessed_width)
+            .map(|_| BinomialExtensionField::add_virtual_to(builder))
+            .collect();
+
+        let preprocessed_next = (0..config.preprocessed_width)
+            .map(|_| BinomialExtensionField::add_virtual_to(builder))
+            .collect();
+
+        let permutation_local = (0..config.permutation_width())
+            .map(|_| BinomialExtensionField::add_virtual_to(builder))
+            .collect();
+
+        let permutation_next = (0..config.permutation_width())
+            .map(|_| BinomialExtensionField::add_virtual_to(builder))
+            .collect();
+
+        let cumulative_sum =
+            (config.num_interactions > 0).then(|| BinomialExtensionField::add_virtual_to(builder));
+
         Self {
             trace_local,
             trace_next,
             quotient_chunks,
+            preprocessed_local,
+            preprocessed_next,
+            permutation_local,
+            permutation_next,
+            cumulative_sum,
         }
     }
 
     pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
         &self,
         witness: &mut W,
-        data: &OpenedValues<F>,
+        data: &OpenedValues<Value<F>, E>,
     ) {
         for i in 0..self.trace_local.len() {
             self.trace_local[i].set_witness(witness, &data.trace_local[i]);
             self.trace_next[i].set_witness(witness, &data.trace_next[i]);
         }
         for i in 0..self.quotient_chunks.len() {
-            self.quotient_chunks[i][0].set_witness(witness, &data.quotient_chunks[i][0]);
-            self.quotient_chunks[i][1].set_witness(witness, &data.quotient_chunks[i][1]);
+            for j in 0..self.quotient_chunks[i].len() {
+                self.quotient_chunks[i][j].set_witness(witness, &data.quotient_chunks[i][j]);
+            }
+        }
+        for i in 0..self.preprocessed_local.len() {
+            self.preprocessed_local[i].set_witness(witness, &data.preprocessed_local[i]);
+            self.preprocessed_next[i].set_witness(witness, &data.preprocessed_next[i]);
+        }
+        for i in 0..self.permutation_local.len() {
+            self.permutation_local[i].set_witness(witness, &data.permutation_local[i]);
+            self.permutation_next[i].set_witness(witness, &data.permutation_next[i]);
+        }
+        if let (Some(cumulative_sum), Some(data)) = (&self.cumulative_sum, &data.cumulative_sum) {
+            cumulative_sum.set_witness(witness, data);
+        }
+    }
+}
+
+impl<F: Clone + Default, const E: usize> OpenedValues<F, E> {
+    /// Opened values of the shape of `config` that are all zero.
+    pub fn dummy(config: &P3Config) -> Self {
+        let zeros = |len| vec![BinomialExtensionField::dummy(); len];
+        Self {
+            trace_local: zeros(config.trace_width),
+            trace_next: zeros(config.trace_width),
+            quotient_chunks: vec![
+                zeros(config.quotient_chunk_width);
+                1 << config.log_quotient_degree
+            ],
+            preprocessed_local: zeros(config.preprocessed_width),
+            preprocessed_next: zeros(config.preprocessed_width),
+            permutation_local: zeros(config.permutation_width()),
+            permutation_next: zeros(config.permutation_width()),
+            cumulative_sum: (config.num_interactions > 0).then(BinomialExtensionField::dummy),
+        }
+    }
+}
+
+impl<F, const E: usize> OpenedValues<F, E> {
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> OpenedValues<G, E> {
+        OpenedValues {
+            trace_local: self
+                .trace_local
+                .into_iter()
+                .map(|v| v.map(&mut f))
+                .collect(),
+            trace_next: self.trace_next.into_iter().map(|v| v.map(&mut f)).collect(),
+            quotient_chunks: self
+                .quotient_chunks
+                .into_iter()
+                .map(|qc| qc.into_iter().map(|v| v.map(&mut f)).collect())
+                .collect(),
+            preprocessed_local: self
+                .preprocessed_local
+                .into_iter()
+                .map(|v| v.map(&mut f))
+                .collect(),
+            preprocessed_next: self
+                .preprocessed_next
+                .into_iter()
+                .map(|v| v.map(&mut f))
+                .collect(),
+            permutation_local: self
+                .permutation_local
+                .into_iter()
+                .map(|v| v.map(&mut f))
+                .collect(),
+            permutation_next: self
+                .permutation_next
+                .into_iter()
+                .map(|v| v.map(&mut f))
+                .collect(),
+            cumulative_sum: self.cumulative_sum.map(|v| v.map(&mut f)),
+        }
+    }
+
+    /// Checks the opened values against the widths and quotient degree of
+    /// `config`.
+    pub fn check_shape(&self, config: &P3Config) -> Result<(), P3VerifierError> {
+        if config.quotient_chunk_width != E {
+            return Err(P3VerifierError::InvalidProofShape(
+                "extension degree doesn't match the config",
+            ));
+        }
+        if self.trace_local.len() != config.trace_width
+            || self.trace_next.len() != config.trace_width
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "trace width doesn't match the config",
+            ));
+        }
+        if self.quotient_chunks.len() != 1 << config.log_quotient_degree
+            || self
+                .quotient_chunks
+                .iter()
+                .any(|qc| qc.len() != config.quotient_chunk_width)
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "quotient chunks don't match the quotient degree",
+            ));
         }
+        if self.preprocessed_local.len() != config.preprocessed_width
+            || self.preprocessed_next.len() != config.preprocessed_width
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "preprocessed width doesn't match the config",
+            ));
+        }
+        if self.permutation_local.len() != config.permutation_width()
+            || self.permutation_next.len() != config.permutation_width()
+            || self.cumulative_sum.is_some() != (config.num_interactions > 0)
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "permutation trace doesn't match the interactions",
+            ));
+        }
+
+        Ok(())
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(serialize = "F: Serialize", deserialize = "F: Deserialize<'de>"))]
 pub struct Commitments<F> {
     pub trace: Commitment<F>,
     pub quotient_chunks: Commitment<F>,
+    /// Commitment to the LogUp permutation trace, only present when the AIR
+    /// has interactions.
+    #[serde(default)]
+    pub permutation: Option<Commitment<F>>,
 }
 
 impl Commitments<Target> {
     pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
+        hash_config: &P3HashConfig,
+        has_permutation: bool,
     ) -> Self {
         Self {
-            trace: Commitment::add_virtual_to(builder),
-            quotient_chunks: Commitment::add_virtual_to(builder),
+            trace: Commitment::add_virtual_to(builder, hash_config),
+            quotient_chunks: Commitment::add_virtual_to(builder, hash_config),
+            permutation: has_permutation.then(|| Commitment::add_virtual_to(builder, hash_config)),
         }
     }
 
     pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
         &self,
         witness: &mut W,
-        data: &Commitments<F>,
+        data: &Commitments<Value<F>>,
     ) {
         self.trace.set_witness(witness, &data.trace);
         self.quotient_chunks
             .set_witness(witness, &data.quotient_chunks);
+        if let (Some(permutation), Some(data)) = (&self.permutation, &data.permutation) {
+            permutation.set_witness(witness, data);
+        }
+    }
+}
+
+impl<F: Clone + Default> Commitments<F> {
+    pub fn dummy(hash_config: &P3HashConfig, has_permutation: bool) -> Self {
+        Self {
+            trace: Commitment::dummy(hash_config),
+            quotient_chunks: Commitment::dummy(hash_config),
+            permutation: has_permutation.then(|| Commitment::dummy(hash_config)),
+        }
+    }
+}
+
+impl<F> Commitments<F> {
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> Commitments<G> {
+        Commitments {
+            trace: self.trace.map(&mut f),
+            quotient_chunks: self.quotient_chunks.map(&mut f),
+            permutation: self.permutation.map(|c| c.map(&mut f)),
+        }
+    }
+
+    /// Checks that every commitment is a digest of `hash_config`, and that the
+    /// permutation commitment is present exactly when `has_permutation`.
+    pub fn check_shape(
+        &self,
+        hash_config: &P3HashConfig,
+        has_permutation: bool,
+    ) -> Result<(), P3VerifierError> {
+        if self.permutation.is_some() != has_permutation {
+            return Err(P3VerifierError::InvalidProofShape(
+                "permutation commitment doesn't match the interactions",
+            ));
+        }
+        if [&self.trace, &self.quotient_chunks]
+            .into_iter()
+            .chain(&self.permutation)
+            .any(|commit| commit.value.len() != hash_config.digest_elems)
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "commitment doesn't match the digest size",
+            ));
+        }
+
+        Ok(())
     }
 }
 
+/// Element of the degree `E` binomial extension the Plonky3 challenges live
+/// in, independently of the extension degree `D` of the plonky2 circuit.
+#[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BinomialExtensionField<F> {
-    pub value: [F; EXT_DEGREE],
+#[serde(bound(serialize = "F: Serialize", deserialize = "F: Deserialize<'de>"))]
+pub struct BinomialExtensionField<F, const E: usize = EXT_DEGREE> {
+    #[serde_as(as = "[_; E]")]
+    pub value: [F; E],
 }
 
-impl BinomialExtensionField<Target> {
+impl<const E: usize> BinomialExtensionField<Target, E> {
     pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
     ) -> Self {
@@ -113,56 +334,106 @@ impl BinomialExtensionField<Target> {
     pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
         &self,
         witness: &mut W,
-        data: &BinomialExtensionField<F>,
+        data: &BinomialExtensionField<Value<F>, E>,
     ) {
-        (0..EXT_DEGREE).for_each(|i| witness.set_target(self.value[i], data.value[i]));
+        (0..E).for_each(|i| witness.set_target(self.value[i], data.value[i].value));
+    }
+}
+
+impl<F: Default, const E: usize> BinomialExtensionField<F, E> {
+    pub fn dummy() -> Self {
+        Self {
+            value: core::array::from_fn(|_| F::default()),
+        }
     }
 }
 
+impl<F, const E: usize> BinomialExtensionField<F, E> {
+    pub fn map<G>(self, f: impl FnMut(F) -> G) -> BinomialExtensionField<G, E> {
+        BinomialExtensionField {
+            value: self.value.map(f),
+        }
+    }
+}
+
+/// Root of a Merkle tree, a digest of [`P3HashConfig::digest_elems`] field
+/// elements.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Commitment<F> {
-    pub value: [F; DIGEST_ELEMS],
+    pub value: Vec<F>,
 }
 
 impl Commitment<Target> {
     pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
+        hash_config: &P3HashConfig,
     ) -> Self {
         Self {
-            value: core::array::from_fn(|_| builder.add_virtual_target()),
+            value: builder.add_virtual_targets(hash_config.digest_elems),
         }
     }
     pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
         &self,
         witness: &mut W,
-        data: &Commitment<F>,
+        data: &Commitment<Value<F>>,
     ) {
-        (0..DIGEST_ELEMS).for_each(|i| witness.set_target(self.value[i], data.value[i]));
+        (0..self.value.len()).for_each(|i| witness.set_target(self.value[i], data.value[i].value));
+    }
+}
+
+impl<F: Clone + Default> Commitment<F> {
+    pub fn dummy(hash_config: &P3HashConfig) -> Self {
+        Self {
+            value: vec![F::default(); hash_config.digest_elems],
+        }
+    }
+}
+
+impl<F> Commitment<F> {
+    pub fn map<G>(self, f: impl FnMut(F) -> G) -> Commitment<G> {
+        Commitment {
+            value: self.value.into_iter().map(f).collect(),
+        }
     }
 }
 
+#[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FriProof<F> {
+#[serde(bound(serialize = "F: Serialize", deserialize = "F: Deserialize<'de>"))]
+pub struct FriProof<F, const E: usize = EXT_DEGREE> {
     pub commit_phase_commits: Vec<Commitment<F>>,
-    pub query_proofs: Vec<QueryProof<F>>,
-    // This could become Vec<FC::Challenge> if this library was generalized to support non-constant
-    // final polynomials.
-    pub final_poly: BinomialExtensionField<F>,
+    pub query_proofs: Vec<QueryProof<F, E>>,
+    /// Coefficients of the final polynomial, lowest degree first. Proofs
+    /// whose final polynomial is a constant may carry it on its own.
+    #[serde_as(as = "OneOrMany<_, PreferMany>")]
+    pub final_poly: Vec<BinomialExtensionField<F, E>>,
     pub pow_witness: F,
 }
 
-impl FriProof<Target> {
+impl<const E: usize> FriProof<Target, E> {
     pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
-        config: &P3Config,
+        fri_config: &FriConfig,
+        hash_config: &P3HashConfig,
+        log_trace_heights: &[usize],
     ) -> Self {
-        let commit_phase_commits = (0..config.log_trace_height)
-            .map(|_| Commitment::add_virtual_to(builder))
+        // An invalid arity is reported by the verifier, which never reaches
+        // the commit phase then.
+        let log_arities = fri_config
+            .log_arities(log_trace_heights)
+            .unwrap_or_default();
+        let log_max_height =
+            log_trace_heights.iter().copied().max().unwrap_or(0) + fri_config.log_blowup;
+        let commit_phase_commits = log_arities
+            .iter()
+            .map(|_| Commitment::add_virtual_to(builder, hash_config))
+            .collect();
+        let query_proofs = (0..fri_config.num_queries)
+            .map(|_| QueryProof::add_virtual_to(builder, log_max_height, &log_arities, hash_config))
             .collect();
-        let query_proofs = (0..config.fri_config.num_queries)
-            .map(|_| QueryProof::add_virtual_to(builder, config.log_trace_height))
+        let final_poly = (0..fri_config.final_poly_len())
+            .map(|_| BinomialExtensionField::add_virtual_to(builder))
             .collect();
-        let final_poly = BinomialExtensionField::add_virtual_to(builder);
         let pow_witness = builder.add_virtual_target();
 
         Self {
@@ -176,7 +447,7 @@ impl FriProof<Target> {
     pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
         &self,
         witness: &mut W,
-        data: &FriProof<F>,
+        data: &FriProof<Value<F>, E>,
     ) {
         for i in 0..self.commit_phase_commits.len() {
             self.commit_phase_commits[i].set_witness(witness, &data.commit_phase_commits[i]);
@@ -184,25 +455,89 @@ impl FriProof<Target> {
         for i in 0..self.query_proofs.len() {
             self.query_proofs[i].set_witness(witness, &data.query_proofs[i]);
         }
-        self.final_poly.set_witness(witness, &data.final_poly);
-        witness.set_target(self.pow_witness, data.pow_witness);
+        for (coeff, data) in self.final_poly.iter().zip(&data.final_poly) {
+            coeff.set_witness(witness, data);
+        }
+        witness.set_target(self.pow_witness, data.pow_witness.value);
+    }
+}
+
+impl<F: Clone + Default, const E: usize> FriProof<F, E> {
+    /// A FRI proof of the shape of
+    /// [`FriProof::add_virtual_to`](FriProof::<Target, E>::add_virtual_to)
+    /// that is all zero.
+    pub fn dummy(
+        fri_config: &FriConfig,
+        hash_config: &P3HashConfig,
+        log_trace_heights: &[usize],
+    ) -> Self {
+        let log_arities = fri_config
+            .log_arities(log_trace_heights)
+            .unwrap_or_default();
+        let log_max_height =
+            log_trace_heights.iter().copied().max().unwrap_or(0) + fri_config.log_blowup;
+
+        Self {
+            commit_phase_commits: vec![Commitment::dummy(hash_config); log_arities.len()],
+            query_proofs: vec![
+                QueryProof::dummy(log_max_height, &log_arities, hash_config);
+                fri_config.num_queries
+            ],
+            final_poly: vec![BinomialExtensionField::dummy(); fri_config.final_poly_len()],
+            pow_witness: F::default(),
+        }
+    }
+}
+
+impl<F, const E: usize> FriProof<F, E> {
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> FriProof<G, E> {
+        FriProof {
+            commit_phase_commits: self
+                .commit_phase_commits
+                .into_iter()
+                .map(|c| c.map(&mut f))
+                .collect(),
+            query_proofs: self
+                .query_proofs
+                .into_iter()
+                .map(|q| q.map(&mut f))
+                .collect(),
+            final_poly: self
+                .final_poly
+                .into_iter()
+                .map(|coeff| coeff.map(&mut f))
+                .collect(),
+            pow_witness: f(self.pow_witness),
+        }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct QueryProof<F> {
+pub struct QueryProof<F, const E: usize = EXT_DEGREE> {
     /// For each commit phase commitment, this contains openings of a commit
     /// phase codeword at the queried location, along with an opening proof.
-    pub commit_phase_openings: Vec<CommitPhaseProofStep<F>>,
+    pub commit_phase_openings: Vec<CommitPhaseProofStep<F, E>>,
 }
 
-impl QueryProof<Target> {
+impl<const E: usize> QueryProof<Target, E> {
     pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
-        log_trace_height: usize,
+        log_max_height: usize,
+        log_arities: &[usize],
+        hash_config: &P3HashConfig,
     ) -> Self {
-        let commit_phase_openings = (0..log_trace_height)
-            .map(|i| CommitPhaseProofStep::add_virtual_to(builder, log_trace_height - i))
+        let mut log_folded_height = log_max_height;
+        let commit_phase_openings = log_arities
+            .iter()
+            .map(|&log_arity| {
+                log_folded_height -= log_arity;
+                CommitPhaseProofStep::add_virtual_to(
+                    builder,
+                    log_arity,
+                    log_folded_height,
+                    hash_config,
+                )
+            })
             .collect();
 
         Self {
@@ -213,7 +548,7 @@ impl QueryProof<Target> {
     pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
         &self,
         witness: &mut W,
-        data: &QueryProof<F>,
+        data: &QueryProof<Value<F>, E>,
     ) {
         for i in 0..self.commit_phase_openings.len() {
             self.commit_phase_openings[i].set_witness(witness, &data.commit_phase_openings[i]);
@@ -221,45 +556,108 @@ impl QueryProof<Target> {
     }
 }
 
+impl<F: Clone + Default, const E: usize> QueryProof<F, E> {
+    pub fn dummy(log_max_height: usize, log_arities: &[usize], hash_config: &P3HashConfig) -> Self {
+        let mut log_folded_height = log_max_height;
+        let commit_phase_openings = log_arities
+            .iter()
+            .map(|&log_arity| {
+                log_folded_height -= log_arity;
+                CommitPhaseProofStep::dummy(log_arity, log_folded_height, hash_config)
+            })
+            .collect();
+
+        Self {
+            commit_phase_openings,
+        }
+    }
+}
+
+impl<F, const E: usize> QueryProof<F, E> {
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> QueryProof<G, E> {
+        QueryProof {
+            commit_phase_openings: self
+                .commit_phase_openings
+                .into_iter()
+                .map(|step| step.map(&mut f))
+                .collect(),
+        }
+    }
+}
+
+#[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CommitPhaseProofStep<F> {
-    /// The opening of the commit phase codeword at the sibling location.
-    // This may change to Vec<FC::Challenge> if the library is generalized to support other FRI
-    // folding arities besides 2, meaning that there can be multiple siblings.
-    pub sibling_value: BinomialExtensionField<F>,
+#[serde(bound(serialize = "F: Serialize", deserialize = "F: Deserialize<'de>"))]
+pub struct CommitPhaseProofStep<F, const E: usize = EXT_DEGREE> {
+    /// The openings of the commit phase codeword at the other points of the
+    /// folded coset of the queried location, in row order. Proofs folding
+    /// pairs may carry their single sibling as `sibling_value`.
+    #[serde(alias = "sibling_value")]
+    #[serde_as(as = "OneOrMany<_, PreferMany>")]
+    pub sibling_values: Vec<BinomialExtensionField<F, E>>,
 
     pub opening_proof: Vec<Vec<F>>,
 }
 
-impl CommitPhaseProofStep<Target> {
+impl<const E: usize> CommitPhaseProofStep<Target, E> {
+    /// Targets of a round folding `2^log_arity` points into one, whose
+    /// codeword is committed as a matrix of `2^log_folded_height` rows.
     pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
-        log_trace_height_minus_i: usize,
+        log_arity: usize,
+        log_folded_height: usize,
+        hash_config: &P3HashConfig,
     ) -> Self {
-        let sibling_value = BinomialExtensionField::add_virtual_to(builder);
-        let opening_proof = (0..log_trace_height_minus_i)
-            .map(|_| builder.add_virtual_targets(DIGEST_ELEMS))
+        let sibling_values = (1..1 << log_arity)
+            .map(|_| BinomialExtensionField::add_virtual_to(builder))
+            .collect();
+        let opening_proof = (0..log_folded_height)
+            .map(|_| builder.add_virtual_targets(hash_config.digest_elems))
             .collect();
         Self {
             opening_proof,
-            sibling_value,
+            sibling_values,
         }
     }
 
     pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
         &self,
         witness: &mut W,
-        data: &CommitPhaseProofStep<F>,
+        data: &CommitPhaseProofStep<Value<F>, E>,
     ) {
-        self.sibling_value.set_witness(witness, &data.sibling_value);
+        for (sibling_value, data) in self.sibling_values.iter().zip(&data.sibling_values) {
+            sibling_value.set_witness(witness, data);
+        }
         for i in 0..self.opening_proof.len() {
             for j in 0..self.opening_proof[i].len() {
-                witness.set_target(self.opening_proof[i][j], data.opening_proof[i][j]);
+                witness.set_target(self.opening_proof[i][j], data.opening_proof[i][j].value);
             }
         }
     }
 }
 
+impl<F: Clone + Default, const E: usize> CommitPhaseProofStep<F, E> {
+    pub fn dummy(log_arity: usize, log_folded_height: usize, hash_config: &P3HashConfig) -> Self {
+        Self {
+            sibling_values: vec![BinomialExtensionField::dummy(); (1 << log_arity) - 1],
+            opening_proof: vec![vec![F::default(); hash_config.digest_elems]; log_folded_height],
+        }
+    }
+}
+
+impl<F, const E: usize> CommitPhaseProofStep<F, E> {
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> CommitPhaseProofStep<G, E> {
+        CommitPhaseProofStep {
+            sibling_values: self
+                .sibling_values
+                .into_iter()
+                .map(|v| v.map(&mut f))
+                .collect(),
+            opening_proof: map_2d(self.opening_proof, &mut f),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatchOpening<F> {
     pub opened_values: Vec<Vec<F>>,
@@ -269,14 +667,14 @@ pub struct BatchOpening<F> {
 impl BatchOpening<Target> {
     pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
-        opened_values_row_count: usize,
-        opened_values_col_count: usize,
+        opened_values_widths: &[usize],
         opening_matrix_log_max_height: usize,
+        hash_config: &P3HashConfig,
     ) -> Self {
         let opened_values =
-            builder.add_2d_vec_array_inputs(opened_values_row_count, opened_values_col_count);
+            builder.add_2d_vec_array_inputs_with_dims_vec(opened_values_widths.to_vec());
         let opening_proof = (0..opening_matrix_log_max_height)
-            .map(|_| builder.add_virtual_hash().elements.to_vec())
+            .map(|_| builder.add_virtual_targets(hash_config.digest_elems))
             .collect();
 
         Self {
@@ -288,42 +686,153 @@ impl BatchOpening<Target> {
     pub fn set_witness<F: RicherField, W: Witness<F>>(
         &self,
         witness: &mut W,
-        data: &BatchOpening<F>,
+        data: &BatchOpening<Value<F>>,
     ) {
-        witness.set_2d_vec_array(&self.opened_values, &data.opened_values);
-        witness.set_2d_vec_array(&self.opening_proof, &data.opening_proof);
+        witness.set_2d_vec_values(&self.opened_values, &data.opened_values);
+        witness.set_2d_vec_values(&self.opening_proof, &data.opening_proof);
+    }
+}
+
+impl<F: Clone + Default> BatchOpening<F> {
+    pub fn dummy(
+        opened_values_widths: &[usize],
+        opening_matrix_log_max_height: usize,
+        hash_config: &P3HashConfig,
+    ) -> Self {
+        Self {
+            opened_values: opened_values_widths
+                .iter()
+                .map(|&width| vec![F::default(); width])
+                .collect(),
+            opening_proof: vec![
+                vec![F::default(); hash_config.digest_elems];
+                opening_matrix_log_max_height
+            ],
+        }
+    }
+}
+
+impl<F> BatchOpening<F> {
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> BatchOpening<G> {
+        BatchOpening {
+            opened_values: map_2d(self.opened_values, &mut f),
+            opening_proof: map_2d(self.opening_proof, &mut f),
+        }
     }
 }
 
+/// Also deserializes from the FRI proofs of Plonky3 0.2, whose query proofs
+/// carry the batch openings of their query as `input_proof`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TwoAdicFriPcsProof<F> {
-    pub fri_proof: FriProof<F>,
+#[serde(
+    from = "TwoAdicFriPcsProofRepr<F, E>",
+    bound(deserialize = "F: Deserialize<'de>")
+)]
+pub struct TwoAdicFriPcsProof<F, const E: usize = EXT_DEGREE> {
+    pub fri_proof: FriProof<F, E>,
     /// For each query, for each committed batch, query openings for that batch
     pub query_openings: Vec<Vec<BatchOpening<F>>>,
 }
 
-impl TwoAdicFriPcsProof<Target> {
+#[derive(Deserialize)]
+#[serde(untagged, bound(deserialize = "F: Deserialize<'de>"))]
+enum TwoAdicFriPcsProofRepr<F, const E: usize> {
+    Split {
+        fri_proof: FriProof<F, E>,
+        query_openings: Vec<Vec<BatchOpening<F>>>,
+    },
+    Inline(FriProofWithInputs<F, Vec<BatchOpening<F>>, E>),
+}
+
+impl<F, const E: usize> From<TwoAdicFriPcsProofRepr<F, E>> for TwoAdicFriPcsProof<F, E> {
+    fn from(repr: TwoAdicFriPcsProofRepr<F, E>) -> Self {
+        match repr {
+            TwoAdicFriPcsProofRepr::Split {
+                fri_proof,
+                query_openings,
+            } => Self {
+                fri_proof,
+                query_openings,
+            },
+            TwoAdicFriPcsProofRepr::Inline(fri_proof) => {
+                let (fri_proof, query_openings) = fri_proof.split();
+                Self {
+                    fri_proof,
+                    query_openings,
+                }
+            }
+        }
+    }
+}
+
+/// A FRI proof as serialized by Plonky3 0.2, each query proof carrying the
+/// input proof `I` of its query along with its commit phase openings.
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(bound(deserialize = "F: Deserialize<'de>, I: Deserialize<'de>"))]
+pub struct FriProofWithInputs<F, I, const E: usize = EXT_DEGREE> {
+    pub commit_phase_commits: Vec<Commitment<F>>,
+    pub query_proofs: Vec<QueryProofWithInput<F, I, E>>,
+    #[serde_as(as = "OneOrMany<_, PreferMany>")]
+    pub final_poly: Vec<BinomialExtensionField<F, E>>,
+    pub pow_witness: F,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(bound(deserialize = "F: Deserialize<'de>, I: Deserialize<'de>"))]
+pub struct QueryProofWithInput<F, I, const E: usize = EXT_DEGREE> {
+    pub input_proof: I,
+    pub commit_phase_openings: Vec<CommitPhaseProofStep<F, E>>,
+}
+
+impl<F, I, const E: usize> FriProofWithInputs<F, I, E> {
+    /// Separates the input proofs of the queries from the FRI proof.
+    pub fn split(self) -> (FriProof<F, E>, Vec<I>) {
+        let (query_proofs, input_proofs) = self
+            .query_proofs
+            .into_iter()
+            .map(|query_proof| {
+                (
+                    QueryProof {
+                        commit_phase_openings: query_proof.commit_phase_openings,
+                    },
+                    query_proof.input_proof,
+                )
+            })
+            .unzip();
+        let fri_proof = FriProof {
+            commit_phase_commits: self.commit_phase_commits,
+            query_proofs,
+            final_poly: self.final_poly,
+            pow_witness: self.pow_witness,
+        };
+        (fri_proof, input_proofs)
+    }
+}
+
+impl<const E: usize> TwoAdicFriPcsProof<Target, E> {
     pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
-        config: &P3Config,
+        fri_config: &FriConfig,
+        hash_config: &P3HashConfig,
+        log_trace_heights: &[usize],
+        batch_widths: &[Vec<usize>],
+        batch_log_heights: &[usize],
     ) -> Self {
-        let fri_proof = FriProof::add_virtual_to(builder, config);
-        let query_openings = (0..config.fri_config.num_queries)
+        let fri_proof =
+            FriProof::add_virtual_to(builder, fri_config, hash_config, log_trace_heights);
+        let query_openings = (0..fri_config.num_queries)
             .map(|_| {
-                vec![
-                    BatchOpening::add_virtual_to(
-                        builder,
-                        1,
-                        config.trace_width,
-                        config.opening_matrix_log_max_height,
-                    ),
-                    BatchOpening::add_virtual_to(
-                        builder,
-                        1,
-                        config.opening_proof_query_openings_opened_values_length,
-                        config.opening_matrix_log_max_height,
-                    ),
-                ]
+                izip!(batch_widths, batch_log_heights)
+                    .map(|(widths, &log_height)| {
+                        BatchOpening::add_virtual_to(
+                            builder,
+                            widths,
+                            log_height + fri_config.log_blowup,
+                            hash_config,
+                        )
+                    })
+                    .collect()
             })
             .collect();
 
@@ -335,7 +844,7 @@ impl TwoAdicFriPcsProof<Target> {
     pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
         &self,
         witness: &mut W,
-        data: &TwoAdicFriPcsProof<F>,
+        data: &TwoAdicFriPcsProof<Value<F>, E>,
     ) {
         self.fri_proof.set_witness(witness, &data.fri_proof);
         for i in 0..self.query_openings.len() {
@@ -346,22 +855,162 @@ impl TwoAdicFriPcsProof<Target> {
     }
 }
 
+impl<F: Clone + Default, const E: usize> TwoAdicFriPcsProof<F, E> {
+    pub fn dummy(
+        fri_config: &FriConfig,
+        hash_config: &P3HashConfig,
+        log_trace_heights: &[usize],
+        batch_widths: &[Vec<usize>],
+    ) -> Self {
+        let log_max_height =
+            log_trace_heights.iter().copied().max().unwrap_or(0) + fri_config.log_blowup;
+        let batch_openings = batch_widths
+            .iter()
+            .map(|widths| BatchOpening::dummy(widths, log_max_height, hash_config))
+            .collect();
+
+        Self {
+            fri_proof: FriProof::dummy(fri_config, hash_config, log_trace_heights),
+            query_openings: vec![batch_openings; fri_config.num_queries],
+        }
+    }
+}
+
+impl<F, const E: usize> TwoAdicFriPcsProof<F, E> {
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> TwoAdicFriPcsProof<G, E> {
+        TwoAdicFriPcsProof {
+            fri_proof: self.fri_proof.map(&mut f),
+            query_openings: self
+                .query_openings
+                .into_iter()
+                .map(|batches| batches.into_iter().map(|b| b.map(&mut f)).collect())
+                .collect(),
+        }
+    }
+
+    /// Checks the FRI proof against traces of `2^log_trace_heights[i]` rows,
+    /// and that every query opens one matrix of each width in `batch_widths`
+    /// through digests of `hash_config`, along the path of a tree as tall as
+    /// the extension of the tallest trace of the batch, of
+    /// `2^batch_log_heights[i]` rows.
+    pub fn check_shape(
+        &self,
+        fri_config: &FriConfig,
+        hash_config: &P3HashConfig,
+        log_trace_heights: &[usize],
+        batch_widths: &[Vec<usize>],
+        batch_log_heights: &[usize],
+    ) -> Result<(), P3VerifierError> {
+        let fri_proof = &self.fri_proof;
+        let log_arities = fri_config.log_arities(log_trace_heights)?;
+        let log_max_height = fri_config.log_max_height(&log_arities);
+        if fri_proof.commit_phase_commits.len() != log_arities.len()
+            || fri_proof.final_poly.len() != fri_config.final_poly_len()
+            || log_max_height > TWO_ADICITY
+        {
+            return Err(FriError::InvalidProofShape.into());
+        }
+        if fri_proof
+            .commit_phase_commits
+            .iter()
+            .any(|commit| commit.value.len() != hash_config.digest_elems)
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "commitment doesn't match the digest size",
+            ));
+        }
+        if fri_proof.query_proofs.len() != fri_config.num_queries {
+            return Err(P3VerifierError::QueryCountMismatch {
+                expected: fri_config.num_queries,
+                actual: fri_proof.query_proofs.len(),
+            });
+        }
+        for query_proof in &fri_proof.query_proofs {
+            if query_proof.commit_phase_openings.len() != log_arities.len() {
+                return Err(FriError::InvalidProofShape.into());
+            }
+            let mut log_folded_height = log_max_height;
+            for (step, &log_arity) in query_proof.commit_phase_openings.iter().zip(&log_arities) {
+                log_folded_height -= log_arity;
+                if step.sibling_values.len() != (1 << log_arity) - 1 {
+                    return Err(FriError::InvalidProofShape.into());
+                }
+                if step.opening_proof.len() != log_folded_height {
+                    return Err(P3VerifierError::CommitPhaseMmcs(MmcsError::WrongHeight));
+                }
+                if step
+                    .opening_proof
+                    .iter()
+                    .any(|d| d.len() != hash_config.digest_elems)
+                {
+                    return Err(P3VerifierError::CommitPhaseMmcs(MmcsError::WrongWidth));
+                }
+            }
+        }
+
+        if self.query_openings.len() != fri_config.num_queries {
+            return Err(P3VerifierError::QueryCountMismatch {
+                expected: fri_config.num_queries,
+                actual: self.query_openings.len(),
+            });
+        }
+        for query_opening in &self.query_openings {
+            if query_opening.len() != batch_widths.len() {
+                return Err(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize));
+            }
+            for (batch_opening, widths, &log_height) in
+                izip!(query_opening, batch_widths, batch_log_heights)
+            {
+                if batch_opening.opened_values.len() != widths.len() {
+                    return Err(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize));
+                }
+                if batch_opening
+                    .opened_values
+                    .iter()
+                    .zip(widths)
+                    .any(|(row, &width)| row.len() != width)
+                    || batch_opening
+                        .opening_proof
+                        .iter()
+                        .any(|d| d.len() != hash_config.digest_elems)
+                {
+                    return Err(P3VerifierError::BatchMmcs(MmcsError::WrongWidth));
+                }
+                if batch_opening.opening_proof.len() != log_height + fri_config.log_blowup {
+                    return Err(P3VerifierError::BatchMmcs(MmcsError::WrongHeight));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Proof<F> {
+pub struct Proof<F, const E: usize = EXT_DEGREE> {
     pub commitments: Commitments<F>,
-    pub opened_values: OpenedValues<F>,
-    pub opening_proof: TwoAdicFriPcsProof<F>,
+    pub opened_values: OpenedValues<F, E>,
+    pub opening_proof: TwoAdicFriPcsProof<F, E>,
     pub degree_bits: usize,
 }
 
-impl Proof<Target> {
+impl<const E: usize> Proof<Target, E> {
     pub fn add_virtual_to<F: RicherField + Extendable<D>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
         config: &P3Config,
     ) -> Self {
-        let commitments = Commitments::add_virtual_to(builder);
-        let opened_values = OpenedValues::add_virtual_to(builder, config.trace_width);
-        let opening_proof = TwoAdicFriPcsProof::add_virtual_to(builder, config);
+        let commitments =
+            Commitments::add_virtual_to(builder, &config.hash_config, config.num_interactions > 0);
+        let opened_values = OpenedValues::add_virtual_to(builder, config);
+        let batch_widths = config.batch_widths();
+        let opening_proof = TwoAdicFriPcsProof::add_virtual_to(
+            builder,
+            &config.fri_config,
+            &config.hash_config,
+            &[config.log_trace_height],
+            &batch_widths,
+            &vec![config.log_trace_height; batch_widths.len()],
+        );
         let degree_bits = config.degree_bits;
 
         Self {
@@ -374,7 +1023,7 @@ impl Proof<Target> {
     pub fn set_witness<F: RicherField + Extendable<D>, const D: usize, W: Witness<F>>(
         &self,
         witness: &mut W,
-        data: &Proof<F>,
+        data: &Proof<Value<F>, E>,
     ) {
         self.commitments.set_witness(witness, &data.commitments);
         self.opened_values.set_witness(witness, &data.opened_values);
@@ -382,6 +1031,127 @@ impl Proof<Target> {
     }
 }
 
+impl<F, const E: usize> Proof<F, E> {
+    /// Applies `f` to every field element of the proof, keeping its shape.
+    pub fn map<G>(self, mut f: impl FnMut(F) -> G) -> Proof<G, E> {
+        Proof {
+            commitments: self.commitments.map(&mut f),
+            opened_values: self.opened_values.map(&mut f),
+            opening_proof: self.opening_proof.map(&mut f),
+            degree_bits: self.degree_bits,
+        }
+    }
+
+    /// Checks that every vector in the proof has the length implied by
+    /// `config`, so that a malformed proof is rejected before it reaches
+    /// [`Proof::<Target>::set_witness`].
+    pub fn check_shape(&self, config: &P3Config) -> Result<(), P3VerifierError> {
+        self.opened_values.check_shape(config)?;
+        self.commitments
+            .check_shape(&config.hash_config, config.num_interactions > 0)?;
+
+        let degree_bits_in_range = match config.min_degree_bits {
+            Some(min_degree_bits) => {
+                (min_degree_bits..=config.log_trace_height).contains(&self.degree_bits)
+            }
+            None => {
+                self.degree_bits == config.degree_bits
+                    && config.degree_bits == config.log_trace_height
+            }
+        };
+        if !degree_bits_in_range {
+            return Err(P3VerifierError::InvalidProofShape(
+                "degree bits don't match the trace height",
+            ));
+        }
+
+        let batch_widths = config.batch_widths();
+        self.opening_proof.check_shape(
+            &config.fri_config,
+            &config.hash_config,
+            &[self.degree_bits],
+            &batch_widths,
+            &vec![self.degree_bits; batch_widths.len()],
+        )
+    }
+}
+
+impl<F: RicherField, const E: usize> Proof<Value<F>, E> {
+    /// Pads a proof of a variable-degree `config` with zeros to the shape of
+    /// its tallest proofs, which is that of the verifier circuit: missing
+    /// commit phase rounds are zero, and Merkle paths are extended by zero
+    /// digests past their root. The proof should first be checked with
+    /// [`Proof::check_shape`].
+    pub fn pad_to_max_degree(&self, config: &P3Config) -> Self {
+        let zero = Value { value: F::ZERO };
+        let zero_digest = vec![zero; config.hash_config.digest_elems];
+        let fri_config = &config.fri_config;
+        let log_arities = fri_config
+            .log_arities(&[config.log_trace_height])
+            .unwrap_or_default();
+        let log_max_height = config.log_trace_height + fri_config.log_blowup;
+
+        let mut proof = self.clone();
+        let fri_proof = &mut proof.opening_proof.fri_proof;
+        fri_proof.commit_phase_commits.resize(
+            log_arities.len(),
+            Commitment {
+                value: zero_digest.clone(),
+            },
+        );
+        for query_proof in &mut fri_proof.query_proofs {
+            let steps = &mut query_proof.commit_phase_openings;
+            steps.resize_with(log_arities.len(), || CommitPhaseProofStep {
+                sibling_values: vec![],
+                opening_proof: vec![],
+            });
+            let mut log_folded_height = log_max_height;
+            for (step, &log_arity) in steps.iter_mut().zip(&log_arities) {
+                log_folded_height -= log_arity;
+                step.sibling_values.resize(
+                    (1 << log_arity) - 1,
+                    BinomialExtensionField { value: [zero; E] },
+                );
+                step.opening_proof
+                    .resize(log_folded_height, zero_digest.clone());
+            }
+        }
+        for batch_opening in proof.opening_proof.query_openings.iter_mut().flatten() {
+            batch_opening
+                .opening_proof
+                .resize(log_max_height, zero_digest.clone());
+        }
+
+        proof
+    }
+}
+
+impl<F: Clone + Default, const E: usize> Proof<F, E> {
+    /// A proof of the shape of the verifier circuit of `config` whose elements
+    /// are all zero, to set the targets of a verification that is switched off
+    /// with [`p3_verify_proof_if`](crate::p3::CircuitBuilderP3Arithmetic::p3_verify_proof_if).
+    pub fn dummy(config: &P3Config) -> Self {
+        Self {
+            commitments: Commitments::dummy(&config.hash_config, config.num_interactions > 0),
+            opened_values: OpenedValues::dummy(config),
+            opening_proof: TwoAdicFriPcsProof::dummy(
+                &config.fri_config,
+                &config.hash_config,
+                &[config.log_trace_height],
+                &config.batch_widths(),
+            ),
+            degree_bits: config.degree_bits,
+        }
+    }
+}
+
+fn map_2d<F, G>(values: Vec<Vec<F>>, f: &mut impl FnMut(F) -> G) -> Vec<Vec<G>> {
+    values
+        .into_iter()
+        .map(|row| row.into_iter().map(&mut *f).collect())
+        .collect()
+}
+
 pub type P3Field = Value<GoldilocksField>;
 pub type P3ProofField = Proof<P3Field>;
 pub type P3OpenedValuesField = OpenedValues<P3Field>;
@@ -399,24 +1169,339 @@ pub type P3FriProof = FriProof<Target>;
 pub type P3BinomialExtension = BinomialExtensionField<Target>;
 pub type P3Commitment = Commitment<Target>;
 
-#[derive(Debug)]
+/// Shape of a Plonky3 proof, which is all the verifier circuit depends on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct P3Config {
     pub fri_config: FriConfig,
+    /// Sponge and compression parameters the commitments were computed with.
+    #[serde(default)]
+    pub hash_config: P3HashConfig,
     pub log_quotient_degree: usize,
     pub log_trace_height: usize,
     pub trace_width: usize,
     pub opening_matrix_log_max_height: usize,
-    pub opening_proof_query_openings_opened_values_length: usize,
+    /// Width of each of the `2^log_quotient_degree` quotient chunk matrices,
+    /// i.e. the number of base field limbs of one extension element. Proofs
+    /// over a degree `E` extension need this to be `E`, see
+    /// [`P3Config::with_ext_degree`].
+    pub quotient_chunk_width: usize,
+    pub num_public_values: usize,
+    pub preprocessed_width: usize,
+    /// Commitment to the preprocessed trace, part of the verifying key rather
+    /// than of the proof. Required whenever `preprocessed_width` is non-zero.
+    pub preprocessed_commit: Option<Vec<GoldilocksField>>,
+    #[serde(default)]
+    pub num_interactions: usize,
     pub degree_bits: usize,
+    /// Smallest `degree_bits` of the proofs accepted by a variable-degree
+    /// circuit, which then accepts any up to `log_trace_height`. `None` for
+    /// circuits verifying proofs of exactly `degree_bits`.
+    #[serde(default)]
+    pub min_degree_bits: Option<usize>,
+}
+
+impl P3Config {
+    /// Derives the shape of the proofs Plonky3 produces for `air` over a trace
+    /// of `2^degree_bits` rows, so that the verifier circuit can be built
+    /// before any proof is available.
+    pub fn new(air: &impl Air, fri_config: FriConfig, degree_bits: usize) -> Self {
+        let log_quotient_degree = air.log_quotient_degree();
+        let opening_matrix_log_max_height = degree_bits + fri_config.log_blowup;
+
+        Self {
+            fri_config,
+            hash_config: P3HashConfig::default(),
+            log_quotient_degree,
+            log_trace_height: degree_bits,
+            trace_width: air.width(),
+            opening_matrix_log_max_height,
+            quotient_chunk_width: EXT_DEGREE,
+            num_public_values: air.num_public_values(),
+            preprocessed_width: air.preprocessed_width(),
+            preprocessed_commit: None,
+            num_interactions: air.num_interactions(),
+            degree_bits,
+            min_degree_bits: None,
+        }
+    }
+
+    /// Sets the commitment to the preprocessed trace of the AIR, as computed
+    /// by Plonky3 when setting up the proving key.
+    pub fn with_preprocessed_commit(mut self, commit: Vec<GoldilocksField>) -> Self {
+        self.preprocessed_commit = Some(commit);
+        self
+    }
+
+    /// Makes the config accept proofs of any `degree_bits` from
+    /// `min_degree_bits` up to its `log_trace_height`, with a single circuit
+    /// whose shape is that of the tallest proofs, see
+    /// [`p3_verify_variable_degree_proof`](crate::p3::CircuitBuilderP3Arithmetic::p3_verify_variable_degree_proof).
+    pub fn with_min_degree_bits(mut self, min_degree_bits: usize) -> Self {
+        self.min_degree_bits = Some(min_degree_bits);
+        self
+    }
+
+    /// Rejects variable-degree configs, which only
+    /// [`p3_verify_variable_degree_proof`](crate::p3::CircuitBuilderP3Arithmetic::p3_verify_variable_degree_proof)
+    /// verifies.
+    pub fn check_fixed_degree(&self) -> Result<(), P3VerifierError> {
+        if self.min_degree_bits.is_some() {
+            return Err(P3VerifierError::InvalidProofShape(
+                "variable-degree configs are verified with p3_verify_variable_degree_proof",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rejects configs whose trace, quotient or LDE domains are taller than
+    /// the largest two-adic subgroup of Goldilocks, `2^32`, for which
+    /// [`p3_two_adic_generator`](crate::p3::extension::CircuitBuilderP3ExtArithmetic::p3_two_adic_generator)
+    /// has no generator.
+    pub fn check_two_adicity(&self) -> Result<(), P3VerifierError> {
+        if self.log_trace_height + self.fri_config.log_blowup > TWO_ADICITY
+            || self.log_trace_height + self.log_quotient_degree > TWO_ADICITY
+        {
+            return Err(FriError::InvalidProofShape.into());
+        }
+        Ok(())
+    }
+
+    /// Sets the sponge and compression parameters, for Plonky3 configs hashing
+    /// with another permutation width, rate or digest size than the default
+    /// Goldilocks Poseidon2 config.
+    pub fn with_hash_config(mut self, hash_config: P3HashConfig) -> Self {
+        self.hash_config = hash_config;
+        self
+    }
+
+    /// Sets the degree of the extension the challenges are drawn from, for
+    /// Plonky3 configs using another extension than the default
+    /// [`EXT_DEGREE`].
+    pub fn with_ext_degree(mut self, ext_degree: usize) -> Self {
+        self.quotient_chunk_width = ext_degree;
+        self
+    }
+
+    /// Width of the permutation trace flattened to base field columns: one
+    /// extension column per interaction plus the running sum.
+    pub fn permutation_width(&self) -> usize {
+        if self.num_interactions == 0 {
+            0
+        } else {
+            (self.num_interactions + 1) * self.quotient_chunk_width
+        }
+    }
+
+    /// Widths of the matrices of every batch opened at each query: the trace,
+    /// one matrix per quotient chunk, then the preprocessed and permutation
+    /// traces if any.
+    pub fn batch_widths(&self) -> Vec<Vec<usize>> {
+        let mut widths = vec![
+            vec![self.trace_width],
+            vec![self.quotient_chunk_width; 1 << self.log_quotient_degree],
+        ];
+        if self.preprocessed_width > 0 {
+            widths.push(vec![self.preprocessed_width]);
+        }
+        if self.num_interactions > 0 {
+            widths.push(vec![self.permutation_width()]);
+        }
+        widths
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+
     use super::*;
+    use crate::p3::tests::fibonacci_fri_config;
 
     #[test]
     fn deserialize_test() {
         let s = include_str!("../../../artifacts/proof_fibonacci.json");
         serde_json::from_str::<P3ProofField>(s).unwrap();
     }
+
+    #[test]
+    fn deserialize_constant_final_poly_and_single_sibling() {
+        let s = include_str!("../../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(s).unwrap();
+        let fri_proof = &proof.opening_proof.fri_proof;
+        assert_eq!(fri_proof.final_poly.len(), 1);
+        assert!(fri_proof
+            .query_proofs
+            .iter()
+            .flat_map(|query_proof| &query_proof.commit_phase_openings)
+            .all(|step| step.sibling_values.len() == 1));
+
+        // Both are written back as sequences.
+        let value = serde_json::to_value(&proof).unwrap();
+        let fri_value = &value["opening_proof"]["fri_proof"];
+        assert!(fri_value["final_poly"].is_array());
+        assert!(
+            fri_value["query_proofs"][0]["commit_phase_openings"][0]["sibling_values"].is_array()
+        );
+        let reparsed = serde_json::from_value::<P3ProofField>(value.clone()).unwrap();
+        assert_eq!(serde_json::to_value(reparsed).unwrap(), value);
+    }
+
+    #[test]
+    fn map_preserves_proof() {
+        let s = include_str!("../../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(s).unwrap();
+
+        let unwrapped: Proof<GoldilocksField> = proof.clone().map(|v| v.value);
+        assert_eq!(unwrapped.degree_bits, proof.degree_bits);
+        assert_eq!(
+            unwrapped.opening_proof.fri_proof.pow_witness,
+            proof.opening_proof.fri_proof.pow_witness.value
+        );
+
+        let rewrapped = unwrapped.map(|value| Value { value });
+        assert_eq!(
+            serde_json::to_value(rewrapped).unwrap(),
+            serde_json::to_value(&proof).unwrap()
+        );
+    }
+
+    #[test]
+    fn add_virtual_follows_quotient_degree() {
+        let s = include_str!("../../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(s).unwrap();
+        let mut config = P3Config {
+            fri_config: FriConfig {
+                log_blowup: 1,
+                num_queries: 2,
+                proof_of_work_bits: 16,
+                max_log_arity: 1,
+                log_final_poly_len: None,
+            },
+            hash_config: P3HashConfig::default(),
+            log_quotient_degree: 2,
+            log_trace_height: 6,
+            trace_width: 3,
+            opening_matrix_log_max_height: 7,
+            quotient_chunk_width: EXT_DEGREE,
+            num_public_values: 0,
+            preprocessed_width: 0,
+            preprocessed_commit: None,
+            num_interactions: 0,
+            degree_bits: 6,
+            min_degree_bits: None,
+        };
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        let target = Proof::<Target>::add_virtual_to(&mut builder, &config);
+        assert_eq!(target.opened_values.quotient_chunks.len(), 4);
+        assert!(target
+            .opened_values
+            .quotient_chunks
+            .iter()
+            .all(|qc| qc.len() == EXT_DEGREE));
+        for query_opening in &target.opening_proof.query_openings {
+            assert_eq!(query_opening[1].opened_values.len(), 4);
+            assert!(query_opening[1]
+                .opened_values
+                .iter()
+                .all(|row| row.len() == EXT_DEGREE));
+        }
+
+        config.fri_config.num_queries = 100;
+        assert!(matches!(
+            proof.check_shape(&config),
+            Err(P3VerifierError::InvalidProofShape(_))
+        ));
+        config.log_quotient_degree = 0;
+        proof.check_shape(&config).unwrap();
+    }
+
+    #[test]
+    fn add_virtual_includes_preprocessed_batch() {
+        let s = include_str!("../../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(s).unwrap();
+        assert!(proof.opened_values.preprocessed_local.is_empty());
+
+        let config = P3Config {
+            fri_config: fibonacci_fri_config(),
+            hash_config: P3HashConfig::default(),
+            log_quotient_degree: 0,
+            log_trace_height: 6,
+            trace_width: 3,
+            opening_matrix_log_max_height: 7,
+            quotient_chunk_width: EXT_DEGREE,
+            num_public_values: 0,
+            preprocessed_width: 2,
+            preprocessed_commit: None,
+            num_interactions: 0,
+            degree_bits: 6,
+            min_degree_bits: None,
+        }
+        .with_preprocessed_commit(vec![
+            GoldilocksField::ZERO;
+            P3HashConfig::default().digest_elems
+        ]);
+        assert_eq!(
+            config.batch_widths(),
+            vec![vec![3], vec![EXT_DEGREE], vec![2]]
+        );
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        let target = Proof::<Target>::add_virtual_to(&mut builder, &config);
+        assert_eq!(target.opened_values.preprocessed_local.len(), 2);
+        assert_eq!(target.opened_values.preprocessed_next.len(), 2);
+        assert!(target
+            .opening_proof
+            .query_openings
+            .iter()
+            .all(|query_opening| query_opening.len() == 3));
+
+        assert!(matches!(
+            proof.check_shape(&config),
+            Err(P3VerifierError::InvalidProofShape(_))
+        ));
+    }
+
+    #[test]
+    fn dummy_matches_add_virtual() {
+        let config = P3Config {
+            fri_config: FriConfig {
+                log_blowup: 2,
+                num_queries: 3,
+                proof_of_work_bits: 0,
+                max_log_arity: 2,
+                log_final_poly_len: Some(1),
+            },
+            hash_config: P3HashConfig::default(),
+            log_quotient_degree: 1,
+            log_trace_height: 7,
+            trace_width: 3,
+            opening_matrix_log_max_height: 9,
+            quotient_chunk_width: EXT_DEGREE,
+            num_public_values: 0,
+            preprocessed_width: 2,
+            preprocessed_commit: None,
+            num_interactions: 1,
+            degree_bits: 7,
+            min_degree_bits: None,
+        }
+        .with_preprocessed_commit(vec![
+            GoldilocksField::ZERO;
+            P3HashConfig::default().digest_elems
+        ]);
+
+        let mut builder =
+            CircuitBuilder::<GoldilocksField, 2>::new(CircuitConfig::standard_recursion_config());
+        let target = Proof::<Target>::add_virtual_to(&mut builder, &config);
+        let dummy = P3ProofField::dummy(&config);
+        dummy.check_shape(&config).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(target.map(|_| 0u64)).unwrap(),
+            serde_json::to_value(dummy.map(|v| v.value.0)).unwrap()
+        );
+    }
 }
@@ -1,9 +1,14 @@
 use plonky2::field::extension::Extendable;
+use plonky2::field::types::Field;
+use plonky2::field::types::PrimeField64;
+use plonky2::iop::target::BoolTarget;
 use plonky2::iop::target::Target;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
 
 use crate::common::richer_field::RicherField;
 use crate::p3::extension::CircuitBuilderP3ExtArithmetic;
+use crate::p3::native::domain::two_adic_generator;
+use crate::p3::native::domain::GENERATOR;
 use crate::p3::serde::proof::BinomialExtensionField;
 use crate::p3::serde::LagrangeSelectors;
 use crate::p3::utils::log2_ceil_usize;
@@ -15,9 +20,29 @@ pub struct TwoAdicMultiplicativeCoset {
     pub shift: Target,
 }
 
-impl TwoAdicMultiplicativeCoset {
-    const TWO_ADICITY: usize = 32;
+/// `x^-1`, computed natively when `x` is a constant, as are the shifts of the
+/// domains of a fixed-degree proof.
+fn inverse<F: RicherField + Extendable<D>, const D: usize>(
+    x: Target,
+    cb: &mut CircuitBuilder<F, D>,
+) -> Target {
+    match cb.target_as_constant(x) {
+        Some(x) => cb.constant(x.inverse()),
+        None => cb.inverse(x),
+    }
+}
 
+/// The generator of the two-adic subgroup of order `2^log_n`, as a constant.
+fn generator<F: RicherField + Extendable<D>, const D: usize>(
+    log_n: usize,
+    cb: &mut CircuitBuilder<F, D>,
+) -> Target {
+    cb.constant(F::from_canonical_u64(
+        two_adic_generator(log_n).to_canonical_u64(),
+    ))
+}
+
+impl TwoAdicMultiplicativeCoset {
     pub fn size(&self) -> usize {
         1 << self.log_n
     }
@@ -30,17 +55,14 @@ impl TwoAdicMultiplicativeCoset {
         &self,
         cb: &mut CircuitBuilder<F, D>,
     ) -> Target {
-        assert!(self.log_n <= Self::TWO_ADICITY);
-
-        let base = cb.constant(F::from_canonical_u64(1_753_635_133_440_165_772));
-        cb.exp_power_of_2(base, Self::TWO_ADICITY - self.log_n)
+        generator(self.log_n, cb)
     }
 
-    pub fn next_point<F: RicherField + Extendable<D>, const D: usize>(
+    pub fn next_point<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
         &self,
-        x: BinomialExtensionField<Target>,
+        x: BinomialExtensionField<Target, E>,
         cb: &mut CircuitBuilder<F, D>,
-    ) -> BinomialExtensionField<Target> {
+    ) -> BinomialExtensionField<Target, E> {
         let gen = self.gen(cb);
         cb.p3_ext_mul_single(&x, gen)
     }
@@ -63,10 +85,9 @@ impl TwoAdicMultiplicativeCoset {
         min_size: usize,
         cb: &mut CircuitBuilder<F, D>,
     ) -> TwoAdicMultiplicativeCoset {
-        let generator = cb.constant(F::from_canonical_u64(7));
         TwoAdicMultiplicativeCoset {
             log_n: log2_ceil_usize(min_size),
-            shift: cb.mul(self.shift, generator),
+            shift: cb.mul_const(F::from_canonical_u64(GENERATOR), self.shift),
         }
     }
 
@@ -76,25 +97,44 @@ impl TwoAdicMultiplicativeCoset {
         cb: &mut CircuitBuilder<F, D>,
     ) -> Vec<TwoAdicMultiplicativeCoset> {
         let log_chunks = log2_strict_usize(num_chunks);
-        let two_adicity_generator = self.gen(cb);
+        let generator = two_adic_generator(self.log_n);
 
         (0..num_chunks)
             .map(|i| TwoAdicMultiplicativeCoset {
                 log_n: self.log_n - log_chunks,
                 shift: {
-                    let two_adic_generator_powers_i = cb.exp_u64(two_adicity_generator, i as u64);
-                    cb.mul(self.shift, two_adic_generator_powers_i)
+                    let generator_powers_i = generator.exp_u64(i as u64).to_canonical_u64();
+                    cb.mul_const(F::from_canonical_u64(generator_powers_i), self.shift)
                 },
             })
             .collect()
     }
 
-    pub fn selectors_at_point<F: RicherField + Extendable<D>, const D: usize>(
+    pub fn selectors_at_point<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
         &self,
-        point: BinomialExtensionField<Target>,
+        point: BinomialExtensionField<Target, E>,
         cb: &mut CircuitBuilder<F, D>,
-    ) -> LagrangeSelectors<BinomialExtensionField<Target>> {
-        let shift_inv = cb.inverse(self.shift);
+    ) -> LagrangeSelectors<BinomialExtensionField<Target, E>> {
+        let shift_inv = inverse(self.shift, cb);
+        let generator_inv = cb.constant(F::from_canonical_u64(
+            two_adic_generator(self.log_n).inverse().to_canonical_u64(),
+        ));
+        self.selectors_at_point_with_inverses(point, shift_inv, generator_inv, cb)
+    }
+
+    /// Same as [`selectors_at_point`](Self::selectors_at_point) given the
+    /// inverses of the shift and of the generator of the domain.
+    pub fn selectors_at_point_with_inverses<
+        F: RicherField + Extendable<D>,
+        const D: usize,
+        const E: usize,
+    >(
+        &self,
+        point: BinomialExtensionField<Target, E>,
+        shift_inv: Target,
+        generator_inv: Target,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> LagrangeSelectors<BinomialExtensionField<Target, E>> {
         let unshifted_point = cb.p3_ext_mul_single(&point, shift_inv);
         let unshifted_point_exp_log_n =
             cb.p3_ext_exp_power_of_2(unshifted_point.clone(), self.log_n);
@@ -106,10 +146,8 @@ impl TwoAdicMultiplicativeCoset {
         let z_h_div_unshifted_point_minus_one =
             cb.p3_ext_div(z_h.clone(), unshifted_point_minus_one);
 
-        let generator = self.gen(cb);
-        let generator_inv = cb.inverse(generator);
         let unshifted_point_minus_generator_inv =
-            cb.p3_ext_sub_single(unshifted_point, generator_inv.clone());
+            cb.p3_ext_sub_single(unshifted_point, generator_inv);
         let z_h_div_unshifted_point_minus_generator_inv =
             cb.p3_ext_div(z_h.clone(), unshifted_point_minus_generator_inv.clone());
 
@@ -121,12 +159,27 @@ impl TwoAdicMultiplicativeCoset {
         }
     }
 
-    pub fn zp_at_point<F: RicherField + Extendable<D>, const D: usize>(
+    pub fn zp_at_point<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+        &self,
+        point: BinomialExtensionField<Target, E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> BinomialExtensionField<Target, E> {
+        let shift_inv = inverse(self.shift, cb);
+        self.zp_at_point_with_shift_inv(point, shift_inv, cb)
+    }
+
+    /// Same as [`zp_at_point`](Self::zp_at_point) given the inverse of the
+    /// shift of the domain.
+    pub fn zp_at_point_with_shift_inv<
+        F: RicherField + Extendable<D>,
+        const D: usize,
+        const E: usize,
+    >(
         &self,
-        point: BinomialExtensionField<Target>,
+        point: BinomialExtensionField<Target, E>,
+        shift_inv: Target,
         cb: &mut CircuitBuilder<F, D>,
-    ) -> BinomialExtensionField<Target> {
-        let shift_inv = cb.inverse(self.shift);
+    ) -> BinomialExtensionField<Target, E> {
         let point_mul_shift_inv = cb.p3_ext_mul_single(&point, shift_inv);
         let point_mul_shift_inv_powers_log_n =
             cb.p3_ext_exp_power_of_2(point_mul_shift_inv, self.log_n);
@@ -139,10 +192,383 @@ impl TwoAdicMultiplicativeCoset {
         point: Target,
         cb: &mut CircuitBuilder<F, D>,
     ) -> Target {
-        let shift_inv = cb.inverse(self.shift);
+        let shift_inv = inverse(self.shift, cb);
+        self.zp_at_single_point_with_shift_inv(point, shift_inv, cb)
+    }
+
+    /// Same as [`zp_at_single_point`](Self::zp_at_single_point) given the
+    /// inverse of the shift of the domain.
+    pub fn zp_at_single_point_with_shift_inv<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        point: Target,
+        shift_inv: Target,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Target {
         let point_mul_shift_inv = cb.mul(shift_inv, point);
         let point_mul_shift_inv_powers_log_n = cb.exp_power_of_2(point_mul_shift_inv, self.log_n);
         let one = cb.one();
         cb.sub(point_mul_shift_inv_powers_log_n, one)
     }
 }
+
+/// The trace domain of the proofs of one degree and its quotient chunk
+/// domains, along with the inverses the verifier takes of them. Proofs of the
+/// same degree verified in one circuit share them, see
+/// [`p3_verify_proofs_batch`](crate::p3::CircuitBuilderP3Arithmetic::p3_verify_proofs_batch).
+#[derive(Clone, Debug)]
+pub struct TwoAdicDomains {
+    pub trace_domain: TwoAdicMultiplicativeCoset,
+    pub quotient_chunks_domains: Vec<TwoAdicMultiplicativeCoset>,
+    trace_shift_inv: Target,
+    trace_generator_inv: Target,
+    quotient_chunks_shift_invs: Vec<Target>,
+    /// For each quotient chunk, the inverse of the vanishing polynomial of the
+    /// other chunks at its first point.
+    zp_normalizers: Vec<Target>,
+}
+
+impl TwoAdicDomains {
+    pub fn new<F: RicherField + Extendable<D>, const D: usize>(
+        log_trace_height: usize,
+        degree_bits: usize,
+        log_quotient_degree: usize,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> TwoAdicDomains {
+        let trace_domain = TwoAdicMultiplicativeCoset::natural_domain_for_degree(
+            log_trace_height,
+            1 << degree_bits,
+            cb,
+        );
+        let mut quotient_domain =
+            trace_domain.create_disjoint_domain(1 << (degree_bits + log_quotient_degree), cb);
+        let quotient_chunks_domains = quotient_domain.split_domains(1 << log_quotient_degree, cb);
+
+        Self::from_domains(trace_domain, quotient_chunks_domains, cb)
+    }
+
+    pub fn from_domains<F: RicherField + Extendable<D>, const D: usize>(
+        trace_domain: TwoAdicMultiplicativeCoset,
+        quotient_chunks_domains: Vec<TwoAdicMultiplicativeCoset>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> TwoAdicDomains {
+        let trace_shift_inv = inverse(trace_domain.shift, cb);
+        let trace_generator_inv = cb.constant(F::from_canonical_u64(
+            two_adic_generator(trace_domain.log_n)
+                .inverse()
+                .to_canonical_u64(),
+        ));
+        let quotient_chunks_shift_invs: Vec<Target> = quotient_chunks_domains
+            .iter()
+            .map(|domain| inverse(domain.shift, cb))
+            .collect();
+
+        let zp_normalizers = quotient_chunks_domains
+            .iter()
+            .enumerate()
+            .map(|(i, domain)| {
+                let one = cb.one();
+                let zp = quotient_chunks_domains
+                    .iter()
+                    .zip(&quotient_chunks_shift_invs)
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .fold(one, |acc, (_, (other_domain, shift_inv))| {
+                        let zp = other_domain.zp_at_single_point_with_shift_inv(
+                            domain.first_point(),
+                            *shift_inv,
+                            cb,
+                        );
+                        cb.mul(acc, zp)
+                    });
+                inverse(zp, cb)
+            })
+            .collect();
+
+        TwoAdicDomains {
+            trace_domain,
+            quotient_chunks_domains,
+            trace_shift_inv,
+            trace_generator_inv,
+            quotient_chunks_shift_invs,
+            zp_normalizers,
+        }
+    }
+
+    /// The Lagrange selectors of the trace domain at `point`.
+    pub fn selectors_at_point<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+        &self,
+        point: BinomialExtensionField<Target, E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> LagrangeSelectors<BinomialExtensionField<Target, E>> {
+        self.trace_domain.selectors_at_point_with_inverses(
+            point,
+            self.trace_shift_inv,
+            self.trace_generator_inv,
+            cb,
+        )
+    }
+
+    /// For each quotient chunk, the vanishing polynomial of the other chunks
+    /// at `point`, normalized to one at the first point of the chunk.
+    pub fn zps_at_point<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+        &self,
+        point: BinomialExtensionField<Target, E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Vec<BinomialExtensionField<Target, E>> {
+        let zps_at_point: Vec<BinomialExtensionField<Target, E>> = self
+            .quotient_chunks_domains
+            .iter()
+            .zip(&self.quotient_chunks_shift_invs)
+            .map(|(domain, shift_inv)| {
+                domain.zp_at_point_with_shift_inv(point.clone(), *shift_inv, cb)
+            })
+            .collect();
+
+        self.zp_normalizers
+            .iter()
+            .enumerate()
+            .map(|(i, normalizer)| {
+                let one = cb.p3_ext_one();
+                let normalizer = cb.p3_ext_mul_single(&one, *normalizer);
+                zps_at_point
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .fold(normalizer, |acc, (_, zp)| cb.p3_ext_mul(&acc, zp))
+            })
+            .collect()
+    }
+}
+
+/// A [`TwoAdicMultiplicativeCoset`] whose size is only known at proving time:
+/// its log size is `min_log_n + i` for the one `i` such that `is_log_n[i]`
+/// holds.
+#[derive(Clone, Debug)]
+pub struct VariableTwoAdicMultiplicativeCoset {
+    pub min_log_n: usize,
+    pub is_log_n: Vec<BoolTarget>,
+    pub shift: Target,
+}
+
+impl VariableTwoAdicMultiplicativeCoset {
+    pub fn natural_domain<F: RicherField + Extendable<D>, const D: usize>(
+        min_log_n: usize,
+        is_log_n: Vec<BoolTarget>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> VariableTwoAdicMultiplicativeCoset {
+        VariableTwoAdicMultiplicativeCoset {
+            min_log_n,
+            is_log_n,
+            shift: cb.one(),
+        }
+    }
+
+    pub fn first_point(&self) -> Target {
+        self.shift
+    }
+
+    /// The constant `value(log_n)` for the actual log size.
+    fn select_constant<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        value: impl Fn(usize) -> u64,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Target {
+        let zero = cb.zero();
+        self.is_log_n
+            .iter()
+            .enumerate()
+            .fold(zero, |acc, (i, is_log_n)| {
+                let value = F::from_canonical_u64(value(self.min_log_n + i));
+                cb.mul_const_add(value, is_log_n.target, acc)
+            })
+    }
+
+    pub fn gen<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Target {
+        self.select_constant(|log_n| two_adic_generator(log_n).to_canonical_u64(), cb)
+    }
+
+    /// `x^(2^log_n)`, the vanishing polynomial of the unshifted domain plus
+    /// one.
+    pub fn exp_size<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        x: Target,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Target {
+        let mut power = cb.exp_power_of_2(x, self.min_log_n);
+        let mut res = cb.zero();
+        for (i, is_log_n) in self.is_log_n.iter().enumerate() {
+            if i > 0 {
+                power = cb.square(power);
+            }
+            res = cb.mul_add(is_log_n.target, power, res);
+        }
+        res
+    }
+
+    pub fn exp_size_ext<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+        &self,
+        x: BinomialExtensionField<Target, E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> BinomialExtensionField<Target, E> {
+        let mut power = cb.p3_ext_exp_power_of_2(x, self.min_log_n);
+        let mut res: BinomialExtensionField<Target, E> = cb.p3_ext_zero();
+        for (i, is_log_n) in self.is_log_n.iter().enumerate() {
+            if i > 0 {
+                power = cb.p3_ext_mul(&power, &power);
+            }
+            for j in 0..E {
+                res.value[j] = cb.mul_add(is_log_n.target, power.value[j], res.value[j]);
+            }
+        }
+        res
+    }
+
+    pub fn next_point<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+        &self,
+        x: BinomialExtensionField<Target, E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> BinomialExtensionField<Target, E> {
+        let gen = self.gen(cb);
+        cb.p3_ext_mul_single(&x, gen)
+    }
+
+    /// The coset `2^log_blowup` times as large as `self`, shifted away from
+    /// it by the multiplicative generator.
+    pub fn create_disjoint_domain<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        log_blowup: usize,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> VariableTwoAdicMultiplicativeCoset {
+        let generator = cb.constant(F::from_canonical_u64(GENERATOR));
+        VariableTwoAdicMultiplicativeCoset {
+            min_log_n: self.min_log_n + log_blowup,
+            is_log_n: self.is_log_n.clone(),
+            shift: cb.mul(self.shift, generator),
+        }
+    }
+
+    pub fn split_domains<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        num_chunks: usize,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Vec<VariableTwoAdicMultiplicativeCoset> {
+        let log_chunks = log2_strict_usize(num_chunks);
+
+        (0..num_chunks)
+            .map(|i| VariableTwoAdicMultiplicativeCoset {
+                min_log_n: self.min_log_n - log_chunks,
+                is_log_n: self.is_log_n.clone(),
+                shift: {
+                    let two_adic_generator_powers_i = self.select_constant(
+                        |log_n| {
+                            two_adic_generator(log_n)
+                                .exp_u64(i as u64)
+                                .to_canonical_u64()
+                        },
+                        cb,
+                    );
+                    cb.mul(self.shift, two_adic_generator_powers_i)
+                },
+            })
+            .collect()
+    }
+
+    pub fn selectors_at_point<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+        &self,
+        point: BinomialExtensionField<Target, E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> LagrangeSelectors<BinomialExtensionField<Target, E>> {
+        let shift_inv = inverse(self.shift, cb);
+        let unshifted_point = cb.p3_ext_mul_single(&point, shift_inv);
+        let unshifted_point_exp_log_n = self.exp_size_ext(unshifted_point.clone(), cb);
+        let one = cb.p3_ext_one();
+
+        let z_h = cb.p3_ext_sub(unshifted_point_exp_log_n, one.clone());
+
+        let unshifted_point_minus_one = cb.p3_ext_sub(unshifted_point.clone(), one);
+        let z_h_div_unshifted_point_minus_one =
+            cb.p3_ext_div(z_h.clone(), unshifted_point_minus_one);
+
+        let generator_inv = self.select_constant(
+            |log_n| two_adic_generator(log_n).inverse().to_canonical_u64(),
+            cb,
+        );
+        let unshifted_point_minus_generator_inv =
+            cb.p3_ext_sub_single(unshifted_point, generator_inv);
+        let z_h_div_unshifted_point_minus_generator_inv =
+            cb.p3_ext_div(z_h.clone(), unshifted_point_minus_generator_inv.clone());
+
+        LagrangeSelectors {
+            is_first_row: z_h_div_unshifted_point_minus_one,
+            is_last_row: z_h_div_unshifted_point_minus_generator_inv,
+            is_transition: unshifted_point_minus_generator_inv,
+            inv_zeroifier: cb.p3_ext_inverse(z_h),
+        }
+    }
+
+    pub fn zp_at_point<F: RicherField + Extendable<D>, const D: usize, const E: usize>(
+        &self,
+        point: BinomialExtensionField<Target, E>,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> BinomialExtensionField<Target, E> {
+        let shift_inv = inverse(self.shift, cb);
+        let point_mul_shift_inv = cb.p3_ext_mul_single(&point, shift_inv);
+        let point_mul_shift_inv_powers_log_n = self.exp_size_ext(point_mul_shift_inv, cb);
+        let one = cb.p3_ext_one();
+        cb.p3_ext_sub(point_mul_shift_inv_powers_log_n, one)
+    }
+
+    pub fn zp_at_single_point<F: RicherField + Extendable<D>, const D: usize>(
+        &self,
+        point: Target,
+        cb: &mut CircuitBuilder<F, D>,
+    ) -> Target {
+        let shift_inv = inverse(self.shift, cb);
+        let point_mul_shift_inv = cb.mul(shift_inv, point);
+        let point_mul_shift_inv_powers_log_n = self.exp_size(point_mul_shift_inv, cb);
+        let one = cb.one();
+        cb.sub(point_mul_shift_inv_powers_log_n, one)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+
+    use super::*;
+
+    type F = GoldilocksField;
+
+    #[test]
+    fn test_domains_of_fixed_degree_are_constants() {
+        let mut cb = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+        let domains = TwoAdicDomains::new(6, 6, 1, &mut cb);
+        assert_eq!(cb.num_gates(), 0);
+
+        // The quotient domain of 2^7 points is split in two chunks, shifted
+        // by the multiplicative generator.
+        let generator = two_adic_generator(7);
+        for (i, domain) in domains.quotient_chunks_domains.iter().enumerate() {
+            assert_eq!(domain.log_n, 6);
+            assert_eq!(
+                cb.target_as_constant(domain.shift),
+                Some(F::from_canonical_u64(GENERATOR) * generator.exp_u64(i as u64))
+            );
+        }
+
+        let gen = domains.trace_domain.gen(&mut cb);
+        assert_eq!(cb.target_as_constant(gen), Some(two_adic_generator(6)));
+        for target in [domains.trace_shift_inv, domains.trace_generator_inv]
+            .into_iter()
+            .chain(domains.quotient_chunks_shift_invs)
+            .chain(domains.zp_normalizers)
+        {
+            assert!(cb.target_as_constant(target).is_some());
+        }
+        assert_eq!(cb.num_gates(), 0);
+    }
+}
@@ -0,0 +1,488 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use anyhow::ensure;
+use anyhow::Result;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::iop::target::Target;
+use plonky2::iop::witness::PartialWitness;
+use plonky2::iop::witness::WitnessWrite;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::CircuitConfig;
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2::plonk::proof::ProofWithPublicInputsTarget;
+
+use crate::common::poseidon2::poseidon2_gate::Poseidon2GoldilocksConfig;
+use crate::p3::air::Air;
+use crate::p3::challenger::DuplexChallengerTarget;
+use crate::p3::challenger::P3Challenger;
+use crate::p3::constants::EXT_DEGREE;
+use crate::p3::permutation::P3Permutation;
+use crate::p3::serde::fri::FriChallenges;
+use crate::p3::serde::proof::BinomialExtensionField;
+use crate::p3::serde::proof::P3Config;
+use crate::p3::serde::proof::P3Field;
+use crate::p3::serde::proof::Proof;
+use crate::p3::serde::two_adic::TwoAdicDomains;
+use crate::p3::verifier::CircuitBuilderP3Verifier;
+use crate::p3::verifier::P3QueryClaims;
+use crate::p3::verifier::P3VerifierError;
+
+type F = GoldilocksField;
+type C = Poseidon2GoldilocksConfig;
+const D: usize = 2;
+
+struct HeaderCircuit<const E: usize> {
+    data: CircuitData<F, C, D>,
+    proof: Proof<Target, E>,
+    public_values: Vec<Target>,
+}
+
+/// Circuit of the shards checking the same number of queries.
+struct ShardCircuit<const E: usize> {
+    data: CircuitData<F, C, D>,
+    proof: Proof<Target, E>,
+    /// The targets the shard agrees on with the header, see [`shared_targets`].
+    shared: Vec<Target>,
+    query_indices: Vec<Target>,
+}
+
+struct AggregationCircuit {
+    data: CircuitData<F, C, D>,
+    header_proof: ProofWithPublicInputsTarget<D>,
+    shard_proofs: Vec<ProofWithPublicInputsTarget<D>>,
+}
+
+/// A plonky2 verifier of Plonky3 proofs of a given shape, split across
+/// several circuits so that none of them has to check every FRI query.
+///
+/// The header circuit runs the transcript and checks the quotient identity.
+/// Each query shard circuit checks a range of the queries, taking the
+/// challenges they're checked against as public inputs, along with the parts
+/// of the proof they depend on. The aggregation circuit verifies the header
+/// proof and every shard proof, and checks that the shards agree with the
+/// header. Its public inputs are those of a
+/// [`P3VerifierCircuit`](crate::p3::circuit::P3VerifierCircuit): the public
+/// values of the AIR followed by the trace commitment.
+///
+/// The header and shard proofs don't depend on each other but for the
+/// challenges, so they can be proven one at a time, on different machines.
+pub struct P3SplitVerifierCircuit<A: Air, const E: usize = EXT_DEGREE> {
+    pub air: A,
+    pub config: P3Config,
+    /// The queries checked by each shard.
+    pub shards: Vec<Range<usize>>,
+    header: HeaderCircuit<E>,
+    /// The shard circuits, by number of queries.
+    shard_circuits: HashMap<usize, ShardCircuit<E>>,
+    aggregation: AggregationCircuit,
+}
+
+impl<A: Air, const E: usize> P3SplitVerifierCircuit<A, E> {
+    /// Builds the circuits checking the queries of `config` in `num_shards`
+    /// shards. Fails if `num_shards` is zero or more than the number of
+    /// queries.
+    pub fn new<H: P3Permutation<F>>(
+        air: A,
+        config: P3Config,
+        num_shards: usize,
+        circuit_config: CircuitConfig,
+    ) -> Result<Self, P3VerifierError>
+    where
+        H::Params: Default,
+    {
+        Self::new_with_challenger::<DuplexChallengerTarget<H>>(
+            air,
+            config,
+            num_shards,
+            circuit_config,
+        )
+    }
+
+    /// Builds the circuits for proofs of the Plonky3 config whose transcript
+    /// is `Ch`, see
+    /// [`P3VerifierCircuit::new_with_challenger`](crate::p3::circuit::P3VerifierCircuit::new_with_challenger).
+    pub fn new_with_challenger<Ch: P3Challenger<F, D>>(
+        air: A,
+        config: P3Config,
+        num_shards: usize,
+        circuit_config: CircuitConfig,
+    ) -> Result<Self, P3VerifierError> {
+        let num_queries = config.fri_config.num_queries;
+        if num_shards == 0 || num_shards > num_queries {
+            return Err(P3VerifierError::InvalidProofShape(
+                "expected between one shard and one per query",
+            ));
+        }
+
+        // The first shards take one more query, so that shards come in at most
+        // two sizes.
+        let mut shards = Vec::with_capacity(num_shards);
+        let mut start = 0;
+        for i in 0..num_shards {
+            let len = num_queries / num_shards + usize::from(i < num_queries % num_shards);
+            shards.push(start..start + len);
+            start += len;
+        }
+
+        let header = Self::build_header::<Ch>(&air, &config, &circuit_config)?;
+        let mut shard_circuits = HashMap::new();
+        for shard in &shards {
+            if !shard_circuits.contains_key(&shard.len()) {
+                let circuit = Self::build_shard::<Ch>(&config, shard.len(), &circuit_config)?;
+                shard_circuits.insert(shard.len(), circuit);
+            }
+        }
+        let aggregation =
+            Self::build_aggregation(&config, &header, &shards, &shard_circuits, circuit_config);
+
+        Ok(Self {
+            air,
+            config,
+            shards,
+            header,
+            shard_circuits,
+            aggregation,
+        })
+    }
+
+    /// Lays out the header circuit, whose public inputs are the public values
+    /// of the AIR, the targets it shares with the shards and the query
+    /// indices.
+    fn build_header<Ch: P3Challenger<F, D>>(
+        air: &A,
+        config: &P3Config,
+        circuit_config: &CircuitConfig,
+    ) -> Result<HeaderCircuit<E>, P3VerifierError> {
+        let mut builder = CircuitBuilder::<F, D>::new(circuit_config.clone());
+        let public_values = builder.add_virtual_targets(config.num_public_values);
+        builder.register_public_inputs(&public_values);
+
+        let proof = Proof::<Target, E>::add_virtual_to(&mut builder, config);
+        let domains = TwoAdicDomains::new(
+            config.log_trace_height,
+            config.degree_bits,
+            config.log_quotient_degree,
+            &mut builder,
+        );
+        let mut challenger = Ch::new(&mut builder, &config.hash_config)?;
+        let condition = builder._true();
+        let claims = builder.__p3_verify_proof_header__(
+            air,
+            &proof,
+            &public_values,
+            config,
+            &domains,
+            &mut challenger,
+            condition,
+        )?;
+
+        builder.register_public_inputs(&shared_targets(&proof, &claims));
+        builder.register_public_inputs(&claims.fri_challenges.query_indices);
+
+        Ok(HeaderCircuit {
+            data: builder.build::<C>(),
+            proof,
+            public_values,
+        })
+    }
+
+    /// Lays out the circuit of a shard of `num_queries` queries, whose public
+    /// inputs are the targets it shares with the header and its query
+    /// indices.
+    fn build_shard<Ch: P3Challenger<F, D>>(
+        config: &P3Config,
+        num_queries: usize,
+        circuit_config: &CircuitConfig,
+    ) -> Result<ShardCircuit<E>, P3VerifierError> {
+        let mut builder = CircuitBuilder::<F, D>::new(circuit_config.clone());
+
+        // The proof of a shard only holds its own queries.
+        let mut shard_config = config.clone();
+        shard_config.fri_config.num_queries = num_queries;
+        let proof = Proof::<Target, E>::add_virtual_to(&mut builder, &shard_config);
+
+        let domains = TwoAdicDomains::new(
+            config.log_trace_height,
+            config.degree_bits,
+            config.log_quotient_degree,
+            &mut builder,
+        );
+        let preprocessed_commit =
+            <CircuitBuilder<F, D> as CircuitBuilderP3Verifier<F, D, E>>::p3_preprocessed_commit(
+                &mut builder,
+                config,
+            )?;
+        let zeta = BinomialExtensionField::add_virtual_to(&mut builder);
+        let commits_and_points = builder.p3_commits_and_points(
+            &proof.commitments,
+            preprocessed_commit,
+            &proof.opened_values,
+            &domains,
+            zeta.clone(),
+        );
+        let claims = P3QueryClaims {
+            zeta,
+            alpha: BinomialExtensionField::add_virtual_to(&mut builder),
+            fri_challenges: FriChallenges {
+                query_indices: builder.add_virtual_targets(num_queries),
+                betas: proof
+                    .opening_proof
+                    .fri_proof
+                    .commit_phase_commits
+                    .iter()
+                    .map(|_| BinomialExtensionField::add_virtual_to(&mut builder))
+                    .collect(),
+            },
+            commits_and_points,
+        };
+
+        let challenger = Ch::new(&mut builder, &config.hash_config)?;
+        let condition = builder._true();
+        builder.p3_verify_queries(
+            &challenger.mmcs(),
+            &config.fri_config,
+            &claims.commits_and_points,
+            &proof.opening_proof,
+            &claims.alpha,
+            &claims.fri_challenges,
+            condition,
+        )?;
+
+        let shared = shared_targets(&proof, &claims);
+        let query_indices = claims.fri_challenges.query_indices;
+        builder.register_public_inputs(&shared);
+        builder.register_public_inputs(&query_indices);
+
+        Ok(ShardCircuit {
+            data: builder.build::<C>(),
+            proof,
+            shared,
+            query_indices,
+        })
+    }
+
+    fn build_aggregation(
+        config: &P3Config,
+        header: &HeaderCircuit<E>,
+        shards: &[Range<usize>],
+        shard_circuits: &HashMap<usize, ShardCircuit<E>>,
+        circuit_config: CircuitConfig,
+    ) -> AggregationCircuit {
+        let mut builder = CircuitBuilder::<F, D>::new(circuit_config);
+
+        let header_proof = builder.add_virtual_proof_with_pis(&header.data.common);
+        let verifier_data = builder.constant_verifier_data(&header.data.verifier_only);
+        builder.verify_proof::<C>(&header_proof, &verifier_data, &header.data.common);
+
+        let num_public_values = config.num_public_values;
+        let (public_values, header_inputs) = header_proof.public_inputs.split_at(num_public_values);
+        let num_shared = header_inputs.len() - config.fri_config.num_queries;
+        let (header_shared, header_query_indices) = header_inputs.split_at(num_shared);
+
+        let shard_proofs = shards
+            .iter()
+            .map(|shard| {
+                let data = &shard_circuits[&shard.len()].data;
+                let shard_proof = builder.add_virtual_proof_with_pis(&data.common);
+                let verifier_data = builder.constant_verifier_data(&data.verifier_only);
+                builder.verify_proof::<C>(&shard_proof, &verifier_data, &data.common);
+
+                let (shared, query_indices) = shard_proof.public_inputs.split_at(num_shared);
+                for (&x, &y) in shared.iter().zip(header_shared) {
+                    builder.connect(x, y);
+                }
+                for (&x, &y) in query_indices
+                    .iter()
+                    .zip(&header_query_indices[shard.clone()])
+                {
+                    builder.connect(x, y);
+                }
+                shard_proof
+            })
+            .collect();
+
+        // The shared targets start with the commitments, trace first.
+        builder.register_public_inputs(public_values);
+        builder.register_public_inputs(&header_shared[..config.hash_config.digest_elems]);
+
+        AggregationCircuit {
+            data: builder.build::<C>(),
+            header_proof,
+            shard_proofs,
+        }
+    }
+
+    /// Runs the transcript of `proof` and checks its quotient identity.
+    pub fn prove_header(
+        &self,
+        proof: &Proof<P3Field, E>,
+        public_values: &[F],
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        proof.check_shape(&self.config)?;
+        if public_values.len() != self.header.public_values.len() {
+            return Err(P3VerifierError::PublicValuesMismatch {
+                expected: self.header.public_values.len(),
+                actual: public_values.len(),
+            }
+            .into());
+        }
+
+        let mut pw = PartialWitness::new();
+        pw.set_target_arr(&self.header.public_values, public_values);
+        self.header.proof.set_witness::<F, D, _>(&mut pw, proof);
+
+        self.header.data.prove(pw)
+    }
+
+    /// Checks the queries of `proof` in the given shard, against the
+    /// challenges exposed by `header_proof`.
+    pub fn prove_shard(
+        &self,
+        shard: usize,
+        proof: &Proof<P3Field, E>,
+        header_proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        proof.check_shape(&self.config)?;
+        ensure!(
+            shard < self.shards.len(),
+            "expected a shard below {}, got {shard}",
+            self.shards.len()
+        );
+        let queries = self.shards[shard].clone();
+        let circuit = &self.shard_circuits[&queries.len()];
+
+        let mut shard_proof = proof.clone();
+        let opening_proof = &mut shard_proof.opening_proof;
+        opening_proof.fri_proof.query_proofs =
+            opening_proof.fri_proof.query_proofs[queries.clone()].to_vec();
+        opening_proof.query_openings = opening_proof.query_openings[queries.clone()].to_vec();
+
+        let header_inputs = &header_proof.public_inputs[self.header.public_values.len()..];
+        let (header_shared, header_query_indices) = header_inputs.split_at(circuit.shared.len());
+
+        let mut pw = PartialWitness::new();
+        circuit.proof.set_witness::<F, D, _>(&mut pw, &shard_proof);
+        pw.set_target_arr(&circuit.shared, header_shared);
+        pw.set_target_arr(&circuit.query_indices, &header_query_indices[queries]);
+
+        circuit.data.prove(pw)
+    }
+
+    /// Checks that the shard proofs, given in order, agree with the header
+    /// proof.
+    pub fn aggregate(
+        &self,
+        header_proof: &ProofWithPublicInputs<F, C, D>,
+        shard_proofs: &[ProofWithPublicInputs<F, C, D>],
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        ensure!(
+            shard_proofs.len() == self.shards.len(),
+            "expected {} shard proofs, got {}",
+            self.shards.len(),
+            shard_proofs.len()
+        );
+
+        let aggregation = &self.aggregation;
+        let mut pw = PartialWitness::new();
+        pw.set_proof_with_pis_target(&aggregation.header_proof, header_proof);
+        for (target, proof) in aggregation.shard_proofs.iter().zip(shard_proofs) {
+            pw.set_proof_with_pis_target(target, proof);
+        }
+
+        aggregation.data.prove(pw)
+    }
+
+    /// Proves the header and every shard in turn, then aggregates them.
+    pub fn prove(
+        &self,
+        proof: &Proof<P3Field, E>,
+        public_values: &[F],
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let header_proof = self.prove_header(proof, public_values)?;
+        let shard_proofs = (0..self.shards.len())
+            .map(|shard| self.prove_shard(shard, proof, &header_proof))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.aggregate(&header_proof, &shard_proofs)
+    }
+
+    pub fn verify(&self, proof: ProofWithPublicInputs<F, C, D>) -> Result<()> {
+        self.aggregation.data.verify(proof)
+    }
+}
+
+/// The targets the header and the shards have to agree on: the proof up to its
+/// queries, and the challenges the queries are checked against but for the
+/// query indices.
+fn shared_targets<const E: usize>(
+    proof: &Proof<Target, E>,
+    claims: &P3QueryClaims<E>,
+) -> Vec<Target> {
+    let mut targets = vec![];
+    proof.commitments.clone().map(|t| targets.push(t));
+    proof.opened_values.clone().map(|t| targets.push(t));
+
+    let fri_proof = &proof.opening_proof.fri_proof;
+    for commit in &fri_proof.commit_phase_commits {
+        targets.extend(&commit.value);
+    }
+    for value in fri_proof
+        .final_poly
+        .iter()
+        .chain([&claims.zeta, &claims.alpha])
+        .chain(&claims.fri_challenges.betas)
+    {
+        targets.extend(value.value);
+    }
+
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::poseidon2::poseidon2::Poseidon2Hash;
+    use crate::p3::circuit::P3VerifierCircuit;
+    use crate::p3::serde::proof::P3ProofField;
+    use crate::p3::tests::fibonacci_fri_config;
+    use crate::p3::tests::FibonacciAir;
+
+    #[test]
+    fn test_split_plonky3_verification() {
+        let proof_str = include_str!("../../artifacts/proof_fibonacci.json");
+        let proof = serde_json::from_str::<P3ProofField>(proof_str).unwrap();
+
+        let fri_config = fibonacci_fri_config();
+        let config = P3Config::new(&FibonacciAir {}, fri_config, proof.degree_bits);
+
+        // 100 queries in 3 shards of 34, 33 and 33 queries.
+        let split = P3SplitVerifierCircuit::<_>::new::<Poseidon2Hash>(
+            FibonacciAir {},
+            config.clone(),
+            3,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+        assert_eq!(split.shards, vec![0..34, 34..67, 67..100]);
+        assert_eq!(split.shard_circuits.len(), 2);
+
+        let split_proof = split.prove(&proof, &[]).unwrap();
+
+        // The split verifier attests to what the single circuit does.
+        let circuit = P3VerifierCircuit::<C, D, _>::new::<Poseidon2Hash>(
+            FibonacciAir {},
+            config,
+            CircuitConfig::standard_recursion_config(),
+        )
+        .unwrap();
+        let wrapped_proof = circuit.prove(&proof, &[]).unwrap();
+        assert_eq!(split_proof.public_inputs, wrapped_proof.public_inputs);
+        split.verify(split_proof).unwrap();
+
+        let header_proof = split.prove_header(&proof, &[]).unwrap();
+        let shard_proof = split.prove_shard(0, &proof, &header_proof).unwrap();
+        assert!(split.aggregate(&header_proof, &[shard_proof]).is_err());
+    }
+}
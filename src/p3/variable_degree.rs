@@ -0,0 +1,526 @@
+use itertools::izip;
+use plonky2::field::extension::Extendable;
+use plonky2::field::types::PrimeField64;
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::common::richer_field::RicherField;
+use crate::p3::air::Air;
+use crate::p3::challenger::P3Challenger;
+use crate::p3::commit::MmcsError;
+use crate::p3::commit::P3Mmcs;
+use crate::p3::constants::EXT_DEGREE;
+use crate::p3::extension::CircuitBuilderP3ExtArithmetic;
+use crate::p3::lookup::NUM_PERMUTATION_CHALLENGES;
+use crate::p3::native::domain::two_adic_generator;
+use crate::p3::native::domain::GENERATOR;
+use crate::p3::serde::fri::FriError;
+use crate::p3::serde::proof::BinomialExtensionField;
+use crate::p3::serde::proof::Commitment;
+use crate::p3::serde::proof::P3Config;
+use crate::p3::serde::proof::Proof;
+use crate::p3::serde::proof::TwoAdicFriPcsProof;
+use crate::p3::serde::two_adic::VariableTwoAdicMultiplicativeCoset;
+use crate::p3::verifier::CircuitBuilderP3Verifier;
+use crate::p3::verifier::P3VerifierError;
+use crate::p3::CircuitBuilderP3Arithmetic;
+
+/// Matrices of one committed batch, all as tall as the trace, with the points
+/// each is opened at and the values there.
+type BatchPoints<const E: usize> = Vec<
+    Vec<(
+        BinomialExtensionField<Target, E>,
+        Vec<BinomialExtensionField<Target, E>>,
+    )>,
+>;
+
+/// Verifier of Plonky3 proofs of any `degree_bits` from the
+/// [`min_degree_bits`](P3Config::min_degree_bits) of a config up to its
+/// `log_trace_height`, with a single circuit for all of them.
+///
+/// `degree_bits` is a target, and whatever depends on it is selected among
+/// its possible values: the generators of the trace and quotient domains, the
+/// depth of every Merkle path, and the number of commit phase rounds, after
+/// the last of which the transcript goes on. Commit phase rounds fold pairs,
+/// and preprocessed columns aren't supported on this path.
+pub trait CircuitBuilderP3VariableDegreeVerifier<
+    F: RicherField + Extendable<D>,
+    const D: usize,
+    const E: usize = EXT_DEGREE,
+>: CircuitBuilderP3Verifier<F, D, E>
+{
+    fn __p3_verify_variable_degree_proof__<C: P3Challenger<F, D>>(
+        &mut self,
+        air: &impl Air,
+        proof: Proof<Target, E>,
+        degree_bits: Target,
+        public_values: &[Target],
+        config: &P3Config,
+        challenger: &mut C,
+    ) -> Result<(), P3VerifierError>;
+
+    /// Flags of the possible values of `degree_bits`, from `min_degree_bits`
+    /// to `max_degree_bits`, exactly one of which holds.
+    fn p3_degree_bits_flags(
+        &mut self,
+        degree_bits: Target,
+        min_degree_bits: usize,
+        max_degree_bits: usize,
+    ) -> Vec<BoolTarget>;
+
+    fn p3_verify_variable_degree_opening_proof<C: P3Challenger<F, D>>(
+        &mut self,
+        config: &P3Config,
+        is_degree_bits: &[BoolTarget],
+        commits_and_points: Vec<(Commitment<Target>, BatchPoints<E>)>,
+        proof: TwoAdicFriPcsProof<Target, E>,
+        challenger: &mut C,
+    ) -> Result<(), P3VerifierError>;
+}
+
+/// Whether the `degree_bits` flagged by `is_degree_bits` is at least `bits`.
+fn is_degree_bits_at_least<F: RicherField + Extendable<D>, const D: usize>(
+    cb: &mut CircuitBuilder<F, D>,
+    min_degree_bits: usize,
+    is_degree_bits: &[BoolTarget],
+    bits: usize,
+) -> BoolTarget {
+    let zero = cb.zero();
+    let is_at_least = is_degree_bits
+        .iter()
+        .skip(bits.saturating_sub(min_degree_bits))
+        .fold(zero, |acc, is_degree_bits| {
+            cb.add(acc, is_degree_bits.target)
+        });
+    // At most one flag holds.
+    BoolTarget::new_unsafe(is_at_least)
+}
+
+impl<F: RicherField + Extendable<D>, const D: usize, const E: usize>
+    CircuitBuilderP3VariableDegreeVerifier<F, D, E> for CircuitBuilder<F, D>
+where
+    Self: CircuitBuilderP3Verifier<F, D, E>,
+{
+    fn __p3_verify_variable_degree_proof__<C: P3Challenger<F, D>>(
+        &mut self,
+        air: &impl Air,
+        proof: Proof<Target, E>,
+        degree_bits: Target,
+        public_values: &[Target],
+        config: &P3Config,
+        challenger: &mut C,
+    ) -> Result<(), P3VerifierError> {
+        let Proof {
+            commitments,
+            opened_values,
+            opening_proof,
+            degree_bits: _,
+        } = proof;
+
+        let min_degree_bits = config
+            .min_degree_bits
+            .ok_or(P3VerifierError::InvalidProofShape(
+                "config doesn't have a minimum degree",
+            ))?;
+        let max_degree_bits = config.log_trace_height;
+        let fri_config = &config.fri_config;
+
+        if fri_config.max_log_arity != 1 {
+            return Err(FriError::InvalidFoldingArity.into());
+        }
+        if min_degree_bits > max_degree_bits {
+            return Err(P3VerifierError::InvalidProofShape(
+                "minimum degree exceeds the trace height",
+            ));
+        }
+        // Proofs without commit phase rounds would leave the transcript in
+        // another state than the others.
+        if min_degree_bits <= fri_config.log_final_poly_len.unwrap_or(0) {
+            return Err(P3VerifierError::InvalidProofShape(
+                "minimum degree doesn't leave a commit phase round",
+            ));
+        }
+        config.check_two_adicity()?;
+        if config.preprocessed_width > 0 {
+            return Err(P3VerifierError::InvalidProofShape(
+                "variable-degree proofs don't support preprocessed columns",
+            ));
+        }
+        if public_values.len() != config.num_public_values {
+            return Err(P3VerifierError::PublicValuesMismatch {
+                expected: config.num_public_values,
+                actual: public_values.len(),
+            });
+        }
+        opened_values.check_shape(config)?;
+
+        let is_degree_bits =
+            self.p3_degree_bits_flags(degree_bits, min_degree_bits, max_degree_bits);
+        let trace_domain = VariableTwoAdicMultiplicativeCoset::natural_domain(
+            min_degree_bits,
+            is_degree_bits.clone(),
+            self,
+        );
+        let quotient_domain = trace_domain.create_disjoint_domain(config.log_quotient_degree, self);
+        let quotient_chunks_domains =
+            quotient_domain.split_domains(1 << config.log_quotient_degree, self);
+
+        challenger.observe_digest(self, &commitments.trace.value);
+        challenger.observe(self, public_values.iter().copied());
+        let permutation_challenges = match &commitments.permutation {
+            Some(commit) => {
+                let challenges = (0..NUM_PERMUTATION_CHALLENGES)
+                    .map(|_| challenger.sample_ext::<E>(self))
+                    .collect();
+                challenger.observe_digest(self, &commit.value);
+                if let Some(cumulative_sum) = &opened_values.cumulative_sum {
+                    challenger.observe(self, cumulative_sum.value);
+                }
+                challenges
+            }
+            None => vec![],
+        };
+        let alpha = challenger.sample_ext::<E>(self);
+        challenger.observe_digest(self, &commitments.quotient_chunks.value);
+
+        let zeta = challenger.sample_ext::<E>(self);
+        let zeta_next = trace_domain.next_point(zeta.clone(), self);
+
+        let mut commits_and_points = vec![
+            (
+                commitments.trace.clone(),
+                vec![vec![
+                    (zeta.clone(), opened_values.trace_local.clone()),
+                    (zeta_next.clone(), opened_values.trace_next.clone()),
+                ]],
+            ),
+            (
+                commitments.quotient_chunks.clone(),
+                opened_values
+                    .quotient_chunks
+                    .iter()
+                    .map(|values| vec![(zeta.clone(), values.clone())])
+                    .collect(),
+            ),
+        ];
+        if let Some(commit) = commitments.permutation {
+            commits_and_points.push((
+                commit,
+                vec![vec![
+                    (zeta.clone(), opened_values.permutation_local.clone()),
+                    (zeta_next, opened_values.permutation_next.clone()),
+                ]],
+            ));
+        }
+
+        // A single table has nothing to interact with, so its interactions
+        // must balance on their own.
+        if let Some(cumulative_sum) = &opened_values.cumulative_sum {
+            let zero = self.p3_ext_zero();
+            self.connect_p3_ext(cumulative_sum, &zero);
+        }
+
+        self.p3_verify_variable_degree_opening_proof::<C>(
+            config,
+            &is_degree_bits,
+            commits_and_points,
+            opening_proof,
+            challenger,
+        )?;
+
+        let mut zps = Vec::with_capacity(quotient_chunks_domains.len());
+        for (i, domain) in quotient_chunks_domains.iter().enumerate() {
+            let mut zp: BinomialExtensionField<Target, E> = self.p3_ext_one();
+            for (j, other_domain) in quotient_chunks_domains.iter().enumerate() {
+                if j == i {
+                    continue;
+                }
+                let other_domain_zeta = other_domain.zp_at_point(zeta.clone(), self);
+                let other_domain_first_point =
+                    other_domain.zp_at_single_point(domain.first_point(), self);
+                let other_domain_first_point_inv = self.inverse(other_domain_first_point);
+                let other_domain_zeta_normalized =
+                    self.p3_ext_mul_single(&other_domain_zeta, other_domain_first_point_inv);
+                zp = self.p3_ext_mul(&zp, &other_domain_zeta_normalized);
+            }
+            zps.push(zp);
+        }
+
+        let sels = trace_domain.selectors_at_point(zeta, self);
+
+        let condition = self._true();
+        self.p3_verify_constraints_with_selectors(
+            air,
+            opened_values,
+            public_values,
+            &permutation_challenges,
+            sels,
+            &zps,
+            alpha,
+            condition,
+        )
+    }
+
+    fn p3_degree_bits_flags(
+        &mut self,
+        degree_bits: Target,
+        min_degree_bits: usize,
+        max_degree_bits: usize,
+    ) -> Vec<BoolTarget> {
+        let is_degree_bits: Vec<BoolTarget> = (min_degree_bits..=max_degree_bits)
+            .map(|bits| {
+                let bits = self.constant(F::from_canonical_usize(bits));
+                self.is_equal(degree_bits, bits)
+            })
+            .collect();
+
+        // Exactly one flag holding range checks `degree_bits`.
+        let zero = self.zero();
+        let num_flags = is_degree_bits.iter().fold(zero, |acc, is_degree_bits| {
+            self.add(acc, is_degree_bits.target)
+        });
+        let one = self.one();
+        self.connect(num_flags, one);
+
+        is_degree_bits
+    }
+
+    fn p3_verify_variable_degree_opening_proof<C: P3Challenger<F, D>>(
+        &mut self,
+        config: &P3Config,
+        is_degree_bits: &[BoolTarget],
+        commits_and_points: Vec<(Commitment<Target>, BatchPoints<E>)>,
+        proof: TwoAdicFriPcsProof<Target, E>,
+        challenger: &mut C,
+    ) -> Result<(), P3VerifierError> {
+        let fri_config = &config.fri_config;
+        let fri_proof = &proof.fri_proof;
+        let min_degree_bits = config.min_degree_bits.unwrap_or(config.log_trace_height);
+        let log_blowup = fri_config.log_blowup;
+        let log_final_poly_len = fri_config.log_final_poly_len.unwrap_or(0);
+        // Rounds of the tallest proofs, of which a proof of `degree_bits` has
+        // the first `degree_bits - log_final_poly_len`.
+        let log_arities = fri_config.log_arities(&[config.log_trace_height])?;
+        let log_max_height = fri_config.log_max_height(&log_arities);
+
+        let alpha = challenger.sample_ext::<E>(self);
+
+        if fri_proof.commit_phase_commits.len() != log_arities.len() {
+            return Err(FriError::InvalidProofShape.into());
+        }
+
+        // The transcript goes on from its state after the last round of the
+        // proof.
+        let mut betas = Vec::with_capacity(log_arities.len());
+        let mut last_round_challenger: Option<C> = None;
+        for (round, commit) in fri_proof.commit_phase_commits.iter().enumerate() {
+            challenger.observe_digest(self, &commit.value);
+            betas.push(challenger.sample_ext::<E>(self));
+
+            let degree_bits = round + 1 + log_final_poly_len;
+            if degree_bits >= min_degree_bits {
+                let is_last_round = is_degree_bits[degree_bits - min_degree_bits];
+                last_round_challenger = Some(match last_round_challenger {
+                    Some(other) => challenger.select(self, is_last_round, &other),
+                    None => challenger.clone(),
+                });
+            }
+        }
+        *challenger = last_round_challenger.ok_or(FriError::InvalidProofShape)?;
+
+        if fri_proof.final_poly.len() != fri_config.final_poly_len() {
+            return Err(FriError::InvalidProofShape.into());
+        }
+        if fri_config.log_final_poly_len.is_some() {
+            for coeff in &fri_proof.final_poly {
+                challenger.observe(self, coeff.value.iter().copied());
+            }
+        }
+
+        if fri_proof.query_proofs.len() != fri_config.num_queries {
+            return Err(P3VerifierError::QueryCountMismatch {
+                expected: fri_config.num_queries,
+                actual: fri_proof.query_proofs.len(),
+            });
+        }
+        if proof.query_openings.len() != fri_config.num_queries {
+            return Err(P3VerifierError::QueryCountMismatch {
+                expected: fri_config.num_queries,
+                actual: proof.query_openings.len(),
+            });
+        }
+
+        let condition = self._true();
+        challenger.check_witness(
+            self,
+            fri_config.proof_of_work_bits,
+            fri_proof.pow_witness,
+            condition,
+        );
+
+        // Plonky3 samples as many bits as the LDE of the proof is tall, which
+        // are the low bits of the same sample.
+        let query_indices: Vec<Target> = (0..fri_config.num_queries)
+            .map(|_| challenger.sample_bits(self, log_max_height))
+            .collect();
+
+        let mmcs = challenger.mmcs();
+        let one = self.one();
+
+        for (&index, query_proof, query_opening) in izip!(
+            &query_indices,
+            &fri_proof.query_proofs,
+            &proof.query_openings
+        ) {
+            let index_bits: Vec<BoolTarget> = self
+                .split_le(index, log_max_height)
+                .into_iter()
+                .enumerate()
+                .map(|(i, bit)| {
+                    let is_index_bit = is_degree_bits_at_least(
+                        self,
+                        min_degree_bits,
+                        is_degree_bits,
+                        (i + 1).saturating_sub(log_blowup),
+                    );
+                    self.and(bit, is_index_bit)
+                })
+                .collect();
+
+            // `w^rev(index)` for `w` of order the height of the LDE, to which
+            // bit `i` of the index contributes the root of unity of order
+            // `2^(i + 1)`, whatever the height.
+            let mut x = one;
+            for (i, &bit) in index_bits.iter().enumerate() {
+                let root_minus_one =
+                    F::from_canonical_u64(two_adic_generator(i + 1).to_canonical_u64()) - F::ONE;
+                let factor = self.mul_const_add(root_minus_one, bit.target, one);
+                x = self.mul(x, factor);
+            }
+
+            // Every matrix is as tall as the LDE of the trace, so their
+            // openings reduce to a single evaluation.
+            let generator = self.p3_constant(GENERATOR);
+            let lde_x = self.mul(generator, x);
+            let mut reduced_opening: BinomialExtensionField<Target, E> = self.p3_ext_zero();
+            let mut alpha_pow: BinomialExtensionField<Target, E> = self.p3_ext_one();
+
+            if query_opening.len() != commits_and_points.len() {
+                return Err(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize));
+            }
+
+            for (batch_opening, (batch_commit, mats)) in izip!(query_opening, &commits_and_points) {
+                if batch_opening.opened_values.len() != mats.len() {
+                    return Err(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize));
+                }
+
+                mmcs.verify_batch_at_depth(
+                    &batch_commit.value,
+                    &index_bits,
+                    min_degree_bits + log_blowup,
+                    is_degree_bits,
+                    &batch_opening.opened_values,
+                    &batch_opening.opening_proof,
+                    self,
+                )
+                .map_err(P3VerifierError::BatchMmcs)?;
+
+                for (mat_opening, mat_points_and_values) in
+                    izip!(&batch_opening.opened_values, mats)
+                {
+                    for (z, ps_at_z) in mat_points_and_values {
+                        if mat_opening.len() != ps_at_z.len() {
+                            return Err(P3VerifierError::BatchMmcs(MmcsError::WrongWidth));
+                        }
+                        for (&p_at_x, p_at_z) in izip!(mat_opening, ps_at_z) {
+                            let p_at_z_neg = self.p3_ext_neg(p_at_z.clone());
+                            let z_neg = self.p3_ext_neg(z.clone());
+                            let p_at_z_plus_p_at_x = self.p3_ext_add_single(p_at_z_neg, p_at_x);
+                            let z_plus_x = self.p3_ext_add_single(z_neg, lde_x);
+
+                            let quotient = self.p3_ext_div(p_at_z_plus_p_at_x, z_plus_x);
+
+                            let alpha_pow_mul_quotient = self.p3_ext_mul(&alpha_pow, &quotient);
+                            reduced_opening =
+                                self.p3_ext_add(reduced_opening, alpha_pow_mul_quotient);
+                            alpha_pow = self.p3_ext_mul(&alpha_pow, &alpha);
+                        }
+                    }
+                }
+            }
+
+            let mut folded_eval = reduced_opening;
+            for (round, (commit, step, beta)) in izip!(
+                &fri_proof.commit_phase_commits,
+                &query_proof.commit_phase_openings,
+                &betas
+            )
+            .enumerate()
+            {
+                if step.sibling_values.len() != 1 {
+                    return Err(FriError::InvalidProofShape.into());
+                }
+
+                // Proofs of fewer degree bits are done folding and left as
+                // they are.
+                let first_degree_bits = (round + 1 + log_final_poly_len).max(min_degree_bits);
+                let is_round = is_degree_bits_at_least(
+                    self,
+                    min_degree_bits,
+                    is_degree_bits,
+                    first_degree_bits,
+                );
+
+                let is_odd = index_bits[round];
+                let sibling = step.sibling_values[0].clone();
+                let evals = vec![
+                    self.p3_ext_if(is_odd, sibling.clone(), folded_eval.clone()),
+                    self.p3_ext_if(is_odd, folded_eval.clone(), sibling),
+                ];
+
+                // The folded codeword of a proof of `degree_bits` is committed
+                // in a tree of `degree_bits + log_blowup - round - 1` levels.
+                mmcs.verify_batch_at_depth(
+                    &commit.value,
+                    &index_bits[round + 1..],
+                    first_degree_bits + log_blowup - round - 1,
+                    &is_degree_bits[first_degree_bits - min_degree_bits..],
+                    &[evals
+                        .iter()
+                        .flat_map(|eval| eval.value.iter().copied())
+                        .collect::<Vec<_>>()],
+                    &step.opening_proof,
+                    self,
+                )
+                .map_err(P3VerifierError::CommitPhaseMmcs)?;
+
+                // The pair starts at `x` or at `-x`.
+                let x_neg = self.neg(x);
+                let x0 = self._if(is_odd, x_neg, x);
+                let folded = self.p3_fold_row(x0, 1, beta, &evals);
+                folded_eval = self.p3_ext_if(is_round, folded, folded_eval);
+
+                let x_squared = self.square(x);
+                x = self._if(is_round, x_squared, x);
+            }
+
+            // Horner's rule, from the leading coefficient down.
+            let (leading_coeff, coeffs) = fri_proof
+                .final_poly
+                .split_last()
+                .ok_or(FriError::InvalidProofShape)?;
+            let final_poly_eval = coeffs
+                .iter()
+                .rev()
+                .fold(leading_coeff.clone(), |acc, coeff| {
+                    let acc_mul_x = self.p3_ext_mul_single(&acc, x);
+                    self.p3_ext_add(acc_mul_x, coeff.clone())
+                });
+
+            self.connect_p3_ext(&folded_eval, &final_poly_eval);
+        }
+
+        Ok(())
+    }
+}
@@ -1,200 +1,795 @@
 use itertools::izip;
 use plonky2::field::extension::Extendable;
+use plonky2::field::types::Field;
+use plonky2::field::types::PrimeField64;
 use plonky2::iop::target::BoolTarget;
 use plonky2::iop::target::Target;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::config::AlgebraicHasher;
 
 use crate::common::richer_field::RicherField;
 use crate::p3::air::Air;
+use crate::p3::air::AirLike;
 use crate::p3::air::VerifierConstraintFolder;
-use crate::p3::challenger::DuplexChallenger;
-use crate::p3::challenger::DuplexChallengerTarget;
-use crate::p3::commit;
+use crate::p3::challenger::P3Challenger;
+use crate::p3::commit::MmcsError;
+use crate::p3::commit::P3Mmcs;
 use crate::p3::constants::EXT_DEGREE;
 use crate::p3::extension::CircuitBuilderP3ExtArithmetic;
+use crate::p3::lookup::CircuitBuilderP3Lookup;
+use crate::p3::lookup::NUM_PERMUTATION_CHALLENGES;
+use crate::p3::native::domain::two_adic_generator as two_adic_generator_value;
+use crate::p3::native::domain::GENERATOR;
+use crate::p3::native::fri::fold_coset;
 use crate::p3::serde::fri::FriChallenges;
 use crate::p3::serde::fri::FriConfig;
 use crate::p3::serde::fri::FriError;
+use crate::p3::serde::multi_proof::MultiProof;
+use crate::p3::serde::multi_proof::P3MultiConfig;
 use crate::p3::serde::proof::BinomialExtensionField;
 use crate::p3::serde::proof::Commitment;
+use crate::p3::serde::proof::Commitments;
 use crate::p3::serde::proof::FriProof;
+use crate::p3::serde::proof::OpenedValues;
 use crate::p3::serde::proof::P3Config;
-use crate::p3::serde::proof::P3Proof;
+use crate::p3::serde::proof::Proof;
 use crate::p3::serde::proof::QueryProof;
 use crate::p3::serde::proof::TwoAdicFriPcsProof;
+use crate::p3::serde::two_adic::TwoAdicDomains;
 use crate::p3::serde::two_adic::TwoAdicMultiplicativeCoset;
 use crate::p3::serde::Dimensions;
+use crate::p3::serde::LagrangeSelectors;
 use crate::p3::utils::log2_strict_usize;
 use crate::p3::CircuitBuilderP3Arithmetic;
 
-pub trait CircuitBuilderP3Verifier<F: RicherField + Extendable<D>, const D: usize>:
-    CircuitBuilderP3ExtArithmetic<F, D>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum P3VerifierError {
+    /// The opened values don't match the AIR width or the quotient degree.
+    InvalidProofShape(&'static str),
+    /// The FRI proof doesn't have one commit phase round per folding round of
+    /// `FriConfig::log_arities`.
+    Fri(FriError),
+    /// A commit phase opening doesn't fit its Merkle batch.
+    CommitPhaseMmcs(MmcsError),
+    /// A trace or quotient opening doesn't fit its Merkle batch.
+    BatchMmcs(MmcsError),
+    /// The number of query proofs or query openings differs from
+    /// `FriConfig::num_queries`.
+    QueryCountMismatch { expected: usize, actual: usize },
+    /// The number of public values differs from `P3Config::num_public_values`.
+    PublicValuesMismatch { expected: usize, actual: usize },
+    /// The hashing config can't be instantiated with the challenger or MMCS.
+    InvalidHashConfig(&'static str),
+}
+
+impl core::fmt::Display for P3VerifierError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidProofShape(reason) => write!(f, "invalid proof shape: {reason}"),
+            Self::Fri(err) => write!(f, "invalid FRI proof: {err:?}"),
+            Self::CommitPhaseMmcs(err) => write!(f, "invalid commit phase opening: {err:?}"),
+            Self::BatchMmcs(err) => write!(f, "invalid batch opening: {err:?}"),
+            Self::QueryCountMismatch { expected, actual } => {
+                write!(f, "expected {expected} queries, got {actual}")
+            }
+            Self::PublicValuesMismatch { expected, actual } => {
+                write!(f, "expected {expected} public values, got {actual}")
+            }
+            Self::InvalidHashConfig(reason) => write!(f, "invalid hash config: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for P3VerifierError {}
+
+impl From<FriError> for P3VerifierError {
+    fn from(err: FriError) -> Self {
+        Self::Fri(err)
+    }
+}
+
+/// The committed matrices of a proof, each batch with the domain of each of
+/// its matrices and the points they're opened at, along with the claimed
+/// evaluations there.
+pub type CommitsAndPoints<const E: usize> = Vec<(
+    Commitment<Target>,
+    Vec<(
+        TwoAdicMultiplicativeCoset,
+        Vec<(
+            BinomialExtensionField<Target, E>,
+            Vec<BinomialExtensionField<Target, E>>,
+        )>,
+    )>,
+)>;
+
+/// What the queries of a proof are checked against once its transcript has
+/// been run, see
+/// [`__p3_verify_proof_header__`](CircuitBuilderP3Verifier::__p3_verify_proof_header__).
+pub struct P3QueryClaims<const E: usize = EXT_DEGREE> {
+    /// The out-of-domain point the trace is opened at.
+    pub zeta: BinomialExtensionField<Target, E>,
+    /// The challenge batching the opened matrices into one FRI input.
+    pub alpha: BinomialExtensionField<Target, E>,
+    pub fri_challenges: FriChallenges<Target, E>,
+    pub commits_and_points: CommitsAndPoints<E>,
+}
+
+fn log_trace_heights<const E: usize>(commits_and_points: &CommitsAndPoints<E>) -> Vec<usize> {
+    commits_and_points
+        .iter()
+        .flat_map(|(_, mats)| {
+            mats.iter()
+                .map(|(domain, _)| log2_strict_usize(domain.size()))
+        })
+        .collect()
+}
+
+/// Verifier of Plonky3 proofs whose challenges live in the degree `E`
+/// extension of the base field.
+pub trait CircuitBuilderP3Verifier<
+    F: RicherField + Extendable<D>,
+    const D: usize,
+    const E: usize = EXT_DEGREE,
+>: CircuitBuilderP3ExtArithmetic<F, D, E>
 {
-    fn __p3_verify_proof__<H: AlgebraicHasher<F>>(
+    fn __p3_verify_proof__<C: P3Challenger<F, D>>(
         &mut self,
         air: &impl Air,
-        proof: P3Proof,
+        proof: Proof<Target, E>,
+        public_values: &[Target],
         config: &P3Config,
-        challenger: &mut DuplexChallengerTarget,
-    );
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError>;
 
-    fn p3_verify_shape_and_sample_challenges<H: AlgebraicHasher<F>>(
+    /// Same as `__p3_verify_proof__` given the domains of the proof, which
+    /// proofs of the same degree may share.
+    fn __p3_verify_proof_with_domains__<C: P3Challenger<F, D>>(
         &mut self,
-        config: &FriConfig,
-        proof: &FriProof<Target>,
-        challenger: &mut DuplexChallengerTarget,
-    ) -> Result<FriChallenges<Target>, FriError>;
+        air: &impl Air,
+        proof: Proof<Target, E>,
+        public_values: &[Target],
+        config: &P3Config,
+        domains: &TwoAdicDomains,
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError>;
+
+    /// Same as `__p3_verify_proof_with_domains__` up to the queries: runs the
+    /// transcript and checks the quotient identity, and returns what the
+    /// queries are to be checked against with
+    /// [`p3_verify_queries`](Self::p3_verify_queries).
+    fn __p3_verify_proof_header__<C: P3Challenger<F, D>>(
+        &mut self,
+        air: &impl Air,
+        proof: &Proof<Target, E>,
+        public_values: &[Target],
+        config: &P3Config,
+        domains: &TwoAdicDomains,
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<P3QueryClaims<E>, P3VerifierError>;
+
+    /// The commitment to the preprocessed trace of `config`, if it has one.
+    fn p3_preprocessed_commit(
+        &mut self,
+        config: &P3Config,
+    ) -> Result<Option<Commitment<Target>>, P3VerifierError>;
 
-    fn p3_verify_opening_proof<H: AlgebraicHasher<F>>(
+    /// The matrices of a single-table proof with the points they're opened
+    /// at, `zeta` and its successor in the trace domain.
+    fn p3_commits_and_points(
+        &mut self,
+        commitments: &Commitments<Target>,
+        preprocessed_commit: Option<Commitment<Target>>,
+        opened_values: &OpenedValues<Target, E>,
+        domains: &TwoAdicDomains,
+        zeta: BinomialExtensionField<Target, E>,
+    ) -> CommitsAndPoints<E>;
+
+    fn __p3_verify_multi_proof__<C: P3Challenger<F, D>>(
+        &mut self,
+        airs: &[&dyn AirLike<F, D, E>],
+        proof: MultiProof<Target, E>,
+        public_values: &[Vec<Target>],
+        config: &P3MultiConfig,
+        challenger: &mut C,
+    ) -> Result<(), P3VerifierError>;
+
+    /// Checks the quotient identity of one table at `zeta` where `condition`
+    /// holds.
+    fn p3_verify_constraints<A: AirLike<F, D, E> + ?Sized>(
+        &mut self,
+        air: &A,
+        opened_values: OpenedValues<Target, E>,
+        public_values: &[Target],
+        permutation_challenges: &[BinomialExtensionField<Target, E>],
+        trace_domain: TwoAdicMultiplicativeCoset,
+        quotient_chunks_domains: &[TwoAdicMultiplicativeCoset],
+        alpha: BinomialExtensionField<Target, E>,
+        zeta: BinomialExtensionField<Target, E>,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError>;
+
+    /// Same as [`p3_verify_constraints`](Self::p3_verify_constraints) given
+    /// the Lagrange selectors of the trace domain at `zeta`, and for each
+    /// quotient chunk the vanishing polynomial of the other chunks at `zeta`,
+    /// normalized to one at the first point of the chunk.
+    fn p3_verify_constraints_with_selectors<A: AirLike<F, D, E> + ?Sized>(
+        &mut self,
+        air: &A,
+        opened_values: OpenedValues<Target, E>,
+        public_values: &[Target],
+        permutation_challenges: &[BinomialExtensionField<Target, E>],
+        sels: LagrangeSelectors<BinomialExtensionField<Target, E>>,
+        zps: &[BinomialExtensionField<Target, E>],
+        alpha: BinomialExtensionField<Target, E>,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError>;
+
+    fn p3_verify_shape_and_sample_challenges<C: P3Challenger<F, D>>(
         &mut self,
         config: &FriConfig,
-        commits_and_points: Vec<(
-            Commitment<Target>,
-            Vec<(
-                TwoAdicMultiplicativeCoset,
-                Vec<(
-                    BinomialExtensionField<Target>,
-                    Vec<BinomialExtensionField<Target>>,
-                )>,
-            )>,
-        )>,
-        proof: TwoAdicFriPcsProof<Target>,
-        challenger: &mut DuplexChallengerTarget,
-    ) -> Result<(), ()>;
+        proof: &FriProof<Target, E>,
+        log_arities: &[usize],
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<FriChallenges<Target, E>, P3VerifierError>;
 
-    fn p3_verify_batch<H: AlgebraicHasher<F>>(
+    fn p3_verify_opening_proof<C: P3Challenger<F, D>>(
+        &mut self,
+        config: &FriConfig,
+        commits_and_points: CommitsAndPoints<E>,
+        proof: TwoAdicFriPcsProof<Target, E>,
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError>;
+
+    /// Samples the challenge batching the opened matrices into one FRI input,
+    /// then checks the shape of `proof` and samples the FRI challenges.
+    fn p3_sample_opening_challenges<C: P3Challenger<F, D>>(
+        &mut self,
+        config: &FriConfig,
+        commits_and_points: &CommitsAndPoints<E>,
+        proof: &FriProof<Target, E>,
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<(BinomialExtensionField<Target, E>, FriChallenges<Target, E>), P3VerifierError>;
+
+    /// Checks the queries of `proof` at `challenges.query_indices`, one per
+    /// query proof and query opening, which may be any subset of the queries
+    /// of the proof.
+    fn p3_verify_queries<M: P3Mmcs<F>>(
         &mut self,
+        mmcs: &M,
+        config: &FriConfig,
+        commits_and_points: &CommitsAndPoints<E>,
+        proof: &TwoAdicFriPcsProof<Target, E>,
+        alpha: &BinomialExtensionField<Target, E>,
+        challenges: &FriChallenges<Target, E>,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError>;
+
+    fn p3_verify_batch<M: P3Mmcs<F>>(
+        &mut self,
+        mmcs: &M,
         commit: &Vec<Target>,
         dimensions: &[Dimensions],
         index: Target,
         opened_values: &Vec<Vec<Target>>,
         proof: &Vec<Vec<Target>>,
-    ) -> Result<(), ()>;
+        condition: BoolTarget,
+    ) -> Result<(), MmcsError>;
 
-    fn p3_verify_challenges<H: AlgebraicHasher<F>>(
+    fn p3_verify_challenges<M: P3Mmcs<F>>(
         &mut self,
+        mmcs: &M,
         config: &FriConfig,
-        proof: &FriProof<Target>,
-        challenges: &FriChallenges<Target>,
-        reduced_openings: &[[BinomialExtensionField<Target>; 32]],
-    ) -> Result<(), ()>;
-
-    fn p3_verify_query<H: AlgebraicHasher<F>>(
+        proof: &FriProof<Target, E>,
+        log_arities: &[usize],
+        challenges: &FriChallenges<Target, E>,
+        reduced_openings: &[[BinomialExtensionField<Target, E>; 32]],
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError>;
+
+    /// Folds the openings of one query down to the final polynomial, and
+    /// returns the folded evaluation with the point it's taken at. The
+    /// commit phase openings are only checked where `condition` holds.
+    fn p3_verify_query<M: P3Mmcs<F>>(
         &mut self,
+        mmcs: &M,
         _config: &FriConfig,
         commit_phase_commits: &Vec<Commitment<Target>>,
+        log_arities: &[usize],
         index: Target,
-        proof: &QueryProof<Target>,
-        betas: &[BinomialExtensionField<Target>],
-        reduced_openings: &[BinomialExtensionField<Target>; 32],
+        proof: &QueryProof<Target, E>,
+        betas: &[BinomialExtensionField<Target, E>],
+        reduced_openings: &[BinomialExtensionField<Target, E>; 32],
         log_max_height: usize,
-    ) -> Result<BinomialExtensionField<Target>, ()>;
+        condition: BoolTarget,
+    ) -> Result<(BinomialExtensionField<Target, E>, Target), P3VerifierError>;
+
+    /// Evaluates at `beta` the polynomial of degree less than `evals.len()`
+    /// that takes the values `evals` on the coset of a commit phase row whose
+    /// first point is `x0`.
+    fn p3_fold_row(
+        &mut self,
+        x0: Target,
+        log_arity: usize,
+        beta: &BinomialExtensionField<Target, E>,
+        evals: &[BinomialExtensionField<Target, E>],
+    ) -> BinomialExtensionField<Target, E>;
 }
 
-impl<F: RicherField + Extendable<D>, const D: usize> CircuitBuilderP3Verifier<F, D>
-    for CircuitBuilder<F, D>
+impl<F: RicherField + Extendable<D>, const D: usize, const E: usize>
+    CircuitBuilderP3Verifier<F, D, E> for CircuitBuilder<F, D>
 where
-    Self: CircuitBuilderP3ExtArithmetic<F, D>,
+    Self: CircuitBuilderP3ExtArithmetic<F, D, E>,
 {
-    fn __p3_verify_proof__<H: AlgebraicHasher<F>>(
+    fn __p3_verify_proof__<C: P3Challenger<F, D>>(
+        &mut self,
+        air: &impl Air,
+        proof: Proof<Target, E>,
+        public_values: &[Target],
+        config: &P3Config,
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError> {
+        if config.min_degree_bits.is_some() {
+            return Err(P3VerifierError::InvalidProofShape(
+                "variable-degree configs are verified with p3_verify_variable_degree_proof",
+            ));
+        }
+
+        let domains = TwoAdicDomains::new(
+            config.log_trace_height,
+            proof.degree_bits,
+            config.log_quotient_degree,
+            self,
+        );
+
+        self.__p3_verify_proof_with_domains__(
+            air,
+            proof,
+            public_values,
+            config,
+            &domains,
+            challenger,
+            condition,
+        )
+    }
+
+    fn __p3_verify_proof_with_domains__<C: P3Challenger<F, D>>(
         &mut self,
         air: &impl Air,
-        proof: P3Proof,
+        proof: Proof<Target, E>,
+        public_values: &[Target],
         config: &P3Config,
-        challenger: &mut DuplexChallengerTarget,
-    ) {
-        let P3Proof {
+        domains: &TwoAdicDomains,
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError> {
+        let claims = self.__p3_verify_proof_header__(
+            air,
+            &proof,
+            public_values,
+            config,
+            domains,
+            challenger,
+            condition,
+        )?;
+
+        self.p3_verify_queries(
+            &challenger.mmcs(),
+            &config.fri_config,
+            &claims.commits_and_points,
+            &proof.opening_proof,
+            &claims.alpha,
+            &claims.fri_challenges,
+            condition,
+        )
+    }
+
+    fn __p3_verify_proof_header__<C: P3Challenger<F, D>>(
+        &mut self,
+        air: &impl Air,
+        proof: &Proof<Target, E>,
+        public_values: &[Target],
+        config: &P3Config,
+        domains: &TwoAdicDomains,
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<P3QueryClaims<E>, P3VerifierError> {
+        let Proof {
             commitments,
             opened_values,
             opening_proof,
             degree_bits,
         } = proof;
 
-        let degree = 1 << degree_bits;
-        let quotient_degree = 1 << config.log_quotient_degree;
+        config.check_fixed_degree()?;
+        config.check_two_adicity()?;
+        if public_values.len() != config.num_public_values {
+            return Err(P3VerifierError::PublicValuesMismatch {
+                expected: config.num_public_values,
+                actual: public_values.len(),
+            });
+        }
 
-        let trace_domain = TwoAdicMultiplicativeCoset::natural_domain_for_degree(
-            config.log_trace_height,
-            degree,
-            self,
+        opened_values.check_shape(config)?;
+        let preprocessed_commit =
+            <Self as CircuitBuilderP3Verifier<F, D, E>>::p3_preprocessed_commit(self, config)?;
+
+        if domains.trace_domain.log_n != *degree_bits
+            || domains.quotient_chunks_domains.len() != 1 << config.log_quotient_degree
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "domains don't match the degree of the proof",
+            ));
+        }
+
+        challenger.observe_digest(self, &commitments.trace.value);
+        if let Some(commit) = &preprocessed_commit {
+            challenger.observe_digest(self, &commit.value);
+        }
+        challenger.observe(self, public_values.iter().copied());
+        let permutation_challenges = match &commitments.permutation {
+            Some(commit) => {
+                let challenges = (0..NUM_PERMUTATION_CHALLENGES)
+                    .map(|_| challenger.sample_ext::<E>(self))
+                    .collect();
+                challenger.observe_digest(self, &commit.value);
+                if let Some(cumulative_sum) = &opened_values.cumulative_sum {
+                    challenger.observe(self, cumulative_sum.value);
+                }
+                challenges
+            }
+            None => vec![],
+        };
+        let alpha = challenger.sample_ext::<E>(self);
+        challenger.observe_digest(self, &commitments.quotient_chunks.value);
+
+        let zeta = challenger.sample_ext::<E>(self);
+        let commits_and_points = self.p3_commits_and_points(
+            commitments,
+            preprocessed_commit,
+            opened_values,
+            domains,
+            zeta.clone(),
         );
-        let mut quotient_domain = trace_domain
-            .create_disjoint_domain(1 << (degree_bits + config.log_quotient_degree), self);
-        let quotient_chunks_domains = quotient_domain.split_domains::<F, D>(quotient_degree, self);
 
-        let air_width = air.width();
-        let valid_shape = opened_values.trace_local.len() == air_width
-            && opened_values.trace_next.len() == air_width
-            && opened_values.quotient_chunks.len() == quotient_degree
-            && opened_values.quotient_chunks.iter().all(|qc| qc.len() == D);
-        if !valid_shape {
-            panic!("Invalid Proof Shape");
+        // A single table has nothing to interact with, so its interactions
+        // must balance on their own.
+        if let Some(cumulative_sum) = &opened_values.cumulative_sum {
+            let zero = self.p3_ext_zero();
+            self.connect_p3_ext_if(condition, cumulative_sum, &zero);
         }
 
-        self.p3_observe::<H>(challenger, commitments.trace.value.clone());
-        let alpha = self.p3_sample_ext::<H>(challenger);
-        self.p3_observe::<H>(challenger, commitments.quotient_chunks.value.clone());
+        let (fri_alpha, fri_challenges) = self.p3_sample_opening_challenges::<C>(
+            &config.fri_config,
+            &commits_and_points,
+            &opening_proof.fri_proof,
+            challenger,
+            condition,
+        )?;
+
+        let sels = domains.selectors_at_point(zeta.clone(), self);
+        let zps = domains.zps_at_point(zeta.clone(), self);
+
+        self.p3_verify_constraints_with_selectors(
+            air,
+            opened_values.clone(),
+            public_values,
+            &permutation_challenges,
+            sels,
+            &zps,
+            alpha,
+            condition,
+        )?;
+
+        Ok(P3QueryClaims {
+            zeta,
+            alpha: fri_alpha,
+            fri_challenges,
+            commits_and_points,
+        })
+    }
+
+    fn p3_preprocessed_commit(
+        &mut self,
+        config: &P3Config,
+    ) -> Result<Option<Commitment<Target>>, P3VerifierError> {
+        match &config.preprocessed_commit {
+            _ if config.preprocessed_width == 0 => Ok(None),
+            Some(commit) if commit.len() != config.hash_config.digest_elems => {
+                Err(P3VerifierError::InvalidProofShape(
+                    "preprocessed commitment doesn't match the digest size",
+                ))
+            }
+            Some(commit) => Ok(Some(Commitment {
+                value: commit
+                    .iter()
+                    .map(|v| self.p3_constant(v.to_canonical_u64()))
+                    .collect(),
+            })),
+            None => Err(P3VerifierError::InvalidProofShape(
+                "missing preprocessed commitment",
+            )),
+        }
+    }
 
-        let zeta = self.p3_sample_ext::<H>(challenger);
+    fn p3_commits_and_points(
+        &mut self,
+        commitments: &Commitments<Target>,
+        preprocessed_commit: Option<Commitment<Target>>,
+        opened_values: &OpenedValues<Target, E>,
+        domains: &TwoAdicDomains,
+        zeta: BinomialExtensionField<Target, E>,
+    ) -> CommitsAndPoints<E> {
+        let trace_domain = domains.trace_domain;
         let zeta_next = trace_domain.next_point(zeta.clone(), self);
 
-        self.p3_verify_opening_proof::<H>(
+        let mut commits_and_points = vec![
+            (
+                commitments.trace.clone(),
+                vec![(
+                    trace_domain,
+                    vec![
+                        (zeta.clone(), opened_values.trace_local.clone()),
+                        (zeta_next.clone(), opened_values.trace_next.clone()),
+                    ],
+                )],
+            ),
+            (
+                commitments.quotient_chunks.clone(),
+                domains
+                    .quotient_chunks_domains
+                    .iter()
+                    .zip(&opened_values.quotient_chunks)
+                    .map(|(domain, values)| (*domain, vec![(zeta.clone(), values.clone())]))
+                    .collect(),
+            ),
+        ];
+        if let Some(commit) = preprocessed_commit {
+            commits_and_points.push((
+                commit,
+                vec![(
+                    trace_domain,
+                    vec![
+                        (zeta.clone(), opened_values.preprocessed_local.clone()),
+                        (zeta_next.clone(), opened_values.preprocessed_next.clone()),
+                    ],
+                )],
+            ));
+        }
+        if let Some(commit) = &commitments.permutation {
+            commits_and_points.push((
+                commit.clone(),
+                vec![(
+                    trace_domain,
+                    vec![
+                        (zeta, opened_values.permutation_local.clone()),
+                        (zeta_next, opened_values.permutation_next.clone()),
+                    ],
+                )],
+            ));
+        }
+
+        commits_and_points
+    }
+
+    fn __p3_verify_multi_proof__<C: P3Challenger<F, D>>(
+        &mut self,
+        airs: &[&dyn AirLike<F, D, E>],
+        proof: MultiProof<Target, E>,
+        public_values: &[Vec<Target>],
+        config: &P3MultiConfig,
+        challenger: &mut C,
+    ) -> Result<(), P3VerifierError> {
+        let MultiProof {
+            commitments,
+            opened_values,
+            opening_proof,
+            degree_bits: _,
+        } = proof;
+        let condition = self._true();
+
+        if airs.len() != config.tables.len()
+            || opened_values.len() != config.tables.len()
+            || public_values.len() != config.tables.len()
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "number of tables doesn't match the config",
+            ));
+        }
+        for (table, opened_values, public_values) in
+            izip!(&config.tables, &opened_values, public_values)
+        {
+            if table.preprocessed_width > 0 {
+                return Err(P3VerifierError::InvalidProofShape(
+                    "multi-table proofs don't support preprocessed columns",
+                ));
+            }
+            if public_values.len() != table.num_public_values {
+                return Err(P3VerifierError::PublicValuesMismatch {
+                    expected: table.num_public_values,
+                    actual: public_values.len(),
+                });
+            }
+            opened_values.check_shape(table)?;
+        }
+
+        let trace_domains: Vec<TwoAdicMultiplicativeCoset> = config
+            .tables
+            .iter()
+            .map(|table| {
+                TwoAdicMultiplicativeCoset::natural_domain_for_degree(
+                    table.log_trace_height,
+                    1 << table.degree_bits,
+                    self,
+                )
+            })
+            .collect();
+        let quotient_chunks_domains: Vec<Vec<TwoAdicMultiplicativeCoset>> =
+            izip!(&config.tables, &trace_domains)
+                .map(|(table, trace_domain)| {
+                    let mut quotient_domain = trace_domain.create_disjoint_domain(
+                        1 << (table.degree_bits + table.log_quotient_degree),
+                        self,
+                    );
+                    quotient_domain.split_domains::<F, D>(1 << table.log_quotient_degree, self)
+                })
+                .collect();
+
+        // Same transcript as a single table, with the public values of every
+        // table observed in table order.
+        challenger.observe_digest(self, &commitments.trace.value);
+        challenger.observe(self, public_values.iter().flatten().copied());
+        let permutation_challenges = match &commitments.permutation {
+            Some(commit) => {
+                let challenges = (0..NUM_PERMUTATION_CHALLENGES)
+                    .map(|_| challenger.sample_ext::<E>(self))
+                    .collect();
+                challenger.observe_digest(self, &commit.value);
+                for cumulative_sum in opened_values.iter().flat_map(|v| &v.cumulative_sum) {
+                    challenger.observe(self, cumulative_sum.value);
+                }
+                challenges
+            }
+            None => vec![],
+        };
+        let alpha = challenger.sample_ext::<E>(self);
+        challenger.observe_digest(self, &commitments.quotient_chunks.value);
+
+        let zeta = challenger.sample_ext::<E>(self);
+
+        let mut trace_mats = vec![];
+        let mut quotient_mats = vec![];
+        let mut permutation_mats = vec![];
+        for (trace_domain, domains, values) in
+            izip!(&trace_domains, &quotient_chunks_domains, &opened_values)
+        {
+            let zeta_next = trace_domain.next_point(zeta.clone(), self);
+            trace_mats.push((
+                *trace_domain,
+                vec![
+                    (zeta.clone(), values.trace_local.clone()),
+                    (zeta_next.clone(), values.trace_next.clone()),
+                ],
+            ));
+            if values.cumulative_sum.is_some() {
+                permutation_mats.push((
+                    *trace_domain,
+                    vec![
+                        (zeta.clone(), values.permutation_local.clone()),
+                        (zeta_next, values.permutation_next.clone()),
+                    ],
+                ));
+            }
+            for (domain, chunk) in izip!(domains, &values.quotient_chunks) {
+                quotient_mats.push((*domain, vec![(zeta.clone(), chunk.clone())]));
+            }
+        }
+
+        let mut commits_and_points = vec![
+            (commitments.trace, trace_mats),
+            (commitments.quotient_chunks, quotient_mats),
+        ];
+        if let Some(commit) = commitments.permutation {
+            commits_and_points.push((commit, permutation_mats));
+        }
+
+        self.p3_verify_opening_proof::<C>(
             &config.fri_config,
-            vec![
-                (
-                    commitments.trace.clone(),
-                    vec![(
-                        trace_domain,
-                        vec![
-                            (zeta.clone(), opened_values.trace_local.clone()),
-                            (zeta_next, opened_values.trace_next.clone()),
-                        ],
-                    )],
-                ),
-                (
-                    commitments.quotient_chunks.clone(),
-                    quotient_chunks_domains
-                        .iter()
-                        .zip(&opened_values.quotient_chunks)
-                        .map(|(domain, values)| (*domain, vec![(zeta.clone(), values.clone())]))
-                        .collect(),
-                ),
-            ],
+            commits_and_points,
             opening_proof,
             challenger,
-        )
-        .unwrap();
+            condition,
+        )?;
 
-        let zps: Vec<BinomialExtensionField<Target>> = quotient_chunks_domains
+        // Every send must be matched by a receive in some table.
+        let cumulative_sums: Vec<BinomialExtensionField<Target, E>> = opened_values
             .iter()
-            .enumerate()
-            .map(|(i, domain)| {
-                quotient_chunks_domains
-                    .iter()
-                    .enumerate()
-                    .filter(|(j, _)| *j != i)
-                    .map(|(_, other_domain)| {
-                        // ((z*g/shift)^n - 1) / (g / shift)
-                        let other_domain_zeta = other_domain.zp_at_point(zeta.clone(), self);
-                        let first_point = domain.first_point();
-                        let other_domain_first_point =
-                            other_domain.zp_at_single_point(first_point, self);
-                        let other_domain_first_point_inv = self.inverse(other_domain_first_point);
-
-                        self.p3_ext_mul_single(&other_domain_zeta, other_domain_first_point_inv)
-                    })
-                    .collect::<Vec<_>>()
-                    .into_iter()
-                    .reduce(|acc, e| self.p3_ext_mul(&acc, &e))
-                    .unwrap_or({
-                        let one = self.one();
-                        BinomialExtensionField::<Target> {
-                            value: self.p3_field_to_arr(one),
-                        }
-                    })
-            })
+            .flat_map(|v| v.cumulative_sum.clone())
             .collect();
+        if let Some(total) = cumulative_sums
+            .into_iter()
+            .reduce(|acc, e| self.p3_ext_add(acc, e))
+        {
+            let zero = self.p3_ext_zero();
+            self.connect_p3_ext_if(condition, &total, &zero);
+        }
+
+        for (air, opened_values, public_values, trace_domain, quotient_chunks_domains) in izip!(
+            airs,
+            opened_values,
+            public_values,
+            trace_domains,
+            &quotient_chunks_domains
+        ) {
+            self.p3_verify_constraints(
+                *air,
+                opened_values,
+                public_values,
+                &permutation_challenges,
+                trace_domain,
+                quotient_chunks_domains,
+                alpha.clone(),
+                zeta.clone(),
+                condition,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn p3_verify_constraints<A: AirLike<F, D, E> + ?Sized>(
+        &mut self,
+        air: &A,
+        opened_values: OpenedValues<Target, E>,
+        public_values: &[Target],
+        permutation_challenges: &[BinomialExtensionField<Target, E>],
+        trace_domain: TwoAdicMultiplicativeCoset,
+        quotient_chunks_domains: &[TwoAdicMultiplicativeCoset],
+        alpha: BinomialExtensionField<Target, E>,
+        zeta: BinomialExtensionField<Target, E>,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError> {
+        let domains =
+            TwoAdicDomains::from_domains(trace_domain, quotient_chunks_domains.to_vec(), self);
+        let sels = domains.selectors_at_point(zeta.clone(), self);
+        let zps = domains.zps_at_point(zeta, self);
+
+        self.p3_verify_constraints_with_selectors(
+            air,
+            opened_values,
+            public_values,
+            permutation_challenges,
+            sels,
+            &zps,
+            alpha,
+            condition,
+        )
+    }
+
+    fn p3_verify_constraints_with_selectors<A: AirLike<F, D, E> + ?Sized>(
+        &mut self,
+        air: &A,
+        opened_values: OpenedValues<Target, E>,
+        public_values: &[Target],
+        permutation_challenges: &[BinomialExtensionField<Target, E>],
+        sels: LagrangeSelectors<BinomialExtensionField<Target, E>>,
+        zps: &[BinomialExtensionField<Target, E>],
+        alpha: BinomialExtensionField<Target, E>,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError> {
+        let air_width = air.width();
+        if opened_values.trace_local.len() != air_width
+            || opened_values.trace_next.len() != air_width
+        {
+            return Err(P3VerifierError::InvalidProofShape(
+                "trace width doesn't match the air width",
+            ));
+        }
 
         let quotient = opened_values
             .quotient_chunks
@@ -218,10 +813,11 @@ where
             .reduce(|acc, e| self.p3_ext_add(acc, e))
             .unwrap();
 
-        let sels = trace_domain.selectors_at_point(zeta, self);
-
         let mut folder = VerifierConstraintFolder {
             main: opened_values,
+            public_values: public_values.to_vec(),
+            permutation_challenges: permutation_challenges.to_vec(),
+            interactions: vec![],
             is_first_row: sels.is_first_row,
             is_last_row: sels.is_last_row,
             is_transition: sels.is_transition,
@@ -230,51 +826,114 @@ where
         };
 
         air.eval(&mut folder, self);
+        self.p3_eval_lookup_constraints(&mut folder)?;
 
         let folded_constraints = folder.accumulator;
 
         let folded_constraints_mul_sels_inv_zeroifier =
             self.p3_ext_mul(&folded_constraints, &sels.inv_zeroifier);
 
-        self.connect_p3_ext(&folded_constraints_mul_sels_inv_zeroifier, &quotient);
+        self.connect_p3_ext_if(
+            condition,
+            &folded_constraints_mul_sels_inv_zeroifier,
+            &quotient,
+        );
+
+        Ok(())
     }
 
-    fn p3_verify_opening_proof<H: AlgebraicHasher<F>>(
+    fn p3_verify_opening_proof<C: P3Challenger<F, D>>(
         &mut self,
         config: &FriConfig,
-        commits_and_points: Vec<(
-            Commitment<Target>,
-            Vec<(
-                TwoAdicMultiplicativeCoset,
-                Vec<(
-                    BinomialExtensionField<Target>,
-                    Vec<BinomialExtensionField<Target>>,
-                )>,
-            )>,
-        )>,
-        proof: TwoAdicFriPcsProof<Target>,
-        challenger: &mut DuplexChallengerTarget,
-    ) -> Result<(), ()> {
-        let alpha = self.p3_sample_ext::<H>(challenger);
+        commits_and_points: CommitsAndPoints<E>,
+        proof: TwoAdicFriPcsProof<Target, E>,
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError> {
+        let (alpha, fri_challenges) = self.p3_sample_opening_challenges::<C>(
+            config,
+            &commits_and_points,
+            &proof.fri_proof,
+            challenger,
+            condition,
+        )?;
 
-        let fri_challenges = self
-            .p3_verify_shape_and_sample_challenges::<H>(config, &proof.fri_proof, challenger)
-            .unwrap();
+        self.p3_verify_queries(
+            &challenger.mmcs(),
+            config,
+            &commits_and_points,
+            &proof,
+            &alpha,
+            &fri_challenges,
+            condition,
+        )
+    }
+
+    fn p3_sample_opening_challenges<C: P3Challenger<F, D>>(
+        &mut self,
+        config: &FriConfig,
+        commits_and_points: &CommitsAndPoints<E>,
+        proof: &FriProof<Target, E>,
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<(BinomialExtensionField<Target, E>, FriChallenges<Target, E>), P3VerifierError>
+    {
+        let alpha = challenger.sample_ext::<E>(self);
+
+        let log_arities = config.log_arities(&log_trace_heights(commits_and_points))?;
+        let fri_challenges = self.p3_verify_shape_and_sample_challenges::<C>(
+            config,
+            proof,
+            &log_arities,
+            challenger,
+            condition,
+        )?;
+
+        Ok((alpha, fri_challenges))
+    }
+
+    fn p3_verify_queries<M: P3Mmcs<F>>(
+        &mut self,
+        mmcs: &M,
+        config: &FriConfig,
+        commits_and_points: &CommitsAndPoints<E>,
+        proof: &TwoAdicFriPcsProof<Target, E>,
+        alpha: &BinomialExtensionField<Target, E>,
+        challenges: &FriChallenges<Target, E>,
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError> {
+        let num_queries = challenges.query_indices.len();
+        for actual in [
+            proof.query_openings.len(),
+            proof.fri_proof.query_proofs.len(),
+        ] {
+            if actual != num_queries {
+                return Err(P3VerifierError::QueryCountMismatch {
+                    expected: num_queries,
+                    actual,
+                });
+            }
+        }
 
-        let log_max_height = proof.fri_proof.commit_phase_commits.len() + config.log_blowup;
+        let log_arities = config.log_arities(&log_trace_heights(commits_and_points))?;
+        let log_max_height = config.log_max_height(&log_arities);
 
-        let reduced_openings: Vec<[BinomialExtensionField<Target>; 32]> = proof
+        let reduced_openings: Vec<[BinomialExtensionField<Target, E>; 32]> = proof
             .query_openings
             .iter()
-            .zip(&fri_challenges.query_indices)
+            .zip(&challenges.query_indices)
             .map(|(query_opening, &index)| {
                 let mut ro = self.p3_ext_arr::<32>();
                 let one = self.p3_ext_one();
-                let mut alpha_pow: [BinomialExtensionField<Target>; 32] =
+                let mut alpha_pow: [BinomialExtensionField<Target, E>; 32] =
                     self.p3_ext_arr_fn(|_| one.clone());
 
+                if query_opening.len() != commits_and_points.len() {
+                    return Err(P3VerifierError::BatchMmcs(MmcsError::WrongBatchSize));
+                }
+
                 for (batch_opening, (batch_commit, mats)) in
-                    izip!(query_opening, &commits_and_points)
+                    izip!(query_opening, commits_and_points)
                 {
                     let batch_dims: Vec<Dimensions> = mats
                         .iter()
@@ -285,13 +944,30 @@ where
                         })
                         .collect();
 
-                    self.p3_verify_batch::<H>(
-                        &batch_commit.value.to_vec(),
+                    // The tree of a batch is as tall as its tallest matrix,
+                    // which may be shorter than the tallest of all batches.
+                    let log_batch_max_height = mats
+                        .iter()
+                        .map(|(domain, _)| log2_strict_usize(domain.size()) + config.log_blowup)
+                        .max()
+                        .unwrap_or(log_max_height);
+                    let batch_index = match log_max_height.checked_sub(log_batch_max_height) {
+                        Some(0) => index,
+                        Some(bits_reduced) => self.p3_rsh(index, bits_reduced as u8),
+                        None => return Err(P3VerifierError::BatchMmcs(MmcsError::WrongHeight)),
+                    };
+
+                    <Self as CircuitBuilderP3Verifier<F, D, E>>::p3_verify_batch(
+                        self,
+                        mmcs,
+                        &batch_commit.value,
                         &batch_dims,
-                        index,
+                        batch_index,
                         &batch_opening.opened_values,
                         &batch_opening.opening_proof,
-                    )?;
+                        condition,
+                    )
+                    .map_err(P3VerifierError::BatchMmcs)?;
 
                     for (mat_opening, (mat_domain, mat_points_and_values)) in
                         izip!(&batch_opening.opened_values, mats)
@@ -303,8 +979,11 @@ where
                         let rev_reduced_index =
                             self.reverse_p3_bits_len(index_right_shifted, log_height);
 
-                        let generator = self.p3_w();
-                        let two_adic_generator = self.p3_two_adic_generator(log_height);
+                        let generator = self.p3_constant(GENERATOR);
+                        let two_adic_generator =
+                            <Self as CircuitBuilderP3ExtArithmetic<F, D, E>>::p3_two_adic_generator(
+                                self, log_height,
+                            );
                         let two_adic_generator_powers_rev_reduced_index =
                             self.exp(two_adic_generator, rev_reduced_index, 64);
 
@@ -332,7 +1011,7 @@ where
                                     ro_at_log_height_plus_alpha_pow_at_log_height_mul_quotient;
 
                                 let alpha_pow_at_log_height_mul_alpha =
-                                    self.p3_ext_mul(&alpha_pow[log_height], &alpha);
+                                    self.p3_ext_mul(&alpha_pow[log_height], alpha);
                                 alpha_pow[log_height] = alpha_pow_at_log_height_mul_alpha;
                             }
                         }
@@ -340,45 +1019,67 @@ where
                 }
                 Ok(ro)
             })
-            .collect::<Result<Vec<_>, ()>>()
-            .unwrap();
+            .collect::<Result<Vec<_>, _>>()?;
 
-        self.p3_verify_challenges::<H>(
+        self.p3_verify_challenges(
+            mmcs,
             config,
             &proof.fri_proof,
-            &fri_challenges,
+            &log_arities,
+            challenges,
             &reduced_openings,
+            condition,
         )
-        .unwrap();
-
-        Ok(())
     }
 
-    fn p3_verify_shape_and_sample_challenges<H: AlgebraicHasher<F>>(
+    fn p3_verify_shape_and_sample_challenges<C: P3Challenger<F, D>>(
         &mut self,
         config: &FriConfig,
-        proof: &FriProof<Target>,
-        challenger: &mut DuplexChallengerTarget,
-    ) -> Result<FriChallenges<Target>, FriError> {
-        let betas: Vec<BinomialExtensionField<Target>> = proof
+        proof: &FriProof<Target, E>,
+        log_arities: &[usize],
+        challenger: &mut C,
+        condition: BoolTarget,
+    ) -> Result<FriChallenges<Target, E>, P3VerifierError> {
+        if proof.commit_phase_commits.len() != log_arities.len() {
+            return Err(FriError::InvalidProofShape.into());
+        }
+
+        let betas: Vec<BinomialExtensionField<Target, E>> = proof
             .commit_phase_commits
             .iter()
             .map(|comm| {
-                self.p3_observe::<H>(challenger, comm.value.clone());
-                self.p3_sample_ext::<H>(challenger)
+                challenger.observe_digest(self, &comm.value);
+                challenger.sample_ext::<E>(self)
             })
             .collect();
 
+        if proof.final_poly.len() != config.final_poly_len() {
+            return Err(FriError::InvalidProofShape.into());
+        }
+        if config.log_final_poly_len.is_some() {
+            for coeff in &proof.final_poly {
+                challenger.observe(self, coeff.value.iter().copied());
+            }
+        }
+
         if proof.query_proofs.len() != config.num_queries {
-            return Err(FriError::InvalidProofShape);
+            return Err(P3VerifierError::QueryCountMismatch {
+                expected: config.num_queries,
+                actual: proof.query_proofs.len(),
+            });
         }
 
-        self.p3_check_witness::<H>(challenger, config.proof_of_work_bits, proof.pow_witness);
+        challenger.check_witness(
+            self,
+            config.proof_of_work_bits,
+            proof.pow_witness,
+            condition,
+        );
 
-        let log_max_height = proof.commit_phase_commits.len() + config.log_blowup;
+        let log_max_height = config.log_max_height(log_arities);
 
         let query_indices: Vec<Target> = (0..config.num_queries)
-            .map(|_| self.p3_sample_bits::<H>(challenger, log_max_height))
+            .map(|_| challenger.sample_bits(self, log_max_height))
             .collect();
 
         Ok(FriChallenges {
@@ -387,159 +1088,237 @@ where
         })
     }
 
-    fn p3_verify_challenges<H: AlgebraicHasher<F>>(
+    fn p3_verify_challenges<M: P3Mmcs<F>>(
         &mut self,
+        mmcs: &M,
         config: &FriConfig,
-        proof: &FriProof<Target>,
-        challenges: &FriChallenges<Target>,
-        reduced_openings: &[[BinomialExtensionField<Target>; 32]],
-    ) -> Result<(), ()> {
-        let log_max_height = proof.commit_phase_commits.len() + config.log_blowup;
+        proof: &FriProof<Target, E>,
+        log_arities: &[usize],
+        challenges: &FriChallenges<Target, E>,
+        reduced_openings: &[[BinomialExtensionField<Target, E>; 32]],
+        condition: BoolTarget,
+    ) -> Result<(), P3VerifierError> {
+        let log_max_height = config.log_max_height(log_arities);
         for (&index, query_proof, ro) in izip!(
             &challenges.query_indices,
             &proof.query_proofs,
             reduced_openings
         ) {
-            let folded_eval = self.p3_verify_query::<H>(
+            let (folded_eval, x) = self.p3_verify_query(
+                mmcs,
                 config,
                 &proof.commit_phase_commits,
+                log_arities,
                 index,
                 query_proof,
                 &challenges.betas,
                 ro,
                 log_max_height,
+                condition,
             )?;
 
-            self.connect_p3_ext(&folded_eval, &proof.final_poly);
+            // Horner's rule, from the leading coefficient down.
+            let (leading_coeff, coeffs) = proof
+                .final_poly
+                .split_last()
+                .ok_or(FriError::InvalidProofShape)?;
+            let final_poly_eval = coeffs
+                .iter()
+                .rev()
+                .fold(leading_coeff.clone(), |acc, coeff| {
+                    let acc_mul_x = self.p3_ext_mul_single(&acc, x);
+                    self.p3_ext_add(acc_mul_x, coeff.clone())
+                });
+
+            self.connect_p3_ext_if(condition, &folded_eval, &final_poly_eval);
         }
 
         Ok(())
     }
 
-    fn p3_verify_query<H: AlgebraicHasher<F>>(
+    fn p3_verify_query<M: P3Mmcs<F>>(
         &mut self,
+        mmcs: &M,
         _config: &FriConfig,
         commit_phase_commits: &Vec<Commitment<Target>>,
+        log_arities: &[usize],
         mut index: Target,
-        proof: &QueryProof<Target>,
-        betas: &[BinomialExtensionField<Target>],
-        reduced_openings: &[BinomialExtensionField<Target>; 32],
+        proof: &QueryProof<Target, E>,
+        betas: &[BinomialExtensionField<Target, E>],
+        reduced_openings: &[BinomialExtensionField<Target, E>; 32],
         log_max_height: usize,
-    ) -> Result<BinomialExtensionField<Target>, ()> {
-        let mut folded_eval = <Self as CircuitBuilderP3ExtArithmetic<F, D>>::p3_ext_zero(self);
-        // TODO: use p3_ext_two_adic_generator
-        let two_adic_generator = self.p3_two_adic_generator(log_max_height);
-        let rev_index_shifted = self.reverse_p3_bits_len(index, log_max_height);
-        let x = self.exp(two_adic_generator, rev_index_shifted, 64);
-        let mut x = BinomialExtensionField::<Target> {
-            value: self.p3_field_to_arr(x),
-        };
+        condition: BoolTarget,
+    ) -> Result<(BinomialExtensionField<Target, E>, Target), P3VerifierError> {
+        if proof.commit_phase_openings.len() != commit_phase_commits.len()
+            || log_arities.len() != commit_phase_commits.len()
+        {
+            return Err(FriError::InvalidProofShape.into());
+        }
 
-        let one = self.one();
+        let mut folded_eval = <Self as CircuitBuilderP3ExtArithmetic<F, D, E>>::p3_ext_zero(self);
+        let two_adic_generator =
+            <Self as CircuitBuilderP3ExtArithmetic<F, D, E>>::p3_two_adic_generator(
+                self,
+                log_max_height,
+            );
+        let rev_index_shifted = self.reverse_p3_bits_len(index, log_max_height);
+        // The point of the queried location, whose `arity`-th power is the
+        // point of the folded location.
+        let mut x = self.exp(two_adic_generator, rev_index_shifted, 64);
 
-        let rev_log_max_height: Vec<_> = (0..log_max_height).rev().collect();
-        for (log_folded_height, commit, step, beta) in izip!(
-            rev_log_max_height,
+        let mut log_height = log_max_height;
+        for (&log_arity, commit, step, beta) in izip!(
+            log_arities,
             commit_phase_commits,
             &proof.commit_phase_openings,
             betas
         ) {
-            folded_eval =
-                self.p3_ext_add(reduced_openings[log_folded_height + 1].clone(), folded_eval);
-
-            let index_sibling = self.p3_xor(index, one);
-
-            let index_pair = self.p3_rsh(index, 1);
-
-            let index_sibling_and_one = self.p3_and(index_sibling, one);
-
-            let is_odd = BoolTarget::new_unsafe(index_sibling_and_one);
-
-            let mut evals = vec![folded_eval.clone(); 2];
-
-            // evals[index_sibling % 2] = step.sibling_value.clone();
-
-            evals[0] = self.p3_ext_if(is_odd, evals[0].clone(), step.sibling_value.clone());
-
-            evals[1] = self.p3_ext_if(is_odd, step.sibling_value.clone(), evals[1].clone());
+            let arity = 1 << log_arity;
+            if step.sibling_values.len() != arity - 1 {
+                return Err(FriError::InvalidProofShape.into());
+            }
+
+            folded_eval = self.p3_ext_add(reduced_openings[log_height].clone(), folded_eval);
+
+            let log_folded_height = log_height - log_arity;
+            let index_row = self.p3_rsh(index, log_arity as u8);
+            let index_row_start = self.mul_const(F::from_canonical_usize(arity), index_row);
+            let index_in_row = self.sub(index, index_row_start);
+            let index_in_row_bits = self.split_le(index_in_row, log_arity);
+
+            // The queried evaluation sits at `index_in_row`, between the
+            // siblings before and after it.
+            let mut is_after_queried = self._false();
+            let mut evals = Vec::with_capacity(arity);
+            for j in 0..arity {
+                let j_target = self.constant(F::from_canonical_usize(j));
+                let is_queried = self.is_equal(index_in_row, j_target);
+                let sibling = match j {
+                    0 => step.sibling_values[0].clone(),
+                    _ if j == arity - 1 => step.sibling_values[j - 1].clone(),
+                    _ => self.p3_ext_if(
+                        is_after_queried,
+                        step.sibling_values[j - 1].clone(),
+                        step.sibling_values[j].clone(),
+                    ),
+                };
+                evals.push(self.p3_ext_if(is_queried, folded_eval.clone(), sibling));
+                is_after_queried = self.or(is_after_queried, is_queried);
+            }
 
             let dims = &[Dimensions {
-                width: 2,
+                width: arity,
                 height: (1 << log_folded_height),
             }];
 
-            self.p3_verify_batch::<H>(
-                &commit.value.to_vec(),
+            <Self as CircuitBuilderP3Verifier<F, D, E>>::p3_verify_batch(
+                self,
+                mmcs,
+                &commit.value,
                 dims,
-                index_pair,
+                index_row,
                 &vec![evals
                     .iter()
                     .flat_map(|row| row.value.iter().copied().collect::<Vec<_>>())
                     .collect::<Vec<_>>()],
                 &step.opening_proof,
+                condition,
             )
-            .unwrap();
-
-            let mut xs = self.p3_ext_arr_fn::<2>(|_| x.clone());
-            let two_adic_generator = self.p3_ext_two_adic_generator(1);
-            let xs_0_mul_two_adic_generator = self.p3_ext_mul(&xs[0], &two_adic_generator);
-            let xs_1_mul_two_adic_generator = self.p3_ext_mul(&xs[1], &two_adic_generator);
-            let one = self.one();
-            let index_sibling_and_one = self.p3_and(index_sibling, one);
-            let is_odd = BoolTarget::new_unsafe(index_sibling_and_one);
-
-            xs[0] = self.p3_ext_if(is_odd, xs[0].clone(), xs_0_mul_two_adic_generator);
-            xs[1] = self.p3_ext_if(is_odd, xs_1_mul_two_adic_generator, xs[1].clone());
-
-            // interpolate and evaluate at beta
-
-            let beta_minus_xs_0 = self.p3_ext_sub(beta.clone(), xs[0].clone());
-
-            let eval_1_minus_eval_0 = self.p3_ext_sub(evals[1].clone(), evals[0].clone());
-
-            let xs_1_minus_xs_0 = self.p3_ext_sub(xs[1].clone(), xs[0].clone());
-
-            let eval_1_minus_eval_0_mul_beta_minus_xs_0 =
-                self.p3_ext_mul(&eval_1_minus_eval_0, &beta_minus_xs_0);
+            .map_err(P3VerifierError::CommitPhaseMmcs)?;
+
+            // The row starts at `x0 = x / w^rev(index_in_row)` for `w` of order
+            // `arity`, and bit `i` of the position contributes the root of
+            // unity of order `2^(i + 1)` to that power.
+            let mut x0 = x;
+            for (i, &bit) in index_in_row_bits.iter().enumerate() {
+                let root_inv = two_adic_generator_value(i + 1).inverse();
+                let root_inv = self.constant(F::from_canonical_u64(root_inv.to_canonical_u64()));
+                let x0_mul_root_inv = self.mul(x0, root_inv);
+                x0 = self._if(bit, x0_mul_root_inv, x0);
+            }
+
+            folded_eval = self.p3_fold_row(x0, log_arity, beta, &evals);
+
+            index = index_row;
+            log_height = log_folded_height;
+            x = self.exp_power_of_2(x, log_arity);
+        }
 
-            let eval_1_minus_eval_0_mul_beta_minus_xs_0_div_xs_1_minus_xs_0 =
-                self.p3_ext_div(eval_1_minus_eval_0_mul_beta_minus_xs_0, xs_1_minus_xs_0);
+        // Inputs as short as the final polynomial are rolled in unfolded.
+        folded_eval = self.p3_ext_add(reduced_openings[log_height].clone(), folded_eval);
 
-            folded_eval = self.p3_ext_add(
-                evals[0].clone(),
-                eval_1_minus_eval_0_mul_beta_minus_xs_0_div_xs_1_minus_xs_0,
-            );
+        Ok((folded_eval, x))
+    }
 
-            index = index_pair;
+    fn p3_fold_row(
+        &mut self,
+        x0: Target,
+        log_arity: usize,
+        beta: &BinomialExtensionField<Target, E>,
+        evals: &[BinomialExtensionField<Target, E>],
+    ) -> BinomialExtensionField<Target, E> {
+        let (points, denominator_invs) = fold_coset(log_arity);
+        let beta_minus_xs: Vec<_> = points
+            .iter()
+            .map(|point| {
+                let point = self.constant(F::from_canonical_u64(point.to_canonical_u64()));
+                let x = self.mul(x0, point);
+                self.p3_ext_sub_single(beta.clone(), x)
+            })
+            .collect();
 
-            x = self.p3_ext_mul(&x, &x);
+        // The numerator of the `j`-th Lagrange basis polynomial is the product
+        // of the `beta - x_m` before `j` and of those after it.
+        let mut products_before = vec![self.p3_ext_one()];
+        for beta_minus_x in &beta_minus_xs[..beta_minus_xs.len() - 1] {
+            let product = self.p3_ext_mul(products_before.last().unwrap(), beta_minus_x);
+            products_before.push(product);
+        }
+        let mut product_after = self.p3_ext_one();
+        let mut sum = self.p3_ext_zero();
+        for j in (0..evals.len()).rev() {
+            let numerator = self.p3_ext_mul(&products_before[j], &product_after);
+            let term = self.p3_ext_mul(&numerator, &evals[j]);
+            let denominator_inv = self.constant(F::from_canonical_u64(
+                denominator_invs[j].to_canonical_u64(),
+            ));
+            let term = self.p3_ext_mul_single(&term, denominator_inv);
+            sum = self.p3_ext_add(sum, term);
+            product_after = self.p3_ext_mul(&product_after, &beta_minus_xs[j]);
         }
 
-        Ok(folded_eval)
+        // The denominators over the coset are those over the unit coset,
+        // scaled by `x0^(arity - 1)`.
+        let x0_pow = self.exp_u64(x0, evals.len() as u64 - 1);
+        let x0_pow_inv = self.inverse(x0_pow);
+        self.p3_ext_mul_single(&sum, x0_pow_inv)
     }
 
-    fn p3_verify_batch<H: AlgebraicHasher<F>>(
+    fn p3_verify_batch<M: P3Mmcs<F>>(
         &mut self,
+        mmcs: &M,
         commit: &Vec<Target>,
         dimensions: &[Dimensions],
         index: Target,
         opened_values: &Vec<Vec<Target>>,
         proof: &Vec<Vec<Target>>,
-    ) -> Result<(), ()> {
+        condition: BoolTarget,
+    ) -> Result<(), MmcsError> {
         let base_dimensions = dimensions
             .iter()
             .map(|dim| Dimensions {
-                width: dim.width * EXT_DEGREE,
+                width: dim.width * E,
                 height: dim.height,
             })
             .collect::<Vec<_>>();
 
-        commit::MerkleTreeMmcs::verify_batch::<H, F, D>(
+        mmcs.verify_batch(
             commit,
             &base_dimensions,
             index,
             &opened_values,
             proof,
+            condition,
             self,
         )
     }